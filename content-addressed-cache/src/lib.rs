@@ -2,7 +2,9 @@
 // SPDX-License-Identifier: Apache-2.0
 
 mod local_cache;
+mod object_store;
 mod synchronizer;
 
 pub use local_cache::*;
+pub use object_store::*;
 pub use synchronizer::*;
@@ -23,6 +23,7 @@ pub type CacheKey = git2::Oid;
 pub trait Cache: Debug {
     fn put(&self, kind: CacheKeyKind, key: CacheKey, value: &[u8]) -> anyhow::Result<()>;
     fn get(&self, kind: CacheKeyKind, key: CacheKey) -> anyhow::Result<Option<Vec<u8>>>;
+    fn delete(&self, kind: CacheKeyKind, key: CacheKey) -> anyhow::Result<()>;
     fn clear(&self) -> anyhow::Result<()>;
 }
 
@@ -107,27 +108,61 @@ impl FromStr for CompositeKey {
     }
 }
 
+/// How long to wait for another process to close a database before giving up.
+const OPEN_RETRY_TIMEOUT: Duration = Duration::from_secs(10);
+
+const OPEN_RETRY_INTERVAL: Duration = Duration::from_millis(100);
+
 impl RocksDBCache {
-    fn make_db(path: &Path, ttl: Duration) -> DB {
+    fn make_db(path: &Path, ttl: Duration) -> anyhow::Result<DB> {
         let mut opts = Options::default();
         opts.create_if_missing(true);
         // Compression settings from https://github.com/facebook/rocksdb/wiki/Setup-Options-and-Basic-Tuning#compression
         opts.set_compression_type(rocksdb::DBCompressionType::Lz4);
         opts.set_bottommost_compression_type(rocksdb::DBCompressionType::Zstd);
-        DB::open_with_ttl(&opts, path, ttl).unwrap()
+        let deadline = std::time::Instant::now() + OPEN_RETRY_TIMEOUT;
+        loop {
+            match DB::open_with_ttl(&opts, path, ttl) {
+                Ok(db) => return Ok(db),
+                // RocksDB holds an exclusive lock on its `LOCK` file while a
+                // database is open, so another process may be using it.
+                Err(e) if is_lock_error(&e) && std::time::Instant::now() < deadline => {
+                    debug!(?path, ?e, "Database is locked, retrying");
+                    std::thread::sleep(OPEN_RETRY_INTERVAL);
+                }
+                Err(e) if is_lock_error(&e) => {
+                    return Err(e).with_context(|| {
+                        format!(
+                            "The database at {} is in use by another process",
+                            path.display()
+                        )
+                    })
+                }
+                Err(e) => {
+                    return Err(e)
+                        .with_context(|| format!("Opening database at {}", path.display()))
+                }
+            }
+        }
     }
 
-    pub fn open_with_ttl(path: impl AsRef<Path>, ttl: Duration) -> Self {
-        Self {
-            db: RefCell::new(Some(Self::make_db(path.as_ref(), ttl))),
+    pub fn open_with_ttl(path: impl AsRef<Path>, ttl: Duration) -> anyhow::Result<Self> {
+        Ok(Self {
+            db: RefCell::new(Some(Self::make_db(path.as_ref(), ttl)?)),
             ttl,
-        }
+        })
     }
-    pub fn open(path: PathBuf) -> Self {
+
+    pub fn open(path: PathBuf) -> anyhow::Result<Self> {
         Self::open_with_ttl(path, Duration::from_secs(0))
     }
 }
 
+fn is_lock_error(error: &rocksdb::Error) -> bool {
+    let message = error.to_string();
+    message.contains("/LOCK") || message.contains("lock hold by current process")
+}
+
 impl Drop for RocksDBCache {
     fn drop(&mut self) {
         let db = self.db.borrow();
@@ -165,6 +200,16 @@ impl Cache for RocksDBCache {
             .with_context(|| format!("Getting {:?} failed", key))
     }
 
+    fn delete(&self, kind: CacheKeyKind, key: CacheKey) -> anyhow::Result<()> {
+        let key: &[u8] = &CompositeKey { kind, key }.to_bytes()[..];
+        self.db
+            .borrow()
+            .as_ref()
+            .unwrap()
+            .delete(key)
+            .with_context(|| format!("Deleting {:?} failed", key))
+    }
+
     fn clear(&self) -> anyhow::Result<()> {
         let path = self.db.borrow().as_ref().unwrap().path().to_path_buf();
         {
@@ -172,7 +217,7 @@ impl Cache for RocksDBCache {
             drop(db);
         }
         DB::destroy(&Options::default(), &path)?;
-        *self.db.borrow_mut() = Some(Self::make_db(&path, self.ttl));
+        *self.db.borrow_mut() = Some(Self::make_db(&path, self.ttl)?);
         Ok(())
     }
 }
@@ -229,7 +274,7 @@ mod tests {
         let tmp_dir = tempdir().unwrap();
         let file_path = tmp_dir.path().join("focus-rocks");
         {
-            let cache = RocksDBCache::open(file_path.clone());
+            let cache = RocksDBCache::open(file_path.clone()).unwrap();
             cache
                 .put(kind(), CacheKey::from_str(KEY).unwrap(), b"abcd")
                 .unwrap();
@@ -237,10 +282,25 @@ mod tests {
         (tmp_dir, file_path)
     }
 
+    #[test]
+    fn test_open_waits_for_another_user_to_close() -> anyhow::Result<()> {
+        let (_temp_dir, file_path) = create_test_repo();
+        let cache = RocksDBCache::open(file_path.clone())?;
+        let closer = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(500));
+            drop(cache);
+        });
+        let cache = RocksDBCache::open(file_path)?;
+        closer.join().unwrap();
+        let value = cache.get(kind(), CacheKey::from_str(KEY).unwrap());
+        assert_eq!(value?.unwrap(), b"abcd".to_vec());
+        Ok(())
+    }
+
     #[test]
     fn test_key_insert_get() -> anyhow::Result<()> {
         let (_temp_dir, file_path) = create_test_repo();
-        let cache = RocksDBCache::open(file_path);
+        let cache = RocksDBCache::open(file_path)?;
         std::thread::sleep(Duration::from_secs(2));
         let value = cache.get(kind(), CacheKey::from_str(KEY).unwrap());
         assert_eq!(value?.unwrap(), b"abcd".to_vec());
@@ -250,16 +310,29 @@ mod tests {
     #[test]
     fn test_key_insert_get_ttl() -> anyhow::Result<()> {
         let (_temp_dir, file_path) = create_test_repo();
-        let cache = RocksDBCache::open_with_ttl(file_path, Duration::from_secs(3600));
+        let cache = RocksDBCache::open_with_ttl(file_path, Duration::from_secs(3600))?;
         let value = cache.get(kind(), CacheKey::from_str(KEY).unwrap());
         assert_eq!(value?.unwrap(), b"abcd".to_vec());
         Ok(())
     }
 
+    #[test]
+    fn test_key_delete() -> anyhow::Result<()> {
+        let (_temp_dir, file_path) = create_test_repo();
+        let cache = RocksDBCache::open(file_path)?;
+        cache.delete(kind(), CacheKey::from_str(KEY).unwrap())?;
+        let value = cache.get(kind(), CacheKey::from_str(KEY).unwrap());
+        assert_eq!(value?, None);
+
+        // Deleting a missing key is not an error.
+        cache.delete(kind(), CacheKey::from_str(BAD_OID).unwrap())?;
+        Ok(())
+    }
+
     #[test]
     fn test_key_missing() -> anyhow::Result<()> {
         let (_temp_dir, file_path) = create_test_repo();
-        let cache = RocksDBCache::open(file_path);
+        let cache = RocksDBCache::open(file_path)?;
         let value = cache.get(kind(), CacheKey::from_str(BAD_OID).unwrap());
         assert_eq!(value?, None);
         Ok(())
@@ -268,7 +341,7 @@ mod tests {
     #[test]
     fn test_function_missing() -> anyhow::Result<()> {
         let (_temp_dir, file_path) = create_test_repo();
-        let cache = RocksDBCache::open(file_path);
+        let cache = RocksDBCache::open(file_path)?;
         let value = cache.get(bad_kind(), CacheKey::from_str(KEY).unwrap());
         assert_eq!(value?, None);
         Ok(())
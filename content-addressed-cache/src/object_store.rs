@@ -0,0 +1,878 @@
+// Copyright 2022 Twitter, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use crate::{
+    Cache, CacheSynchronizer, CompositeKey, Keyset, KeysetID, KeysetNotFound, PopulateResult,
+    MAX_DELTA_CHAIN_LENGTH,
+};
+use anyhow::{bail, Context, Result};
+
+use core::fmt;
+use focus_util::lock_file::WaitingLock;
+use git2::{ObjectType, Oid};
+use reqwest::{
+    blocking::Client,
+    header::{ETAG, IF_MATCH, IF_NONE_MATCH},
+    StatusCode,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::fmt::Debug;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+use tracing::{debug, info, instrument, warn};
+use url::Url;
+
+/// Remotes with this prefix are served by an [ObjectStoreCacheSynchronizer]
+/// rather than a Git server, e.g. `objects+https://cache.example.com/index`
+/// or `objects+file:///srv/index`.
+pub const OBJECT_STORE_SCHEME_PREFIX: &str = "objects+";
+
+/// How many times to try updating the remote listing when racing with other
+/// writers.
+const LISTING_UPDATE_ATTEMPTS: usize = 10;
+
+/// Storage for opaque objects addressed by relative paths.
+pub trait ObjectStore: Debug {
+    /// Read an object, returning `None` if it does not exist.
+    fn get(&self, path: &str) -> Result<Option<Vec<u8>>>;
+
+    /// Write an object, replacing any existing object at the path.
+    fn put(&self, path: &str, value: Vec<u8>) -> Result<()>;
+
+    fn exists(&self, path: &str) -> Result<bool>;
+
+    /// Read an object along with a tag identifying its current version, or
+    /// `None` if it does not exist.
+    fn get_versioned(&self, path: &str) -> Result<Option<(Vec<u8>, String)>>;
+
+    /// Write an object only if it is still at `version`, as returned by
+    /// [ObjectStore::get_versioned], or still absent if `version` is `None`.
+    /// Returns whether the object was written.
+    fn put_if_unchanged(&self, path: &str, value: Vec<u8>, version: Option<&str>) -> Result<bool>;
+}
+
+/// An object store that uses HTTP GET, HEAD and PUT against a base URL.
+pub struct HttpObjectStore {
+    endpoint: Url,
+    client: Client,
+}
+
+impl fmt::Debug for HttpObjectStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HttpObjectStore")
+            .field("endpoint", &self.endpoint.as_str())
+            .finish()
+    }
+}
+
+impl HttpObjectStore {
+    pub fn new(endpoint: Url) -> Result<Self> {
+        static APP_USER_AGENT: &str = concat!("focus", "/", env!("CARGO_PKG_VERSION"));
+        let client = Client::builder()
+            .timeout(Duration::from_secs(30))
+            .user_agent(APP_USER_AGENT)
+            .build()
+            .context("Creating HTTP client failed")?;
+        Ok(Self { endpoint, client })
+    }
+
+    fn url_for(&self, path: &str) -> Url {
+        let mut url = self.endpoint.clone();
+        url.set_path(&format!(
+            "{}/{}",
+            self.endpoint.path().trim_end_matches('/'),
+            path
+        ));
+        url
+    }
+}
+
+impl ObjectStore for HttpObjectStore {
+    fn get(&self, path: &str) -> Result<Option<Vec<u8>>> {
+        let url = self.url_for(path);
+        debug!(url = ?url.as_str(), "GET");
+        let response = self.client.get(url).send().context("GET failed")?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let bytes = response
+            .error_for_status()?
+            .bytes()
+            .context("Reading response failed")?;
+        Ok(Some(bytes.to_vec()))
+    }
+
+    fn put(&self, path: &str, value: Vec<u8>) -> Result<()> {
+        let url = self.url_for(path);
+        debug!(url = ?url.as_str(), "PUT");
+        self.client
+            .put(url)
+            .body(value)
+            .send()
+            .context("PUT failed")?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    fn exists(&self, path: &str) -> Result<bool> {
+        let url = self.url_for(path);
+        let response = self.client.head(url).send().context("HEAD failed")?;
+        match response.status() {
+            StatusCode::NOT_FOUND => Ok(false),
+            status if status.is_success() => Ok(true),
+            status => bail!("HEAD returned unexpected status {}", status),
+        }
+    }
+
+    fn get_versioned(&self, path: &str) -> Result<Option<(Vec<u8>, String)>> {
+        let url = self.url_for(path);
+        debug!(url = ?url.as_str(), "GET");
+        let response = self.client.get(url).send().context("GET failed")?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let response = response.error_for_status()?;
+        let etag = response
+            .headers()
+            .get(ETAG)
+            .and_then(|etag| etag.to_str().ok())
+            .map(String::from)
+            .ok_or_else(|| {
+                anyhow::anyhow!(
+                    "The object store returned no ETag for {}, so it can't be updated safely",
+                    path
+                )
+            })?;
+        let bytes = response.bytes().context("Reading response failed")?;
+        Ok(Some((bytes.to_vec(), etag)))
+    }
+
+    fn put_if_unchanged(&self, path: &str, value: Vec<u8>, version: Option<&str>) -> Result<bool> {
+        let url = self.url_for(path);
+        debug!(url = ?url.as_str(), ?version, "Conditional PUT");
+        let request = match version {
+            Some(etag) => self.client.put(url).header(IF_MATCH, etag),
+            None => self.client.put(url).header(IF_NONE_MATCH, "*"),
+        };
+        let response = request.body(value).send().context("PUT failed")?;
+        if response.status() == StatusCode::PRECONDITION_FAILED {
+            return Ok(false);
+        }
+        response.error_for_status()?;
+        Ok(true)
+    }
+}
+
+/// An object store backed by a local (or network mounted) directory.
+#[derive(Debug)]
+pub struct LocalObjectStore {
+    root: PathBuf,
+}
+
+impl LocalObjectStore {
+    pub fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+}
+
+impl ObjectStore for LocalObjectStore {
+    fn get(&self, path: &str) -> Result<Option<Vec<u8>>> {
+        match std::fs::read(self.root.join(path)) {
+            Ok(content) => Ok(Some(content)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).with_context(|| format!("Reading object {}", path)),
+        }
+    }
+
+    fn put(&self, path: &str, value: Vec<u8>) -> Result<()> {
+        let path = self.root.join(path);
+        let dir = path
+            .parent()
+            .ok_or_else(|| anyhow::anyhow!("Object path has no parent"))?;
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Creating directory {}", dir.display()))?;
+        // Write to a temporary file and rename it into place so that readers never see partial objects.
+        let temp_file = tempfile::NamedTempFile::new_in(dir).context("Creating temporary file")?;
+        std::fs::write(temp_file.path(), value).context("Writing temporary file")?;
+        temp_file
+            .persist(&path)
+            .with_context(|| format!("Moving object into place at {}", path.display()))?;
+        Ok(())
+    }
+
+    fn exists(&self, path: &str) -> Result<bool> {
+        Ok(self.root.join(path).is_file())
+    }
+
+    fn get_versioned(&self, path: &str) -> Result<Option<(Vec<u8>, String)>> {
+        Ok(self.get(path)?.map(|content| {
+            let version = local_version(&content);
+            (content, version)
+        }))
+    }
+
+    fn put_if_unchanged(&self, path: &str, value: Vec<u8>, version: Option<&str>) -> Result<bool> {
+        // Writers take a lock next to the object so that checking its version and replacing it is atomic.
+        let object_path = self.root.join(path);
+        let dir = object_path
+            .parent()
+            .ok_or_else(|| anyhow::anyhow!("Object path has no parent"))?;
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Creating directory {}", dir.display()))?;
+        let mut lock_path = object_path.clone().into_os_string();
+        lock_path.push(".lock");
+        let _lock = WaitingLock::acquire(Path::new(&lock_path))?;
+
+        let current_version = self.get(path)?.map(|content| local_version(&content));
+        if current_version.as_deref() != version {
+            return Ok(false);
+        }
+        self.put(path, value)?;
+        Ok(true)
+    }
+}
+
+/// The version of an object in a local store is the hash of its content.
+fn local_version(content: &[u8]) -> String {
+    Oid::hash_object(ObjectType::Blob, content)
+        .map(|oid| oid.to_string())
+        .unwrap_or_default()
+}
+
+/// Describes a keyset: which objects hold the value of each key.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct KeysetManifest {
+    pub keyset_id: String,
+    /// The keyset this one is a delta against, if any. Entries of the parent
+    /// chain are part of this keyset unless overridden here.
+    pub parent: Option<String>,
+    /// Maps composite keys to the ID of the object holding their value.
+    pub entries: BTreeMap<String, String>,
+}
+
+/// Synchronize using a plain object store (e.g. an HTTP server accepting
+/// `PUT`s). Values are stored as content-addressed objects, so values shared
+/// between keysets are only stored once, and each keyset is stored as a
+/// manifest mapping its keys to objects. A listing of keysets is maintained
+/// alongside since object stores generally can't enumerate their contents.
+/// The listing is updated with conditional writes, so HTTP stores must
+/// support `ETag`s with `If-Match` and `If-None-Match`.
+///
+/// Layout beneath the endpoint:
+/// ```text
+/// <namespace>/keysets.txt             -- one keyset ID per line
+/// <namespace>/keysets/<keyset ID>.json
+/// <namespace>/objects/<2 hex digits>/<remaining 38 hex digits>
+/// ```
+pub struct ObjectStoreCacheSynchronizer {
+    store: Box<dyn ObjectStore>,
+    path: PathBuf,
+    namespace: String,
+}
+
+impl fmt::Debug for ObjectStoreCacheSynchronizer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ObjectStoreCacheSynchronizer")
+            .field("store", &self.store)
+            .field("path", &self.path)
+            .field("namespace", &self.namespace)
+            .finish()
+    }
+}
+
+impl ObjectStoreCacheSynchronizer {
+    /// Whether the given remote should be served by this synchronizer.
+    pub fn handles_remote(remote: &str) -> bool {
+        remote.starts_with(OBJECT_STORE_SCHEME_PREFIX)
+    }
+
+    /// Create a synchronizer for the given remote, keeping fetched manifests in `path`.
+    pub fn create(path: PathBuf, remote: &str, namespace: String) -> Result<Self> {
+        let url = remote
+            .strip_prefix(OBJECT_STORE_SCHEME_PREFIX)
+            .ok_or_else(|| anyhow::anyhow!("Remote '{}' is not an object store", remote))?;
+        let url = Url::parse(url).with_context(|| format!("Parsing remote URL '{}'", url))?;
+        let store: Box<dyn ObjectStore> = match url.scheme() {
+            "file" => Box::new(LocalObjectStore::new(url.to_file_path().map_err(|_| {
+                anyhow::anyhow!("Remote '{}' does not refer to a local path", remote)
+            })?)),
+            "http" | "https" => Box::new(HttpObjectStore::new(url)?),
+            scheme => bail!("Unsupported object store scheme '{}'", scheme),
+        };
+        Ok(Self::with_store(path, store, namespace))
+    }
+
+    pub fn with_store(path: PathBuf, store: Box<dyn ObjectStore>, namespace: String) -> Self {
+        Self {
+            store,
+            path,
+            namespace,
+        }
+    }
+
+    fn listing_path(&self) -> String {
+        format!("{}/keysets.txt", self.namespace)
+    }
+
+    fn manifest_path(&self, keyset_id: KeysetID) -> String {
+        format!("{}/keysets/{}.json", self.namespace, keyset_id)
+    }
+
+    fn object_path(&self, object_id: &str) -> String {
+        format!(
+            "{}/objects/{}/{}",
+            self.namespace,
+            &object_id[..2],
+            &object_id[2..]
+        )
+    }
+
+    fn local_manifest_path(&self, keyset_id: KeysetID) -> PathBuf {
+        self.path
+            .join("keysets")
+            .join(format!("{}.json", keyset_id))
+    }
+
+    fn read_listing(&self) -> Result<BTreeSet<String>> {
+        let listing = self
+            .store
+            .get(&self.listing_path())
+            .context("Reading keyset listing")?
+            .unwrap_or_default();
+        parse_listing(&listing)
+    }
+
+    /// Apply `update` to the listing, which returns whether it changed
+    /// anything. The listing is only replaced if no other writer has changed
+    /// it since it was read; otherwise the update is retried.
+    fn update_listing(&self, update: impl Fn(&mut BTreeSet<String>) -> bool) -> Result<()> {
+        for _ in 0..LISTING_UPDATE_ATTEMPTS {
+            let (mut listing, version) = match self
+                .store
+                .get_versioned(&self.listing_path())
+                .context("Reading keyset listing")?
+            {
+                Some((content, version)) => (parse_listing(&content)?, Some(version)),
+                None => (BTreeSet::new(), None),
+            };
+            if !update(&mut listing) {
+                return Ok(());
+            }
+            let mut content = listing.into_iter().collect::<Vec<_>>().join("\n");
+            if !content.is_empty() {
+                content.push('\n');
+            }
+            if self
+                .store
+                .put_if_unchanged(
+                    &self.listing_path(),
+                    content.into_bytes(),
+                    version.as_deref(),
+                )
+                .context("Writing keyset listing")?
+            {
+                return Ok(());
+            }
+            warn!("The keyset listing was changed by a concurrent update; retrying");
+        }
+        bail!("Failed to update the keyset listing")
+    }
+
+    fn add_to_listing(&self, keyset_id: KeysetID) -> Result<()> {
+        let keyset_id = keyset_id.to_string();
+        self.update_listing(|listing| listing.insert(keyset_id.clone()))
+            .with_context(|| format!("Adding keyset {} to the listing", keyset_id))
+    }
+
+    fn remove_from_listing(&self, keyset_ids: &HashSet<KeysetID>) -> Result<()> {
+        let keyset_ids: HashSet<String> = keyset_ids.iter().map(|id| id.to_string()).collect();
+        self.update_listing(|listing| {
+            let count = listing.len();
+            listing.retain(|keyset_id| !keyset_ids.contains(keyset_id));
+            listing.len() != count
+        })
+        .context("Removing keysets from the listing")
+    }
+
+    fn load_remote_manifest(&self, keyset_id: KeysetID) -> Result<KeysetManifest> {
+        let content = self
+            .store
+            .get(&self.manifest_path(keyset_id))?
+            .ok_or(KeysetNotFound(keyset_id))?;
+        serde_json::from_slice(&content).context("Parsing keyset manifest")
+    }
+
+    fn load_local_manifest(&self, keyset_id: KeysetID) -> Result<KeysetManifest> {
+        let path = self.local_manifest_path(keyset_id);
+        let content = std::fs::read(&path)
+            .with_context(|| format!("Reading fetched manifest {}", path.display()))?;
+        serde_json::from_slice(&content).context("Parsing keyset manifest")
+    }
+
+    /// Load a manifest followed by the manifests it is a delta against, newest first.
+    fn load_chain(
+        &self,
+        keyset_id: KeysetID,
+        load: impl Fn(KeysetID) -> Result<KeysetManifest>,
+    ) -> Result<Vec<KeysetManifest>> {
+        let mut chain = vec![load(keyset_id)?];
+        while chain.len() < MAX_DELTA_CHAIN_LENGTH {
+            let parent = match chain.last().unwrap().parent.as_ref() {
+                Some(parent) => Oid::from_str(parent).context("Parsing parent keyset ID")?,
+                None => break,
+            };
+            match load(parent) {
+                Ok(manifest) => chain.push(manifest),
+                Err(e) => {
+                    warn!(%parent, ?e, "Delta chain is truncated");
+                    break;
+                }
+            }
+        }
+        Ok(chain)
+    }
+}
+
+fn parse_listing(content: &[u8]) -> Result<BTreeSet<String>> {
+    let listing = std::str::from_utf8(content).context("Keyset listing is not UTF-8")?;
+    Ok(listing
+        .lines()
+        .map(|line| line.trim().to_owned())
+        .filter(|line| !line.is_empty())
+        .collect())
+}
+
+/// Merge the entries of a delta chain, with newer manifests taking precedence.
+fn merge_chain(chain: &[KeysetManifest]) -> BTreeMap<String, String> {
+    let mut entries = BTreeMap::new();
+    for manifest in chain {
+        for (composite_key, object_id) in manifest.entries.iter() {
+            entries
+                .entry(composite_key.clone())
+                .or_insert_with(|| object_id.clone());
+        }
+    }
+    entries
+}
+
+impl CacheSynchronizer for ObjectStoreCacheSynchronizer {
+    #[instrument]
+    fn fetch(&self, keyset_id: KeysetID) -> Result<KeysetID> {
+        let chain = self.load_chain(keyset_id, |id| self.load_remote_manifest(id))?;
+        for manifest in chain {
+            let id = Oid::from_str(&manifest.keyset_id).context("Parsing keyset ID")?;
+            let path = self.local_manifest_path(id);
+            std::fs::create_dir_all(path.parent().unwrap())
+                .context("Creating directory for fetched manifests")?;
+            std::fs::write(&path, serde_json::to_vec(&manifest)?)
+                .with_context(|| format!("Writing fetched manifest {}", path.display()))?;
+        }
+        Ok(keyset_id)
+    }
+
+    #[instrument]
+    fn populate(&self, keyset_id: &KeysetID, dest_cache: &dyn Cache) -> Result<PopulateResult> {
+        let chain = self.load_chain(*keyset_id, |id| self.load_local_manifest(id))?;
+        debug!(chain_length = chain.len(), "Populating from delta chain");
+        let entries = merge_chain(&chain);
+
+        let mut result = PopulateResult::default();
+        for (composite_key, object_id) in entries.iter() {
+            result.entry_count += 1;
+
+            let CompositeKey { kind, key } = match CompositeKey::from_str(composite_key) {
+                Ok(key) => key,
+                Err(_) => {
+                    warn!("Manifest entry {} is not a composite key", composite_key);
+                    continue;
+                }
+            };
+
+            match dest_cache.get(kind, key) {
+                Ok(Some(_)) => {
+                    // Already present, do nothing.
+                    continue;
+                }
+                Ok(None) => {
+                    // Insert below.
+                }
+                Err(e) => {
+                    // Insert below.
+                    warn!(?kind, ?key, ?e, "Failed to get key from cache");
+                }
+            }
+
+            let value = match self.store.get(&self.object_path(object_id)) {
+                Ok(Some(value)) => value,
+                Ok(None) => {
+                    result.failed_entry_count += 1;
+                    warn!(%object_id, "Object is missing from the remote");
+                    continue;
+                }
+                Err(e) => {
+                    result.failed_entry_count += 1;
+                    warn!(%object_id, ?e, "Failed to fetch object");
+                    continue;
+                }
+            };
+
+            match dest_cache.put(kind, key, &value) {
+                Ok(()) => {
+                    result.new_entry_count += 1;
+                }
+                Err(e) => {
+                    result.failed_entry_count += 1;
+                    warn!(%object_id, ?e, "Failed to insert key into Cache");
+                }
+            }
+        }
+
+        Ok(result)
+    }
+
+    fn fetch_and_populate(
+        &self,
+        keyset_id: KeysetID,
+        dest_cache: &dyn Cache,
+    ) -> Result<(PopulateResult, KeysetID)> {
+        let fetched_keyset_id = self.fetch(keyset_id).context("Fetching index updates")?;
+
+        let populate_result = self
+            .populate(&fetched_keyset_id, dest_cache)
+            .with_context(|| format!("Populating cache from keyset {}", fetched_keyset_id))?;
+        if !populate_result.is_noop() {
+            info!(?populate_result, keyset_id = %fetched_keyset_id, "Populated index");
+        }
+
+        Ok((populate_result, fetched_keyset_id))
+    }
+
+    #[instrument(skip(keyset))]
+    fn share(
+        &self,
+        keyset_id: KeysetID,
+        keyset: &Keyset,
+        cache: &dyn Cache,
+        previous_keyset_id: Option<KeysetID>,
+    ) -> Result<KeysetID> {
+        // Only entries missing from the previous keyset's chain are recorded, unless the chain is already at its maximum length.
+        let (parent, previous_entries) = match previous_keyset_id {
+            Some(previous_keyset_id) => {
+                let chain = self
+                    .load_chain(previous_keyset_id, |id| self.load_remote_manifest(id))
+                    .context("Loading previous keyset")?;
+                if chain.len() < MAX_DELTA_CHAIN_LENGTH {
+                    (Some(previous_keyset_id), merge_chain(&chain))
+                } else {
+                    info!(%previous_keyset_id, "Delta chain is at its maximum length; sharing a full keyset");
+                    (None, BTreeMap::new())
+                }
+            }
+            None => (None, BTreeMap::new()),
+        };
+        // Objects referenced by the previous keyset are known to exist already.
+        let known_objects: HashSet<&String> = previous_entries.values().collect();
+
+        let mut manifest = KeysetManifest {
+            keyset_id: keyset_id.to_string(),
+            parent: parent.map(|id| id.to_string()),
+            entries: BTreeMap::new(),
+        };
+        let mut uploaded_count = 0;
+        for (kind, key) in keyset.iter() {
+            let payload = cache
+                .get(*kind, *key)?
+                .ok_or_else(|| anyhow::anyhow!("Key {} is missing from the cache", key))?;
+            let object_id = Oid::hash_object(ObjectType::Blob, &payload)
+                .context("Hashing value")?
+                .to_string();
+            let composite_key = CompositeKey {
+                kind: *kind,
+                key: *key,
+            }
+            .to_string();
+            if previous_entries.get(&composite_key) == Some(&object_id) {
+                continue;
+            }
+            let object_path = self.object_path(&object_id);
+            if !known_objects.contains(&object_id) && !self.store.exists(&object_path)? {
+                self.store
+                    .put(&object_path, payload)
+                    .with_context(|| format!("Uploading object {}", object_id))?;
+                uploaded_count += 1;
+            }
+            manifest.entries.insert(composite_key, object_id);
+        }
+        debug!(
+            entry_count = keyset.len(),
+            delta_entry_count = manifest.entries.len(),
+            uploaded_count,
+            "Uploaded objects"
+        );
+
+        self.store
+            .put(
+                &self.manifest_path(keyset_id),
+                serde_json::to_vec(&manifest)?,
+            )
+            .context("Uploading keyset manifest")?;
+        self.add_to_listing(keyset_id)?;
+        Ok(keyset_id)
+    }
+
+    fn available_remote_keysets(&self) -> Result<HashSet<KeysetID>> {
+        let mut available_keys = HashSet::<KeysetID>::new();
+        for keyset_id in self.read_listing()? {
+            match Oid::from_str(&keyset_id) {
+                Ok(oid) => {
+                    available_keys.insert(oid);
+                }
+                Err(e) => warn!(%keyset_id, ?e, "Ignoring malformed keyset in listing"),
+            }
+        }
+        Ok(available_keys)
+    }
+
+    /// Keysets are removed from the listing only. Their manifests and objects
+    /// are kept since later keysets may be deltas against them.
+    #[instrument(skip(keyset_ids))]
+    fn remove_remote_keysets(&self, keyset_ids: &HashSet<KeysetID>) -> Result<()> {
+        self.remove_from_listing(keyset_ids)
+    }
+}
+
+/// Helper used by tests and tooling to open a local object store at a path.
+pub fn local_object_store_remote(path: &Path) -> String {
+    format!("{}file://{}", OBJECT_STORE_SCHEME_PREFIX, path.display())
+}
+
+#[cfg(test)]
+mod tests {
+    use maplit::hashset;
+    use rand::Rng;
+    use tempfile::{tempdir, TempDir};
+
+    use crate::{CacheKey, RocksDBCache};
+
+    use super::*;
+
+    const RANDOM_KEY_COUNT: usize = 50;
+
+    fn kind() -> [u8; 2] {
+        hex::decode("f5b3").unwrap().try_into().unwrap()
+    }
+
+    fn keyset_id(n: u8) -> KeysetID {
+        Oid::from_bytes(&[n; 20]).unwrap()
+    }
+
+    fn setup_synchronizer(remote_dir: &Path) -> (TempDir, ObjectStoreCacheSynchronizer) {
+        let tmp_dir = tempdir().unwrap();
+        let synchronizer = ObjectStoreCacheSynchronizer::create(
+            tmp_dir.path().join("index"),
+            &local_object_store_remote(remote_dir),
+            "cache".to_string(),
+        )
+        .unwrap();
+        (tmp_dir, synchronizer)
+    }
+
+    fn setup_rocks_db() -> (TempDir, RocksDBCache) {
+        let tmp_dir = tempdir().unwrap();
+        let cache = RocksDBCache::open(tmp_dir.path().join("rocks")).unwrap();
+        (tmp_dir, cache)
+    }
+
+    fn populate_random_keyset(cache: &dyn Cache) -> Keyset {
+        let mut rng = rand::thread_rng();
+        let mut keyset = Keyset::new();
+        for _ in 0..RANDOM_KEY_COUNT {
+            let mut key_bytes: [u8; 20] = [0; 20];
+            rng.fill(&mut key_bytes);
+            let key = CacheKey::from_bytes(&key_bytes).unwrap();
+            let mut value: [u8; 256] = [0; 256];
+            rng.fill(&mut value);
+            cache.put(kind(), key, &value).unwrap();
+            keyset.insert((kind(), key));
+        }
+        keyset
+    }
+
+    #[test]
+    fn test_share_and_fetch() -> Result<()> {
+        let remote_dir = tempdir()?;
+        let (_dir_1, synchronizer_1) = setup_synchronizer(remote_dir.path());
+        let (_dir_2, synchronizer_2) = setup_synchronizer(remote_dir.path());
+        let (_rocks_dir_1, cache_1) = setup_rocks_db();
+        let (_rocks_dir_2, cache_2) = setup_rocks_db();
+
+        let keyset = populate_random_keyset(&cache_1);
+        synchronizer_1.share(keyset_id(1), &keyset, &cache_1, None)?;
+        assert_eq!(
+            synchronizer_2.available_remote_keysets()?,
+            hashset! {keyset_id(1)}
+        );
+
+        let (result, fetched_keyset_id) =
+            synchronizer_2.fetch_and_populate(keyset_id(1), &cache_2)?;
+        assert_eq!(fetched_keyset_id, keyset_id(1));
+        assert_eq!(
+            result,
+            PopulateResult {
+                entry_count: RANDOM_KEY_COUNT,
+                new_entry_count: RANDOM_KEY_COUNT,
+                failed_entry_count: 0,
+            }
+        );
+        for (kind, key) in keyset.iter() {
+            assert_eq!(cache_1.get(*kind, *key)?, cache_2.get(*kind, *key)?);
+        }
+
+        // Populating again is a no-op since everything is present.
+        let result = synchronizer_2.populate(&keyset_id(1), &cache_2)?;
+        assert_eq!(result.new_entry_count, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_shared_values_are_stored_once() -> Result<()> {
+        let remote_dir = tempdir()?;
+        let (_dir, synchronizer) = setup_synchronizer(remote_dir.path());
+        let (_rocks_dir, cache) = setup_rocks_db();
+
+        let keyset = populate_random_keyset(&cache);
+        synchronizer.share(keyset_id(1), &keyset, &cache, None)?;
+        synchronizer.share(keyset_id(2), &keyset, &cache, Some(keyset_id(1)))?;
+
+        let object_count = walkdir_count(&remote_dir.path().join("cache").join("objects"));
+        assert_eq!(object_count, RANDOM_KEY_COUNT);
+
+        // The second keyset is identical to the first, so its delta is empty.
+        let manifest = synchronizer.load_remote_manifest(keyset_id(2))?;
+        assert_eq!(manifest.parent, Some(keyset_id(1).to_string()));
+        assert!(manifest.entries.is_empty());
+        assert_eq!(
+            synchronizer.available_remote_keysets()?,
+            hashset! {keyset_id(1), keyset_id(2)}
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_populate_delta_chain() -> Result<()> {
+        let remote_dir = tempdir()?;
+        let (_dir_1, synchronizer_1) = setup_synchronizer(remote_dir.path());
+        let (_dir_2, synchronizer_2) = setup_synchronizer(remote_dir.path());
+        let (_rocks_dir_1, cache_1) = setup_rocks_db();
+        let (_rocks_dir_2, cache_2) = setup_rocks_db();
+
+        let mut keyset = Keyset::new();
+        let mut previous_keyset_id = None;
+        for n in 1..=3 {
+            keyset.extend(populate_random_keyset(&cache_1));
+            synchronizer_1.share(keyset_id(n), &keyset, &cache_1, previous_keyset_id)?;
+            let manifest = synchronizer_1.load_remote_manifest(keyset_id(n))?;
+            assert_eq!(manifest.entries.len(), RANDOM_KEY_COUNT);
+            previous_keyset_id = Some(keyset_id(n));
+        }
+
+        let (result, _) = synchronizer_2.fetch_and_populate(keyset_id(3), &cache_2)?;
+        assert_eq!(result.new_entry_count, 3 * RANDOM_KEY_COUNT);
+        for (kind, key) in keyset.iter() {
+            assert_eq!(cache_1.get(*kind, *key)?, cache_2.get(*kind, *key)?);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_remove_remote_keysets() -> Result<()> {
+        let remote_dir = tempdir()?;
+        let (_dir, synchronizer) = setup_synchronizer(remote_dir.path());
+        let (_rocks_dir, cache) = setup_rocks_db();
+
+        let keyset = populate_random_keyset(&cache);
+        synchronizer.share(keyset_id(1), &keyset, &cache, None)?;
+        synchronizer.share(keyset_id(2), &keyset, &cache, Some(keyset_id(1)))?;
+        synchronizer.remove_remote_keysets(&hashset! {keyset_id(1)})?;
+        assert_eq!(
+            synchronizer.available_remote_keysets()?,
+            hashset! {keyset_id(2)}
+        );
+
+        // The delta chain remains intact.
+        let (_rocks_dir_2, cache_2) = setup_rocks_db();
+        let (result, _) = synchronizer.fetch_and_populate(keyset_id(2), &cache_2)?;
+        assert_eq!(result.new_entry_count, RANDOM_KEY_COUNT);
+        Ok(())
+    }
+
+    #[test]
+    fn test_concurrent_listing_updates_are_not_lost() -> Result<()> {
+        let remote_dir = tempdir()?;
+        let handles = (1..=8)
+            .map(|n| {
+                let remote_dir = remote_dir.path().to_owned();
+                std::thread::spawn(move || {
+                    let (_dir, synchronizer) = setup_synchronizer(&remote_dir);
+                    synchronizer.add_to_listing(keyset_id(n)).unwrap();
+                })
+            })
+            .collect::<Vec<_>>();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let (_dir, synchronizer) = setup_synchronizer(remote_dir.path());
+        assert_eq!(
+            synchronizer.available_remote_keysets()?,
+            (1..=8).map(keyset_id).collect::<HashSet<_>>()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_conditional_put_detects_concurrent_changes() -> Result<()> {
+        let remote_dir = tempdir()?;
+        let store = LocalObjectStore::new(remote_dir.path().to_owned());
+        assert!(store.put_if_unchanged("a/listing", b"one".to_vec(), None)?);
+        assert!(!store.put_if_unchanged("a/listing", b"two".to_vec(), None)?);
+
+        let (_, version) = store.get_versioned("a/listing")?.unwrap();
+        store.put("a/listing", b"three".to_vec())?;
+        assert!(!store.put_if_unchanged("a/listing", b"four".to_vec(), Some(&version))?);
+        assert_eq!(store.get("a/listing")?, Some(b"three".to_vec()));
+        Ok(())
+    }
+
+    #[test]
+    fn test_fetching_missing_keyset_fails() -> Result<()> {
+        let remote_dir = tempdir()?;
+        let (_dir, synchronizer) = setup_synchronizer(remote_dir.path());
+        let error = synchronizer.fetch(keyset_id(3)).unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<KeysetNotFound>(),
+            Some(&KeysetNotFound(keyset_id(3)))
+        );
+        assert!(synchronizer.available_remote_keysets()?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_handles_remote() {
+        assert!(ObjectStoreCacheSynchronizer::handles_remote(
+            "objects+https://cache.example.com/index"
+        ));
+        assert!(!ObjectStoreCacheSynchronizer::handles_remote(
+            "https://git.example.com/focus-index"
+        ));
+    }
+
+    fn walkdir_count(path: &Path) -> usize {
+        std::fs::read_dir(path)
+            .unwrap()
+            .map(|entry| std::fs::read_dir(entry.unwrap().path()).unwrap().count())
+            .sum()
+    }
+}
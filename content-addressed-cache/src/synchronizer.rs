@@ -11,12 +11,22 @@ use git2::{Commit, Repository};
 use regex::Regex;
 use std::fmt::{Debug, Display};
 use std::ops::AddAssign;
-use std::{collections::HashSet, path::PathBuf, str::FromStr, sync::Arc};
-use tracing::{info, instrument, warn};
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+    str::FromStr,
+    sync::Arc,
+};
+use tracing::{debug, info, instrument, warn};
 
 pub type Keyset = HashSet<(CacheKeyKind, CacheKey)>;
 pub type KeysetID = Oid;
 
+/// Keysets may be shared as a delta against a previous keyset, which may
+/// itself be a delta. Once a chain reaches this length, the next keyset is
+/// shared in full so that fetching and populating stay bounded.
+pub const MAX_DELTA_CHAIN_LENGTH: usize = 32;
+
 pub fn refspec_fmt(namespace: impl Display, tag_name: impl Display) -> String {
     format!(
         "+refs/tags/{namespace}/{}:refs/tags/{namespace}/{}",
@@ -28,6 +38,19 @@ pub fn tag_fmt(namespace: impl Display, tag_name: impl Display) -> String {
     format!("refs/tags/{}/{}", namespace, tag_name)
 }
 
+/// Returned when fetching a keyset that doesn't exist on the remote, for
+/// example because it was pruned after the remote keysets were listed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KeysetNotFound(pub KeysetID);
+
+impl Display for KeysetNotFound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Keyset {} does not exist on the remote", self.0)
+    }
+}
+
+impl std::error::Error for KeysetNotFound {}
+
 #[derive(Clone, Debug, Default, PartialEq, Eq)]
 pub struct PopulateResult {
     pub entry_count: usize,
@@ -36,7 +59,7 @@ pub struct PopulateResult {
 }
 
 impl PopulateResult {
-    fn is_noop(&self) -> bool {
+    pub(crate) fn is_noop(&self) -> bool {
         let PopulateResult {
             entry_count,
             new_entry_count,
@@ -83,8 +106,15 @@ pub trait CacheSynchronizer: Debug {
         previous_keyset_id: Option<KeysetID>,
     ) -> Result<git2::Oid>;
     fn available_remote_keysets(&self) -> Result<HashSet<KeysetID>>;
+    /// Remove the given keysets from the remote so that they are no longer
+    /// listed. Keysets that other keysets are deltas against remain readable
+    /// through those keysets.
+    fn remove_remote_keysets(&self, keyset_ids: &HashSet<KeysetID>) -> Result<()>;
 }
 
+/// How many tags to delete per push when removing keysets from a Git remote.
+const REMOVE_KEYSETS_BATCH_SIZE: usize = 100;
+
 /// Synchronize using Git as a key-value store. Keysets are pushed as tags to
 /// the remote server. Key-value pairs are stored as entries in a tree, where
 /// the entry name is the hash of the key and the entry value is a blob
@@ -136,30 +166,58 @@ impl GitBackedCacheSynchronizer {
             parse_tags_regex,
         })
     }
-}
 
-impl CacheSynchronizer for GitBackedCacheSynchronizer {
-    #[instrument]
-    fn fetch(&self, keyset_id: KeysetID) -> Result<KeysetID> {
-        git_helper::fetch_refs(
-            self.path.as_path(),
-            [refspec_fmt(&self.namespace, keyset_id)].iter(),
-            self.remote.as_str(),
-            self.app.clone(),
-            Some(1),
-        )
-        .context("Fetching")
-        .map(|_x| keyset_id)
+    /// The commits making up a keyset: the commit itself followed by the
+    /// keysets it is a delta against, newest first. A chain truncated by a
+    /// shallow fetch ends early.
+    fn delta_chain<'repo>(&self, commit: Commit<'repo>) -> Vec<Commit<'repo>> {
+        let mut chain = vec![commit];
+        while chain.len() < MAX_DELTA_CHAIN_LENGTH {
+            let last = chain.last().unwrap();
+            if last.parent_count() == 0 {
+                break;
+            }
+            match last.parent(0) {
+                Ok(parent) => chain.push(parent),
+                Err(e) => {
+                    warn!(commit = %last.id(), ?e, "Delta chain is truncated");
+                    break;
+                }
+            }
+        }
+        chain
     }
 
-    #[instrument]
-    fn populate(&self, keyset_id: &KeysetID, dest_cache: &dyn Cache) -> Result<PopulateResult> {
-        let commit = self
-            .repo
-            .find_reference(&tag_fmt(&self.namespace, keyset_id))
-            .context("Resolving reference")?;
-        let kv_tree = commit.peel_to_tree().context("Resolving tree")?;
+    /// Map each composite key in a keyset's delta chain to the blob holding its value.
+    fn chain_entries(&self, chain: &[Commit]) -> Result<HashMap<String, Oid>> {
+        let mut entries = HashMap::new();
+        for commit in chain {
+            for tree_entry in commit.tree().context("Resolving tree")?.iter() {
+                if let Some(name) = tree_entry.name() {
+                    entries
+                        .entry(name.to_owned())
+                        .or_insert_with(|| tree_entry.id());
+                }
+            }
+        }
+        Ok(entries)
+    }
+
+    fn find_keyset_commit(&self, keyset_id: KeysetID) -> Result<Commit> {
+        let tag = tag_fmt(&self.namespace, keyset_id);
+        if self.repo.find_reference(&tag).is_err() {
+            self.fetch(keyset_id)
+                .with_context(|| format!("Fetching keyset {}", keyset_id))?;
+        }
+        self.repo
+            .find_reference(&tag)
+            .context("Resolving reference")?
+            .peel_to_commit()
+            .context("Resolving commit")
+    }
 
+    /// Copy the entries of a single keyset tree into the cache.
+    fn populate_tree(&self, kv_tree: &git2::Tree, dest_cache: &dyn Cache) -> PopulateResult {
         let mut entry_count = 0;
         let mut new_entry_count = 0;
         let mut failed_entry_count = 0;
@@ -223,11 +281,55 @@ impl CacheSynchronizer for GitBackedCacheSynchronizer {
             }
         }
 
-        Ok(PopulateResult {
+        PopulateResult {
             entry_count,
             new_entry_count,
             failed_entry_count,
+        }
+    }
+}
+
+impl CacheSynchronizer for GitBackedCacheSynchronizer {
+    #[instrument]
+    fn fetch(&self, keyset_id: KeysetID) -> Result<KeysetID> {
+        git_helper::fetch_refs(
+            self.path.as_path(),
+            [refspec_fmt(&self.namespace, keyset_id)].iter(),
+            self.remote.as_str(),
+            self.app.clone(),
+            Some(MAX_DELTA_CHAIN_LENGTH as u64),
+        )
+        .or_else(|e| {
+            // Git doesn't distinguish a missing ref in its exit status, so check whether the tag is still listed.
+            match self.available_remote_keysets() {
+                Ok(available_keysets) if !available_keysets.contains(&keyset_id) => {
+                    Err(KeysetNotFound(keyset_id).into())
+                }
+                _ => Err(e),
+            }
         })
+        .context("Fetching")
+        .map(|_x| keyset_id)
+    }
+
+    #[instrument]
+    fn populate(&self, keyset_id: &KeysetID, dest_cache: &dyn Cache) -> Result<PopulateResult> {
+        let commit = self
+            .repo
+            .find_reference(&tag_fmt(&self.namespace, keyset_id))
+            .context("Resolving reference")?
+            .peel_to_commit()
+            .context("Resolving commit")?;
+
+        // Newer keysets in the chain are applied first; keys already present are skipped, so their values take precedence.
+        let mut result = PopulateResult::default();
+        let chain = self.delta_chain(commit);
+        debug!(chain_length = chain.len(), "Populating from delta chain");
+        for commit in chain {
+            let kv_tree = commit.tree().context("Resolving tree")?;
+            result += self.populate_tree(&kv_tree, dest_cache);
+        }
+        Ok(result)
     }
 
     fn fetch_and_populate(
@@ -255,6 +357,22 @@ impl CacheSynchronizer for GitBackedCacheSynchronizer {
         cache: &dyn Cache,
         previous_keyset_id: Option<KeysetID>,
     ) -> Result<KeysetID> {
+        // Only entries missing from the previous keyset's chain are written, unless the chain is already at its maximum length.
+        let previous_commit = match previous_keyset_id {
+            Some(previous_keyset_id) => {
+                let commit = self.find_keyset_commit(previous_keyset_id)?;
+                let chain = self.delta_chain(commit.clone());
+                if chain.len() < MAX_DELTA_CHAIN_LENGTH {
+                    let entries = self.chain_entries(&chain)?;
+                    Some((commit, entries))
+                } else {
+                    info!(%previous_keyset_id, "Delta chain is at its maximum length; sharing a full keyset");
+                    None
+                }
+            }
+            None => None,
+        };
+
         let mut kv_tree = self
             .repo
             .treebuilder(None)
@@ -262,31 +380,36 @@ impl CacheSynchronizer for GitBackedCacheSynchronizer {
 
         for (kind, key) in keyset.iter() {
             let payload = cache.get(*kind, *key)?.unwrap();
+            let name = CompositeKey {
+                key: *key,
+                kind: *kind,
+            }
+            .to_string();
+            if let Some((_, previous_entries)) = previous_commit.as_ref() {
+                let value_oid = Oid::hash_object(git2::ObjectType::Blob, &payload)?;
+                if previous_entries.get(&name) == Some(&value_oid) {
+                    continue;
+                }
+            }
             let value_oid = self
                 .repo
                 .blob(&payload)
                 .context("writing DependencyValue as blob")?;
 
             kv_tree
-                .insert(
-                    CompositeKey {
-                        key: *key,
-                        kind: *kind,
-                    }
-                    .to_string(),
-                    value_oid,
-                    git2::FileMode::Blob.into(),
-                )
+                .insert(name, value_oid, git2::FileMode::Blob.into())
                 .context("adding entry to tree")?;
         }
+        debug!(
+            entry_count = keyset.len(),
+            delta_entry_count = kv_tree.len(),
+            "Built keyset tree"
+        );
 
         let kv_tree_oid = kv_tree.write().context("writing new tree")?;
         let signature = git2::Signature::now(&self.username, &self.email)?;
-        let prev_commit_vec = match previous_keyset_id {
-            Some(prev_commit_oid) => vec![self
-                .repo
-                .find_reference(&tag_fmt(&self.namespace, prev_commit_oid)[..])?
-                .peel_to_commit()?],
+        let prev_commit_vec = match previous_commit {
+            Some((commit, _)) => vec![commit],
             None => vec![],
         };
         let vec_of_prev_commit_references: Vec<&Commit> = prev_commit_vec.iter().collect();
@@ -337,6 +460,24 @@ impl CacheSynchronizer for GitBackedCacheSynchronizer {
         }
         Ok(available_keys)
     }
+
+    #[instrument(skip(keyset_ids))]
+    fn remove_remote_keysets(&self, keyset_ids: &HashSet<KeysetID>) -> Result<()> {
+        let refspecs: Vec<String> = keyset_ids
+            .iter()
+            .map(|keyset_id| format!(":{}", tag_fmt(&self.namespace, keyset_id)))
+            .collect();
+        for batch in refspecs.chunks(REMOVE_KEYSETS_BATCH_SIZE) {
+            git_helper::push_refs(
+                self.path.as_path(),
+                batch.iter().cloned(),
+                self.remote.as_str(),
+                self.app.clone(),
+            )
+            .context("Deleting remote tags")?;
+        }
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -417,7 +558,7 @@ mod tests {
     fn setup_rocks_db(name: &str) -> (TempDir, impl Cache) {
         let tmp_dir = tempdir().unwrap();
         let file_path = tmp_dir.path().join(name);
-        let cache = RocksDBCache::open(file_path);
+        let cache = RocksDBCache::open(file_path).unwrap();
         (tmp_dir, cache)
     }
 
@@ -545,6 +686,81 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_share_delta() -> anyhow::Result<()> {
+        let (_server_dir, server_path) = setup_server_repo_locally().unwrap();
+        let server_string = server_path.into_os_string().into_string().unwrap();
+        let (_git_cache_dir_1, memo_cache_sync_1) =
+            setup_local_git_cache("fairly-local", server_string.as_str());
+        let (_git_cache_dir_2, memo_cache_sync_2) =
+            setup_local_git_cache("fairly-local2", server_string.as_str());
+        let (_rocks_dir_1, memo_cache_1) = setup_rocks_db("cache-rocks1");
+        let (_rocks_dir_2, memo_cache_2) = setup_rocks_db("cache-rocks2");
+
+        let kind = kind();
+        let keyset_id1 = keyset_id_1();
+        let commit_1_keys = populate_demo_hashset(&memo_cache_1, kind);
+        memo_cache_sync_1.share(keyset_id1, &commit_1_keys, &memo_cache_1, None)?;
+
+        // The second keyset is a superset of the first, so only the new keys are written.
+        let keyset_id2 = keyset_id_2();
+        let mut commit_2_keys = populate_demo_hashset(&memo_cache_1, kind);
+        commit_2_keys.extend(commit_1_keys.iter().cloned());
+        memo_cache_sync_1.share(keyset_id2, &commit_2_keys, &memo_cache_1, Some(keyset_id1))?;
+
+        let tree = memo_cache_sync_1
+            .repo
+            .find_reference(&tag_fmt("cache", keyset_id2)[..])?
+            .peel_to_tree()?;
+        assert_eq!(tree.len(), RANDOM_KEY_COUNT);
+
+        let (results, _) = memo_cache_sync_2.fetch_and_populate(keyset_id2, &memo_cache_2)?;
+        assert_eq!(
+            results,
+            PopulateResult {
+                new_entry_count: 2 * RANDOM_KEY_COUNT,
+                entry_count: 2 * RANDOM_KEY_COUNT,
+                failed_entry_count: 0
+            }
+        );
+        assert_caches_match(commit_2_keys, &memo_cache_1, &memo_cache_2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_remove_remote_keysets() -> anyhow::Result<()> {
+        let (_server_dir, server_path) = setup_server_repo_locally().unwrap();
+        let server_string = server_path.into_os_string().into_string().unwrap();
+        let (_git_cache_dir_1, memo_cache_sync_1) =
+            setup_local_git_cache("fairly-local", server_string.as_str());
+        let (_git_cache_dir_2, memo_cache_sync_2) =
+            setup_local_git_cache("fairly-local2", server_string.as_str());
+        let (_rocks_dir_1, memo_cache_1) = setup_rocks_db("cache-rocks1");
+        let (_rocks_dir_2, memo_cache_2) = setup_rocks_db("cache-rocks2");
+
+        let kind = kind();
+        let commit_1_keys = populate_demo_hashset(&memo_cache_1, kind);
+        memo_cache_sync_1.share(keyset_id_1(), &commit_1_keys, &memo_cache_1, None)?;
+        let commit_2_keys = populate_demo_hashset(&memo_cache_1, kind);
+        memo_cache_sync_1.share(
+            keyset_id_2(),
+            &commit_2_keys,
+            &memo_cache_1,
+            Some(keyset_id_1()),
+        )?;
+
+        memo_cache_sync_1.remove_remote_keysets(&hashset! {keyset_id_1()})?;
+        assert_eq!(
+            memo_cache_sync_2.available_remote_keysets()?,
+            hashset! {keyset_id_2()}
+        );
+
+        // The removed keyset is still reachable through the delta built on it.
+        memo_cache_sync_2.fetch_and_populate(keyset_id_2(), &memo_cache_2)?;
+        assert_caches_match(commit_1_keys, &memo_cache_1, &memo_cache_2);
+        Ok(())
+    }
+
     #[test]
     fn test_using_local_transport() -> anyhow::Result<()> {
         let (_server_dir, server_path) = setup_server_repo_locally().unwrap();
@@ -7,7 +7,7 @@ use std::{
     path::{Path, PathBuf},
     sync::Arc,
     thread,
-    time::Instant,
+    time::{Duration, Instant},
 };
 
 use anyhow::{bail, Context, Result};
@@ -24,17 +24,18 @@ use focus_util::{
     time::FocusTime,
 };
 
-use focus_internals::{target::TargetTypes, tracker::Tracker};
+use focus_internals::{index::GraphFormat, target::TargetTypes, tracker::Tracker};
 use focus_operations::{
-    clone::{CloneArgs, ClonedRepoTemplate},
+    clone::CloneArgs,
     maintenance::{self, ScheduleOpts},
     project::lint,
     selection::save,
     sync::{SyncMode, SyncRequest},
 };
+use regex::Regex;
 use strum::VariantNames;
 use termion::{color, style};
-use tracing::{debug, debug_span, error, info};
+use tracing::{debug, debug_span, error, info, warn};
 
 #[derive(Parser, Clone, Debug)]
 struct NewArgs {
@@ -46,13 +47,13 @@ struct NewArgs {
     #[clap(parse(from_os_str))]
     sparse_repo: PathBuf,
 
-    /// The name of the branch to clone.
-    #[clap(short, long, default_value = "master")]
-    branch: String,
+    /// The name of the branch to clone. Defaults to the template's branch, or "master".
+    #[clap(short, long)]
+    branch: Option<String>,
 
-    /// Days of history to maintain in the sparse repo. If greater than zero, the repository will be shallow. If set to zero, the repository will have the entire history.
-    #[clap(long, default_value = "90")]
-    days_of_history: u64,
+    /// Days of history to maintain in the sparse repo. If greater than zero, the repository will be shallow. If set to zero, the repository will have the entire history. Defaults to the template's days of history, or 90.
+    #[clap(long)]
+    days_of_history: Option<u64>,
 
     /// Copy only the specified branch rather than all local branches.
     #[clap(long, parse(try_from_str), default_value = "true")]
@@ -61,8 +62,27 @@ struct NewArgs {
     /// Initial projects and targets to add to the repo.
     projects_and_targets: Vec<String>,
 
-    #[clap(long, possible_values = ClonedRepoTemplate::VARIANTS)]
-    template: Option<ClonedRepoTemplate>,
+    /// The name of the clone template providing defaults for the repo. If not
+    /// given, the first template matching the origin is used; `none` uses no
+    /// template.
+    #[clap(long)]
+    template: Option<String>,
+
+    /// Continue an interrupted clone into the sparse repo path from the last
+    /// step it completed, using the arguments it was started with.
+    #[clap(long)]
+    resume: bool,
+
+    /// Clone from a bundle written by `focus bundle create` instead of the
+    /// dense repo, then fetch the rest of the history from the repo the bundle
+    /// was made from.
+    #[clap(long, parse(from_os_str))]
+    from_bundle: Option<PathBuf>,
+
+    /// Borrow objects from this repo, such as a shared object cache, so that
+    /// only the objects missing from it are fetched.
+    #[clap(long, parse(from_os_str))]
+    reference: Option<PathBuf>,
 }
 
 #[derive(Parser, Clone, Debug)]
@@ -121,6 +141,22 @@ enum Subcommand {
         all: bool,
     },
 
+    /// Temporarily check out directories without adding them to the
+    /// selection. They are removed by the first sync after they expire.
+    Peek {
+        /// Directories to check out, relative to the root of the repo.
+        #[clap(required_unless_present = "clear")]
+        directories: Vec<String>,
+
+        /// How long to keep the directories checked out (e.g. "4h").
+        #[clap(long, parse(try_from_str = humantime::parse_duration), default_value = "1d")]
+        duration: Duration,
+
+        /// Remove all peeked directories now.
+        #[clap(long, conflicts_with = "directories")]
+        clear: bool,
+    },
+
     /// Display which projects and targets are selected.
     Status {
         ///Unwrap all projects until only targets are displayed
@@ -235,6 +271,31 @@ enum Subcommand {
     /// Incorporate changes from `prefetch` into the current branch.
     Pull,
 
+    /// Read the focus logs. Run `focus logs help` for more information.
+    Logs {
+        #[clap(subcommand)]
+        subcommand: LogsSubcommand,
+    },
+
+    /// Write repos to git bundles that new repos can be cloned from.
+    Bundle {
+        #[clap(subcommand)]
+        subcommand: BundleSubcommand,
+    },
+
+    /// Add or remove focused linked worktrees.
+    Worktree {
+        #[clap(subcommand)]
+        subcommand: WorktreeSubcommand,
+    },
+
+    /// Install or remove the git hooks focus uses to respond to merges and
+    /// commits.
+    Hooks {
+        #[clap(subcommand)]
+        subcommand: HooksSubcommand,
+    },
+
     Selection {
         #[clap(subcommand)]
         subcommand: SelectionSubcommand,
@@ -264,6 +325,7 @@ fn feature_name_for(subcommand: &Subcommand) -> String {
         },
         Subcommand::Add { .. } => "add".to_string(),
         Subcommand::Remove { .. } => "remove".to_string(),
+        Subcommand::Peek { .. } => "peek".to_string(),
         Subcommand::Status { .. } => "status".to_string(),
         Subcommand::Projects { .. } => "projects".to_string(),
         Subcommand::Project { subcommand } => match subcommand {
@@ -279,6 +341,7 @@ fn feature_name_for(subcommand: &Subcommand) -> String {
             BranchSubcommand::List { .. } => "branch-list".to_string(),
             BranchSubcommand::Search { .. } => "branch-search".to_string(),
             BranchSubcommand::Add { .. } => "branch-add".to_string(),
+            BranchSubcommand::SyncRules { .. } => "branch-sync-rules".to_string(),
         },
         Subcommand::Maintenance { subcommand, .. } => match subcommand {
             MaintenanceSubcommand::Run { .. } => "maintenance-run".to_string(),
@@ -289,6 +352,10 @@ fn feature_name_for(subcommand: &Subcommand) -> String {
             MaintenanceSubcommand::SandboxCleanup { .. } => {
                 "maintenance-sandbox-cleanup".to_string()
             }
+            MaintenanceSubcommand::FlushToolInsights { .. } => {
+                "maintenance-flush-tool-insights".to_string()
+            }
+            MaintenanceSubcommand::Status { .. } => "maintenance-status".to_string(),
             MaintenanceSubcommand::Schedule { subcommand } => match subcommand {
                 MaintenanceScheduleSubcommand::Enable { .. } => {
                     "maintenance-schedule-enable".to_string()
@@ -306,13 +373,27 @@ fn feature_name_for(subcommand: &Subcommand) -> String {
             IndexSubcommand::Fetch { .. } => "index-fetch".to_string(),
             IndexSubcommand::Get { .. } => "index-get".to_string(),
             IndexSubcommand::Generate { .. } => "index-generate".to_string(),
+            IndexSubcommand::Graph { .. } => "index-graph".to_string(),
             IndexSubcommand::Hash { .. } => "index-hash".to_string(),
+            IndexSubcommand::LoadGraph { .. } => "index-load-graph".to_string(),
             IndexSubcommand::Push { .. } => "index-push".to_string(),
+            IndexSubcommand::Prune { .. } => "index-prune".to_string(),
+            IndexSubcommand::Rdeps { .. } => "index-rdeps".to_string(),
             IndexSubcommand::Resolve { .. } => "index-resolve".to_string(),
+            IndexSubcommand::Verify { .. } => "index-verify".to_string(),
         },
         Subcommand::ProjectCache { subcommand } => match subcommand {
             ProjectCacheSubcommand::Push { .. } => "project-cache-push".to_string(),
+            ProjectCacheSubcommand::List { .. } => "project-cache-list".to_string(),
+            ProjectCacheSubcommand::Show { .. } => "project-cache-show".to_string(),
+            ProjectCacheSubcommand::Verify { .. } => "project-cache-verify".to_string(),
+            ProjectCacheSubcommand::Gc { .. } => "project-cache-gc".to_string(),
         },
+        // The post-checkout hook is passed commit IDs, which would make every
+        // feature name unique.
+        Subcommand::Event { args } if args.first().map(String::as_str) == Some("post-checkout") => {
+            "event-post-checkout".to_string()
+        }
         Subcommand::Event { args } => {
             let mut temp_args = args.to_owned();
             temp_args.insert(0, "event".to_string());
@@ -323,8 +404,25 @@ fn feature_name_for(subcommand: &Subcommand) -> String {
             BackgroundSubcommand::Enable { .. } => "background-enable".to_string(),
             BackgroundSubcommand::Disable { .. } => "background-disable".to_string(),
             BackgroundSubcommand::Sync { .. } => "background-sync".to_string(),
+            BackgroundSubcommand::Daemon { .. } => "background-daemon".to_string(),
         },
         Subcommand::Pull => "pull".to_string(),
+        Subcommand::Logs { subcommand } => match subcommand {
+            LogsSubcommand::Tail { .. } => "logs-tail".to_string(),
+            LogsSubcommand::Grep { .. } => "logs-grep".to_string(),
+            LogsSubcommand::Bundle { .. } => "logs-bundle".to_string(),
+        },
+        Subcommand::Bundle { subcommand } => match subcommand {
+            BundleSubcommand::Create { .. } => "bundle-create".to_string(),
+        },
+        Subcommand::Worktree { subcommand } => match subcommand {
+            WorktreeSubcommand::Add { .. } => "worktree-add".to_string(),
+            WorktreeSubcommand::Remove { .. } => "worktree-remove".to_string(),
+        },
+        Subcommand::Hooks { subcommand } => match subcommand {
+            HooksSubcommand::Install { .. } => "hooks-install".to_string(),
+            HooksSubcommand::Uninstall { .. } => "hooks-uninstall".to_string(),
+        },
         Subcommand::Selection { subcommand } => match subcommand {
             SelectionSubcommand::Save { .. } => "selection-save".to_string(),
         },
@@ -393,6 +491,16 @@ enum MaintenanceSubcommand {
         #[clap(long)]
         max_num_sandboxes: Option<u32>,
     },
+
+    /// Deliver the tool insights messages spooled by frequent invocations, such
+    /// as those from git hooks, to the tool insights daemon. This is also done
+    /// by `focus maintenance run`.
+    #[clap(hide = true)]
+    FlushToolInsights {},
+
+    /// Show when maintenance last ran and succeeded in each tracked repo, any
+    /// failures since, and when it is next scheduled to run.
+    Status {},
 }
 
 #[derive(Parser, Clone, Debug)]
@@ -477,6 +585,16 @@ enum BranchSubcommand {
     ///
     /// The passed in name should not end in `/`.
     Add { name: String },
+
+    /// Rewrite the fetch refspecs to track the branches matching the rules in
+    /// the repo config, and prune remote-tracking refs which no longer match.
+    ///
+    /// Rules are set with e.g. `git config focus.branchrule.mine.prefix username/`.
+    /// Each rule can set `prefix`, `regex` (matched against the branch name),
+    /// `owner` (a regex matched against the tip commit's author as `Name <email>`)
+    /// and `maxage` (e.g. `30d`), and a branch is tracked if it meets every
+    /// condition of any rule.
+    SyncRules {},
 }
 
 #[derive(Parser, Clone, Debug)]
@@ -594,6 +712,22 @@ enum IndexSubcommand {
         break_on_missing_keys: bool,
     },
 
+    /// Print the dependency graph of the selected projects and targets (or the
+    /// provided targets) at HEAD.
+    Graph {
+        /// The format in which to print the graph.
+        #[clap(long, arg_enum, default_value = "dot")]
+        format: GraphFormat,
+
+        /// Include only dependencies at most this many edges away from the
+        /// targets.
+        #[clap(long)]
+        max_depth: Option<usize>,
+
+        /// The targets to start from, instead of the current selection.
+        targets: Vec<String>,
+    },
+
     /// Calculate and print the content hashes of the provided targets.
     Hash {
         /// The commit at which to hash the provided targets.
@@ -604,6 +738,18 @@ enum IndexSubcommand {
         targets: Vec<String>,
     },
 
+    /// Print the `load` dependencies between build files, as read from the
+    /// repository without invoking Bazel.
+    LoadGraph {
+        /// The commit at which to read the build files.
+        #[clap(long, default_value = "HEAD")]
+        commit: String,
+
+        /// The build files to start from, as labels like `//foo:BUILD`. If
+        /// none are given, all build files in the repository are included.
+        files: Vec<String>,
+    },
+
     /// Generate and push the pre-computed index to the remote store for others
     /// to fetch.
     Push {
@@ -625,6 +771,37 @@ enum IndexSubcommand {
         break_on_missing_keys: bool,
     },
 
+    /// Delete keysets outside of a retention window from the remote store.
+    Prune {
+        /// Path to the sparse repository.
+        #[clap(parse(from_os_str), default_value = ".")]
+        sparse_repo: PathBuf,
+
+        /// The remote to prune.
+        #[clap(long, default_value = focus_operations::index::INDEX_DEFAULT_REMOTE)]
+        remote: String,
+
+        /// Keep the keysets of this many of the most recent first-parent ancestors of HEAD that have one.
+        #[clap(long, default_value = "100")]
+        keep_last: usize,
+
+        /// Keep keysets for commits made more recently than this (e.g. "30d").
+        #[clap(long, parse(try_from_str = humantime::parse_duration), default_value = "30d")]
+        older_than: Duration,
+
+        /// Print the keysets that would be deleted without deleting them.
+        #[clap(short = 'N', long = "dry-run")]
+        dry_run: bool,
+    },
+
+    /// List the projects whose outlines include the given path or Bazel
+    /// label at HEAD.
+    Rdeps {
+        /// A path relative to the repository root, or a Bazel label like
+        /// `//foo/bar:baz`.
+        query: String,
+    },
+
     /// Resolve the targets to their resulting pattern sets.
     Resolve {
         targets: Vec<String>,
@@ -633,6 +810,22 @@ enum IndexSubcommand {
         #[clap(long)]
         break_on_missing_keys: bool,
     },
+
+    /// Resolve the entries in the local index again with Bazel and report any
+    /// whose stored dependencies differ.
+    Verify {
+        /// Path to the sparse repository.
+        #[clap(parse(from_os_str), default_value = ".")]
+        sparse_repo: PathBuf,
+
+        /// Verify only this many randomly-chosen entries instead of all of them.
+        #[clap(long)]
+        sample: Option<usize>,
+
+        /// Remove mismatched entries from the local index.
+        #[clap(long)]
+        evict: bool,
+    },
 }
 
 #[derive(Parser, Clone, Debug)]
@@ -655,6 +848,59 @@ enum ProjectCacheSubcommand {
         #[clap(long)]
         shard_count: usize,
     },
+
+    /// List the build graphs present in the local project cache.
+    List {
+        /// Path to the sparse repository.
+        #[clap(long, parse(from_os_str), default_value = ".")]
+        sparse_repo: PathBuf,
+
+        /// Also read each build graph's manifest from the configured remote to report its shard count.
+        #[clap(long)]
+        remote: bool,
+    },
+
+    /// Print the patterns cached for a build graph's mandatory projects, or for the given optional project.
+    Show {
+        /// Path to the sparse repository.
+        #[clap(long, parse(from_os_str), default_value = ".")]
+        sparse_repo: PathBuf,
+
+        /// Read from the configured remote instead of the local project cache.
+        #[clap(long)]
+        remote: bool,
+
+        /// The build graph hash (as printed by `focus project-cache list`).
+        build_graph_hash: String,
+
+        /// The optional project to show patterns for.
+        project: Option<String>,
+    },
+
+    /// Check that the remote manifest for a build graph agrees with its shards.
+    Verify {
+        /// Path to the sparse repository.
+        #[clap(long, parse(from_os_str), default_value = ".")]
+        sparse_repo: PathBuf,
+
+        /// The commit whose build graph to verify if no build graph hash is given.
+        #[clap(long, default_value = "HEAD")]
+        commit: String,
+
+        /// The build graph hash to verify.
+        build_graph_hash: Option<String>,
+    },
+
+    /// Remove old content from the local project cache (and from the endpoint, if it is a local database).
+    Gc {
+        /// Path to the sparse repository.
+        #[clap(long, parse(from_os_str), default_value = ".")]
+        sparse_repo: PathBuf,
+
+        /// Remove content stored longer ago than this (e.g. "7d" or "12h").
+        #[clap(long, parse(try_from_str = humantime::parse_duration))]
+        older_than: Duration,
+    },
 }
 
 #[derive(Parser, Clone, Debug)]
@@ -691,6 +937,116 @@ enum BackgroundSubcommand {
         #[clap(parse(from_os_str), default_value = ".")]
         sparse_repo: PathBuf,
     },
+
+    /// Run a daemon which syncs all registered repos when commits are fetched
+    /// or checked out. It runs until it is killed; `focus status` shows what it
+    /// last did.
+    Daemon {
+        /// How long changes must stop for before a repo is synced (e.g. "5s").
+        #[clap(long, parse(try_from_str = humantime::parse_duration), default_value = "2s")]
+        debounce: Duration,
+    },
+}
+
+#[derive(Parser, Clone, Debug)]
+enum LogsSubcommand {
+    /// Print the most recent log lines.
+    Tail {
+        /// The number of lines to print.
+        #[clap(short = 'n', long, default_value = "50")]
+        lines: usize,
+
+        /// Keep printing lines as they are written.
+        #[clap(short = 'f', long)]
+        follow: bool,
+    },
+
+    /// Print the log lines of an invocation, or those matching a pattern.
+    Grep {
+        /// Only print lines written by this invocation. Each log line starts
+        /// with the ID of the invocation that wrote it in brackets.
+        #[clap(long)]
+        invocation: Option<String>,
+
+        /// A regular expression the printed lines must match.
+        pattern: Option<String>,
+    },
+
+    /// Write the recent log files to a gzipped tarball to attach to bug
+    /// reports.
+    Bundle {
+        /// Include log files written to within this long (e.g. "2d").
+        #[clap(long, parse(try_from_str = humantime::parse_duration), default_value = "1d")]
+        since: Duration,
+
+        /// The path to write the bundle to.
+        #[clap(parse(from_os_str), default_value = "focus-logs.tar.gz")]
+        output: PathBuf,
+    },
+}
+
+#[derive(Parser, Clone, Debug)]
+enum BundleSubcommand {
+    /// Write the branch checked out in a repo to a git bundle, along with its
+    /// selection and index, for `focus new --from-bundle`. The repo must have
+    /// been cloned with `--days-of-history 0`.
+    Create {
+        #[clap(long, parse(from_os_str), default_value = ".")]
+        repo: PathBuf,
+
+        /// The path to write the bundle to. The sidecar is written next to it.
+        #[clap(parse(from_os_str))]
+        output: PathBuf,
+    },
+}
+
+#[derive(Parser, Clone, Debug)]
+enum WorktreeSubcommand {
+    /// Add a linked worktree with its own selection and outlining tree, which
+    /// shares the object store, index, and project cache of the repo. It
+    /// starts with the repo's selection.
+    Add {
+        #[clap(long, parse(from_os_str), default_value = ".")]
+        repo: PathBuf,
+
+        /// The path to add the worktree at.
+        #[clap(parse(from_os_str))]
+        path: PathBuf,
+
+        /// The branch to check out. By default, a branch named after the
+        /// directory is created from HEAD.
+        branch: Option<String>,
+    },
+
+    /// Remove a linked worktree along with its outlining tree.
+    Remove {
+        #[clap(long, parse(from_os_str), default_value = ".")]
+        repo: PathBuf,
+
+        /// The path of the worktree to remove.
+        #[clap(parse(from_os_str))]
+        path: PathBuf,
+
+        /// Remove the worktree even if it has changes.
+        #[clap(long)]
+        force: bool,
+    },
+}
+
+#[derive(Parser, Clone, Debug)]
+enum HooksSubcommand {
+    /// Install the hooks in the directory git runs hooks from, which is set by
+    /// `core.hooksPath`. Existing hooks are kept and run before focus's.
+    Install {
+        #[clap(long, parse(from_os_str), default_value = ".")]
+        repo: PathBuf,
+    },
+
+    /// Remove the hooks installed by focus, restoring the hooks they replaced.
+    Uninstall {
+        #[clap(long, parse(from_os_str), default_value = ".")]
+        repo: PathBuf,
+    },
 }
 
 #[derive(Parser, Clone, Debug)]
@@ -790,6 +1146,9 @@ fn run_subcommand(app: Arc<App>, tracker: &Tracker, options: FocusOpts) -> Resul
             copy_branches,
             projects_and_targets,
             template,
+            resume,
+            from_bundle,
+            reference,
         })
         | Subcommand::Clone(NewArgs {
             dense_repo,
@@ -799,8 +1158,10 @@ fn run_subcommand(app: Arc<App>, tracker: &Tracker, options: FocusOpts) -> Resul
             copy_branches,
             projects_and_targets,
             template,
+            resume,
+            from_bundle,
+            reference,
         }) => {
-            let origin = focus_operations::clone::Origin::try_from(dense_repo.as_str())?;
             let sparse_repo = {
                 let current_dir =
                     std::env::current_dir().context("Failed to obtain current directory")?;
@@ -809,7 +1170,31 @@ fn run_subcommand(app: Arc<App>, tracker: &Tracker, options: FocusOpts) -> Resul
                 current_dir.join(expanded)
             };
 
-            info!("Cloning {:?} into {}", dense_repo, sparse_repo.display());
+            if resume {
+                info!("Resuming clone into {}", sparse_repo.display());
+                focus_operations::clone::resume(sparse_repo.clone(), tracker, app.clone())?;
+                perform_pending_migrations(&sparse_repo, app)
+                    .context("Performing initial migrations after clone")?;
+                return Ok(ExitCode(0));
+            }
+
+            // The origin of a bundle is read from its sidecar.
+            let origin = match &from_bundle {
+                Some(bundle) => {
+                    info!(
+                        "Cloning bundle {} into {}",
+                        bundle.display(),
+                        sparse_repo.display()
+                    );
+                    None
+                }
+                None => {
+                    info!("Cloning {:?} into {}", dense_repo, sparse_repo.display());
+                    Some(focus_operations::clone::Origin::try_from(
+                        dense_repo.as_str(),
+                    )?)
+                }
+            };
 
             // Add targets length to TI custom map.
             ti_client.get_context().add_to_custom_map(
@@ -818,11 +1203,13 @@ fn run_subcommand(app: Arc<App>, tracker: &Tracker, options: FocusOpts) -> Resul
             );
 
             let clone_args = CloneArgs {
-                origin: Some(origin),
+                origin,
                 branch,
                 days_of_history,
                 copy_branches,
                 projects_and_targets,
+                bundle: from_bundle,
+                reference,
                 ..Default::default()
             };
 
@@ -939,6 +1326,9 @@ fn run_subcommand(app: Arc<App>, tracker: &Tracker, options: FocusOpts) -> Resul
                 BranchSubcommand::Add { name } => {
                     focus_operations::branch::add(app, repo, &remote_name, &name)
                 }
+                BranchSubcommand::SyncRules {} => {
+                    focus_operations::branch::sync_rules(app, repo, &remote_name)
+                }
             }
         }
 
@@ -1013,6 +1403,22 @@ fn run_subcommand(app: Arc<App>, tracker: &Tracker, options: FocusOpts) -> Resul
             Ok(ExitCode(0))
         }
 
+        Subcommand::Peek {
+            directories,
+            duration,
+            clear,
+        } => {
+            let sparse_repo = paths::find_repo_root_from(app.clone(), std::env::current_dir()?)?;
+            paths::assert_focused_repo(&sparse_repo)?;
+            let _lock_file = hold_lock_file(&sparse_repo)?;
+            if clear {
+                focus_operations::peek::clear(&sparse_repo, app)?;
+            } else {
+                focus_operations::peek::add(&sparse_repo, directories, duration, app)?;
+            }
+            Ok(ExitCode(0))
+        }
+
         Subcommand::Status {
             targets,
             target_types,
@@ -1049,6 +1455,7 @@ fn run_subcommand(app: Arc<App>, tracker: &Tracker, options: FocusOpts) -> Resul
                         git_config_key,
                         git_config_path,
                         tracked,
+                        ..Default::default()
                     },
                     time_period,
                     tracker,
@@ -1057,6 +1464,10 @@ fn run_subcommand(app: Arc<App>, tracker: &Tracker, options: FocusOpts) -> Resul
 
                 sandbox::cleanup::run_with_default()?;
 
+                if let Err(e) = ti_client.get_inner().flush_spool() {
+                    warn!(?e, "Failed to flush spooled tool insights messages");
+                }
+
                 Ok(ExitCode(0))
             }
 
@@ -1131,6 +1542,17 @@ fn run_subcommand(app: Arc<App>, tracker: &Tracker, options: FocusOpts) -> Resul
 
                 Ok(ExitCode(0))
             }
+
+            MaintenanceSubcommand::FlushToolInsights {} => {
+                let count = ti_client.get_inner().flush_spool()?;
+                info!(count, "Flushed spooled tool insights messages");
+                Ok(ExitCode(0))
+            }
+
+            MaintenanceSubcommand::Status {} => maintenance::status(
+                tracker,
+                &maintenance::state::MaintenanceState::default_path(),
+            ),
         },
 
         Subcommand::GitTrace { input, output } => {
@@ -1190,12 +1612,30 @@ fn run_subcommand(app: Arc<App>, tracker: &Tracker, options: FocusOpts) -> Resul
                 Ok(exit_code)
             }
 
+            IndexSubcommand::Graph {
+                format,
+                max_depth,
+                targets,
+            } => {
+                let sparse_repo = paths::find_repo_root_from(app.clone(), PathBuf::from("."))?;
+                let exit_code =
+                    focus_operations::index::graph(app, &sparse_repo, targets, format, max_depth)?;
+                Ok(exit_code)
+            }
+
             IndexSubcommand::Hash { commit, targets } => {
                 let sparse_repo = paths::find_repo_root_from(app.clone(), PathBuf::from("."))?;
                 let exit_code = focus_operations::index::hash(app, &sparse_repo, commit, &targets)?;
                 Ok(exit_code)
             }
 
+            IndexSubcommand::LoadGraph { commit, files } => {
+                let sparse_repo = paths::find_repo_root_from(app.clone(), PathBuf::from("."))?;
+                let exit_code =
+                    focus_operations::index::load_graph(app, &sparse_repo, commit, &files)?;
+                Ok(exit_code)
+            }
+
             IndexSubcommand::Push {
                 sparse_repo,
                 remote,
@@ -1213,6 +1653,31 @@ fn run_subcommand(app: Arc<App>, tracker: &Tracker, options: FocusOpts) -> Resul
                 Ok(exit_code)
             }
 
+            IndexSubcommand::Prune {
+                sparse_repo,
+                remote,
+                keep_last,
+                older_than,
+                dry_run,
+            } => {
+                let sparse_repo = paths::find_repo_root_from(app.clone(), sparse_repo)?;
+                let exit_code = focus_operations::index::prune(
+                    app,
+                    sparse_repo,
+                    remote,
+                    keep_last,
+                    older_than,
+                    dry_run,
+                )?;
+                Ok(exit_code)
+            }
+
+            IndexSubcommand::Rdeps { query } => {
+                let sparse_repo = paths::find_repo_root_from(app.clone(), PathBuf::from("."))?;
+                let exit_code = focus_operations::index::rdeps(app, &sparse_repo, &query)?;
+                Ok(exit_code)
+            }
+
             IndexSubcommand::Resolve {
                 targets,
                 break_on_missing_keys,
@@ -1226,6 +1691,17 @@ fn run_subcommand(app: Arc<App>, tracker: &Tracker, options: FocusOpts) -> Resul
                 )?;
                 Ok(exit_code)
             }
+
+            IndexSubcommand::Verify {
+                sparse_repo,
+                sample,
+                evict,
+            } => {
+                let sparse_repo = paths::find_repo_root_from(app.clone(), sparse_repo)?;
+                let _lock_file = hold_lock_file(&sparse_repo)?;
+                let exit_code = focus_operations::index::verify(app, sparse_repo, sample, evict)?;
+                Ok(exit_code)
+            }
         },
 
         Subcommand::ProjectCache { subcommand } => match subcommand {
@@ -1245,6 +1721,48 @@ fn run_subcommand(app: Arc<App>, tracker: &Tracker, options: FocusOpts) -> Resul
                 )?;
                 Ok(exit_code)
             }
+
+            ProjectCacheSubcommand::List {
+                sparse_repo,
+                remote,
+            } => {
+                let sparse_repo = paths::find_repo_root_from(app.clone(), sparse_repo)?;
+                focus_operations::project_cache::list(app, sparse_repo, remote)
+            }
+
+            ProjectCacheSubcommand::Show {
+                sparse_repo,
+                remote,
+                build_graph_hash,
+                project,
+            } => {
+                let sparse_repo = paths::find_repo_root_from(app.clone(), sparse_repo)?;
+                focus_operations::project_cache::show(
+                    app,
+                    sparse_repo,
+                    build_graph_hash,
+                    project,
+                    remote,
+                )
+            }
+
+            ProjectCacheSubcommand::Verify {
+                sparse_repo,
+                commit,
+                build_graph_hash,
+            } => {
+                let sparse_repo = paths::find_repo_root_from(app.clone(), sparse_repo)?;
+                focus_operations::project_cache::verify(app, sparse_repo, build_graph_hash, commit)
+            }
+
+            ProjectCacheSubcommand::Gc {
+                sparse_repo,
+                older_than,
+            } => {
+                let sparse_repo = paths::find_repo_root_from(app.clone(), sparse_repo)?;
+                let _lock_file = hold_lock_file(&sparse_repo)?;
+                focus_operations::project_cache::gc(app, sparse_repo, older_than)
+            }
         },
 
         Subcommand::Project { subcommand } => match subcommand {
@@ -1255,7 +1773,12 @@ fn run_subcommand(app: Arc<App>, tracker: &Tracker, options: FocusOpts) -> Resul
             }
         },
 
-        Subcommand::Event { args: _ } => Ok(ExitCode(0)),
+        Subcommand::Event { args } => match args.split_first() {
+            Some((hook, args)) if hook == "post-checkout" => {
+                focus_operations::event::post_checkout(app, args)
+            }
+            _ => Ok(ExitCode(0)),
+        },
 
         Subcommand::Version => {
             println!("package-name: {}", env!("CARGO_PKG_NAME"));
@@ -1288,11 +1811,80 @@ fn run_subcommand(app: Arc<App>, tracker: &Tracker, options: FocusOpts) -> Resul
                 let sparse_repo = paths::find_repo_root_from(app.clone(), sparse_repo)?;
                 focus_operations::background::sync(app, sparse_repo)
             }
+            BackgroundSubcommand::Daemon { debounce } => focus_operations::daemon::run(
+                app,
+                tracker,
+                &focus_operations::daemon::default_socket_path(),
+                debounce,
+            ),
         },
         Subcommand::Pull => {
             let sparse_repo = paths::find_repo_root_from(app.clone(), std::env::current_dir()?)?;
             focus_operations::pull::run(app, sparse_repo)
         }
+        Subcommand::Logs { subcommand } => {
+            let log_dir = focus_tracing::log_dir()?;
+            match subcommand {
+                LogsSubcommand::Tail { lines, follow } => {
+                    focus_tracing::logs::tail(&log_dir, lines, follow, &mut std::io::stdout())?;
+                    Ok(ExitCode(0))
+                }
+                LogsSubcommand::Grep {
+                    invocation,
+                    pattern,
+                } => {
+                    let pattern = pattern
+                        .map(|pattern| Regex::new(&pattern))
+                        .transpose()
+                        .context("Invalid pattern")?;
+                    let count = focus_tracing::logs::grep(
+                        &log_dir,
+                        invocation.as_deref(),
+                        pattern.as_ref(),
+                        &mut std::io::stdout(),
+                    )?;
+                    Ok(ExitCode(if count > 0 { 0 } else { 1 }))
+                }
+                LogsSubcommand::Bundle { since, output } => {
+                    let included = focus_tracing::logs::bundle(&log_dir, since, &output)?;
+                    eprintln!(
+                        "Wrote {} log file(s) to {}",
+                        included.len(),
+                        output.display()
+                    );
+                    Ok(ExitCode(0))
+                }
+            }
+        }
+        Subcommand::Bundle { subcommand } => match subcommand {
+            BundleSubcommand::Create { repo, output } => {
+                let repo = paths::find_repo_root_from(app.clone(), repo)?;
+                focus_operations::bundle::create(app, &repo, &output)?;
+                Ok(ExitCode(0))
+            }
+        },
+        Subcommand::Worktree { subcommand } => match subcommand {
+            WorktreeSubcommand::Add { repo, path, branch } => {
+                let repo = paths::find_repo_root_from(app.clone(), repo)?;
+                focus_operations::worktree::add(&repo, &path, branch, tracker, app)?;
+                Ok(ExitCode(0))
+            }
+            WorktreeSubcommand::Remove { repo, path, force } => {
+                let repo = paths::find_repo_root_from(app.clone(), repo)?;
+                focus_operations::worktree::remove(&repo, &path, force, app)?;
+                Ok(ExitCode(0))
+            }
+        },
+        Subcommand::Hooks { subcommand } => match subcommand {
+            HooksSubcommand::Install { repo } => {
+                focus_operations::event::init(&paths::find_repo_root_from(app, repo)?)?;
+                Ok(ExitCode(0))
+            }
+            HooksSubcommand::Uninstall { repo } => {
+                focus_operations::event::uninstall(&paths::find_repo_root_from(app, repo)?)?;
+                Ok(ExitCode(0))
+            }
+        },
         Subcommand::Selection { subcommand } => match subcommand {
             SelectionSubcommand::Save {
                 project_name,
@@ -1386,14 +1978,21 @@ fn main_and_drop_locals() -> Result<ExitCode> {
     }
 
     let preserve_sandbox = true;
+    let feature_name = feature_name_for(&options.cmd);
 
     let app = Arc::from(App::new(
         preserve_sandbox,
-        Some(&feature_name_for(&options.cmd)),
+        Some(&feature_name),
         Some(env!("CARGO_PKG_NAME").to_owned()),
         Some(env!("CARGO_PKG_VERSION").to_owned()),
     )?);
     let ti_context = app.tool_insights_client();
+    // Git hooks run `focus event` on every checkout and merge, so spool those
+    // messages for maintenance to deliver in bulk rather than writing a file
+    // or sending a request to the collector for each.
+    if matches!(options.cmd, Subcommand::Event { .. }) {
+        ti_context.get_inner().spool_messages();
+    }
 
     setup_thread_pool(*resolution_threads)?;
 
@@ -1402,10 +2001,28 @@ fn main_and_drop_locals() -> Result<ExitCode> {
     let sandbox_dir = app.sandbox().path().to_owned();
     let tracker = Tracker::from_config_dir()?;
 
+    // All invocations share a rotated log file, so tag each line with the name
+    // of the sandbox to tell invocations apart.
+    let invocation = {
+        let ti_context = ti_context.get_context();
+        focus_tracing::InvocationContext {
+            invocation_id: sandbox_dir
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned()),
+            trace_id: Some(format!("{:x}", ti_context.get_trace_id())),
+            span_id: Some(format!("{:x}", ti_context.get_span_id())),
+            feature_name: Some(feature_name),
+            repo_path: Repository::discover(".")
+                .ok()
+                .and_then(|repo| repo.workdir().map(|path| path.to_owned())),
+        }
+    };
     let _guard = focus_tracing::init_tracing(focus_tracing::TracingOpts {
         is_tty,
         no_color: *no_color,
-        log_dir: Some(sandbox_dir.to_owned()),
+        log_dir: None,
+        invocation,
+        trace_dir: Some(sandbox_dir.clone()),
     })?;
 
     info!(path = ?sandbox_dir, "Created sandbox");
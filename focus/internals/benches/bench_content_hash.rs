@@ -75,7 +75,7 @@ pub fn bench_content_hash(c: &mut Criterion) {
     }
 
     {
-        let odb = RocksDBCache::new(git_repo);
+        let odb = RocksDBCache::new(git_repo).unwrap();
         c.bench_function("content_hash_insert_rocks_db", |b| {
             b.iter_batched(
                 || {
@@ -9,8 +9,6 @@ use std::path::Path;
 use std::path::PathBuf;
 use std::str::FromStr;
 
-use lazy_static::lazy_static;
-use regex::Regex;
 use thiserror::Error;
 use tracing::debug;
 use tracing::error;
@@ -19,13 +17,14 @@ use tracing::warn;
 
 use crate::target::Label;
 use crate::target::TargetName;
-use focus_util::paths::is_relevant_to_build_graph;
+use focus_util::paths::{is_relevant_to_build_graph, GLOBAL_BUILD_INPUTS};
 
+use super::load_graph::extract_load_statements;
 use super::DependencyKey;
 
 /// This value is mixed into all content hashes. Update this value when
 /// content-hashing changes in a backward-incompatible way.
-const VERSION: usize = 7;
+const VERSION: usize = 8;
 
 /// The hash of a [`DependencyKey`]'s syntactic content.
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -74,6 +73,9 @@ pub struct Caches {
 
     /// Cache of dependencies loaded from the `prelude_bazel` file.
     prelude_deps_cache: Option<BTreeSet<Label>>,
+
+    /// Cache of the global build inputs present in the repository.
+    global_build_inputs_cache: Option<BTreeSet<PathBuf>>,
 }
 
 /// Context used to compute a content hash.
@@ -163,6 +165,29 @@ pub fn content_hash(ctx: &HashContext, key: &DependencyKey) -> Result<ContentHas
     content_hash_dependency_key(ctx, key)
 }
 
+/// Compute the content hashes of the direct inputs to the content hash of the
+/// provided [`DependencyKey`]. Paths hashed directly are reported as
+/// [`DependencyKey::Path`]s.
+pub fn content_hash_inputs(
+    ctx: &HashContext,
+    key: &DependencyKey,
+) -> Result<Vec<(DependencyKey, ContentHash)>> {
+    let (_kind, _maybe_label, values_to_hash) = get_dependencies(ctx, key)?;
+    values_to_hash
+        .into_iter()
+        .map(|key_or_hash| match key_or_hash {
+            KeyOrPath::Key(dep_key) => {
+                let hash = content_hash_dependency_key(ctx, dep_key.clone())?;
+                Ok((dep_key, hash))
+            }
+            KeyOrPath::Path(path) => {
+                let hash = content_hash_tree_path(ctx, path)?;
+                Ok((DependencyKey::Path(path.to_owned()), hash))
+            }
+        })
+        .collect()
+}
+
 fn content_hash_dependency_key(ctx: &HashContext, key: DependencyKey) -> Result<ContentHash> {
     debug!(?key, "Hashing dependency key");
 
@@ -314,6 +339,16 @@ fn get_dependencies<'a>(
             // here.
             dep_keys.push(DependencyKey::Path("WORKSPACE".into()));
 
+            // Likewise for the Bzlmod and Bazel configuration files, which
+            // affect how every build file is evaluated. Since every package
+            // depends on the `WORKSPACE` build file, this makes them inputs to
+            // every package as well.
+            dep_keys.extend(
+                get_global_build_inputs(ctx)?
+                    .into_iter()
+                    .map(DependencyKey::Path),
+            );
+
             Ok((
                 "BazelBuildFile",
                 Some(label),
@@ -379,6 +414,30 @@ pub fn get_prelude_deps(ctx: &HashContext) -> Result<BTreeSet<Label>> {
     Ok(result)
 }
 
+/// Get the files at the root of the repository which affect the evaluation of
+/// the entire build graph, in addition to the `WORKSPACE` file. Only files
+/// which are present are returned, so that the content hashes of repositories
+/// without them are unaffected.
+pub fn get_global_build_inputs(ctx: &HashContext) -> Result<BTreeSet<PathBuf>> {
+    if let Some(global_build_inputs) = &ctx.caches.borrow().global_build_inputs_cache {
+        return Ok(global_build_inputs.clone());
+    }
+
+    let mut result = BTreeSet::new();
+    for file_name in GLOBAL_BUILD_INPUTS.iter() {
+        match ctx.head_tree.get_path(Path::new(file_name)) {
+            Ok(_) => {
+                result.insert(PathBuf::from(file_name));
+            }
+            Err(err) if err.code() == git2::ErrorCode::NotFound => {}
+            Err(err) => return Err(Error::ReadTreeEntry(err)),
+        }
+    }
+
+    ctx.caches.borrow_mut().global_build_inputs_cache = Some(result.clone());
+    Ok(result)
+}
+
 fn content_hash_tree_path(ctx: &HashContext, path: &Path) -> Result<ContentHash> {
     if let Some(hash) = ctx.caches.borrow().tree_path_cache.get(path) {
         return Ok(hash.clone());
@@ -503,75 +562,6 @@ fn extract_load_statements_from_tree_entry(
         }
     };
 
-    let deps = extract_load_statement_package_dependencies(content);
+    let deps = extract_load_statements(content);
     Ok(deps)
 }
-
-fn extract_load_statement_package_dependencies(content: &str) -> BTreeSet<Label> {
-    lazy_static! {
-        static ref RE: Regex = Regex::new(
-            r#"(?x)
-# Literal "load".
-load
-\s*?
-
-# Open parenthesis.
-\(
-\s*?
-
-# String literal enclosed in quotes.
-(?:
-    "( [[:print:]--"]*? )"
-  | '( [[:print:]--']*? )'
-)
-
-# Either a closing parenthesis or a comma to start the argument list.
-\s*?
-(?:
-  ,
-| \)
-)
-"#
-        )
-        .unwrap();
-    }
-
-    let mut result = BTreeSet::new();
-    for cap in RE.captures_iter(content) {
-        let value = cap.get(1).or_else(|| cap.get(2)).unwrap().as_str();
-        let label: Label = match value.parse() {
-            Ok(label) => label,
-            Err(e) => {
-                warn!(?e, "Failed to parse label in load statement");
-                continue;
-            }
-        };
-        result.insert(label);
-    }
-    result
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn test_extract_load_statements() -> Result<()> {
-        let content = r#"
-load("//foo/bar:baz.bzl")
-load   (
-    '//foo/qux:qux.bzl'
-
-,    qux = 'grault')
-"#;
-        let labels = extract_load_statement_package_dependencies(content);
-        insta::assert_debug_snapshot!(labels, @r###"
-        {
-            Label("//foo/bar:baz.bzl"),
-            Label("//foo/qux:qux.bzl"),
-        }
-        "###);
-
-        Ok(())
-    }
-}
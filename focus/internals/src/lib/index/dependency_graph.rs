@@ -62,10 +62,10 @@ pub enum DependencyKey {
     /// need to read the contents of *that* `.bzl` file and mix them into the
     /// hash for this dependency.
     ///
-    /// NOTE: currently, the Bazel resolver does not produce any
-    /// `BazelBuildFile` dependencies. Instead, it produces the `BazelPackage`
-    /// which contains those `.bzl` files. So the only uses of this are where we
-    /// manually construct it as part of content hashing.
+    /// The Bazel resolver produces these for the `.bzl` files loaded by the
+    /// build file of each rule's package, as read from the repository by
+    /// [`crate::index::LoadGraph`]. They are also constructed manually as part
+    /// of content hashing.
     BazelBuildFile(Label),
 
     /// Represents a path (probably a directory) which should be checked out as
@@ -412,7 +412,7 @@ sh_binary(
         let head_oid = fix.commit_all("Wrote files")?;
 
         let repo = fix.repo()?;
-        let odb = RocksDBCache::new(&repo);
+        let odb = RocksDBCache::new(&repo)?;
         let files_to_materialize = {
             let head_commit = repo.find_commit(head_oid)?;
             let head_tree = head_commit.tree()?;
@@ -428,7 +428,7 @@ sh_binary(
                         Label("//package1:foo"),
                     ),
                     ContentHash(
-                        88ac08d5fc3ad9e7c278929a6c649f113e15686c,
+                        d9e3f3adf5ec93dca183784e55bb0d9e02c35380,
                     ),
                 ),
             },
@@ -595,7 +595,7 @@ New contents
         let cache_options = CacheOptions::default();
         let resolve_result = resolver.resolve(&request, &cache_options, app.clone())?;
 
-        let odb = RocksDBCache::new(&repo);
+        let odb = RocksDBCache::new(&repo)?;
         let files_to_materialize = {
             let head_commit = repo.find_commit(head_oid)?;
             let head_tree = head_commit.tree()?;
@@ -617,12 +617,19 @@ New contents
                 BazelPackage(
                     Label("//package2:contents"),
                 ),
+                BazelBuildFile(
+                    Label("//macro:macro.bzl"),
+                ),
+                BazelBuildFile(
+                    Label("//macro:macro_inner.bzl"),
+                ),
                 Path(
                     "WORKSPACE",
                 ),
             },
             paths: {
                 "WORKSPACE",
+                "macro",
                 "package1",
                 "package2",
             },
@@ -665,7 +672,7 @@ def my_macro_inner(name):
                         Label("//package1:foo"),
                     ),
                     ContentHash(
-                        8d824d3f5ea161a4532e45a8a2686a8b196ac461,
+                        b2d67b7e6ad2a1aae501cb05aca2fada695a56a6,
                     ),
                 ),
             },
@@ -701,12 +708,19 @@ def my_macro_inner(name):
                 BazelPackage(
                     Label("//package3:contents"),
                 ),
+                BazelBuildFile(
+                    Label("//macro:macro.bzl"),
+                ),
+                BazelBuildFile(
+                    Label("//macro:macro_inner.bzl"),
+                ),
                 Path(
                     "WORKSPACE",
                 ),
             },
             paths: {
                 "WORKSPACE",
+                "macro",
                 "package1",
                 "package3",
             },
@@ -820,7 +834,7 @@ def some_macro():
                         Label("//package1:foo"),
                     ),
                     ContentHash(
-                        d340b3bb64561cebebe211ff4870602a6e385393,
+                        74472ef902fad1bb01582aafa35ed3c242219261,
                     ),
                 ),
             },
@@ -841,6 +855,58 @@ def some_macro():
         Ok(())
     }
 
+    #[test]
+    fn test_bzlmod_files_are_global_inputs() -> anyhow::Result<()> {
+        init_logging();
+
+        let temp = tempfile::tempdir()?;
+        let fix = ScratchGitRepo::new_static_fixture(temp.path())?;
+
+        write_files(
+            &fix,
+            r#"
+file: WORKSPACE
+
+file: MODULE.bazel
+module(name = "example", version = "1.0")
+
+file: .bazelversion
+6.0.0
+
+file: package1/BUILD
+sh_binary(
+    name = "foo",
+    srcs = ["foo.sh"],
+)
+"#,
+        )?;
+        let repo = fix.repo()?;
+        let package_key = parse_label("//package1:foo")?;
+
+        let head_oid = fix.commit_all("Wrote files")?;
+        let (workspace_deps, hash_before) = {
+            let head_tree = repo.find_commit(head_oid)?.tree()?;
+            let ctx = HashContext::new(&repo, &head_tree)?;
+            (
+                get_workspace_deps(&ctx)?,
+                crate::index::content_hash(&ctx, &package_key)?,
+            )
+        };
+        assert!(workspace_deps.contains(&DependencyKey::Path("MODULE.bazel".into())));
+        assert!(workspace_deps.contains(&DependencyKey::Path(".bazelversion".into())));
+        assert!(!workspace_deps.contains(&DependencyKey::Path(".bazelrc".into())));
+
+        let head_oid = fix.write_and_commit_file(".bazelversion", "6.1.0", "bump Bazel")?;
+        let hash_after = {
+            let head_tree = repo.find_commit(head_oid)?.tree()?;
+            let ctx = HashContext::new(&repo, &head_tree)?;
+            crate::index::content_hash(&ctx, &package_key)?
+        };
+        assert_ne!(hash_before, hash_after);
+
+        Ok(())
+    }
+
     #[test]
     fn test_prelude_bazel_dependency() -> anyhow::Result<()> {
         init_logging();
@@ -972,7 +1038,7 @@ def macro2(name):
                         Label("//package1:foo"),
                     ),
                     ContentHash(
-                        92e8d6005643b9d223d1196651c00da334edcb2a,
+                        e225f393ef96e38f5237dda56db2f939124d90f2,
                     ),
                 ),
             },
@@ -1151,6 +1217,7 @@ foo(
         insta::assert_debug_snapshot!(resolve_result, @r###"
         ResolutionResult {
             paths: {
+                "macro",
                 "package1",
                 "package1/some/sub/package",
             },
@@ -1174,6 +1241,9 @@ foo(
                         BazelPackage(
                             Label("//package1/some/sub/package:foo.sh"),
                         ),
+                        BazelBuildFile(
+                            Label("//macro:macro.bzl"),
+                        ),
                     },
                 },
                 BazelPackage(
@@ -1205,12 +1275,16 @@ foo(
                 BazelPackage(
                     Label("//package1/some/sub/package:foo.sh"),
                 ),
+                BazelBuildFile(
+                    Label("//macro:macro.bzl"),
+                ),
                 Path(
                     "WORKSPACE",
                 ),
             },
             paths: {
                 "WORKSPACE",
+                "macro",
                 "package1",
                 "package1/some/sub/package",
             },
@@ -1255,7 +1329,7 @@ def foo(name, srcs):
                         Label("//package1/some/sub/package:foo"),
                     ),
                     ContentHash(
-                        b326065aa1cb441a90f10d302dfa3af954c3a45f,
+                        5c1d23c42a54dfd18bcfa5fb5a86f48cbfccb4bf,
                     ),
                 ),
                 (
@@ -1263,7 +1337,7 @@ def foo(name, srcs):
                         Label("//package1/some/sub/package:foo.sh"),
                     ),
                     ContentHash(
-                        ec800f4eb7f1921099c813d7fd10820322366e43,
+                        108e48ca8fdbc4aad72cca48933c4e42c8109112,
                     ),
                 ),
             },
@@ -1289,6 +1363,7 @@ def foo(name, srcs):
         insta::assert_debug_snapshot!(resolve_result, @r###"
         ResolutionResult {
             paths: {
+                "macro",
                 "package1",
                 "package1/some/sub/package",
             },
@@ -1317,6 +1392,9 @@ def foo(name, srcs):
                         BazelPackage(
                             Label("//package1/some/sub/package:foo.sh"),
                         ),
+                        BazelBuildFile(
+                            Label("//macro:macro.bzl"),
+                        ),
                     },
                 },
             },
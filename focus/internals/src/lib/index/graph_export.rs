@@ -0,0 +1,329 @@
+// Copyright 2022 Twitter, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::io::Write;
+
+use anyhow::Context;
+use serde::Serialize;
+use tracing::debug;
+
+use super::{DependencyKey, DependencyValue, HashContext, ObjectDatabase};
+
+/// The format in which to write a [`DependencyGraph`].
+#[derive(clap::ArgEnum, Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub enum GraphFormat {
+    /// Graphviz DOT.
+    Dot,
+
+    /// GraphML.
+    Graphml,
+
+    /// JSON, as an object with `nodes` and `edges` arrays.
+    Json,
+}
+
+/// The dependency graph between [`DependencyKey`]s, as recorded in the object
+/// database.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DependencyGraph {
+    edges: BTreeMap<DependencyKey, BTreeSet<DependencyKey>>,
+    unresolved: BTreeSet<DependencyKey>,
+}
+
+#[derive(Serialize)]
+struct JsonNode {
+    id: String,
+    kind: &'static str,
+    resolved: bool,
+}
+
+#[derive(Serialize)]
+struct JsonEdge {
+    from: String,
+    to: String,
+}
+
+#[derive(Serialize)]
+struct JsonGraph {
+    nodes: Vec<JsonNode>,
+    edges: Vec<JsonEdge>,
+}
+
+impl DependencyGraph {
+    /// Collect the graph reachable from the provided keys by following the
+    /// dependencies of the values stored in the object database. If `max_depth`
+    /// is provided, keys more than that many edges away from the provided keys
+    /// are not included.
+    pub fn collect(
+        ctx: &HashContext,
+        odb: &dyn ObjectDatabase,
+        dep_keys: HashSet<DependencyKey>,
+        max_depth: Option<usize>,
+    ) -> anyhow::Result<Self> {
+        let mut result = Self::default();
+        let mut seen_keys: HashSet<DependencyKey> = dep_keys.iter().cloned().collect();
+        let mut current_keys: Vec<DependencyKey> = dep_keys.into_iter().collect();
+        let mut depth = 0;
+        while !current_keys.is_empty() {
+            let mut next_keys = Vec::new();
+            for dep_key in current_keys {
+                let deps = match &dep_key {
+                    DependencyKey::BazelPackage(_) => {
+                        let (hash, dep_value) = odb.get(ctx, &dep_key)?;
+                        match dep_value {
+                            Some(DependencyValue::PackageInfo { deps }) => deps,
+                            Some(
+                                DependencyValue::Path { .. } | DependencyValue::DummyForTesting(_),
+                            ) => Default::default(),
+                            None => {
+                                debug!(?dep_key, ?hash, "No stored value for key");
+                                result.unresolved.insert(dep_key.clone());
+                                Default::default()
+                            }
+                        }
+                    }
+
+                    DependencyKey::BazelBuildFile(_)
+                    | DependencyKey::Path(_)
+                    | DependencyKey::DummyForTesting(_) => Default::default(),
+                };
+
+                let deps = if max_depth.map_or(true, |max_depth| depth < max_depth) {
+                    deps
+                } else {
+                    Default::default()
+                };
+                for dep in deps.iter() {
+                    if seen_keys.insert(dep.clone()) {
+                        next_keys.push(dep.clone());
+                    }
+                }
+                result.edges.insert(dep_key, deps);
+            }
+            current_keys = next_keys;
+            depth += 1;
+        }
+        Ok(result)
+    }
+
+    /// The edges of the graph, from each key to the keys it depends on.
+    pub fn edges(&self) -> &BTreeMap<DependencyKey, BTreeSet<DependencyKey>> {
+        &self.edges
+    }
+
+    /// Keys in the graph which had no value in the object database, and whose
+    /// dependencies are therefore unknown.
+    pub fn unresolved_keys(&self) -> &BTreeSet<DependencyKey> {
+        &self.unresolved
+    }
+
+    /// Write the graph in the given format.
+    pub fn write(&self, format: GraphFormat, writer: &mut dyn Write) -> anyhow::Result<()> {
+        match format {
+            GraphFormat::Dot => self.write_dot(writer),
+            GraphFormat::Graphml => self.write_graphml(writer),
+            GraphFormat::Json => self.write_json(writer),
+        }
+        .context("Writing dependency graph")
+    }
+
+    fn write_dot(&self, writer: &mut dyn Write) -> anyhow::Result<()> {
+        writeln!(writer, "digraph dependencies {{")?;
+        for dep_key in self.edges.keys() {
+            writeln!(
+                writer,
+                "  \"{}\" [kind=\"{}\"{}];",
+                escape_dot(&node_id(dep_key)),
+                node_kind(dep_key),
+                if self.unresolved.contains(dep_key) {
+                    ", style=dashed"
+                } else {
+                    ""
+                }
+            )?;
+        }
+        for (dep_key, deps) in self.edges.iter() {
+            for dep in deps {
+                writeln!(
+                    writer,
+                    "  \"{}\" -> \"{}\";",
+                    escape_dot(&node_id(dep_key)),
+                    escape_dot(&node_id(dep))
+                )?;
+            }
+        }
+        writeln!(writer, "}}")?;
+        Ok(())
+    }
+
+    fn write_graphml(&self, writer: &mut dyn Write) -> anyhow::Result<()> {
+        writeln!(writer, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(
+            writer,
+            r#"<graphml xmlns="http://graphml.graphdrawing.org/xmlns">"#
+        )?;
+        writeln!(
+            writer,
+            r#"  <key id="kind" for="node" attr.name="kind" attr.type="string"/>"#
+        )?;
+        writeln!(
+            writer,
+            r#"  <key id="resolved" for="node" attr.name="resolved" attr.type="boolean"/>"#
+        )?;
+        writeln!(
+            writer,
+            r#"  <graph id="dependencies" edgedefault="directed">"#
+        )?;
+        for dep_key in self.edges.keys() {
+            writeln!(
+                writer,
+                r#"    <node id="{}"><data key="kind">{}</data><data key="resolved">{}</data></node>"#,
+                escape_xml(&node_id(dep_key)),
+                node_kind(dep_key),
+                !self.unresolved.contains(dep_key)
+            )?;
+        }
+        for (dep_key, deps) in self.edges.iter() {
+            for dep in deps {
+                writeln!(
+                    writer,
+                    r#"    <edge source="{}" target="{}"/>"#,
+                    escape_xml(&node_id(dep_key)),
+                    escape_xml(&node_id(dep))
+                )?;
+            }
+        }
+        writeln!(writer, "  </graph>")?;
+        writeln!(writer, "</graphml>")?;
+        Ok(())
+    }
+
+    fn write_json(&self, writer: &mut dyn Write) -> anyhow::Result<()> {
+        let graph = JsonGraph {
+            nodes: self
+                .edges
+                .keys()
+                .map(|dep_key| JsonNode {
+                    id: node_id(dep_key),
+                    kind: node_kind(dep_key),
+                    resolved: !self.unresolved.contains(dep_key),
+                })
+                .collect(),
+            edges: self
+                .edges
+                .iter()
+                .flat_map(|(dep_key, deps)| {
+                    deps.iter().map(|dep| JsonEdge {
+                        from: node_id(dep_key),
+                        to: node_id(dep),
+                    })
+                })
+                .collect(),
+        };
+        serde_json::to_writer_pretty(&mut *writer, &graph)?;
+        writeln!(writer)?;
+        Ok(())
+    }
+}
+
+fn node_id(dep_key: &DependencyKey) -> String {
+    match dep_key {
+        DependencyKey::BazelPackage(label) | DependencyKey::BazelBuildFile(label) => {
+            label.to_string()
+        }
+        DependencyKey::Path(path) => path.display().to_string(),
+        DependencyKey::DummyForTesting(inner_dep_key) => {
+            format!("dummy:{}", node_id(inner_dep_key))
+        }
+    }
+}
+
+fn node_kind(dep_key: &DependencyKey) -> &'static str {
+    match dep_key {
+        DependencyKey::BazelPackage(_) => "BazelPackage",
+        DependencyKey::BazelBuildFile(_) => "BazelBuildFile",
+        DependencyKey::Path(_) => "Path",
+        DependencyKey::DummyForTesting(_) => "DummyForTesting",
+    }
+}
+
+fn escape_dot(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn escape_xml(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+#[cfg(test)]
+mod tests {
+    use maplit::{btreeset, hashset};
+
+    use crate::index::testing::{package, with_scratch_index};
+
+    use super::*;
+
+    #[test]
+    fn test_dependency_graph() -> anyhow::Result<()> {
+        with_scratch_index(|ctx, odb| {
+            let foo = package("//foo:foo")?;
+            let bar = package("//bar:bar")?;
+            let baz = package("//baz:baz")?;
+            odb.put(
+                ctx,
+                &foo,
+                DependencyValue::PackageInfo {
+                    deps: btreeset! { bar.clone() },
+                },
+            )?;
+            odb.put(
+                ctx,
+                &bar,
+                DependencyValue::PackageInfo {
+                    deps: btreeset! { baz.clone() },
+                },
+            )?;
+
+            let graph = DependencyGraph::collect(ctx, odb, hashset! { foo.clone() }, Some(1))?;
+            assert_eq!(
+                graph.edges().keys().cloned().collect::<BTreeSet<_>>(),
+                btreeset! { foo.clone(), bar.clone() }
+            );
+            assert!(graph.unresolved_keys().is_empty());
+
+            let graph = DependencyGraph::collect(ctx, odb, hashset! { foo }, None)?;
+            assert_eq!(graph.unresolved_keys(), &btreeset! { baz });
+
+            let mut dot = Vec::new();
+            graph.write(GraphFormat::Dot, &mut dot)?;
+            insta::assert_snapshot!(String::from_utf8(dot)?, @r###"
+            digraph dependencies {
+              "//bar:bar" [kind="BazelPackage"];
+              "//baz:baz" [kind="BazelPackage", style=dashed];
+              "//foo:foo" [kind="BazelPackage"];
+              "//bar:bar" -> "//baz:baz";
+              "//foo:foo" -> "//bar:bar";
+            }
+            "###);
+
+            let mut json = Vec::new();
+            graph.write(GraphFormat::Json, &mut json)?;
+            let json: serde_json::Value = serde_json::from_slice(&json)?;
+            assert_eq!(json["nodes"].as_array().unwrap().len(), 3);
+            assert_eq!(json["edges"][0]["from"], "//bar:bar");
+            assert_eq!(json["edges"][0]["to"], "//baz:baz");
+
+            let mut graphml = Vec::new();
+            graph.write(GraphFormat::Graphml, &mut graphml)?;
+            let graphml = String::from_utf8(graphml)?;
+            assert!(graphml.contains(r#"<edge source="//foo:foo" target="//bar:bar"/>"#));
+
+            Ok(())
+        })
+    }
+}
@@ -0,0 +1,386 @@
+// Copyright 2022 Twitter, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Extraction of the Starlark `load` graph directly from a Git tree, without
+//! invoking Bazel.
+
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use lazy_static::lazy_static;
+use regex::Regex;
+use tracing::{debug, warn};
+
+use crate::target::{Label, TargetName};
+use focus_util::paths::is_relevant_to_build_graph;
+
+/// The names of the files defining a Bazel package, in order of precedence.
+const BUILD_FILE_NAMES: &[&str] = &["BUILD.bazel", "BUILD"];
+
+/// The `load` dependencies between the build files (`BUILD`, `.bzl`, etc.) of
+/// a single commit, read directly from its Git tree.
+///
+/// Files are identified by labels such as `//foo/bar:baz.bzl`. Files in
+/// external repositories appear as the targets of edges, but their own
+/// dependencies are not known.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LoadGraph {
+    loads: BTreeMap<Label, BTreeSet<Label>>,
+    missing: BTreeSet<Label>,
+}
+
+impl LoadGraph {
+    /// Build the load graph for every build file in the given tree.
+    pub fn for_tree(repo: &git2::Repository, tree: &git2::Tree) -> anyhow::Result<Self> {
+        let mut roots = Vec::new();
+        tree.walk(git2::TreeWalkMode::PreOrder, |dir, entry| {
+            if entry.kind() == Some(git2::ObjectType::Blob) {
+                if let Some(name) = entry.name() {
+                    if is_relevant_to_build_graph(Path::new(name)) {
+                        roots.push(file_label(
+                            dir.split('/').filter(|component| !component.is_empty()),
+                            name,
+                        ));
+                    }
+                }
+            }
+            git2::TreeWalkResult::Ok
+        })
+        .context("Walking tree")?;
+        Self::from_roots(repo, tree, roots)
+    }
+
+    /// Build the load graph reachable from the given build files.
+    pub fn from_roots(
+        repo: &git2::Repository,
+        tree: &git2::Tree,
+        roots: impl IntoIterator<Item = Label>,
+    ) -> anyhow::Result<Self> {
+        let mut result = Self::default();
+        let mut queue: VecDeque<Label> = roots.into_iter().map(normalize).collect();
+        while let Some(file) = queue.pop_front() {
+            if result.loads.contains_key(&file) || file.external_repository.is_some() {
+                continue;
+            }
+
+            let content = match read_file(repo, tree, &file)? {
+                Some(content) => content,
+                None => {
+                    result.missing.insert(file.clone());
+                    result.loads.insert(file, BTreeSet::new());
+                    continue;
+                }
+            };
+            let loads = extract_load_statements_in_package(&content, &file.path_components);
+            queue.extend(
+                loads
+                    .iter()
+                    .filter(|label| !result.loads.contains_key(label))
+                    .cloned(),
+            );
+            result.loads.insert(file, loads);
+        }
+        debug!(
+            file_count = result.loads.len(),
+            missing_count = result.missing.len(),
+            "Built load graph"
+        );
+        Ok(result)
+    }
+
+    /// Find the file defining the package at the given path in the tree, if
+    /// it is a package.
+    pub fn build_file_for_package(tree: &git2::Tree, path_components: &[String]) -> Option<Label> {
+        BUILD_FILE_NAMES.iter().find_map(|name| {
+            let path: PathBuf = path_components
+                .iter()
+                .map(String::as_str)
+                .chain([*name])
+                .collect();
+            match tree.get_path(&path) {
+                Ok(_) => Some(normalize(file_label(
+                    path_components.iter().map(String::as_str),
+                    name,
+                ))),
+                Err(_) => None,
+            }
+        })
+    }
+
+    /// The files in the graph, including external and missing files.
+    pub fn files(&self) -> BTreeSet<&Label> {
+        self.loads
+            .keys()
+            .chain(self.loads.values().flatten())
+            .collect()
+    }
+
+    /// The edges of the graph, from each file to the files it loads.
+    pub fn edges(&self) -> &BTreeMap<Label, BTreeSet<Label>> {
+        &self.loads
+    }
+
+    /// Files which were loaded but do not exist in the tree.
+    pub fn missing_files(&self) -> &BTreeSet<Label> {
+        &self.missing
+    }
+
+    /// The files directly loaded by the given file, if it is part of the graph.
+    pub fn direct_loads(&self, file: &Label) -> Option<&BTreeSet<Label>> {
+        self.loads.get(file)
+    }
+
+    /// All files loaded by the given file, directly or indirectly.
+    pub fn transitive_loads(&self, file: &Label) -> BTreeSet<Label> {
+        let mut result = BTreeSet::new();
+        let mut stack: Vec<&Label> = match self.loads.get(file) {
+            Some(loads) => loads.iter().collect(),
+            None => return result,
+        };
+        while let Some(label) = stack.pop() {
+            if !result.insert(label.clone()) {
+                continue;
+            }
+            if let Some(loads) = self.loads.get(label) {
+                stack.extend(loads.iter());
+            }
+        }
+        result
+    }
+}
+
+/// Construct the label of a file from its directory components and name.
+fn file_label<'a>(path_components: impl Iterator<Item = &'a str>, name: &str) -> Label {
+    Label {
+        external_repository: None,
+        path_components: path_components.map(|s| s.to_string()).collect(),
+        target_name: TargetName::Name(name.to_string()),
+    }
+}
+
+/// Labels in the root package parse with a single empty path component
+/// (`//:foo.bzl`), but are constructed with none; use the latter consistently.
+fn normalize(label: Label) -> Label {
+    if label.path_components.len() == 1 && label.path_components[0].is_empty() {
+        Label {
+            path_components: Vec::new(),
+            ..label
+        }
+    } else {
+        label
+    }
+}
+
+fn read_file(
+    repo: &git2::Repository,
+    tree: &git2::Tree,
+    file: &Label,
+) -> anyhow::Result<Option<String>> {
+    let name = match &file.target_name {
+        TargetName::Name(name) => name,
+        TargetName::Ellipsis => return Ok(None),
+    };
+    let path: PathBuf = file
+        .path_components
+        .iter()
+        .map(String::as_str)
+        .chain([name.as_str()])
+        .collect();
+    let tree_entry = match tree.get_path(&path) {
+        Ok(tree_entry) => tree_entry,
+        Err(e) if e.code() == git2::ErrorCode::NotFound => return Ok(None),
+        Err(e) => return Err(e).with_context(|| format!("Reading {}", path.display())),
+    };
+    let object = tree_entry
+        .to_object(repo)
+        .with_context(|| format!("Reading {}", path.display()))?;
+    let blob = match object.as_blob() {
+        Some(blob) => blob,
+        None => {
+            warn!(?path, "Tree entry was not a blob");
+            return Ok(None);
+        }
+    };
+    match std::str::from_utf8(blob.content()) {
+        Ok(content) => Ok(Some(content.to_owned())),
+        Err(e) => {
+            warn!(?path, ?e, "Could not decode non-UTF-8 blob content");
+            Ok(None)
+        }
+    }
+}
+
+/// Find the string literals passed as the first argument of `load` statements.
+fn load_statement_strings(content: &str) -> Vec<&str> {
+    lazy_static! {
+        static ref RE: Regex = Regex::new(
+            r#"(?x)
+# Literal "load".
+load
+\s*?
+
+# Open parenthesis.
+\(
+\s*?
+
+# String literal enclosed in quotes.
+(?:
+    "( [[:print:]--"]*? )"
+  | '( [[:print:]--']*? )'
+)
+
+# Either a closing parenthesis or a comma to start the argument list.
+\s*?
+(?:
+  ,
+| \)
+)
+"#
+        )
+        .unwrap();
+    }
+
+    RE.captures_iter(content)
+        .map(|cap| cap.get(1).or_else(|| cap.get(2)).unwrap().as_str())
+        .collect()
+}
+
+/// Extract the labels from the `load` statements in the given content,
+/// exactly as written.
+pub(super) fn extract_load_statements(content: &str) -> BTreeSet<Label> {
+    let mut result = BTreeSet::new();
+    for value in load_statement_strings(content) {
+        let label: Label = match value.parse() {
+            Ok(label) => label,
+            Err(e) => {
+                warn!(?e, "Failed to parse label in load statement");
+                continue;
+            }
+        };
+        result.insert(label);
+    }
+    result
+}
+
+/// Extract the labels from the `load` statements of a file in the given
+/// package, resolving package-relative labels like `:foo.bzl`.
+fn extract_load_statements_in_package(
+    content: &str,
+    package_components: &[String],
+) -> BTreeSet<Label> {
+    let mut result = BTreeSet::new();
+    for value in load_statement_strings(content) {
+        let label = match value.strip_prefix(':') {
+            Some(name) => file_label(package_components.iter().map(String::as_str), name),
+            None => match value.parse() {
+                Ok(label) => normalize(label),
+                Err(e) => {
+                    warn!(?e, "Failed to parse label in load statement");
+                    continue;
+                }
+            },
+        };
+        result.insert(label);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use focus_testing::ScratchGitRepo;
+
+    use super::*;
+
+    #[test]
+    fn test_extract_load_statements() -> anyhow::Result<()> {
+        let content = r#"
+load("//foo/bar:baz.bzl")
+load   (
+    '//foo/qux:qux.bzl'
+
+,    qux = 'grault')
+"#;
+        let labels = extract_load_statements(content);
+        insta::assert_debug_snapshot!(labels, @r###"
+        {
+            Label("//foo/bar:baz.bzl"),
+            Label("//foo/qux:qux.bzl"),
+        }
+        "###);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_graph() -> anyhow::Result<()> {
+        let temp = tempfile::tempdir()?;
+        let fix = ScratchGitRepo::new_static_fixture(temp.path())?;
+        for (path, content) in [
+            ("WORKSPACE", r#"load("//:defs.bzl", "defs")"#),
+            ("defs.bzl", ""),
+            ("macro/BUILD", ""),
+            (
+                "macro/macro.bzl",
+                r#"load(":inner.bzl", "inner")
+load("@rules_foo//foo:foo.bzl", "foo")"#,
+            ),
+            ("macro/inner.bzl", r#"load("//missing:missing.bzl", "x")"#),
+            ("package1/BUILD.bazel", r#"load("//macro:macro.bzl", "m")"#),
+            ("package1/BUILD", ""),
+        ] {
+            fix.write_file(path, content)?;
+            fix.add_file(path)?;
+        }
+        let head_oid = fix.commit_all("Wrote files")?;
+        let repo = fix.repo()?;
+        let tree = repo.find_commit(head_oid)?.tree()?;
+
+        let build_file =
+            LoadGraph::build_file_for_package(&tree, &["package1".to_string()]).unwrap();
+        assert_eq!(build_file.to_string(), "//package1:BUILD.bazel");
+        assert_eq!(
+            LoadGraph::build_file_for_package(&tree, &["missing".to_string()]),
+            None
+        );
+
+        let graph = LoadGraph::from_roots(&repo, &tree, [build_file.clone()])?;
+        let transitive_loads: Vec<String> = graph
+            .transitive_loads(&build_file)
+            .iter()
+            .map(|label| label.to_string())
+            .collect();
+        assert_eq!(
+            transitive_loads,
+            vec![
+                "//macro:inner.bzl",
+                "//macro:macro.bzl",
+                "//missing:missing.bzl",
+                "@rules_foo//foo:foo.bzl",
+            ]
+        );
+        let missing: Vec<String> = graph
+            .missing_files()
+            .iter()
+            .map(|label| label.to_string())
+            .collect();
+        assert_eq!(missing, vec!["//missing:missing.bzl"]);
+
+        let graph = LoadGraph::for_tree(&repo, &tree)?;
+        let workspace = file_label(std::iter::empty::<&str>(), "WORKSPACE");
+        assert_eq!(
+            graph
+                .direct_loads(&workspace)
+                .unwrap()
+                .iter()
+                .map(|label| label.to_string())
+                .collect::<Vec<_>>(),
+            vec!["//:defs.bzl"]
+        );
+        assert!(graph
+            .edges()
+            .contains_key(&file_label(["package1"].into_iter(), "BUILD")));
+
+        Ok(())
+    }
+}
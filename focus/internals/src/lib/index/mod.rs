@@ -10,17 +10,25 @@
 mod churn;
 mod content_hash;
 mod dependency_graph;
+mod graph_export;
+mod load_graph;
 mod object_database;
+mod rdeps;
+mod verify;
 
 pub use churn::print_churn_stats;
-pub use content_hash::{content_hash, ContentHash, HashContext};
+pub use content_hash::{content_hash, content_hash_inputs, ContentHash, HashContext};
 pub use dependency_graph::{
     get_files_to_materialize, update_object_database_from_resolution, DependencyKey,
     DependencyValue, PathsToMaterializeResult,
 };
+pub use graph_export::{DependencyGraph, GraphFormat};
+pub use load_graph::LoadGraph;
 pub use object_database::{
     ObjectDatabase, RocksDBCache, RocksDBMemoizationCacheExt, SimpleGitOdb, FUNCTION_ID,
 };
+pub use rdeps::{find_dependent_projects, rdeps_query_path, DependentProjects};
+pub use verify::{find_stored_keys, verify_object_database, VerificationMismatch};
 
 #[cfg(test)]
 pub use object_database::testing;
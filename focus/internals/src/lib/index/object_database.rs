@@ -32,6 +32,9 @@ pub trait ObjectDatabase {
         value: DependencyValue,
     ) -> anyhow::Result<()>;
 
+    /// Remove the entry with the provided key hash, if any.
+    fn remove(&self, hash: &ContentHash) -> anyhow::Result<()>;
+
     /// Clear all entries.
     fn clear(&self) -> anyhow::Result<()>;
 }
@@ -87,6 +90,12 @@ impl<T: Cache> ObjectDatabase for T {
         Ok(())
     }
 
+    fn remove(&self, hash: &ContentHash) -> anyhow::Result<()> {
+        debug!(?hash, "Removing entry from object database");
+        self.delete(*FUNCTION_ID, hash.0)?;
+        Ok(())
+    }
+
     fn clear(&self) -> anyhow::Result<()> {
         self.clear()?;
         Ok(())
@@ -95,15 +104,19 @@ impl<T: Cache> ObjectDatabase for T {
 
 /// Helper functions to use [`RocksDBMemoizationCache`] as an [`ObjectDatabase`].
 pub trait RocksDBMemoizationCacheExt {
-    /// Create the cache in a fixed directory under `.git`.
-    fn new(repo: &git2::Repository) -> Self;
+    /// Open the cache in a fixed directory under `.git`, waiting briefly if
+    /// another process has it open.
+    fn new(repo: &git2::Repository) -> anyhow::Result<Self>
+    where
+        Self: Sized;
 }
 
 const ROCKSDB_CACHE_TTL: Duration = Duration::from_secs(3600 * 24 * 14);
 
 impl RocksDBMemoizationCacheExt for RocksDBCache {
-    fn new(repo: &git2::Repository) -> RocksDBCache {
-        let rocksdb_path = repo.path().join("focus/focus-index-rocks-db");
+    fn new(repo: &git2::Repository) -> anyhow::Result<RocksDBCache> {
+        // Linked worktrees share the index with the repo they were added to.
+        let rocksdb_path = repo.commondir().join("focus/focus-index-rocks-db");
         let span = info_span!("Opening index database");
         let _guard = span.enter();
         RocksDBCache::open_with_ttl(rocksdb_path, ROCKSDB_CACHE_TTL)
@@ -180,11 +193,36 @@ pub mod testing {
             Ok(())
         }
 
+        fn remove(&self, hash: &ContentHash) -> anyhow::Result<()> {
+            self.entries.lock().expect("poisoned mutex").remove(hash);
+            Ok(())
+        }
+
         fn clear(&self) -> anyhow::Result<()> {
             self.entries.lock().unwrap().clear();
             Ok(())
         }
     }
+
+    /// The key for the Bazel package containing the given label.
+    pub fn package(label: &str) -> anyhow::Result<DependencyKey> {
+        let label: crate::target::Label = label.parse()?;
+        Ok(DependencyKey::BazelPackage(label))
+    }
+
+    /// Call `f` with a hash context for a scratch repository containing only
+    /// a `WORKSPACE` file and an empty in-memory object database.
+    pub fn with_scratch_index(
+        f: impl FnOnce(&HashContext, &HashMapOdb) -> anyhow::Result<()>,
+    ) -> anyhow::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let fix = focus_testing::ScratchGitRepo::new_static_fixture(temp_dir.path())?;
+        fix.write_and_commit_file("WORKSPACE", "", "add WORKSPACE")?;
+        let repo = fix.repo()?;
+        let head_tree = repo.head()?.peel_to_tree()?;
+        let ctx = HashContext::new(&repo, &head_tree)?;
+        f(&ctx, &HashMapOdb::new())
+    }
 }
 
 /// Simple object database which stores key-value pairs in the same repository
@@ -291,6 +329,40 @@ impl ObjectDatabase for SimpleGitOdb<'_> {
         Ok(())
     }
 
+    fn remove(&self, hash: &ContentHash) -> anyhow::Result<()> {
+        let tree = match self.repo.find_reference(Self::REF_NAME) {
+            Ok(reference) => reference
+                .peel_to_tree()
+                .context("peeling kv tree reference")?,
+            Err(e) if e.code() == git2::ErrorCode::NotFound => return Ok(()),
+            Err(e) => return Err(e.into()),
+        };
+
+        let ContentHash(key_oid) = hash;
+        let key_name = key_oid.to_string();
+        if tree.get_name(&key_name).is_none() {
+            return Ok(());
+        }
+
+        let mut kv_tree = self
+            .repo
+            .treebuilder(Some(&tree))
+            .context("initializing TreeBuilder from kv tree reference")?;
+        kv_tree
+            .remove(&key_name)
+            .context("removing entry from tree")?;
+        let kv_tree_oid = kv_tree.write().context("writing new tree")?;
+        self.repo
+            .reference(
+                Self::REF_NAME,
+                kv_tree_oid,
+                true,
+                &format!("removing key hash {}", hash),
+            )
+            .context("updating reference")?;
+        Ok(())
+    }
+
     fn clear(&self) -> anyhow::Result<()> {
         match self.repo.find_reference(Self::REF_NAME) {
             Ok(mut reference) => {
@@ -352,6 +424,10 @@ mod tests {
         odb.put(&ctx, &key, value.clone())?;
         assert_eq!(odb.get(&ctx, &key)?.1, Some(value));
 
+        let hash = content_hash(&ctx, &key)?;
+        odb.remove(&hash)?;
+        assert!(odb.get(&ctx, &key)?.1.is_none());
+
         Ok(())
     }
 }
@@ -0,0 +1,238 @@
+// Copyright 2022 Twitter, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use tracing::debug;
+
+use crate::{
+    index::{
+        get_files_to_materialize, DependencyKey, HashContext, ObjectDatabase,
+        PathsToMaterializeResult,
+    },
+    model::selection::{resolve_targets_for_project, Project},
+    target::{Label, Target},
+};
+
+/// The projects whose resolved outlines contain a given path.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DependentProjects {
+    /// The names of the projects whose outlines contain the path.
+    pub projects: BTreeSet<String>,
+
+    /// The names of the projects which could not be checked because the
+    /// object database is missing entries for them.
+    pub unresolved_projects: BTreeSet<String>,
+}
+
+/// Convert a reverse-dependency query into the repository path it refers to.
+/// The query may be a path relative to the repository root, a `directory:`
+/// target, or a Bazel label (optionally prefixed with `bazel:`), in which case
+/// the path of its package is used.
+pub fn rdeps_query_path(query: &str) -> anyhow::Result<PathBuf> {
+    let target = if query.starts_with("//") || query.starts_with('@') {
+        Target::Bazel(
+            query
+                .parse()
+                .with_context(|| format!("Parsing label {query}"))?,
+        )
+    } else if query.contains(':') {
+        Target::try_from(query).with_context(|| format!("Parsing target {query}"))?
+    } else {
+        Target::Directory(query.to_owned())
+    };
+
+    match target {
+        Target::Bazel(Label {
+            external_repository: Some(_),
+            ..
+        }) => anyhow::bail!("Labels in external repositories are not part of any outline"),
+        Target::Bazel(Label {
+            external_repository: None,
+            path_components,
+            target_name: _,
+        }) => Ok(path_components
+            .into_iter()
+            .filter(|component| !component.is_empty())
+            .collect()),
+        Target::Directory(path) => Ok(Path::new(path.trim_end_matches('/')).to_owned()),
+    }
+}
+
+/// Find the projects whose resolved outlines at the commit in `ctx` contain
+/// `path`, using only the entries in the object database.
+///
+/// An outline contains a path if it materializes the path itself, a directory
+/// above it, or anything beneath it. The root package (an empty path) only
+/// contains itself, since materializing it doesn't check out the whole
+/// repository.
+pub fn find_dependent_projects<'a>(
+    ctx: &HashContext,
+    odb: &dyn ObjectDatabase,
+    projects: impl IntoIterator<Item = &'a Project>,
+    available_subprojects: &HashMap<String, Project>,
+    path: &Path,
+) -> anyhow::Result<DependentProjects> {
+    let mut result = DependentProjects::default();
+    for project in projects {
+        let targets = resolve_targets_for_project(vec![project.clone()], available_subprojects)?;
+        let dep_keys: HashSet<DependencyKey> =
+            targets.into_iter().map(DependencyKey::from).collect();
+        match get_files_to_materialize(ctx, odb, dep_keys)? {
+            PathsToMaterializeResult::Ok {
+                seen_keys: _,
+                paths,
+            } => {
+                if paths
+                    .iter()
+                    .any(|materialized| outline_path_contains(materialized, path))
+                {
+                    result.projects.insert(project.name.clone());
+                }
+            }
+
+            PathsToMaterializeResult::MissingKeys {
+                missing_keys,
+                seen_keys: _,
+            } => {
+                debug!(project = ?project.name, ?missing_keys, "Missing keys for project");
+                result.unresolved_projects.insert(project.name.clone());
+            }
+        }
+    }
+    Ok(result)
+}
+
+fn outline_path_contains(materialized: &Path, path: &Path) -> bool {
+    let is_root = |path: &Path| path.as_os_str().is_empty();
+    if is_root(materialized) || is_root(path) {
+        return is_root(materialized) && is_root(path);
+    }
+    path.starts_with(materialized) || materialized.starts_with(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use maplit::{btreeset, hashmap};
+
+    use crate::index::{
+        testing::{package, with_scratch_index},
+        DependencyValue,
+    };
+
+    use super::*;
+
+    fn project(name: &str, targets: &[&str]) -> Project {
+        Project {
+            name: name.to_owned(),
+            targets: targets.iter().map(|target| target.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_rdeps_query_path() -> anyhow::Result<()> {
+        assert_eq!(rdeps_query_path("foo/bar/")?, PathBuf::from("foo/bar"));
+        assert_eq!(rdeps_query_path("directory:foo")?, PathBuf::from("foo"));
+        assert_eq!(rdeps_query_path("//foo/bar:baz")?, PathBuf::from("foo/bar"));
+        assert_eq!(rdeps_query_path("bazel://foo/...")?, PathBuf::from("foo"));
+        assert_eq!(rdeps_query_path("//:baz")?, PathBuf::from(""));
+        assert!(rdeps_query_path("@foo//bar:baz").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_dependent_projects() -> anyhow::Result<()> {
+        with_scratch_index(|ctx, odb| {
+            let foo = package("//foo:foo")?;
+            let bar = package("//bar/baz:baz")?;
+            odb.put(
+                ctx,
+                &foo,
+                DependencyValue::PackageInfo {
+                    deps: btreeset! { bar.clone() },
+                },
+            )?;
+            odb.put(
+                ctx,
+                &bar,
+                DependencyValue::PackageInfo { deps: btreeset! {} },
+            )?;
+
+            let projects = hashmap! {
+                "uses_foo".to_owned() => project("uses_foo", &["bazel://foo:foo"]),
+                "uses_bar".to_owned() => project("uses_bar", &["bazel://bar/baz:baz"]),
+                "uses_qux".to_owned() => project("uses_qux", &["bazel://qux:qux"]),
+                "uses_dir".to_owned() => project("uses_dir", &["directory:docs"]),
+            };
+
+            let result = find_dependent_projects(
+                ctx,
+                odb,
+                projects.values(),
+                &projects,
+                Path::new("bar/baz/file.txt"),
+            )?;
+            assert_eq!(
+                result,
+                DependentProjects {
+                    projects: btreeset! { "uses_foo".to_owned(), "uses_bar".to_owned() },
+                    unresolved_projects: btreeset! { "uses_qux".to_owned() },
+                }
+            );
+
+            let result =
+                find_dependent_projects(ctx, odb, projects.values(), &projects, Path::new("docs"))?;
+            assert_eq!(result.projects, btreeset! { "uses_dir".to_owned() });
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_find_dependent_projects_of_root_package() -> anyhow::Result<()> {
+        with_scratch_index(|ctx, odb| {
+            let root = package("//:target")?;
+            let foo = package("//foo:foo")?;
+            odb.put(
+                ctx,
+                &root,
+                DependencyValue::PackageInfo { deps: btreeset! {} },
+            )?;
+            odb.put(
+                ctx,
+                &foo,
+                DependencyValue::PackageInfo { deps: btreeset! {} },
+            )?;
+
+            let projects = hashmap! {
+                "uses_root".to_owned() => project("uses_root", &["bazel://:target"]),
+                "uses_foo".to_owned() => project("uses_foo", &["bazel://foo:foo"]),
+            };
+
+            let result = find_dependent_projects(
+                ctx,
+                odb,
+                projects.values(),
+                &projects,
+                &rdeps_query_path("//:target")?,
+            )?;
+            assert_eq!(result.projects, btreeset! { "uses_root".to_owned() });
+
+            let result = find_dependent_projects(
+                ctx,
+                odb,
+                projects.values(),
+                &projects,
+                Path::new("foo/file.txt"),
+            )?;
+            assert_eq!(result.projects, btreeset! { "uses_foo".to_owned() });
+
+            Ok(())
+        })
+    }
+}
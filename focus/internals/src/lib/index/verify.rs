@@ -0,0 +1,176 @@
+// Copyright 2022 Twitter, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::collections::{BTreeSet, HashSet};
+
+use tracing::debug;
+
+use super::{
+    content_hash_inputs, ContentHash, DependencyKey, DependencyValue, HashContext, ObjectDatabase,
+};
+use crate::target_resolver::ResolutionResult;
+
+/// A [`DependencyValue`] stored in the object database which does not agree
+/// with the value produced by resolving its key again.
+#[derive(Clone, Debug)]
+pub struct VerificationMismatch {
+    /// The key which was resolved.
+    pub key: DependencyKey,
+
+    /// The content hash of the key, under which the stored value is found.
+    pub hash: ContentHash,
+
+    /// The value found in the object database.
+    pub stored_value: DependencyValue,
+
+    /// The value produced by resolving the key again, if the resolution
+    /// produced one at all.
+    pub resolved_value: Option<DependencyValue>,
+
+    /// The direct inputs to the content hash of the key, and their hashes.
+    pub hash_inputs: Vec<(DependencyKey, ContentHash)>,
+}
+
+/// Find the keys which have entries in the object database, starting from the
+/// provided keys and following the dependencies of the stored values.
+pub fn find_stored_keys(
+    ctx: &HashContext,
+    odb: &dyn ObjectDatabase,
+    dep_keys: HashSet<DependencyKey>,
+) -> anyhow::Result<BTreeSet<DependencyKey>> {
+    let mut stored_keys = BTreeSet::new();
+    let mut seen_keys = HashSet::new();
+    let mut dep_keys: Vec<DependencyKey> = dep_keys.into_iter().collect();
+    while let Some(dep_key) = dep_keys.pop() {
+        if !seen_keys.insert(dep_key.clone()) {
+            continue;
+        }
+
+        match &dep_key {
+            DependencyKey::BazelPackage(_) => {}
+
+            // These keys are never stored in the object database.
+            DependencyKey::BazelBuildFile(_)
+            | DependencyKey::Path(_)
+            | DependencyKey::DummyForTesting(_) => continue,
+        }
+
+        let (hash, dep_value) = odb.get(ctx, &dep_key)?;
+        match dep_value {
+            Some(DependencyValue::PackageInfo { deps }) => {
+                dep_keys.extend(deps);
+                stored_keys.insert(dep_key);
+            }
+            Some(DependencyValue::Path { .. } | DependencyValue::DummyForTesting(_)) => {
+                stored_keys.insert(dep_key);
+            }
+            None => {
+                debug!(?dep_key, ?hash, "No stored value for key");
+            }
+        }
+    }
+    Ok(stored_keys)
+}
+
+/// Compare the stored values of the provided keys with the values in a fresh
+/// resolution of those keys, returning those which differ.
+pub fn verify_object_database(
+    ctx: &HashContext,
+    odb: &dyn ObjectDatabase,
+    dep_keys: &BTreeSet<DependencyKey>,
+    resolution_result: &ResolutionResult,
+) -> anyhow::Result<Vec<VerificationMismatch>> {
+    let mut mismatches = Vec::new();
+    for dep_key in dep_keys {
+        let (hash, stored_value) = odb.get(ctx, dep_key)?;
+        let stored_value = match stored_value {
+            Some(stored_value) => stored_value,
+            None => {
+                debug!(?dep_key, ?hash, "No stored value to verify");
+                continue;
+            }
+        };
+
+        let resolved_value = resolution_result.package_deps.get(dep_key);
+        if resolved_value == Some(&stored_value) {
+            continue;
+        }
+
+        mismatches.push(VerificationMismatch {
+            key: dep_key.clone(),
+            hash,
+            stored_value,
+            resolved_value: resolved_value.cloned(),
+            hash_inputs: content_hash_inputs(ctx, dep_key)?,
+        });
+    }
+    Ok(mismatches)
+}
+
+#[cfg(test)]
+mod tests {
+    use maplit::{btreemap, btreeset, hashset};
+
+    use crate::index::testing::{package, with_scratch_index};
+
+    use super::*;
+
+    #[test]
+    fn test_verify_object_database() -> anyhow::Result<()> {
+        with_scratch_index(|ctx, odb| {
+            let foo = package("//foo:foo")?;
+            let bar = package("//bar:bar")?;
+            let baz = package("//baz:baz")?;
+            let qux = package("//qux:qux")?;
+            odb.put(
+                ctx,
+                &foo,
+                DependencyValue::PackageInfo {
+                    deps: btreeset! { bar.clone(), baz.clone() },
+                },
+            )?;
+            odb.put(
+                ctx,
+                &bar,
+                DependencyValue::PackageInfo { deps: btreeset! {} },
+            )?;
+
+            // `baz` has no stored value, and `qux` is not reachable from `foo`.
+            odb.put(
+                ctx,
+                &qux,
+                DependencyValue::PackageInfo { deps: btreeset! {} },
+            )?;
+            let stored_keys = find_stored_keys(ctx, odb, hashset! { foo.clone() })?;
+            assert_eq!(stored_keys, btreeset! { foo.clone(), bar.clone() });
+
+            let resolution_result = ResolutionResult {
+                paths: Default::default(),
+                package_deps: btreemap! {
+                    foo.clone() => DependencyValue::PackageInfo {
+                        deps: btreeset! { bar.clone() },
+                    },
+                    bar.clone() => DependencyValue::PackageInfo {
+                        deps: btreeset! {},
+                    },
+                },
+            };
+            let mismatches = verify_object_database(ctx, odb, &stored_keys, &resolution_result)?;
+            assert_eq!(mismatches.len(), 1);
+            let mismatch = &mismatches[0];
+            assert_eq!(mismatch.key, foo);
+            assert_eq!(
+                mismatch.resolved_value,
+                Some(DependencyValue::PackageInfo {
+                    deps: btreeset! { bar },
+                })
+            );
+            assert!(mismatch
+                .hash_inputs
+                .iter()
+                .any(|(key, _hash)| key == &DependencyKey::Path("foo".into())));
+
+            Ok(())
+        })
+    }
+}
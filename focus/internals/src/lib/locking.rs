@@ -7,8 +7,10 @@ use std::{path::Path, sync::Arc};
 
 use focus_util::{app::App, git_helper, lock_file::LockFile};
 
+/// Hold a lock in the repo's common git directory, so that it is shared with
+/// its linked worktrees, which share the index database and project cache too.
 pub fn hold_lock(repo_path: &Path, file_name: &Path, app: Arc<App>) -> Result<LockFile> {
-    let git_dir = git_helper::git_dir(repo_path, app)?;
+    let git_dir = git_helper::git_common_dir(repo_path, app)?;
     let focus_dir = git_dir.join(".focus");
     std::fs::create_dir_all(&focus_dir)?;
     let lock_path = focus_dir.join(file_name);
@@ -8,6 +8,8 @@ pub struct IndexConfig {
     /// Whether fetching is enabled
     pub enabled: bool,
 
-    /// Remote URL
+    /// Remote URL. Git remotes are used as-is; URLs prefixed with `objects+`
+    /// (e.g. `objects+https://cache.example.com/index`) are treated as plain
+    /// HTTP or file object stores.
     pub remote: String,
 }
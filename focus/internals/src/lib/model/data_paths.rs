@@ -14,6 +14,7 @@ pub struct DataPaths {
     pub data_dir: PathBuf,
     pub project_dir: PathBuf,
     pub selection_file: PathBuf,
+    pub peek_file: PathBuf,
 }
 
 impl DataPaths {
@@ -23,6 +24,7 @@ impl DataPaths {
         let data_dir = dot_focus_dir.join("focus");
         let project_dir = focus_dir.join("projects");
         let selection_file = dot_focus_dir.join("user.selection.json");
+        let peek_file = dot_focus_dir.join("peeks.json");
 
         let instance = Self {
             dot_focus_dir,
@@ -30,6 +32,7 @@ impl DataPaths {
             data_dir,
             project_dir,
             selection_file,
+            peek_file,
         };
         instance
             .ensure_directories_are_set_up_correctly()
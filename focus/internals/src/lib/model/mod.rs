@@ -4,6 +4,7 @@
 pub mod configuration;
 pub mod data_paths;
 pub mod outlining;
+pub mod peek;
 mod persistence;
 pub mod repo;
 pub mod selection;
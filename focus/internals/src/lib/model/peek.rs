@@ -0,0 +1,174 @@
+// Copyright 2022 Twitter, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::fmt::Display;
+use std::path::{Component, Path};
+
+use anyhow::{bail, Result};
+use chrono::{DateTime, Local, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::model::persistence::{load_model, store_model};
+use crate::target::{Target, TargetSet};
+
+/// A directory temporarily added to the outline, apart from the selection.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Peek {
+    /// The path of the directory relative to the root of the repo.
+    pub directory: String,
+
+    /// When the directory is removed again. It stays until the next sync after
+    /// this time.
+    pub expires_at: DateTime<Utc>,
+}
+
+impl Peek {
+    pub fn is_expired(&self, now: DateTime<Utc>) -> bool {
+        self.expires_at <= now
+    }
+
+    pub fn target(&self) -> Target {
+        Target::Directory(self.directory.clone())
+    }
+}
+
+/// The directories peeked at in a working tree, which are stored next to the
+/// selection.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Peeks {
+    pub peeks: Vec<Peek>,
+}
+
+impl Peeks {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        load_model(path)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        store_model(path, self)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.peeks.is_empty()
+    }
+
+    /// Peek at `directory` until `expires_at`. Peeking at a directory again
+    /// extends its expiry but never shortens it.
+    pub fn add(&mut self, directory: &str, expires_at: DateTime<Utc>) -> Result<()> {
+        let directory = normalize_directory(directory)?;
+        match self
+            .peeks
+            .iter_mut()
+            .find(|peek| peek.directory == directory)
+        {
+            Some(peek) => peek.expires_at = peek.expires_at.max(expires_at),
+            None => self.peeks.push(Peek {
+                directory,
+                expires_at,
+            }),
+        }
+        self.peeks.sort_by(|a, b| a.directory.cmp(&b.directory));
+        Ok(())
+    }
+
+    /// Remove the peeks which expired by `now`, returning them.
+    pub fn remove_expired(&mut self, now: DateTime<Utc>) -> Vec<Peek> {
+        let (expired, current) = self.peeks.drain(..).partition(|peek| peek.is_expired(now));
+        self.peeks = current;
+        expired
+    }
+
+    /// The directory targets to add to the outline.
+    pub fn targets(&self) -> TargetSet {
+        self.peeks.iter().map(Peek::target).collect()
+    }
+}
+
+impl Display for Peeks {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "--- Peeked directories ---")?;
+        let now = Utc::now();
+        for peek in self.peeks.iter() {
+            if peek.is_expired(now) {
+                writeln!(f, "{}   (expired, removed on the next sync)", peek.target())?;
+            } else {
+                writeln!(
+                    f,
+                    "{}   (until {})",
+                    peek.target(),
+                    peek.expires_at
+                        .with_timezone(&Local)
+                        .format("%Y-%m-%d %H:%M")
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Turn a directory given by the user, with or without the `directory:`
+/// scheme, into the form used by directory targets.
+fn normalize_directory(directory: &str) -> Result<String> {
+    let stripped = directory.strip_prefix("directory:").unwrap_or(directory);
+    let mut components = Vec::new();
+    for component in Path::new(stripped).components() {
+        match component {
+            Component::Normal(name) => match name.to_str() {
+                Some(name) => components.push(name),
+                None => bail!("{:?} is not valid UTF-8", directory),
+            },
+            Component::CurDir => (),
+            _ => bail!(
+                "{:?} must be a path relative to the root of the repo",
+                directory
+            ),
+        }
+    }
+    if components.is_empty() {
+        bail!("Peeking at the whole repo is not supported; use `focus off` instead");
+    }
+    Ok(components.join("/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::*;
+
+    #[test]
+    fn peeks_expire_and_keep_the_latest_expiry() -> Result<()> {
+        let now = Utc::now();
+        let mut peeks = Peeks::default();
+        peeks.add("./library/b/", now + Duration::hours(2))?;
+        peeks.add("directory:library/a", now - Duration::minutes(1))?;
+        peeks.add("library/b", now + Duration::hours(1))?;
+        assert_eq!(
+            peeks
+                .peeks
+                .iter()
+                .map(|peek| peek.directory.as_str())
+                .collect::<Vec<_>>(),
+            vec!["library/a", "library/b"]
+        );
+        assert_eq!(peeks.peeks[1].expires_at, now + Duration::hours(2));
+        assert!(peeks.add("../elsewhere", now).is_err());
+        assert!(peeks.add("/library", now).is_err());
+        assert!(peeks.add(".", now).is_err());
+
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("peeks.json");
+        peeks.save(&path)?;
+        let mut loaded = Peeks::load(&path)?;
+        assert_eq!(loaded, peeks);
+
+        let expired = loaded.remove_expired(now);
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].directory, "library/a");
+        assert_eq!(
+            loaded.targets(),
+            TargetSet::from([Target::Directory(String::from("library/b"))])
+        );
+        Ok(())
+    }
+}
@@ -53,6 +53,10 @@ use uuid::Uuid;
 
 const SPARSE_SYNC_REF_NAME: &str = "refs/focus/sync";
 const PREEMPTIVE_SYNC_REF_NAME: &str = "refs/focus/presync";
+// Linked worktrees keep their sync refs in git's per-worktree namespace so
+// that syncing one doesn't move the others'.
+const WORKTREE_SPARSE_SYNC_REF_NAME: &str = "refs/worktree/focus/sync";
+const WORKTREE_PREEMPTIVE_SYNC_REF_NAME: &str = "refs/worktree/focus/presync";
 const UUID_CONFIG_KEY: &str = "focus.uuid";
 const PREEMPTIVE_SYNC_ENABLED_CONFIG_KEY: &str = "focus.preemptive-sync.enabled";
 const PREEMPTIVE_SYNC_USER_IDLE_MILLIS_THRESHOLD_CONFIG_KEY: &str =
@@ -335,14 +339,32 @@ impl WorkingTree {
         }
     }
 
+    /// The name of the ref recording the commit the working tree was last synced to.
+    pub fn sparse_sync_ref_name(&self) -> &'static str {
+        if self.repo.is_worktree() {
+            WORKTREE_SPARSE_SYNC_REF_NAME
+        } else {
+            SPARSE_SYNC_REF_NAME
+        }
+    }
+
+    /// The name of the ref recording the commit last preemptively synced.
+    pub fn preemptive_sync_ref_name(&self) -> &'static str {
+        if self.repo.is_worktree() {
+            WORKTREE_PREEMPTIVE_SYNC_REF_NAME
+        } else {
+            PREEMPTIVE_SYNC_REF_NAME
+        }
+    }
+
     /// Reads the commit ID of the sparse sync ref (named SYNC_REF_NAME)
     pub fn read_sparse_sync_point_ref(&self) -> Result<Option<Oid>> {
-        self.read_ref(SPARSE_SYNC_REF_NAME)
+        self.read_ref(self.sparse_sync_ref_name())
     }
 
     /// Reads the commit ID of the preemptive sync ref (named SYNC_REF_NAME)
     pub fn read_preemptive_sync_point_ref(&self) -> Result<Option<Oid>> {
-        self.read_ref(PREEMPTIVE_SYNC_REF_NAME)
+        self.read_ref(self.preemptive_sync_ref_name())
     }
 
     pub fn primary_branch_name(&self) -> Result<String> {
@@ -357,7 +379,7 @@ impl WorkingTree {
 
     pub fn write_sync_point_ref_internal(&self, name: &str, commit_id: git2::Oid) -> Result<()> {
         self.repo
-            .reference(SPARSE_SYNC_REF_NAME, commit_id, true, "focus sync")
+            .reference(self.sparse_sync_ref_name(), commit_id, true, "focus sync")
             .with_context(|| {
                 format!(
                     "Recording sync point ref {} in repo {} to {}",
@@ -374,13 +396,13 @@ impl WorkingTree {
         let head_commit = self
             .get_head_commit()
             .context("Determining the HEAD commit")?;
-        self.write_sync_point_ref_internal(SPARSE_SYNC_REF_NAME, head_commit.id())
+        self.write_sync_point_ref_internal(self.sparse_sync_ref_name(), head_commit.id())
             .context("Updating the sparse sync ref")
     }
 
     /// Updates the sparse sync ref to the value of the HEAD ref (named SYNC_REF_NAME)
     pub fn write_preemptive_sync_point_ref(&self, commit_id: git2::Oid) -> Result<()> {
-        self.write_sync_point_ref_internal(PREEMPTIVE_SYNC_REF_NAME, commit_id)
+        self.write_sync_point_ref_internal(self.preemptive_sync_ref_name(), commit_id)
             .context("Updating the preemptive sync ref")
     }
 
@@ -397,7 +419,24 @@ impl WorkingTree {
         )
     }
 
+    /// Linked worktrees share the config of the repo they were added to, so
+    /// their UUIDs are kept in their own git dirs instead.
+    fn linked_worktree_uuid_path(&self) -> Option<PathBuf> {
+        if self.repo.is_worktree() {
+            Some(Repo::focus_git_dir_path(self.git_dir()).join("uuid"))
+        } else {
+            None
+        }
+    }
+
     pub fn read_uuid(&self) -> Result<Option<Uuid>> {
+        if let Some(path) = self.linked_worktree_uuid_path() {
+            return match std::fs::read_to_string(&path) {
+                Ok(uuid) => Ok(Some(Uuid::from_str(uuid.trim())?)),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+                Err(e) => Err(e).with_context(|| format!("Reading {}", path.display())),
+            };
+        }
         let config_snapshot = self.repo.config()?.snapshot()?;
         match config_snapshot.get_str(UUID_CONFIG_KEY) {
             Ok(uuid) => {
@@ -410,6 +449,15 @@ impl WorkingTree {
 
     pub fn write_generated_uuid(&self) -> Result<Uuid> {
         let uuid = Uuid::new_v4();
+        if let Some(path) = self.linked_worktree_uuid_path() {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)
+                    .with_context(|| format!("Creating {}", parent.display()))?;
+            }
+            fs::write(&path, uuid.to_string())
+                .with_context(|| format!("Writing {}", path.display()))?;
+            return Ok(uuid);
+        }
         self.repo
             .config()?
             .set_str(UUID_CONFIG_KEY, uuid.to_string().as_str())?;
@@ -830,7 +878,10 @@ impl Repo {
         };
 
         let outlining_tree_path = Self::outlining_tree_path(&git_dir);
-        let outlining_tree_git_dir = git_dir.join("worktrees").join(OUTLINING_TREE_NAME);
+        let outlining_tree_git_dir = repo
+            .commondir()
+            .join("worktrees")
+            .join(Self::outlining_tree_name(&git_dir));
         let outlining_tree: Option<Arc<dyn Outliner>> = if outlining_tree_path.is_dir() {
             Some(Arc::new(OutliningTreeOutliner::new(Arc::new(
                 WorkingTree::from_git_dir(&outlining_tree_git_dir)?,
@@ -871,7 +922,21 @@ impl Repo {
     }
 
     pub fn outlining_tree_path(git_dir: &Path) -> PathBuf {
-        Self::focus_git_dir_path(git_dir).join(OUTLINING_TREE_NAME)
+        Self::focus_git_dir_path(git_dir).join(Self::outlining_tree_name(git_dir))
+    }
+
+    /// The name of the outlining tree of the working tree with `git_dir`. Git
+    /// names worktrees after their directories, and the outlining trees of all
+    /// working trees are worktrees of the same repo, so each linked worktree's
+    /// outlining tree is named after it.
+    fn outlining_tree_name(git_dir: &Path) -> String {
+        // Only the git dirs of linked worktrees point to a common dir.
+        match git_dir.file_name() {
+            Some(name) if git_dir.join("commondir").is_file() => {
+                format!("{}-{}", OUTLINING_TREE_NAME, name.to_string_lossy())
+            }
+            _ => OUTLINING_TREE_NAME.to_owned(),
+        }
     }
 
     pub fn path(&self) -> &Path {
@@ -1231,7 +1296,10 @@ impl Repo {
     }
 
     fn outlining_tree_git_dir(&self) -> PathBuf {
-        self.git_dir.join("worktrees").join(OUTLINING_TREE_NAME)
+        self.repo
+            .commondir()
+            .join("worktrees")
+            .join(Self::outlining_tree_name(&self.git_dir))
     }
 
     pub fn create_working_tree(&self) -> Result<()> {
@@ -1272,9 +1340,9 @@ impl Repo {
         self.git_dir().join("focus")
     }
 
-    /// Returns $GIT_DIR/focus/project-cache
+    /// Returns $GIT_COMMON_DIR/focus/project-cache, which linked worktrees share
     pub fn project_cache_dir(&self) -> PathBuf {
-        self.git_focus_dir().join("project-cache")
+        Self::focus_git_dir_path(self.repo.commondir()).join("project-cache")
     }
 
     /// Write git config to support gitstats instrumentation.
@@ -0,0 +1,144 @@
+// Copyright 2022 Twitter, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::io::Write;
+
+use anyhow::{bail, Context, Result};
+use libflate::gzip::{Decoder, Encoder};
+use serde::{de::DeserializeOwned, Serialize};
+
+/// Compression level used when writing zstd-compressed exports. Exports are written once by CI and read by every client, so we favor ratio over speed.
+const ZSTD_COMPRESSION_LEVEL: i32 = 19;
+
+/// The encodings used to store manifests and exports on the remote. Each project cache version maps to exactly one format.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// Version 1: gzipped JSON.
+    GzippedJson,
+    /// Version 2: zstd-compressed CBOR.
+    ZstdCbor,
+}
+
+impl ExportFormat {
+    pub fn for_version(version: usize) -> Result<Self> {
+        match version {
+            1 => Ok(ExportFormat::GzippedJson),
+            2 => Ok(ExportFormat::ZstdCbor),
+            _ => bail!("Unsupported project cache version {}", version),
+        }
+    }
+
+    /// The file extension used for models stored in this format.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::GzippedJson => "json.gz",
+            ExportFormat::ZstdCbor => "cbor.zst",
+        }
+    }
+
+    pub fn encode<T>(&self, value: &T) -> Result<Vec<u8>>
+    where
+        T: Serialize,
+    {
+        match self {
+            ExportFormat::GzippedJson => {
+                let mut encoder = Encoder::new(Vec::new())?;
+                {
+                    let json = serde_json::to_vec(value)?;
+                    encoder.write_all(json.as_slice())?;
+                }
+                encoder.finish().into_result().map_err(anyhow::Error::new)
+            }
+            ExportFormat::ZstdCbor => {
+                let mut cbor = Vec::new();
+                ciborium::ser::into_writer(value, &mut cbor).context("Encoding CBOR failed")?;
+                zstd::stream::encode_all(cbor.as_slice(), ZSTD_COMPRESSION_LEVEL)
+                    .context("Compressing failed")
+            }
+        }
+    }
+
+    pub fn decode<T>(&self, bytes: &[u8]) -> Result<T>
+    where
+        T: DeserializeOwned,
+    {
+        match self {
+            ExportFormat::GzippedJson => {
+                let decoder = Decoder::new(bytes)?;
+                serde_json::from_reader(decoder).map_err(anyhow::Error::new)
+            }
+            ExportFormat::ZstdCbor => {
+                let cbor = zstd::stream::decode_all(bytes).context("Decompressing failed")?;
+                ciborium::de::from_reader(cbor.as_slice()).context("Decoding CBOR failed")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod testing {
+    use std::{collections::BTreeMap, path::PathBuf};
+
+    use crate::{
+        model::outlining::{Pattern, PatternSet},
+        project_cache::{Export, Value},
+    };
+
+    use super::*;
+
+    fn export_with_deep_paths() -> Export {
+        let mut items = BTreeMap::new();
+        for project in 0..20 {
+            let patterns: PatternSet = (0..200)
+                .map(|i| Pattern::Directory {
+                    precedence: i,
+                    path: PathBuf::from(format!(
+                        "src/main/scala/com/example/team_{}/service/module_{}",
+                        project, i
+                    )),
+                    recursive: i % 2 == 0,
+                })
+                .collect();
+            items.insert(
+                format!(
+                    "example.com/repo;optional-project-pattern-set:build-graph-hash=abcd:project=team_{};V2",
+                    project
+                ),
+                Value::OptionalProjectPatternSet(patterns),
+            );
+        }
+        Export {
+            shard_index: 0,
+            shard_count: 1,
+            items,
+        }
+    }
+
+    #[test]
+    fn test_formats_round_trip() -> Result<()> {
+        let export = export_with_deep_paths();
+        for format in [ExportFormat::GzippedJson, ExportFormat::ZstdCbor] {
+            let encoded = format.encode(&export)?;
+            let decoded: Export = format.decode(&encoded)?;
+            assert_eq!(decoded, export, "{:?} did not round trip", format);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_compact_format_is_smaller() -> Result<()> {
+        let export = export_with_deep_paths();
+        let json = ExportFormat::GzippedJson.encode(&export)?;
+        let cbor = ExportFormat::ZstdCbor.encode(&export)?;
+        assert!(cbor.len() < json.len());
+        Ok(())
+    }
+
+    #[test]
+    fn test_decoding_with_the_wrong_format_fails() -> Result<()> {
+        let export = export_with_deep_paths();
+        let json = ExportFormat::GzippedJson.encode(&export)?;
+        assert!(ExportFormat::ZstdCbor.decode::<Export>(&json).is_err());
+        Ok(())
+    }
+}
@@ -45,12 +45,11 @@ impl ProjectCacheBackend for HttpCacheBackend {
         tracing::debug!(url = ?url.as_str(), "GET");
         let mut buf = Vec::<u8>::new();
         {
-            let mut response = self
-                .client
-                .get(url)
-                .send()
-                .context("GET failed")?
-                .error_for_status()?;
+            let response = self.client.get(url.clone()).send().context("GET failed")?;
+            if response.status() == reqwest::StatusCode::NOT_FOUND {
+                return Err(remote::NotFound(url).into());
+            }
+            let mut response = response.error_for_status()?;
             tracing::debug!(status = ?response.status(), "OK");
             response
                 .read_to_end(&mut buf)
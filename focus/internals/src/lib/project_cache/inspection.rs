@@ -0,0 +1,425 @@
+// Copyright 2022 Twitter, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::str::FromStr;
+
+use anyhow::Result;
+use rocksdb::{IteratorMode, DB};
+
+use super::*;
+
+/// Summary of the content stored in the local project cache database for a single build graph.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct BuildGraphSummary {
+    pub build_graph_hash: Vec<u8>,
+    /// How many commits are known to map to this build graph.
+    pub commit_count: usize,
+    pub has_mandatory_patterns: bool,
+    pub optional_project_count: usize,
+    /// Total size of the stored keys and values in bytes.
+    pub size: usize,
+    pub imported: bool,
+    /// The shard count recorded in the remote manifest, if it was requested and could be fetched.
+    pub shard_count: Option<usize>,
+}
+
+/// Problems found while checking a remote manifest against its shards.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct VerificationReport {
+    /// The project cache version the remote content was stored with.
+    pub version: usize,
+    pub shard_count: usize,
+    pub mandatory_item_count: usize,
+    pub optional_item_count: usize,
+    pub problems: Vec<String>,
+}
+
+impl VerificationReport {
+    pub fn is_ok(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct GarbageCollectionResult {
+    /// Entries dropped because they were older than the retention period.
+    pub expired_entries: usize,
+    /// Import receipts removed because the content they vouch for is gone.
+    pub orphaned_receipts: usize,
+}
+
+/// Parse a raw database key, returning `None` if it belongs to another repository or to a cache version which can't be read.
+fn parse_database_key(identifier: &RepoIdentifier, key: &[u8]) -> Option<Key> {
+    let key = std::str::from_utf8(key).ok()?;
+    let key = key.strip_prefix(identifier.to_string().as_str())?;
+    let key = key.strip_prefix(';')?;
+    let key = READABLE_PROJECT_CACHE_VERSIONS
+        .iter()
+        .find_map(|version| key.strip_suffix(format!(";V{}", version).as_str()))?;
+    Key::from_str(key).ok()
+}
+
+/// Remove import receipts whose build graph has no mandatory patterns in the database, returning how many were removed.
+fn remove_orphaned_receipts(identifier: &RepoIdentifier, database: &DB) -> Result<usize> {
+    // A receipt without the mandatory patterns it was imported with would prevent the content from being fetched again.
+    let mut receipts = Vec::<(Box<[u8]>, Vec<u8>)>::new();
+    let mut build_graphs_with_content = HashSet::<Vec<u8>>::new();
+    for entry in database.iterator(IteratorMode::Start) {
+        let (raw_key, _value) = entry.context("Reading project cache entry failed")?;
+        match parse_database_key(identifier, &raw_key) {
+            Some(Key::ImportReceipt { build_graph_hash }) => {
+                receipts.push((raw_key, build_graph_hash))
+            }
+            Some(Key::MandatoryProjectPatternSet { build_graph_hash }) => {
+                build_graphs_with_content.insert(build_graph_hash);
+            }
+            _ => {}
+        }
+    }
+
+    let mut batch = WriteBatch::default();
+    for (raw_key, build_graph_hash) in receipts {
+        if !build_graphs_with_content.contains(&build_graph_hash) {
+            debug!(build_graph_hash = ?hex::encode(&build_graph_hash), "Removing orphaned import receipt");
+            batch.delete(raw_key);
+        }
+    }
+    let orphaned_receipts = batch.len();
+    database
+        .write(batch)
+        .context("Removing orphaned import receipts failed")?;
+    Ok(orphaned_receipts)
+}
+
+impl<'cache> ProjectCache<'cache> {
+    fn namespaced_key(&self, key: Key) -> NamespacedKey {
+        NamespacedKey {
+            repository: self.identifier.clone(),
+            underlying: key,
+            version: PROJECT_CACHE_VERSION,
+        }
+    }
+
+    /// Read a value from the local database without faulting it.
+    fn read_local(&self, key: &Key) -> Result<Option<Value>> {
+        match self.read_raw(key)? {
+            Some(value_slice) => Ok(Some(
+                serde_json::from_slice(&value_slice).context("Parsing value failed")?,
+            )),
+            None => Ok(None),
+        }
+    }
+
+    /// Summarize the content of the local database by build graph. If `with_manifests` is set, the shard count of each build graph is read from the remote manifest.
+    pub fn list(&self, with_manifests: bool) -> Result<Vec<BuildGraphSummary>> {
+        let mut summaries = BTreeMap::<Vec<u8>, BuildGraphSummary>::new();
+
+        for entry in self.database.iterator(IteratorMode::Start) {
+            let (raw_key, value) = entry.context("Reading project cache entry failed")?;
+            let key = match parse_database_key(&self.identifier, &raw_key) {
+                Some(key) => key,
+                None => continue,
+            };
+            let build_graph_hash = match key.build_graph_hash() {
+                Some(build_graph_hash) => build_graph_hash.clone(),
+                None => {
+                    match serde_json::from_slice::<Value>(&value).context("Parsing value failed")? {
+                        Value::BuildGraphHash { build_graph_hash } => build_graph_hash,
+                        other => bail!("Unexpected value {:?} for key '{}'", other, key),
+                    }
+                }
+            };
+
+            let summary = summaries
+                .entry(build_graph_hash.clone())
+                .or_insert_with(|| BuildGraphSummary {
+                    build_graph_hash,
+                    ..Default::default()
+                });
+            summary.size += raw_key.len() + value.len();
+            match key {
+                Key::CommitToBuildGraphHash { .. } => summary.commit_count += 1,
+                Key::MandatoryProjectPatternSet { .. } => summary.has_mandatory_patterns = true,
+                Key::OptionalProjectPatternSet { .. } => summary.optional_project_count += 1,
+                Key::ImportReceipt { .. } => summary.imported = true,
+            }
+        }
+
+        if with_manifests {
+            let backend = self.backend()?;
+            for summary in summaries.values_mut() {
+                match fetch_manifest(backend, &summary.build_graph_hash) {
+                    Ok((manifest, _version)) => summary.shard_count = Some(manifest.shard_count),
+                    Err(e) => debug!(
+                        build_graph_hash = ?hex::encode(&summary.build_graph_hash),
+                        ?e,
+                        "No manifest available"
+                    ),
+                }
+            }
+        }
+
+        Ok(summaries.into_values().collect())
+    }
+
+    /// Read the patterns stored for the mandatory projects (or the given optional project) of a build graph. If `remote` is set, the content is read from the remote rather than the local database.
+    pub fn show(
+        &self,
+        build_graph_hash: &Vec<u8>,
+        project_name: Option<&str>,
+        remote: bool,
+    ) -> Result<Option<PatternSet>> {
+        let key = match project_name {
+            Some(project_name) => Key::OptionalProjectPatternSet {
+                build_graph_hash: build_graph_hash.clone(),
+                project_name: project_name.to_owned(),
+            },
+            None => Key::MandatoryProjectPatternSet {
+                build_graph_hash: build_graph_hash.clone(),
+            },
+        };
+
+        let value = if remote {
+            let key_str: String = self.namespaced_key(key.clone()).try_into()?;
+            let (mut manifest, exports) = fetch_exports(self.backend()?, build_graph_hash)?;
+            manifest.mandatory_items.remove(&key_str).or_else(|| {
+                exports
+                    .into_iter()
+                    .find_map(|mut export| export.items.remove(&key_str))
+            })
+        } else {
+            self.read_local(&key)?
+        };
+
+        match value {
+            Some(Value::MandatoryProjectPatternSet(patterns))
+            | Some(Value::OptionalProjectPatternSet(patterns)) => Ok(Some(patterns)),
+            Some(other) => bail!("Unexpected value {:?} for key '{}'", other, key),
+            None => Ok(None),
+        }
+    }
+
+    /// Check that the remote manifest for the given build graph agrees with the shards stored alongside it.
+    pub fn verify(&self, build_graph_hash: &Vec<u8>) -> Result<VerificationReport> {
+        let backend = self.backend()?;
+        let (manifest, version) = fetch_manifest(backend, build_graph_hash).with_context(|| {
+            format!(
+                "Fetching manifest for build graph {} failed",
+                hex::encode(build_graph_hash)
+            )
+        })?;
+        let mut report = VerificationReport {
+            version,
+            shard_count: manifest.shard_count,
+            mandatory_item_count: manifest.mandatory_items.len(),
+            ..Default::default()
+        };
+
+        if manifest.shard_count == 0 {
+            report
+                .problems
+                .push(String::from("Manifest has a shard count of zero"));
+        }
+        if manifest.mandatory_items.is_empty() {
+            report.problems.push(String::from(
+                "Manifest contains no mandatory project patterns",
+            ));
+        }
+        for (key, value) in manifest.mandatory_items.iter() {
+            if let Some(problem) = self.check_item(build_graph_hash, key, value, true) {
+                report.problems.push(problem);
+            }
+        }
+
+        let mut seen_keys = BTreeSet::<String>::new();
+        for shard_index in 0..manifest.shard_count {
+            let export = match fetch_export(
+                backend,
+                build_graph_hash,
+                shard_index,
+                manifest.shard_count,
+                version,
+            ) {
+                Ok(export) => export,
+                Err(e) => {
+                    report.problems.push(format!("{:#}", e));
+                    continue;
+                }
+            };
+
+            if export.shard_index != shard_index || export.shard_count != manifest.shard_count {
+                report.problems.push(format!(
+                    "Shard {} of {} identifies itself as shard {} of {}",
+                    shard_index + 1,
+                    manifest.shard_count,
+                    export.shard_index + 1,
+                    export.shard_count
+                ));
+            }
+
+            for (key, value) in export.items.iter() {
+                report.optional_item_count += 1;
+                if let Some(problem) = self.check_item(build_graph_hash, key, value, false) {
+                    report.problems.push(problem);
+                }
+                if !seen_keys.insert(key.clone()) {
+                    report
+                        .problems
+                        .push(format!("Key '{}' appears in more than one shard", key));
+                }
+            }
+        }
+
+        Ok(report)
+    }
+
+    fn check_item(
+        &self,
+        build_graph_hash: &Vec<u8>,
+        key_str: &str,
+        value: &Value,
+        mandatory: bool,
+    ) -> Option<String> {
+        let key = match parse_database_key(&self.identifier, key_str.as_bytes()) {
+            Some(key) => key,
+            None => {
+                return Some(format!(
+                    "Key '{}' is malformed or belongs to another repository or cache version",
+                    key_str
+                ))
+            }
+        };
+
+        if key.build_graph_hash() != Some(build_graph_hash) {
+            return Some(format!(
+                "Key '{}' belongs to a different build graph",
+                key_str
+            ));
+        }
+
+        match (mandatory, &key, value) {
+            (
+                true,
+                Key::MandatoryProjectPatternSet { .. },
+                Value::MandatoryProjectPatternSet(_),
+            )
+            | (false, Key::OptionalProjectPatternSet { .. }, Value::OptionalProjectPatternSet(_)) => {
+                None
+            }
+            _ => Some(format!(
+                "Key '{}' has an unexpected kind or value type",
+                key_str
+            )),
+        }
+    }
+
+    /// Remove entries written to the local project cache database of the given repo more than `older_than` ago, along with any import receipts whose content is gone. This must not be called while a `ProjectCache` is open for the same repo.
+    pub fn collect_garbage(repo: &Repo, older_than: Duration) -> Result<GarbageCollectionResult> {
+        let identifier = RepoIdentifier::from(repo.underlying())?;
+        let (database, expired_entries) =
+            storage::open_database_and_expire(repo.project_cache_dir(), older_than)
+                .context("Expiring project cache entries")?;
+
+        let orphaned_receipts = remove_orphaned_receipts(&identifier, &database)?;
+
+        Ok(GarbageCollectionResult {
+            expired_entries,
+            orphaned_receipts,
+        })
+    }
+}
+
+#[cfg(test)]
+mod testing {
+    use super::*;
+
+    #[test]
+    fn test_parse_database_key() {
+        let identifier = RepoIdentifier {
+            host: String::from("github.com"),
+            name: String::from("twitter/focus"),
+        };
+        let key = Key::OptionalProjectPatternSet {
+            build_graph_hash: vec![0xab, 0xcd],
+            project_name: String::from("team_banzai/project_a"),
+        };
+        let key_str: String = NamespacedKey {
+            repository: identifier.clone(),
+            underlying: key.clone(),
+            version: PROJECT_CACHE_VERSION,
+        }
+        .try_into()
+        .unwrap();
+
+        assert_eq!(
+            parse_database_key(&identifier, key_str.as_bytes()),
+            Some(key)
+        );
+
+        let other_identifier = RepoIdentifier {
+            host: String::from("github.com"),
+            name: String::from("twitter/other"),
+        };
+        assert_eq!(
+            parse_database_key(&other_identifier, key_str.as_bytes()),
+            None
+        );
+
+        let older_version = key_str.replace(VERSION_KEY_SUFFIX.as_str(), ";V1");
+        assert_eq!(
+            parse_database_key(&identifier, older_version.as_bytes()),
+            Some(key)
+        );
+
+        let other_version = key_str.replace(VERSION_KEY_SUFFIX.as_str(), ";V0");
+        assert_eq!(
+            parse_database_key(&identifier, other_version.as_bytes()),
+            None
+        );
+    }
+
+    #[test]
+    fn test_remove_orphaned_receipts_of_older_versions() -> Result<()> {
+        let identifier = RepoIdentifier {
+            host: String::from("github.com"),
+            name: String::from("twitter/focus"),
+        };
+        let key_str = |underlying: Key, version: usize| -> Result<String> {
+            NamespacedKey {
+                repository: identifier.clone(),
+                underlying,
+                version,
+            }
+            .try_into()
+        };
+        let orphaned = key_str(
+            Key::ImportReceipt {
+                build_graph_hash: vec![0x01],
+            },
+            1,
+        )?;
+        let kept = key_str(
+            Key::ImportReceipt {
+                build_graph_hash: vec![0x02],
+            },
+            1,
+        )?;
+        let content = key_str(
+            Key::MandatoryProjectPatternSet {
+                build_graph_hash: vec![0x02],
+            },
+            PROJECT_CACHE_VERSION,
+        )?;
+
+        let temp_dir = tempfile::tempdir()?;
+        let database = storage::open_database(temp_dir.path(), Duration::from_secs(3600))?;
+        for key in [&orphaned, &kept, &content] {
+            database.put(key.as_bytes(), b"{}")?;
+        }
+
+        assert_eq!(remove_orphaned_receipts(&identifier, &database)?, 1);
+        assert!(database.get(orphaned.as_bytes())?.is_none());
+        assert!(database.get(kept.as_bytes())?.is_some());
+        Ok(())
+    }
+}
@@ -28,26 +28,46 @@ impl LocalCacheBackend {
             ));
         }
 
-        let path = endpoint
-            .to_file_path()
-            .map_err(|_| anyhow::anyhow!("Endpoint is missing path"))
-            .context("Converting the endpoint to a local path failed")?;
+        let path = Self::database_path(&endpoint)?;
         let database = storage::open_database(&path, Duration::from_secs(84600))
             .context("Opening local project cache database")?;
 
         Ok(Self { endpoint, database })
     }
+
+    /// Remove content stored more than `older_than` ago from the database at the given endpoint. Returns the number of entries removed.
+    pub fn collect_garbage(endpoint: &Url, older_than: Duration) -> Result<usize> {
+        let path = Self::database_path(endpoint)?;
+        let (_database, expired_entries) = storage::open_database_and_expire(&path, older_than)
+            .context("Expiring local project cache database entries")?;
+        Ok(expired_entries)
+    }
+
+    fn database_path(endpoint: &Url) -> Result<PathBuf> {
+        endpoint
+            .to_file_path()
+            .map_err(|_| anyhow::anyhow!("Endpoint is missing path"))
+            .context("Converting the endpoint to a local path failed")
+    }
 }
 
 impl ProjectCacheBackend for LocalCacheBackend {
     fn load_model(&self, url: Url) -> Result<Vec<u8>> {
         let key = url.path();
-        if let Ok(Some(repr)) = self.database.borrow().get(key) {
-            debug!(?key, "GET: Found");
-            Ok(repr)
-        } else {
-            warn!(?key, "GET: Missing");
-            Err(anyhow::anyhow!("Missing"))
+        match self
+            .database
+            .borrow()
+            .get(key)
+            .with_context(|| format!("Reading key '{}' failed", &key))?
+        {
+            Some(repr) => {
+                debug!(?key, "GET: Found");
+                Ok(repr)
+            }
+            None => {
+                warn!(?key, "GET: Missing");
+                Err(remote::NotFound(url).into())
+            }
         }
     }
 
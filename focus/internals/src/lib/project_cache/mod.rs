@@ -1,11 +1,15 @@
 // Copyright 2022 Twitter, Inc.
 // SPDX-License-Identifier: Apache-2.0
 
+mod encoding;
 mod http_cache_backend;
+mod inspection;
 mod local_cache_backend;
 
 mod remote;
+pub use encoding::ExportFormat;
 pub use http_cache_backend::HttpCacheBackend;
+pub use inspection::{BuildGraphSummary, GarbageCollectionResult, VerificationReport};
 pub use local_cache_backend::LocalCacheBackend;
 pub use remote::ProjectCacheBackend;
 mod model;
@@ -43,10 +47,14 @@ use crate::storage;
 
 use self::{
     model::NamespacedKey,
-    remote::{fetch_exports, store_export},
+    remote::{fetch_export, fetch_exports, fetch_manifest, store_export},
 };
 
-const PROJECT_CACHE_VERSION: usize = 1;
+/// The version of project cache content written by this client. It is embedded in keys and remote paths, and determines the `ExportFormat` used on the remote.
+const PROJECT_CACHE_VERSION: usize = 2;
+
+/// Versions of remote content this client can read, in order of preference.
+const READABLE_PROJECT_CACHE_VERSIONS: &[usize] = &[2, 1];
 
 lazy_static! {
     static ref VERSION_KEY_SUFFIX: String = format!(";V{}", PROJECT_CACHE_VERSION);
@@ -65,12 +73,26 @@ pub struct ProjectCache<'cache> {
     repo: &'cache Repo,
     identifier: RepoIdentifier,
     database: rocksdb::DB,
-    backend: Box<dyn ProjectCacheBackend>,
+    backend: Option<Box<dyn ProjectCacheBackend>>,
 }
 
 impl<'cache> ProjectCache<'cache> {
     /// Create a new project cache instance for the provided Repo.
     pub fn new(repo: &'cache Repo, endpoint: Url, app: Arc<App>) -> anyhow::Result<Self> {
+        let backend = Self::make_backend(&endpoint)?;
+        Self::open(repo, Some(backend), app)
+    }
+
+    /// Create a project cache instance for the provided Repo that only has access to the local database. Operations requiring a remote will fail.
+    pub fn local(repo: &'cache Repo, app: Arc<App>) -> anyhow::Result<Self> {
+        Self::open(repo, None, app)
+    }
+
+    fn open(
+        repo: &'cache Repo,
+        backend: Option<Box<dyn ProjectCacheBackend>>,
+        app: Arc<App>,
+    ) -> anyhow::Result<Self> {
         let identifier = RepoIdentifier::from(repo.underlying())?;
         let database = {
             let span = info_span!("Opening project cache");
@@ -83,7 +105,6 @@ impl<'cache> ProjectCache<'cache> {
             debug!(?database_path, "Database is open");
             result
         };
-        let backend = Self::make_backend(&endpoint)?;
         Ok(Self {
             app,
             repo,
@@ -93,6 +114,38 @@ impl<'cache> ProjectCache<'cache> {
         })
     }
 
+    /// Read the raw value stored locally for a key under any readable project cache version. Values are stored locally as JSON whatever the version, so a value found under an older version's key is copied to the current one rather than fetched again.
+    pub(crate) fn read_raw(&self, key: &Key) -> anyhow::Result<Option<Vec<u8>>> {
+        for &version in READABLE_PROJECT_CACHE_VERSIONS {
+            let key_str: String = NamespacedKey {
+                repository: self.identifier.clone(),
+                underlying: key.to_owned(),
+                version,
+            }
+            .try_into()?;
+            let value = self
+                .database
+                .get(key_str.as_bytes())
+                .with_context(|| format!("Reading value '{}' failed", &key_str))?;
+            if let Some(value) = value {
+                if version != PROJECT_CACHE_VERSION {
+                    let current_key_str: String = NamespacedKey {
+                        repository: self.identifier.clone(),
+                        underlying: key.to_owned(),
+                        version: PROJECT_CACHE_VERSION,
+                    }
+                    .try_into()?;
+                    debug!(key = ?key_str, "Upgrading value from an older project cache version");
+                    self.database
+                        .put(current_key_str.as_bytes(), &value)
+                        .with_context(|| format!("Writing value '{}' failed", current_key_str))?;
+                }
+                return Ok(Some(value));
+            }
+        }
+        Ok(None)
+    }
+
     /// Read a value from the cache, possibly faulting it using the optional callback.
     #[allow(clippy::type_complexity)] // Can't do anything about `fault_cb` because of the ref.
     pub(crate) fn read_or_fault(
@@ -107,11 +160,7 @@ impl<'cache> ProjectCache<'cache> {
         };
         let key_str: String = outer_key.clone().try_into()?;
 
-        if let Some(value_slice) = self
-            .database
-            .get_pinned(key_str.as_bytes())
-            .with_context(|| format!("Reading value '{}' failed", &key_str))?
-        {
+        if let Some(value_slice) = self.read_raw(key)? {
             // A value was found, deserialize it and return it.
             let value: Value =
                 serde_json::from_slice(&value_slice).context("Parsing value failed")?;
@@ -473,7 +522,7 @@ impl<'cache> ProjectCache<'cache> {
 
         store_export(
             self.repo,
-            self.backend.as_ref(),
+            self.backend()?,
             build_graph_hash,
             &manifest,
             &export,
@@ -484,8 +533,8 @@ impl<'cache> ProjectCache<'cache> {
     pub fn fetch(&self, build_graph_hash: &Vec<u8>) -> anyhow::Result<()> {
         // TODO: Expensive in terms of memory consumed. Figure out a better transaction / streaming strategy later.
         // TODO: We decode something to just encode it, which is wasteful. Fix that.
-        let (manifest, exports) = fetch_exports(self.backend.as_ref(), build_graph_hash)
-            .with_context(|| {
+        let (manifest, exports) =
+            fetch_exports(self.backend()?, build_graph_hash).with_context(|| {
                 anyhow::anyhow!(
                     "Fetching project cache data for build graph @ {} failed",
                     hex::encode(build_graph_hash)
@@ -548,8 +597,14 @@ impl<'cache> ProjectCache<'cache> {
 
     /// Determine if the given build graph hash is marked as having been imported
     pub fn is_imported(&self, build_graph_hash: &Vec<u8>) -> anyhow::Result<bool> {
-        let key_str: String = self.import_receipt_key(build_graph_hash).try_into()?;
-        Ok(self.database.get_pinned(&key_str)?.is_some())
+        let key = self.import_receipt_key(build_graph_hash).underlying;
+        Ok(self.read_raw(&key)?.is_some())
+    }
+
+    fn backend(&self) -> anyhow::Result<&dyn ProjectCacheBackend> {
+        self.backend
+            .as_deref()
+            .ok_or_else(|| anyhow::anyhow!("Project cache remote endpoint not configured"))
     }
 
     pub fn make_backend(endpoint: &Url) -> anyhow::Result<Box<dyn ProjectCacheBackend>> {
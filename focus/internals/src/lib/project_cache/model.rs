@@ -3,7 +3,7 @@
 
 use anyhow::Context;
 use serde::{Deserialize, Serialize};
-use std::{collections::BTreeMap, fmt::Display};
+use std::{collections::BTreeMap, fmt::Display, str::FromStr};
 use url::Url;
 
 use crate::model::outlining::PatternSet;
@@ -143,6 +143,63 @@ impl Display for Key {
     }
 }
 
+impl FromStr for Key {
+    type Err = anyhow::Error;
+
+    /// Parse a key from its `Display` representation.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, rest) = s
+            .split_once(':')
+            .ok_or_else(|| anyhow::anyhow!("Malformed key '{}'", s))?;
+        let decode = |field: &str, value: &str| -> anyhow::Result<Vec<u8>> {
+            let value = value
+                .strip_prefix(field)
+                .and_then(|value| value.strip_prefix('='))
+                .ok_or_else(|| anyhow::anyhow!("Key '{}' is missing field '{}'", s, field))?;
+            hex::decode(value).with_context(|| format!("Decoding field '{}' of key '{}'", field, s))
+        };
+
+        match kind {
+            "commit-to-build-graph-hash" => Ok(Key::CommitToBuildGraphHash {
+                commit_id: decode("commit", rest)?,
+            }),
+            "mandatory-project-pattern-set" => Ok(Key::MandatoryProjectPatternSet {
+                build_graph_hash: decode("build-graph-hash", rest)?,
+            }),
+            "optional-project-pattern-set" => {
+                let (build_graph_hash, project) = rest
+                    .split_once(':')
+                    .ok_or_else(|| anyhow::anyhow!("Key '{}' is missing a project", s))?;
+                let project_name = project
+                    .strip_prefix("project=")
+                    .ok_or_else(|| anyhow::anyhow!("Key '{}' is missing a project", s))?;
+                Ok(Key::OptionalProjectPatternSet {
+                    build_graph_hash: decode("build-graph-hash", build_graph_hash)?,
+                    project_name: project_name.to_owned(),
+                })
+            }
+            "import-reciept" => Ok(Key::ImportReceipt {
+                build_graph_hash: decode("build-graph-hash", rest)?,
+            }),
+            _ => Err(anyhow::anyhow!("Unknown key kind '{}'", kind)),
+        }
+    }
+}
+
+impl Key {
+    /// The build graph hash this key belongs to, if any.
+    pub fn build_graph_hash(&self) -> Option<&Vec<u8>> {
+        match self {
+            Key::CommitToBuildGraphHash { .. } => None,
+            Key::MandatoryProjectPatternSet { build_graph_hash }
+            | Key::OptionalProjectPatternSet {
+                build_graph_hash, ..
+            }
+            | Key::ImportReceipt { build_graph_hash } => Some(build_graph_hash),
+        }
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
 pub enum Value {
     BuildGraphHash {
@@ -168,10 +225,8 @@ impl TryInto<String> for NamespacedKey {
 
     fn try_into(self) -> Result<String, Self::Error> {
         Ok(format!(
-            "{};{}{}",
-            self.repository,
-            self.underlying,
-            super::VERSION_KEY_SUFFIX.as_str()
+            "{};{};V{}",
+            self.repository, self.underlying, self.version
         ))
     }
 }
@@ -225,6 +280,32 @@ mod testing {
         );
     }
 
+    #[test]
+    fn test_key_round_trips_through_display() {
+        let keys = vec![
+            Key::CommitToBuildGraphHash {
+                commit_id: vec![0xab, 0xcd],
+            },
+            Key::MandatoryProjectPatternSet {
+                build_graph_hash: vec![0x01, 0x02, 0x03],
+            },
+            Key::OptionalProjectPatternSet {
+                build_graph_hash: vec![0x01, 0x02, 0x03],
+                project_name: String::from("team_banzai/project_a"),
+            },
+            Key::ImportReceipt {
+                build_graph_hash: vec![0xff],
+            },
+        ];
+
+        for key in keys {
+            assert_eq!(Key::from_str(&key.to_string()).unwrap(), key);
+        }
+
+        assert!(Key::from_str("bogus").is_err());
+        assert!(Key::from_str("optional-project-pattern-set:build-graph-hash=0102").is_err());
+    }
+
     #[cfg(feature = "twttr")]
     #[test]
     fn test_repository_identifier_from_url_eliminates_ro_in_twttr_mode() {
@@ -3,12 +3,11 @@
 
 use std::{
     fs::File,
-    io::{BufRead, BufReader, Write},
+    io::{BufRead, BufReader},
 };
 
 use anyhow::{bail, Context, Result};
 
-use libflate::gzip::{Decoder, Encoder};
 use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
 use serde::{de::DeserializeOwned, Serialize};
 use url::Url;
@@ -20,8 +19,13 @@ pub struct RequestOptions {
     pub headers: Option<HeaderMap>,
 }
 
+/// Returned by a backend when there is nothing stored at the requested URL.
+#[derive(thiserror::Error, Debug)]
+#[error("Nothing stored at {0}")]
+pub struct NotFound(pub Url);
+
 pub trait ProjectCacheBackend {
-    // Fetch a model from the given URL and decode it from its JSON representation.
+    // Fetch a model from the given URL and decode it from its JSON representation. Fails with `NotFound` if there is nothing at the URL.
     fn load_model(&self, url: Url) -> Result<Vec<u8>>;
 
     fn store(&self, url: Url, request_options: &RequestOptions, value: Vec<u8>) -> Result<()>;
@@ -31,16 +35,21 @@ pub trait ProjectCacheBackend {
 
 pub trait ProjectCacheBackendInternal: Sync + Send {}
 
-fn manifest_path(backend: &dyn ProjectCacheBackend, build_graph_hash: &Vec<u8>) -> Url {
+fn manifest_path(
+    backend: &dyn ProjectCacheBackend,
+    build_graph_hash: &Vec<u8>,
+    version: usize,
+) -> Result<Url> {
     let mut url = backend.endpoint();
     let path = format!(
-        "{}/{}.manifest_v{}.json.gz",
+        "{}/{}.manifest_v{}.{}",
         url.path(),
         hex::encode(build_graph_hash).as_str(),
-        PROJECT_CACHE_VERSION,
+        version,
+        ExportFormat::for_version(version)?.extension(),
     );
     url.set_path(&path);
-    url
+    Ok(url)
 }
 
 fn export_path(
@@ -48,35 +57,37 @@ fn export_path(
     build_graph_hash: &Vec<u8>,
     shard_index: usize,
     shard_count: usize,
-) -> Url {
+    version: usize,
+) -> Result<Url> {
     let mut url = backend.endpoint();
     let path = format!(
-        "{}/{}_{}_{}.export_v{}.json.gz",
+        "{}/{}_{}_{}.export_v{}.{}",
         url.path(),
         hex::encode(build_graph_hash).as_str(),
         shard_index + 1,
         shard_count,
-        PROJECT_CACHE_VERSION,
+        version,
+        ExportFormat::for_version(version)?.extension(),
     );
     url.set_path(&path);
-    url
+    Ok(url)
 }
 
-/// Load and deserialize a model from the given backend.
+/// Load and deserialize a model stored with the given project cache version from the backend.
 fn load_model<T>(
     backend: &dyn ProjectCacheBackend,
     _request_options: &RequestOptions,
     url: Url,
+    version: usize,
 ) -> Result<T>
 where
     T: DeserializeOwned,
 {
-    let zipped = backend.load_model(url)?;
-    let decoder = Decoder::new(zipped.as_slice())?;
-    serde_json::from_reader(decoder).map_err(anyhow::Error::new)
+    let encoded = backend.load_model(url)?;
+    ExportFormat::for_version(version)?.decode(&encoded)
 }
 
-/// Serialize and store a model to the given backend.
+/// Serialize a model in the format of the current project cache version and store it to the given backend.
 fn store_model<T>(
     backend: &dyn ProjectCacheBackend,
     url: Url,
@@ -86,13 +97,97 @@ fn store_model<T>(
 where
     T: Serialize,
 {
-    let mut encoder = Encoder::new(Vec::new())?;
-    {
-        let json = serde_json::to_vec(value)?;
-        encoder.write_all(json.as_slice())?;
+    let encoded = ExportFormat::for_version(PROJECT_CACHE_VERSION)?.encode(value)?;
+    backend.store(url, request_options, encoded)
+}
+
+/// Rewrite keys stored by an older project cache version so that they match the keys of the current version. The content they refer to is the same; only the encoding on the remote differs.
+fn upgrade_keys(items: BTreeMap<String, Value>, version: usize) -> BTreeMap<String, Value> {
+    if version == PROJECT_CACHE_VERSION {
+        return items;
     }
-    let zipped = encoder.finish().into_result().map_err(anyhow::Error::new)?;
-    backend.store(url, request_options, zipped)
+
+    let old_suffix = format!(";V{}", version);
+    items
+        .into_iter()
+        .map(|(key, value)| match key.strip_suffix(old_suffix.as_str()) {
+            Some(stem) => (format!("{}{}", stem, VERSION_KEY_SUFFIX.as_str()), value),
+            None => (key, value),
+        })
+        .collect()
+}
+
+/// Fetch the manifest for the given build graph hash, trying each readable project cache version from newest to oldest. Older versions are only tried when a newer one has no manifest; any other failure is returned. Returns the manifest along with the version it was stored with; shards must be fetched with the same version.
+pub fn fetch_manifest(
+    backend: &dyn ProjectCacheBackend,
+    build_graph_hash: &Vec<u8>,
+) -> Result<(ExportManifest, usize)> {
+    let request_options = Default::default();
+    let mut last_error = None;
+    for &version in READABLE_PROJECT_CACHE_VERSIONS {
+        match load_model::<ExportManifest>(
+            backend,
+            &request_options,
+            manifest_path(backend, build_graph_hash, version)?,
+            version,
+        ) {
+            Ok(manifest) => {
+                if version != PROJECT_CACHE_VERSION {
+                    tracing::info!(
+                        ?version,
+                        "Using manifest from an older project cache version"
+                    );
+                }
+                let manifest = ExportManifest {
+                    mandatory_items: upgrade_keys(manifest.mandatory_items, version),
+                    ..manifest
+                };
+                return Ok((manifest, version));
+            }
+            Err(e) if e.downcast_ref::<NotFound>().is_some() => {
+                debug!(?version, ?e, "No manifest for version");
+                last_error = Some(e);
+            }
+            Err(e) => {
+                return Err(e).with_context(|| {
+                    format!(
+                        "Failed to fetch manifest for project cache version {}",
+                        version
+                    )
+                })
+            }
+        }
+    }
+
+    Err(last_error.unwrap_or_else(|| anyhow::anyhow!("No readable project cache versions")))
+}
+
+/// Fetch a single shard of the export for the given build graph hash stored with the given project cache version.
+pub fn fetch_export(
+    backend: &dyn ProjectCacheBackend,
+    build_graph_hash: &Vec<u8>,
+    shard_index: usize,
+    shard_count: usize,
+    version: usize,
+) -> Result<Export> {
+    let request_options = Default::default();
+    let export: Export = load_model(
+        backend,
+        &request_options,
+        export_path(backend, build_graph_hash, shard_index, shard_count, version)?,
+        version,
+    )
+    .with_context(|| {
+        format!(
+            "Failed to fetch shard {} of {}",
+            shard_index + 1,
+            shard_count
+        )
+    })?;
+    Ok(Export {
+        items: upgrade_keys(export.items, version),
+        ..export
+    })
 }
 
 /// Fetch all exports for the given build graph hash by reading the manifest and fetching each shard.
@@ -102,28 +197,18 @@ pub fn fetch_exports(
 ) -> Result<(ExportManifest, Vec<Export>)> {
     let span = tracing::info_span!("Fetching project cache data");
     let _guard = span.enter();
-    let request_options = Default::default();
     // Fetch the manifest to determine how many shards there are.
-    let manifest: ExportManifest = load_model(
-        backend,
-        &request_options,
-        manifest_path(backend, build_graph_hash),
-    )?;
+    let (manifest, version) = fetch_manifest(backend, build_graph_hash)?;
     let mut exports = Vec::<Export>::with_capacity(manifest.shard_count);
 
     for shard_index in 0..manifest.shard_count {
-        let export = load_model(
+        let export = fetch_export(
             backend,
-            &request_options,
-            export_path(backend, build_graph_hash, shard_index, manifest.shard_count),
-        )
-        .with_context(|| {
-            format!(
-                "Failed to fetch shard {} of {}",
-                shard_index + 1,
-                manifest.shard_count
-            )
-        })?;
+            build_graph_hash,
+            shard_index,
+            manifest.shard_count,
+            version,
+        )?;
         exports.push(export);
     }
 
@@ -164,14 +249,17 @@ pub fn store_export(
     manifest: &ExportManifest,
     export: &Export,
 ) -> Result<()> {
-    let manifest_path = manifest_path(backend, build_graph_hash);
+    let manifest_path = manifest_path(backend, build_graph_hash, PROJECT_CACHE_VERSION)?;
     let span = tracing::info_span!("Uploading project cache manifest");
     let _guard = span.enter();
 
     let request_options = request_options(repo)?;
-    if let Ok(existing_manifest) =
-        load_model::<ExportManifest>(backend, &request_options, manifest_path.clone())
-    {
+    if let Ok(existing_manifest) = load_model::<ExportManifest>(
+        backend,
+        &request_options,
+        manifest_path.clone(),
+        PROJECT_CACHE_VERSION,
+    ) {
         // If a manifest exists, make sure that it is identical.
         if manifest.ne(&existing_manifest) {
             tracing::warn!(new_manifest = ?manifest, ?existing_manifest, "Manifests differ");
@@ -191,9 +279,94 @@ pub fn store_export(
             build_graph_hash,
             export.shard_index,
             export.shard_count,
-        ),
+            PROJECT_CACHE_VERSION,
+        )?,
         &request_options,
         export,
     )
     .context("Failed to store export")
 }
+
+#[cfg(test)]
+mod testing {
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+
+    use super::*;
+
+    /// A backend holding content in memory, which fails every request once `broken` is set.
+    struct MemoryBackend {
+        content: RefCell<HashMap<String, Vec<u8>>>,
+        broken: bool,
+    }
+
+    impl ProjectCacheBackend for MemoryBackend {
+        fn load_model(&self, url: Url) -> Result<Vec<u8>> {
+            if self.broken {
+                bail!("Connection refused");
+            }
+            match self.content.borrow().get(url.path()) {
+                Some(value) => Ok(value.clone()),
+                None => Err(NotFound(url).into()),
+            }
+        }
+
+        fn store(&self, url: Url, _request_options: &RequestOptions, value: Vec<u8>) -> Result<()> {
+            self.content
+                .borrow_mut()
+                .insert(url.path().to_owned(), value);
+            Ok(())
+        }
+
+        fn endpoint(&self) -> Url {
+            Url::parse("memory:///cache").unwrap()
+        }
+    }
+
+    #[test]
+    fn test_fetch_manifest_falls_back_only_when_not_found() -> Result<()> {
+        let build_graph_hash = vec![0xcd];
+        let mut backend = MemoryBackend {
+            content: Default::default(),
+            broken: false,
+        };
+        let manifest = ExportManifest {
+            shard_count: 3,
+            mandatory_items: Default::default(),
+        };
+        backend.store(
+            manifest_path(&backend, &build_graph_hash, 1)?,
+            &Default::default(),
+            ExportFormat::for_version(1)?.encode(&manifest)?,
+        )?;
+
+        let (fetched, version) = fetch_manifest(&backend, &build_graph_hash)?;
+        assert_eq!(version, 1);
+        assert_eq!(fetched.shard_count, 3);
+
+        backend.broken = true;
+        let error = fetch_manifest(&backend, &build_graph_hash).unwrap_err();
+        assert!(format!("{:#}", error).contains("version 2: Connection refused"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_upgrade_keys_from_older_version() {
+        let value = Value::BuildGraphHash {
+            build_graph_hash: vec![0xab],
+        };
+        let mut items = BTreeMap::new();
+        items.insert(String::from("example.com/repo;some-key;V1"), value.clone());
+
+        let upgraded = upgrade_keys(items.clone(), 1);
+        assert_eq!(
+            upgraded.into_iter().collect::<Vec<_>>(),
+            vec![(
+                format!("example.com/repo;some-key{}", VERSION_KEY_SUFFIX.as_str()),
+                value
+            )]
+        );
+
+        assert_eq!(upgrade_keys(items.clone(), PROJECT_CACHE_VERSION), items);
+    }
+}
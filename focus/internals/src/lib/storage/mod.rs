@@ -2,7 +2,7 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use anyhow::Context;
-use rocksdb::{DBCompressionType, Options, DB};
+use rocksdb::{DBCompressionType, IteratorMode, Options, DB};
 use std::{path::Path, time::Duration};
 
 pub fn open_database(path: impl AsRef<Path>, ttl: Duration) -> anyhow::Result<DB> {
@@ -16,3 +16,25 @@ pub fn open_database(path: impl AsRef<Path>, ttl: Duration) -> anyhow::Result<DB
         .with_context(|| format!("Opening database at {}", path.display()))
         .map_err(|e| anyhow::anyhow!(e))
 }
+
+/// Count the entries in the database.
+pub fn count_entries(database: &DB) -> anyhow::Result<usize> {
+    let mut count = 0;
+    for entry in database.iterator(IteratorMode::Start) {
+        entry.context("Reading database entry")?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// Open the database at the given path and compact it fully. Since databases are opened with a TTL, compaction drops every entry written more than `ttl` ago. Returns the open database and the number of entries removed.
+pub fn open_database_and_expire(
+    path: impl AsRef<Path>,
+    ttl: Duration,
+) -> anyhow::Result<(DB, usize)> {
+    let database = open_database(path, ttl)?;
+    let before = count_entries(&database)?;
+    database.compact_range(None::<&[u8]>, None::<&[u8]>);
+    let after = count_entries(&database)?;
+    Ok((database, before.saturating_sub(after)))
+}
@@ -12,6 +12,7 @@ use anyhow::{bail, Result};
 use focus_util::sandbox_command::{SandboxCommand, SandboxCommandOutput};
 use tracing::{debug, info};
 
+use crate::index::LoadGraph;
 use crate::target::{Label, TargetName};
 
 use super::*;
@@ -95,16 +96,11 @@ impl IncrementalBazelResolver {
         let dep_labels = {
             let query = format!(
                 // Use `deps(...)` so that we preserve the actual names of the
-                // targets which were declared as dependencies, but also add in
-                // any `buildfiles` dependencies that might exist in the
-                // repository. This includes dependencies on `BUILD` or `.bzl`
-                // files (such as those `load`ed by the other `BUILD` files).
-                //
-                // We limit buildfile dependencies to only those in the
-                // repository, because `.bzl` files, etc., in external
-                // repositories are typically not supported, so querying them
-                // fails.
-                "deps({0}) union kind(rule, filter('^//', buildfiles(deps({0}))))",
+                // targets which were declared as dependencies. Dependencies on
+                // `BUILD` or `.bzl` files (such as those `load`ed by the other
+                // `BUILD` files) are read directly from the repository
+                // instead; see [`LoadGraph`].
+                "deps({0})",
                 bazel_common::make_set(labels.iter().copied())
             );
 
@@ -113,7 +109,7 @@ impl IncrementalBazelResolver {
 
             // Initialize `labels` to the set of labels that we were given.
             // It's possible that those labels will not appear in the
-            // `buildfiles(deps(...))` output if the only dependencies for those
+            // `deps(...)` output if the only dependencies for those
             // labels are in external repositories, so we need to make sure to
             // include them explicitly.
             let mut dep_labels: BTreeSet<Label> = labels.iter().copied().cloned().collect();
@@ -145,9 +141,19 @@ impl IncrementalBazelResolver {
             .chain(recursive_package_query_deps.into_iter())
             .collect();
 
+        // Loaded build files only appear as dependencies of rules, but their
+        // packages must be checked out too.
+        let build_file_deps = deps.values().flat_map(|dep_value| match dep_value {
+            DependencyValue::PackageInfo { deps } => deps
+                .iter()
+                .filter(|dep_key| matches!(dep_key, DependencyKey::BazelBuildFile(_)))
+                .collect::<Vec<_>>(),
+            DependencyValue::Path { .. } | DependencyValue::DummyForTesting(_) => Vec::new(),
+        });
         let paths = deps
-            .iter()
-            .filter_map(|(dep_key, _dep_value)| match dep_key {
+            .keys()
+            .chain(build_file_deps)
+            .filter_map(|dep_key| match dep_key {
                 DependencyKey::BazelPackage(Label {
                     external_repository: None,
                     path_components,
@@ -245,6 +251,47 @@ impl IncrementalBazelResolver {
         Ok(parsed_result)
     }
 
+    /// Find the in-repository `.bzl` files loaded, directly or indirectly, by
+    /// the build file of each package containing the provided targets.
+    fn build_file_loads(
+        request: &ResolutionRequest,
+        targets: &BTreeSet<Label>,
+    ) -> Result<BTreeMap<Vec<String>, BTreeSet<Label>>> {
+        let repo = git2::Repository::open(&request.repo).context("Opening repository")?;
+        let tree = repo
+            .head()
+            .and_then(|head| head.peel_to_tree())
+            .context("Resolving HEAD tree")?;
+
+        let build_files: BTreeMap<&Vec<String>, Label> = targets
+            .iter()
+            .filter(|label| label.external_repository.is_none())
+            .map(|label| &label.path_components)
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .filter_map(|path_components| {
+                LoadGraph::build_file_for_package(&tree, path_components)
+                    .map(|build_file| (path_components, build_file))
+            })
+            .collect();
+        let load_graph = LoadGraph::from_roots(&repo, &tree, build_files.values().cloned())?;
+
+        Ok(build_files
+            .into_iter()
+            .map(|(path_components, build_file)| {
+                let loads = load_graph
+                    .transitive_loads(&build_file)
+                    .into_iter()
+                    .filter(|label| {
+                        label.external_repository.is_none()
+                            && !load_graph.missing_files().contains(label)
+                    })
+                    .collect();
+                (path_components.clone(), loads)
+            })
+            .collect())
+    }
+
     /// Calculate the immediate dependencies of the provided targets.
     fn extract_immediate_dependencies(
         &self,
@@ -252,6 +299,7 @@ impl IncrementalBazelResolver {
         request: &ResolutionRequest,
         targets: BTreeSet<Label>,
     ) -> Result<BTreeMap<DependencyKey, DependencyValue>> {
+        let build_file_loads = Self::build_file_loads(request, &targets)?;
         let query = bazel_common::make_set(targets.iter());
         let bazel_de::Query { rules } =
             Self::run_bazel_query_xml(app, request, vec!["--noimplicit_deps"], &query)?;
@@ -292,6 +340,15 @@ impl IncrementalBazelResolver {
                         }
                     }
 
+                    // The rule's meaning may depend on the macros loaded by its
+                    // package's build file.
+                    if target_label.external_repository.is_none() {
+                        if let Some(loads) = build_file_loads.get(&target_label.path_components) {
+                            rule_inputs
+                                .extend(loads.iter().cloned().map(DependencyKey::BazelBuildFile));
+                        }
+                    }
+
                     let key = DependencyKey::BazelPackage(target_label);
                     immediate_deps.entry(key).or_default().extend(rule_inputs);
                 }
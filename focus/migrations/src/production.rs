@@ -9,6 +9,8 @@ fn migrations() -> Migrations {
     vec![
         Box::new(HooksMigration),
         Box::new(UseOneshotSyncByDefaultMigration),
+        Box::new(ChainedHooksMigration),
+        Box::new(PostCheckoutHookMigration),
     ]
 }
 
@@ -36,7 +38,7 @@ impl Migration for HooksMigration {
     }
 
     fn upgrade(&self, path: &Path, _app: Arc<App>) -> Result<()> {
-        focus_operations::event::init(path)
+        focus_operations::event::init_or_warn(path)
     }
 }
 
@@ -56,3 +58,33 @@ impl Migration for UseOneshotSyncByDefaultMigration {
         Ok(())
     }
 }
+
+struct ChainedHooksMigration;
+impl Migration for ChainedHooksMigration {
+    fn id(&self) -> Identifier {
+        Identifier::Serial(3)
+    }
+
+    fn description(&self) -> &str {
+        "Reinstall hooks so they chain to existing hooks and honour core.hooksPath"
+    }
+
+    fn upgrade(&self, path: &Path, _app: Arc<App>) -> Result<()> {
+        focus_operations::event::init_or_warn(path)
+    }
+}
+
+struct PostCheckoutHookMigration;
+impl Migration for PostCheckoutHookMigration {
+    fn id(&self) -> Identifier {
+        Identifier::Serial(4)
+    }
+
+    fn description(&self) -> &str {
+        "Install the post-checkout hook to sync on branch switches"
+    }
+
+    fn upgrade(&self, path: &Path, _app: Arc<App>) -> Result<()> {
+        focus_operations::event::init_or_warn(path)
+    }
+}
@@ -1,16 +1,32 @@
 // Copyright 2022 Twitter, Inc.
 // SPDX-License-Identifier: Apache-2.0
 
-use std::{path::PathBuf, sync::Arc};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    ffi::OsString,
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 
 use focus_internals::model::repo::Repo;
 use focus_util::{
     app::{App, ExitCode},
-    git_helper::ConfigExt,
+    git_helper::{self, ConfigExt},
+    sandbox_command::SandboxCommandOutput,
 };
-use tracing::error;
+use regex::Regex;
+use tracing::{error, info};
+
+use crate::maintenance::regex_escape;
+
+const BRANCH_RULE_CONFIG_PREFIX: &str = "focus.branchrule.";
+
+/// Where tip commits which aren't in the repo yet are fetched to while the
+/// rules are evaluated.
+const CANDIDATE_REF_PREFIX: &str = "refs/focus/branch-rules/";
 
 pub fn list(app: Arc<App>, sparse_repo_path: PathBuf, remote_name: &str) -> Result<()> {
     let repo = Repo::open(&sparse_repo_path, app).context("Failed to open repo")?;
@@ -84,6 +100,308 @@ pub fn add(
     Ok(ExitCode(0))
 }
 
+/// A branch-tracking rule from the repo config, e.g.
+///
+/// ```text
+/// [focus "branchrule.mine"]
+///     prefix = alice/
+///     maxage = 30d
+/// ```
+///
+/// A branch is tracked if it meets every condition set in any rule.
+#[derive(Debug, Default)]
+struct BranchRule {
+    name: String,
+    /// The branch name starts with this.
+    prefix: Option<String>,
+    /// The branch name matches this regex.
+    regex: Option<Regex>,
+    /// The author of the tip commit, as `Name <email>`, matches this regex.
+    owner: Option<Regex>,
+    /// The tip commit was committed at most this long ago.
+    max_age: Option<Duration>,
+}
+
+impl BranchRule {
+    fn load_all(config: &git2::Config) -> Result<Vec<BranchRule>> {
+        let mut rules: BTreeMap<String, BranchRule> = BTreeMap::new();
+        for (key, value) in config.dump_config(Some(&format!(
+            "^{}",
+            regex::escape(BRANCH_RULE_CONFIG_PREFIX)
+        )))? {
+            let (name, field) = match key
+                .strip_prefix(BRANCH_RULE_CONFIG_PREFIX)
+                .and_then(|rest| rest.rsplit_once('.'))
+            {
+                Some(split) => split,
+                None => bail!("Malformed branch rule key {}", key),
+            };
+            let rule = rules.entry(name.to_owned()).or_insert_with(|| BranchRule {
+                name: name.to_owned(),
+                ..Default::default()
+            });
+            match field {
+                "prefix" => rule.prefix = Some(value),
+                "regex" => {
+                    rule.regex = Some(Regex::new(&value).with_context(|| {
+                        format!("Invalid regex {:?} in branch rule {}", value, name)
+                    })?)
+                }
+                "owner" => {
+                    rule.owner = Some(Regex::new(&value).with_context(|| {
+                        format!("Invalid owner regex {:?} in branch rule {}", value, name)
+                    })?)
+                }
+                "maxage" => {
+                    rule.max_age = Some(humantime::parse_duration(&value).with_context(|| {
+                        format!("Invalid max age {:?} in branch rule {}", value, name)
+                    })?)
+                }
+                _ => bail!("Unknown key {} in branch rule {}", field, name),
+            }
+        }
+
+        for rule in rules.values() {
+            if rule.prefix.is_none()
+                && rule.regex.is_none()
+                && rule.owner.is_none()
+                && rule.max_age.is_none()
+            {
+                bail!("Branch rule {} has no conditions", rule.name);
+            }
+        }
+        Ok(rules.into_values().collect())
+    }
+
+    fn matches_name(&self, branch: &str) -> bool {
+        self.prefix
+            .as_ref()
+            .map_or(true, |prefix| branch.starts_with(prefix.as_str()))
+            && self
+                .regex
+                .as_ref()
+                .map_or(true, |regex| regex.is_match(branch))
+    }
+
+    fn needs_commit(&self) -> bool {
+        self.owner.is_some() || self.max_age.is_some()
+    }
+
+    fn matches_commit(&self, commit: &git2::Commit, now: SystemTime) -> bool {
+        let owner_matches = self.owner.as_ref().map_or(true, |owner| {
+            let author = commit.author();
+            owner.is_match(&format!(
+                "{} <{}>",
+                author.name().unwrap_or_default(),
+                author.email().unwrap_or_default()
+            ))
+        });
+        let age_matches = self.max_age.map_or(true, |max_age| {
+            let committed_at =
+                UNIX_EPOCH + Duration::from_secs(commit.time().seconds().max(0) as u64);
+            now.duration_since(committed_at).unwrap_or_default() <= max_age
+        });
+        owner_matches && age_matches
+    }
+
+    /// Rules which only match a prefix can be followed by git itself with a
+    /// wildcard refspec, which also picks up branches created later.
+    fn wildcard_refspec(&self, remote_name: &str) -> Option<String> {
+        match self {
+            BranchRule {
+                prefix: Some(prefix),
+                regex: None,
+                owner: None,
+                max_age: None,
+                ..
+            } => Some(format!(
+                "+refs/heads/{}*:refs/remotes/{}/{}*",
+                prefix, remote_name, prefix
+            )),
+            _ => None,
+        }
+    }
+}
+
+fn branch_refspec(remote_name: &str, branch: &str) -> String {
+    format!(
+        "+refs/heads/{}:refs/remotes/{}/{}",
+        branch, remote_name, branch
+    )
+}
+
+/// Rewrite the fetch refspecs of the remote to track the branches matching the
+/// rules configured under `focus.branchrule.<name>`, and delete the
+/// remote-tracking refs which no refspec fetches any more.
+///
+/// Only the refspecs written by previous runs are replaced, which are recorded
+/// in `remote.<name>.focusrulefetch`, so that ones added by `focus branch add`
+/// or by hand are kept.
+pub fn sync_rules(app: Arc<App>, sparse_repo_path: PathBuf, remote_name: &str) -> Result<ExitCode> {
+    let repo = Repo::open(&sparse_repo_path, app.clone()).context("Failed to open repo")?;
+    let underlying_repo = repo.underlying();
+    let config = underlying_repo
+        .config()
+        .context("Could not get config for sparse repo")?;
+    let rules = BranchRule::load_all(&config).context("Could not read branch rules")?;
+    if rules.is_empty() {
+        error!(
+            "No branch rules are configured. Add one with e.g. `git config {}<name>.prefix <prefix>`.",
+            BRANCH_RULE_CONFIG_PREFIX
+        );
+        return Ok(ExitCode(1));
+    }
+
+    // List the branches on the remote.
+    let branches: Vec<(String, git2::Oid)> = {
+        let mut remote = underlying_repo
+            .find_remote(remote_name)
+            .with_context(|| format!("Could not find remote named {}", remote_name))?;
+        remote
+            .connect(git2::Direction::Fetch)
+            .with_context(|| format!("Could not connect to remote {}", remote_name))?;
+        let heads = remote
+            .list()
+            .with_context(|| format!("Could not list remote refs from {}", remote_name))?;
+        heads
+            .iter()
+            .filter_map(|head| {
+                get_ref_names_from_ref_location(head.name())
+                    .map(|branch| (branch.to_owned(), head.oid()))
+            })
+            .collect()
+    };
+
+    let mut desired: BTreeSet<String> = rules
+        .iter()
+        .filter_map(|rule| rule.wildcard_refspec(remote_name))
+        .collect();
+    let mut candidates: Vec<(&str, git2::Oid, Vec<&BranchRule>)> = Vec::new();
+    for (branch, oid) in branches.iter() {
+        let matching_rules: Vec<&BranchRule> = rules
+            .iter()
+            .filter(|rule| rule.matches_name(branch))
+            .collect();
+        if matching_rules
+            .iter()
+            .any(|rule| rule.wildcard_refspec(remote_name).is_some())
+        {
+            continue;
+        }
+        if matching_rules.iter().any(|rule| !rule.needs_commit()) {
+            desired.insert(branch_refspec(remote_name, branch));
+        } else if !matching_rules.is_empty() {
+            candidates.push((branch, *oid, matching_rules));
+        }
+    }
+
+    // The owner and age of a branch are read from its tip commit, so fetch the
+    // ones we don't have yet. A shallow or partial fetch would permanently make
+    // the repo shallow or a partial clone, so they are fetched in full.
+    let missing: Vec<&str> = candidates
+        .iter()
+        .filter(|(_, oid, _)| underlying_repo.find_commit(*oid).is_err())
+        .map(|(branch, _, _)| *branch)
+        .collect();
+    if !missing.is_empty() {
+        let (mut cmd, scmd) = git_helper::git_command(app)?;
+        let mut args: Vec<OsString> = vec!["fetch".into(), "--no-tags".into(), remote_name.into()];
+        args.extend(missing.iter().map(|branch| {
+            format!("+refs/heads/{}:{}{}", branch, CANDIDATE_REF_PREFIX, branch).into()
+        }));
+        scmd.ensure_success_or_log(
+            cmd.current_dir(&sparse_repo_path).args(args),
+            SandboxCommandOutput::Stderr,
+        )
+        .context("Failed to fetch the tips of candidate branches")?;
+    }
+
+    let now = SystemTime::now();
+    for (branch, oid, matching_rules) in candidates.iter() {
+        let commit = underlying_repo
+            .find_commit(*oid)
+            .with_context(|| format!("Could not find the tip commit of {}", branch))?;
+        if matching_rules
+            .iter()
+            .any(|rule| rule.matches_commit(&commit, now))
+        {
+            desired.insert(branch_refspec(remote_name, branch));
+        }
+    }
+
+    for mut reference in underlying_repo
+        .references_glob(&format!("{}*", CANDIDATE_REF_PREFIX))?
+        .flatten()
+    {
+        reference.delete()?;
+    }
+
+    // Replace the refspecs written last time with the new ones.
+    let mut local_config = config
+        .open_level(git2::ConfigLevel::Local)
+        .context("Could not open the repo's local config")?;
+    let fetch_key = format!("remote.{}.fetch", remote_name);
+    let managed_key = format!("remote.{}.focusrulefetch", remote_name);
+    let previous: BTreeSet<String> = local_config
+        .multivar_values(&managed_key, None)?
+        .into_iter()
+        .collect();
+    let current: BTreeSet<String> = local_config
+        .multivar_values(&fetch_key, None)?
+        .into_iter()
+        .collect();
+    for refspec in previous.difference(&desired) {
+        if current.contains(refspec) {
+            local_config.remove_multivar(&fetch_key, &regex_escape(refspec))?;
+        }
+        local_config.remove_multivar(&managed_key, &regex_escape(refspec))?;
+    }
+    for refspec in desired.iter() {
+        if !current.contains(refspec) {
+            local_config.set_multivar(&fetch_key, &regex_escape(refspec), refspec)?;
+        }
+        if !previous.contains(refspec) {
+            local_config.set_multivar(&managed_key, &regex_escape(refspec), refspec)?;
+        }
+    }
+
+    // Prune the remote-tracking refs which nothing fetches any more.
+    let remote = underlying_repo
+        .find_remote(remote_name)
+        .with_context(|| format!("Could not find remote named {}", remote_name))?;
+    let fetch_refspecs: Vec<git2::Refspec> = remote
+        .refspecs()
+        .filter(|refspec| refspec.direction() == git2::Direction::Fetch)
+        .collect();
+    let head_ref_name = format!("refs/remotes/{}/HEAD", remote_name);
+    let mut pruned = 0;
+    for reference in underlying_repo.references_glob(&format!("refs/remotes/{}/*", remote_name))? {
+        let mut reference = reference?;
+        let name = match reference.name() {
+            Some(name) if name != head_ref_name => name.to_owned(),
+            _ => continue,
+        };
+        if !fetch_refspecs
+            .iter()
+            .any(|refspec| refspec.dst_matches(&name))
+        {
+            info!(%name, "Pruning remote-tracking ref");
+            reference
+                .delete()
+                .with_context(|| format!("Could not delete {}", name))?;
+            pruned += 1;
+        }
+    }
+
+    println!(
+        "Tracking {} refspecs from {}; pruned {} remote-tracking refs",
+        desired.len(),
+        remote_name,
+        pruned
+    );
+    Ok(ExitCode(0))
+}
+
 fn filter_ref_names_from_remote<'a>(
     remote: &'a git2::Remote,
     search_term: &str,
@@ -193,6 +511,182 @@ mod testing {
         Ok(())
     }
 
+    fn commit_to_branch(
+        repo: &Repository,
+        branch: &str,
+        author: &str,
+        age: Duration,
+    ) -> anyhow::Result<()> {
+        let parent = repo.head()?.peel_to_commit()?;
+        let committed_at = SystemTime::now() - age;
+        let time = git2::Time::new(committed_at.duration_since(UNIX_EPOCH)?.as_secs() as i64, 0);
+        let signature = git2::Signature::new(author, &format!("{}@example.com", author), &time)?;
+        repo.commit(
+            Some(&format!("refs/heads/{}", branch)),
+            &signature,
+            &signature,
+            branch,
+            &parent.tree()?,
+            &[&parent],
+        )?;
+        Ok(())
+    }
+
+    fn remote_tracking_branches(repo: &Repository) -> anyhow::Result<Vec<String>> {
+        let mut names = Vec::new();
+        for reference in repo.references_glob("refs/remotes/origin/*")? {
+            names.push(reference?.name().unwrap().to_owned());
+        }
+        names.sort();
+        Ok(names)
+    }
+
+    #[test]
+    fn test_sync_rules() -> anyhow::Result<()> {
+        const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+        let temp_dir = tempfile::tempdir()?;
+        let scratch_remote = ScratchGitRepo::new_static_fixture(temp_dir.path())?;
+        let remote_repo = scratch_remote.repo()?;
+        commit_to_branch(&remote_repo, "alice/recent", "alice", DAY)?;
+        commit_to_branch(&remote_repo, "alice/stale", "alice", 90 * DAY)?;
+        commit_to_branch(&remote_repo, "bob/feature", "bob", DAY)?;
+        commit_to_branch(&remote_repo, "team/x", "bob", 90 * DAY)?;
+
+        let scratch_sparse = ScratchGitRepo::new_local_clone(scratch_remote.path())?;
+        // Created after cloning, so its tip has to be fetched to check the rules.
+        commit_to_branch(&remote_repo, "alice/new", "alice", DAY)?;
+        let sparse_repo = Repository::open(scratch_sparse.path())?;
+        {
+            let mut config = sparse_repo.config()?.open_level(git2::ConfigLevel::Local)?;
+            config.set_str(
+                "remote.origin.fetch",
+                "+refs/heads/main:refs/remotes/origin/main",
+            )?;
+            config.set_str("focus.branchrule.mine.owner", "^alice <")?;
+            config.set_str("focus.branchrule.mine.maxage", "30d")?;
+            config.set_str("focus.branchrule.team.prefix", "team/")?;
+        }
+        let app = Arc::new(App::new_for_testing()?);
+        super::add(
+            app.clone(),
+            scratch_sparse.path().to_path_buf(),
+            "origin",
+            "bob/feature",
+        )?;
+
+        let exit_code = sync_rules(app.clone(), scratch_sparse.path().to_path_buf(), "origin")?;
+        assert_eq!(exit_code, ExitCode(0));
+        let fetch_refspecs = || -> anyhow::Result<Vec<String>> {
+            let mut refspecs = sparse_repo
+                .config()?
+                .multivar_values("remote.origin.fetch", None)?;
+            refspecs.sort();
+            Ok(refspecs)
+        };
+        assert_eq!(
+            fetch_refspecs()?,
+            vec![
+                "+refs/heads/alice/new:refs/remotes/origin/alice/new",
+                "+refs/heads/alice/recent:refs/remotes/origin/alice/recent",
+                "+refs/heads/bob/feature:refs/remotes/origin/bob/feature",
+                "+refs/heads/main:refs/remotes/origin/main",
+                "+refs/heads/team/*:refs/remotes/origin/team/*",
+            ]
+        );
+        assert_eq!(
+            remote_tracking_branches(&sparse_repo)?,
+            vec![
+                "refs/remotes/origin/HEAD",
+                "refs/remotes/origin/alice/recent",
+                "refs/remotes/origin/bob/feature",
+                "refs/remotes/origin/main",
+                "refs/remotes/origin/team/x",
+            ]
+        );
+        assert_eq!(sparse_repo.references_glob("refs/focus/*")?.count(), 0);
+
+        // Dropping a rule removes only the refspecs it added.
+        sparse_repo
+            .config()?
+            .open_level(git2::ConfigLevel::Local)?
+            .remove("focus.branchrule.team.prefix")?;
+        sync_rules(app, scratch_sparse.path().to_path_buf(), "origin")?;
+        assert_eq!(
+            fetch_refspecs()?,
+            vec![
+                "+refs/heads/alice/new:refs/remotes/origin/alice/new",
+                "+refs/heads/alice/recent:refs/remotes/origin/alice/recent",
+                "+refs/heads/bob/feature:refs/remotes/origin/bob/feature",
+                "+refs/heads/main:refs/remotes/origin/main",
+            ]
+        );
+        assert!(!remote_tracking_branches(&sparse_repo)?
+            .contains(&String::from("refs/remotes/origin/team/x")));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_sync_rules_keeps_the_repo_complete() -> anyhow::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let scratch_remote = ScratchGitRepo::new_static_fixture(temp_dir.path())?;
+        let scratch_sparse = ScratchGitRepo::new_local_clone(scratch_remote.path())?;
+        let remote_repo = scratch_remote.repo()?;
+        commit_to_branch(&remote_repo, "alice/new", "alice", Duration::from_secs(60))?;
+        let sparse_repo = Repository::open(scratch_sparse.path())?;
+        sparse_repo
+            .config()?
+            .open_level(git2::ConfigLevel::Local)?
+            .set_str("focus.branchrule.mine.owner", "^alice <")?;
+
+        // Only the refspecs of the remote are expected to change.
+        let config_path = sparse_repo.path().join("config");
+        let config_without_refspecs = || -> anyhow::Result<Vec<String>> {
+            Ok(std::fs::read_to_string(&config_path)?
+                .lines()
+                .filter(|line| !line.trim_start().starts_with("fetch"))
+                .filter(|line| !line.trim_start().starts_with("focusrulefetch"))
+                .map(String::from)
+                .collect())
+        };
+        let config_before = config_without_refspecs()?;
+
+        let app = Arc::new(App::new_for_testing()?);
+        let exit_code = sync_rules(app, scratch_sparse.path().to_path_buf(), "origin")?;
+        assert_eq!(exit_code, ExitCode(0));
+        assert_eq!(config_without_refspecs()?, config_before);
+        assert!(!sparse_repo.path().join("shallow").exists());
+        assert!(sparse_repo
+            .config()?
+            .multivar_values("remote.origin.fetch", None)?
+            .contains(&String::from(
+                "+refs/heads/alice/new:refs/remotes/origin/alice/new"
+            )));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_branch_rules_are_validated() -> anyhow::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let path = temp_dir.path().join("config");
+        std::fs::File::create(&path)?;
+        let mut config = git2::Config::open(&path)?;
+        config.set_str("focus.branchrule.a.regex", "^release/[0-9]+$")?;
+        let rules = BranchRule::load_all(&config)?;
+        assert_eq!(rules.len(), 1);
+        assert!(rules[0].matches_name("release/12"));
+        assert!(!rules[0].matches_name("release/12-fix"));
+        assert_eq!(rules[0].wildcard_refspec("origin"), None);
+
+        config.set_str("focus.branchrule.b.maxage", "a fortnight")?;
+        assert!(BranchRule::load_all(&config).is_err());
+        config.remove("focus.branchrule.b.maxage")?;
+        config.set_str("focus.branchrule.b.colour", "blue")?;
+        assert!(BranchRule::load_all(&config).is_err());
+        Ok(())
+    }
+
     #[test]
     fn test_get_ref_names_from_ref_locations() -> anyhow::Result<()> {
         let ref_names: Vec<&str> = vec!["refs/heads/master", "refs/heads/test/*"]
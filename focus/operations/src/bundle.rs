@@ -0,0 +1,191 @@
+// Copyright 2022 Twitter, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+
+use anyhow::{bail, Context, Result};
+use content_addressed_cache::{CacheKey, CacheKeyKind, CompositeKey};
+use focus_internals::model::repo::Repo;
+use focus_util::app::App;
+use focus_util::git_helper;
+use focus_util::sandbox_command::SandboxCommandOutput;
+use serde_derive::{Deserialize, Serialize};
+use tracing::info;
+
+use crate::index;
+
+/// Appended to the path of a bundle to name the file describing it.
+pub const SIDECAR_SUFFIX: &str = ".focus.json";
+
+/// What focus needs to set up a repo from a git bundle besides the history in
+/// it. It is kept in a file next to the bundle.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BundleSidecar {
+    /// The URL of the repo the bundle was made from, which the rest of the
+    /// history is fetched from.
+    pub origin: String,
+
+    /// The branch in the bundle.
+    pub branch: String,
+
+    /// The projects and targets selected in the repo the bundle was made from.
+    pub projects_and_targets: Vec<String>,
+
+    /// The index entries for the selection at the bundled commit, keyed by
+    /// their composite key, with hex-encoded values.
+    #[serde(default)]
+    pub index: BTreeMap<String, String>,
+}
+
+impl BundleSidecar {
+    /// The path of the sidecar for the bundle at `bundle_path`.
+    pub fn path_for(bundle_path: &Path) -> PathBuf {
+        let mut path = OsString::from(bundle_path.as_os_str());
+        path.push(SIDECAR_SUFFIX);
+        PathBuf::from(path)
+    }
+
+    /// Read the sidecar of the bundle at `bundle_path`.
+    pub fn load(bundle_path: &Path) -> Result<Self> {
+        let path = Self::path_for(bundle_path);
+        let contents =
+            std::fs::read(&path).with_context(|| format!("Reading {}", path.display()))?;
+        serde_json::from_slice(&contents).with_context(|| format!("Parsing {}", path.display()))
+    }
+
+    /// Write the sidecar of the bundle at `bundle_path`.
+    pub fn save(&self, bundle_path: &Path) -> Result<()> {
+        let path = Self::path_for(bundle_path);
+        std::fs::write(&path, serde_json::to_vec_pretty(self)?)
+            .with_context(|| format!("Writing {}", path.display()))
+    }
+
+    pub fn set_index_entries(
+        &mut self,
+        entries: impl IntoIterator<Item = (CacheKeyKind, CacheKey, Vec<u8>)>,
+    ) {
+        self.index = entries
+            .into_iter()
+            .map(|(kind, key, value)| (CompositeKey { kind, key }.to_string(), hex::encode(value)))
+            .collect();
+    }
+
+    pub fn index_entries(&self) -> Result<Vec<(CacheKeyKind, CacheKey, Vec<u8>)>> {
+        self.index
+            .iter()
+            .map(|(key, value)| {
+                let CompositeKey { kind, key } = CompositeKey::from_str(key)
+                    .map_err(|e| anyhow::anyhow!("Invalid index key {:?}: {:?}", key, e))?;
+                let value = hex::decode(value)
+                    .with_context(|| format!("Invalid index value for {}", key))?;
+                Ok((kind, key, value))
+            })
+            .collect()
+    }
+}
+
+/// Write the branch checked out in a sparse repo to a git bundle at
+/// `bundle_path`, along with a sidecar recording its origin, selection, and
+/// index, so that new repos can be set up from it with `focus new
+/// --from-bundle`.
+pub fn create(app: Arc<App>, sparse_repo_path: &Path, bundle_path: &Path) -> Result<()> {
+    let bundle_path = std::env::current_dir()
+        .context("Failed to obtain current directory")?
+        .join(bundle_path);
+    let repo = Repo::open(sparse_repo_path, app.clone()).context("Failed to open repo")?;
+    let underlying = repo.underlying();
+    if underlying.is_shallow() {
+        bail!("Bundles must contain the whole history of the branch; create them from a repo cloned with `--days-of-history 0`");
+    }
+
+    let head = underlying.head().context("Resolving HEAD")?;
+    if !head.is_branch() {
+        bail!("HEAD is detached; check out the branch to bundle");
+    }
+    let branch = head
+        .shorthand()
+        .context("The branch name is not valid UTF-8")?
+        .to_owned();
+    let origin = underlying
+        .find_remote("origin")
+        .context("Finding the origin remote")?
+        .url()
+        .context("The origin remote has no URL")?
+        .to_owned();
+
+    let selections = repo.selection_manager()?;
+    let selection = selections.selection()?;
+    let mut projects_and_targets: Vec<String> = selection
+        .projects
+        .into_iter()
+        .map(|project| project.name)
+        .chain(
+            selection
+                .targets
+                .into_iter()
+                .map(|target| target.to_string()),
+        )
+        .collect();
+    projects_and_targets.sort();
+
+    let mut sidecar = BundleSidecar {
+        origin,
+        branch: branch.clone(),
+        projects_and_targets,
+        index: Default::default(),
+    };
+    let targets = selections.compute_complete_target_set()?;
+    sidecar.set_index_entries(index::export_entries(
+        app.clone(),
+        sparse_repo_path,
+        targets,
+    )?);
+
+    info!(%branch, path = ?bundle_path, "Writing bundle");
+    let (mut cmd, scmd) = git_helper::git_command(app)?;
+    scmd.ensure_success_or_log(
+        cmd.current_dir(sparse_repo_path)
+            .arg("bundle")
+            .arg("create")
+            .arg(&bundle_path)
+            .arg(&branch),
+        SandboxCommandOutput::Stderr,
+    )
+    .context("git bundle create failed")?;
+    sidecar.save(&bundle_path)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sidecar_round_trips_index_entries() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let bundle_path = dir.path().join("source.bundle");
+        let entries = vec![
+            (*b"ab", CacheKey::from_str(&"1".repeat(40))?, vec![1, 2, 3]),
+            (*b"ab", CacheKey::from_str(&"2".repeat(40))?, vec![]),
+        ];
+        let mut sidecar = BundleSidecar {
+            origin: String::from("https://git.example.com/source"),
+            branch: String::from("main"),
+            projects_and_targets: vec![String::from("team/base")],
+            index: Default::default(),
+        };
+        sidecar.set_index_entries(entries.clone());
+        sidecar.save(&bundle_path)?;
+
+        assert!(dir.path().join("source.bundle.focus.json").is_file());
+        let loaded = BundleSidecar::load(&bundle_path)?;
+        assert_eq!(loaded, sidecar);
+        assert_eq!(loaded.index_entries()?, entries);
+        Ok(())
+    }
+}
@@ -1,23 +1,25 @@
 // Copyright 2022 Twitter, Inc.
 // SPDX-License-Identifier: Apache-2.0
 
+use crate::bundle::BundleSidecar;
+use crate::clone_template::{self, ClonedRepoTemplate, ClonedRepoTemplates};
 use crate::event;
+use crate::index;
 use crate::sync::SyncMode;
 use focus_internals::index::RocksDBMemoizationCacheExt;
 use focus_internals::model::selection::{Operation, OperationAction};
 
 use anyhow::{bail, Context, Result};
-use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use chrono::{DateTime, NaiveDateTime, Utc};
 use content_addressed_cache::RocksDBCache;
 use focus_internals::{model::repo::Repo, target::TargetSet, tracker::Tracker};
 
-use focus_util::sandbox_command::SandboxCommand;
 use focus_util::{self, app::App, git_helper, sandbox_command::SandboxCommandOutput};
 use git2::Repository;
 
-use std::collections::HashSet;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fs::OpenOptions;
-use std::process::Command;
 use std::{
     ffi::OsString,
     fs::File,
@@ -28,251 +30,6 @@ use std::{
 use tracing::{debug, error, info, info_span, warn};
 use url::Url;
 
-pub fn run_clone(mut clone_builder: CloneBuilder, app: Arc<App>) -> Result<()> {
-    clone_builder.add_clone_args(vec!["--progress"]);
-    let (mut cmd, scmd) = clone_builder.build(app)?;
-
-    scmd.ensure_success_or_log(&mut cmd, SandboxCommandOutput::Stderr)
-        .map(|_| ())
-}
-
-#[derive(Debug, Eq, Hash, PartialEq)]
-pub enum InitOpt {
-    NoCheckout,
-    FollowTags,
-    Bare,
-    Sparse,
-    Progress,
-}
-
-#[derive(Debug)]
-pub struct CloneBuilder {
-    // note, to be totally typesafe we'd have a type that represented
-    // when the target_path was set, and another to represent another
-    // where it was unset, but that's unnecessarily complex for our needs
-    target_path: PathBuf,
-    fetch_url: Option<String>,
-    push_url: Option<String>,
-    shallow_since: Option<NaiveDate>,
-    branch_name: String,
-    filter: Option<String>,
-    // additional repo config keys that will be passed to clone using
-    // the -c flag. should be strings of "key=value"
-    repo_config: Vec<String>,
-    // set of options controlling various flags to pass to clone
-    init_opts: HashSet<InitOpt>,
-    // additional args to git, before the subcommand
-    git_args: Vec<String>,
-    // additional args to the clone subcommand
-    clone_args: Vec<String>,
-}
-
-const DEFAULT_SINGLE_BRANCH: &str = "master";
-
-pub fn parse_shallow_since_date(s: &str) -> Result<NaiveDate> {
-    Ok(NaiveDate::parse_from_str(s, "%Y-%m-%d")?)
-}
-
-impl Default for CloneBuilder {
-    fn default() -> Self {
-        Self {
-            target_path: PathBuf::new(),
-            fetch_url: None,
-            push_url: None,
-            shallow_since: None,
-            branch_name: String::from(DEFAULT_SINGLE_BRANCH),
-            filter: None,
-            repo_config: Vec::new(),
-            init_opts: HashSet::new(),
-            git_args: Vec::new(),
-            clone_args: Vec::new(),
-        }
-    }
-}
-
-impl CloneBuilder {
-    pub fn new(target_path: PathBuf) -> Self {
-        Self {
-            target_path,
-            ..Default::default()
-        }
-    }
-
-    pub fn build(self, app: Arc<App>) -> Result<(Command, SandboxCommand)> {
-        let mut opt_args: Vec<String> = Vec::new();
-
-        if let Some(ss) = self.shallow_since {
-            opt_args.push(format!["--shallow-since={}", ss.format("%Y-%m-%d")]);
-        }
-
-        opt_args.push("-b".to_string());
-        opt_args.push(self.branch_name.to_owned());
-
-        if self.opt_set(InitOpt::Bare) {
-            opt_args.push(String::from("--bare"));
-        }
-
-        if let Some(f) = self.filter.to_owned() {
-            opt_args.push(format!["--filter={}", f]);
-        }
-
-        if !self.opt_set(InitOpt::FollowTags) {
-            opt_args.push(String::from("--no-tags"));
-        }
-
-        if self.opt_set(InitOpt::NoCheckout) {
-            opt_args.push(String::from("--no-checkout"));
-        }
-
-        if self.opt_set(InitOpt::Sparse) {
-            opt_args.push(String::from("--sparse"));
-        }
-
-        if self.opt_set(InitOpt::Progress) {
-            opt_args.push(String::from("--progress"));
-        }
-
-        if let Some(push_url) = self.push_url.to_owned() {
-            opt_args.push(String::from("-c"));
-            opt_args.push(format!["remote.origin.pushUrl={}", push_url]);
-        }
-
-        for kv in self.repo_config {
-            opt_args.push(String::from("-c"));
-            opt_args.push(kv);
-        }
-
-        let fetch_url = self
-            .fetch_url
-            .ok_or_else(|| anyhow::anyhow!("Fetch URL not provided"))?;
-
-        let (mut cmd, scmd) = git_helper::git_command(app)?;
-        cmd.args(self.git_args)
-            .arg("clone")
-            .args(opt_args)
-            .args(self.clone_args)
-            .arg(fetch_url)
-            .arg(self.target_path);
-
-        Ok((cmd, scmd))
-    }
-
-    fn opt_set(&self, opt: InitOpt) -> bool {
-        self.init_opts.contains(&opt)
-    }
-
-    pub fn shallow_since(&mut self, d: NaiveDate) -> &mut Self {
-        self.shallow_since = Some(d);
-        self
-    }
-
-    pub fn branch(&mut self, name: String) -> &mut Self {
-        self.branch_name = name;
-        self
-    }
-
-    pub fn push_url(&mut self, url: String) -> &mut Self {
-        self.push_url = Some(url);
-        self
-    }
-
-    #[allow(dead_code)]
-    pub fn sparse(&mut self, b: bool) -> &mut Self {
-        self.add_or_remove_init_opt(InitOpt::Sparse, b);
-        self
-    }
-
-    #[allow(dead_code)]
-    pub fn bare(&mut self, b: bool) -> &mut Self {
-        self.add_or_remove_init_opt(InitOpt::Bare, b);
-        self
-    }
-
-    pub fn filter(&mut self, spec: String) -> &mut Self {
-        self.filter = Some(spec);
-        self
-    }
-
-    #[allow(dead_code)]
-    pub fn follow_tags(&mut self, b: bool) -> &mut Self {
-        self.add_or_remove_init_opt(InitOpt::FollowTags, b);
-        self
-    }
-
-    #[allow(dead_code)]
-    pub fn no_checkout(&mut self, b: bool) -> &mut Self {
-        self.add_or_remove_init_opt(InitOpt::NoCheckout, b);
-        self
-    }
-
-    pub fn fetch_url(&mut self, ourl: String) -> &mut Self {
-        self.fetch_url = Some(ourl);
-        self
-    }
-
-    // small helper to avoid repetition, if add is true, then add the
-    // opt to the set, otherwise remove it
-    fn add_or_remove_init_opt(&mut self, opt: InitOpt, add: bool) -> &mut Self {
-        if add {
-            self.init_opts.insert(opt);
-        } else {
-            self.init_opts.remove(&opt);
-        }
-        self
-    }
-
-    pub fn init_opts<I>(&mut self, args: I) -> &mut Self
-    where
-        I: IntoIterator<Item = InitOpt>,
-    {
-        for i in args.into_iter() {
-            self.init_opts.insert(i);
-        }
-        self
-    }
-
-    #[allow(dead_code)]
-    pub fn add_git_args<I, S>(&mut self, args: I) -> &mut Self
-    where
-        I: IntoIterator<Item = S>,
-        S: AsRef<String>,
-    {
-        for arg in args {
-            self.git_args.push(arg.as_ref().to_owned());
-        }
-        self
-    }
-
-    #[allow(dead_code)]
-    pub fn add_repo_config<K, V>(&mut self, k: K, v: V) -> &mut Self
-    where
-        K: Into<String>,
-        V: Into<String>,
-    {
-        self.repo_config.push(format!["{}={}", k.into(), v.into()]);
-        self
-    }
-
-    pub fn add_clone_arg<I>(&mut self, arg: I) -> &mut Self
-    where
-        I: Into<String>,
-    {
-        self.clone_args.push(arg.into());
-        self
-    }
-
-    pub fn add_clone_args<I, S>(&mut self, args: I) -> &mut Self
-    where
-        I: IntoIterator<Item = S>,
-        S: Into<String>,
-    {
-        for arg in args {
-            self.add_clone_arg(arg);
-        }
-        self
-    }
-}
-
 #[derive(Debug)]
 pub enum Origin {
     /// Clone from a local path
@@ -296,37 +53,151 @@ impl TryFrom<&str> for Origin {
     }
 }
 
+/// The branch cloned if neither the arguments nor the template name one.
+pub const DEFAULT_BRANCH: &str = "master";
+
+/// The days of history cloned if neither the arguments nor the template give
+/// them.
+pub const DEFAULT_DAYS_OF_HISTORY: u64 = 90;
+
 #[derive(Debug)]
 pub struct CloneArgs {
     pub origin: Option<Origin>,
-    pub branch: String,
+    pub branch: Option<String>,
     pub projects_and_targets: Vec<String>,
     pub copy_branches: bool,
-    pub days_of_history: u64,
+    pub days_of_history: Option<u64>,
     pub do_post_clone_fetch: bool,
     pub sync_mode: SyncMode,
+    /// A bundle written by `focus bundle create` to clone from instead of the
+    /// origin, which is read from the bundle's sidecar.
+    pub bundle: Option<PathBuf>,
+    /// A repo to borrow objects from, so that only the objects missing from
+    /// it are fetched.
+    pub reference: Option<PathBuf>,
 }
 
 impl Default for CloneArgs {
     fn default() -> CloneArgs {
         Self {
             origin: None,
-            branch: String::from("master"),
+            branch: None,
             projects_and_targets: Vec::default(),
             copy_branches: true,
-            days_of_history: 90,
+            days_of_history: None,
             do_post_clone_fetch: true,
             sync_mode: SyncMode::Incremental,
+            bundle: None,
+            reference: None,
+        }
+    }
+}
+
+/// The name of the file recording the progress of a clone in its staging
+/// directory.
+const CHECKPOINT_FILE_NAME: &str = "checkpoint.json";
+
+/// The name of the repo being set up in a clone's staging directory.
+const STAGED_REPO_DIR_NAME: &str = "repo";
+
+/// The stages of a clone, in the order they are run. The last one completed is
+/// recorded in the checkpoint so that an interrupted clone can be resumed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+enum CloneStage {
+    Started,
+    Cloned,
+    SparseRepoSetUp,
+    Fetched,
+    HooksSetUp,
+    Moved,
+}
+
+/// The clone template used by every stage of a clone.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+enum TemplateChoice {
+    /// Not chosen yet, since the origin may have templates committed to it
+    /// which can only be read once it is cloned.
+    Pending,
+    Chosen(Option<ClonedRepoTemplate>),
+}
+
+impl Default for TemplateChoice {
+    fn default() -> Self {
+        Self::Pending
+    }
+}
+
+/// The arguments of a clone and how far it got, kept in its staging directory
+/// until it completes.
+#[derive(Debug, Serialize, Deserialize)]
+struct CloneCheckpoint {
+    origin: String,
+    branch: String,
+    projects_and_targets: Vec<String>,
+    copy_branches: bool,
+    days_of_history: u64,
+    do_post_clone_fetch: bool,
+    one_shot: bool,
+    template: Option<String>,
+    #[serde(default)]
+    template_choice: TemplateChoice,
+    #[serde(default)]
+    bundle: Option<PathBuf>,
+    #[serde(default)]
+    reference: Option<PathBuf>,
+    completed: CloneStage,
+}
+
+impl CloneCheckpoint {
+    fn load(staging_dir: &Path) -> Result<Self> {
+        let path = staging_dir.join(CHECKPOINT_FILE_NAME);
+        let file = File::open(&path).with_context(|| format!("Opening {}", path.display()))?;
+        serde_json::from_reader(file).with_context(|| format!("Reading {}", path.display()))
+    }
+
+    fn save(&self, staging_dir: &Path) -> Result<()> {
+        let path = staging_dir.join(CHECKPOINT_FILE_NAME);
+        let temp_path = path.with_extension("json.tmp");
+        std::fs::write(&temp_path, serde_json::to_vec_pretty(self)?)
+            .with_context(|| format!("Writing {}", temp_path.display()))?;
+        std::fs::rename(&temp_path, &path)
+            .with_context(|| format!("Renaming {} to {}", temp_path.display(), path.display()))
+    }
+
+    fn complete(&mut self, stage: CloneStage, staging_dir: &Path) -> Result<()> {
+        debug!(?stage, "Completed clone stage");
+        self.completed = stage;
+        self.save(staging_dir)
+    }
+
+    fn sync_mode(&self) -> SyncMode {
+        if self.one_shot {
+            SyncMode::OneShot
+        } else {
+            SyncMode::Incremental
         }
     }
 }
 
+/// The directory a clone into `sparse_repo_path` is staged in. It is next to
+/// the sparse repo so that the finished repo can be moved into place, and is
+/// kept if the clone fails so that it can be resumed.
+fn staging_dir_for(sparse_repo_path: &Path) -> Result<PathBuf> {
+    let file_name = sparse_repo_path
+        .file_name()
+        .with_context(|| format!("{} has no file name", sparse_repo_path.display()))?;
+    let mut staging_dir_name = OsString::from(".");
+    staging_dir_name.push(file_name);
+    staging_dir_name.push(".focus-new");
+    Ok(sparse_repo_path.with_file_name(staging_dir_name))
+}
+
 /// Entrypoint for clone operations.
 #[tracing::instrument]
 pub fn run(
     sparse_repo_path: PathBuf,
     clone_args: CloneArgs,
-    template: Option<ClonedRepoTemplate>,
+    template: Option<String>,
     tracker: &Tracker,
     app: Arc<App>,
 ) -> Result<()> {
@@ -338,105 +209,460 @@ pub fn run(
         days_of_history,
         do_post_clone_fetch,
         sync_mode,
+        bundle,
+        reference,
     } = clone_args;
 
+    let current_dir = std::env::current_dir().context("Failed to obtain current directory")?;
+    // Record absolute paths so that the clone can be resumed from another
+    // directory.
+    let bundle = bundle.map(|bundle| current_dir.join(bundle));
+    let reference = reference.map(|reference| current_dir.join(reference));
+    let sidecar = bundle.as_deref().map(BundleSidecar::load).transpose()?;
+    let (origin, branch, projects_and_targets) = match &sidecar {
+        Some(sidecar) => {
+            let mut projects_and_targets = projects_and_targets;
+            for entry in sidecar.projects_and_targets.iter() {
+                if !projects_and_targets.contains(entry) {
+                    projects_and_targets.push(entry.clone());
+                }
+            }
+            (
+                Some(Origin::try_from(sidecar.origin.as_str())?),
+                branch.or_else(|| Some(sidecar.branch.clone())),
+                projects_and_targets,
+            )
+        }
+        None => (origin, branch, projects_and_targets),
+    };
+
     let origin = match origin {
+        Some(Origin::Local(dense_repo_path)) => Origin::Local(current_dir.join(dense_repo_path)),
         Some(origin) => origin,
         None => bail!("Clone does not have a valid origin"),
     };
 
+    let template_choice = resolve_initial_template(&origin, template.as_deref(), bundle.is_some())?;
+    let initial_template = match &template_choice {
+        TemplateChoice::Chosen(template) => template.as_ref(),
+        TemplateChoice::Pending => None,
+    };
+    let branch = branch
+        .or_else(|| initial_template.and_then(|t| t.branch.clone()))
+        .unwrap_or_else(|| DEFAULT_BRANCH.to_owned());
+    let days_of_history = days_of_history
+        .or_else(|| initial_template.and_then(|t| t.days_of_history))
+        .unwrap_or(DEFAULT_DAYS_OF_HISTORY);
+    let origin = match origin {
+        Origin::Local(dense_repo_path) => dense_repo_path.display().to_string(),
+        Origin::Remote(url) => url.to_string(),
+    };
+
     if sparse_repo_path.is_dir() {
         bail!("{} already exists", sparse_repo_path.display());
     }
 
+    let staging_dir = staging_dir_for(&sparse_repo_path)?;
+    if staging_dir.exists() {
+        bail!(
+            "An interrupted clone into {} exists; run `focus new --resume {}` to continue it, or delete {} to start over",
+            sparse_repo_path.display(),
+            sparse_repo_path.display(),
+            staging_dir.display()
+        );
+    }
+
     //create the sparse repo dir, so other clones don't use the same name
     std::fs::create_dir_all(&sparse_repo_path).context("Failed to create repo directory")?;
+    std::fs::create_dir_all(&staging_dir).context("Failed to create staging directory")?;
 
-    let mut tmp_sparse_repo_path = PathBuf::from(app.sandbox().path());
-    tmp_sparse_repo_path.push("tmp_sparse_repo");
-
-    let configure_repo_then_move_in_place = || -> Result<()> {
-        let template = match origin {
-            Origin::Local(dense_repo_path) => {
-                tracing::info!(path = ?dense_repo_path, "Cloning from local path");
-                clone_local(
-                    &dense_repo_path,
-                    &tmp_sparse_repo_path,
-                    &branch,
-                    copy_branches,
-                    days_of_history,
-                    app.clone(),
-                )?;
+    let checkpoint = CloneCheckpoint {
+        origin,
+        branch,
+        projects_and_targets,
+        copy_branches,
+        days_of_history,
+        do_post_clone_fetch,
+        one_shot: sync_mode == SyncMode::OneShot,
+        template,
+        template_choice,
+        bundle,
+        reference,
+        completed: CloneStage::Started,
+    };
+    checkpoint.save(&staging_dir)?;
 
-                template
+    run_stages(&sparse_repo_path, &staging_dir, checkpoint, tracker, app)
+}
+
+/// Continue an interrupted clone into `sparse_repo_path` from the last stage it
+/// completed.
+#[tracing::instrument]
+pub fn resume(sparse_repo_path: PathBuf, tracker: &Tracker, app: Arc<App>) -> Result<()> {
+    let staging_dir = staging_dir_for(&sparse_repo_path)?;
+    let checkpoint = CloneCheckpoint::load(&staging_dir).with_context(|| {
+        format!(
+            "There is no interrupted clone into {} to resume",
+            sparse_repo_path.display()
+        )
+    })?;
+    info!(completed = ?checkpoint.completed, origin = %checkpoint.origin, "Resuming clone");
+
+    std::fs::create_dir_all(&sparse_repo_path).context("Failed to create repo directory")?;
+
+    run_stages(&sparse_repo_path, &staging_dir, checkpoint, tracker, app)
+}
+
+/// Run the stages of a clone which the checkpoint doesn't record as completed.
+fn run_stages(
+    sparse_repo_path: &Path,
+    staging_dir: &Path,
+    mut checkpoint: CloneCheckpoint,
+    tracker: &Tracker,
+    app: Arc<App>,
+) -> Result<()> {
+    let tmp_sparse_repo_path = staging_dir.join(STAGED_REPO_DIR_NAME);
+
+    let mut configure_repo_then_move_in_place = || -> Result<()> {
+        let origin = Origin::try_from(checkpoint.origin.as_str())?;
+
+        if checkpoint.completed < CloneStage::Cloned {
+            // An interrupted clone stage is restarted in the staged repo, which
+            // is safe to do however far it got.
+            let no_repo_config = BTreeMap::new();
+            let settings = CommonCloneSettings {
+                repo_config: match &checkpoint.template_choice {
+                    TemplateChoice::Chosen(Some(template)) => &template.repo_config,
+                    _ => &no_repo_config,
+                },
+                reference: checkpoint.reference.as_deref(),
+            };
+            match (&checkpoint.bundle, &origin) {
+                (Some(bundle_path), _) => {
+                    tracing::info!(path = ?bundle_path, "Cloning from bundle");
+                    clone_bundle(
+                        bundle_path,
+                        &tmp_sparse_repo_path,
+                        &checkpoint.branch,
+                        &checkpoint.origin,
+                        &settings,
+                        app.clone(),
+                    )?;
+                }
+                (None, Origin::Local(dense_repo_path)) => {
+                    tracing::info!(path = ?dense_repo_path, "Cloning from local path");
+                    clone_local(
+                        dense_repo_path,
+                        &tmp_sparse_repo_path,
+                        &checkpoint.branch,
+                        checkpoint.copy_branches,
+                        checkpoint.days_of_history,
+                        &settings,
+                        app.clone(),
+                    )?;
+                }
+                (None, Origin::Remote(url)) => {
+                    tracing::info!(?url, "Cloning from remote");
+                    clone_remote(
+                        url.clone(),
+                        &tmp_sparse_repo_path,
+                        &checkpoint.branch,
+                        checkpoint.days_of_history,
+                        &settings,
+                        app.clone(),
+                    )?;
+                }
             }
-            Origin::Remote(url) => {
-                tracing::info!(?url, "Cloning from remote");
-                clone_remote(
-                    url.clone(),
-                    &tmp_sparse_repo_path,
-                    &branch,
-                    days_of_history,
-                    app.clone(),
-                )?;
-
-                let template = template.or_else(|| ClonedRepoTemplate::from_url(url.clone()));
-                if let Some(template) = template {
-                    info!(?template, %url, "Using repo template for url");
+            checkpoint.complete(CloneStage::Cloned, staging_dir)?;
+        }
+
+        if checkpoint.completed < CloneStage::SparseRepoSetUp {
+            let template = match checkpoint.template_choice.clone() {
+                TemplateChoice::Chosen(template) => template,
+                TemplateChoice::Pending => {
+                    let template = resolve_template_after_cloning(
+                        &tmp_sparse_repo_path,
+                        checkpoint.template.as_deref(),
+                        &checkpoint.origin,
+                    )?;
+                    checkpoint.template_choice = TemplateChoice::Chosen(template.clone());
+                    checkpoint.save(staging_dir)?;
+                    template
                 }
-                template
+            };
+            if let Some(template) = &template {
+                info!(template = %template.name, origin = %checkpoint.origin, "Using clone template");
             }
-        };
 
-        set_up_sparse_repo(
-            &tmp_sparse_repo_path,
-            projects_and_targets,
-            template,
-            sync_mode,
-            app.clone(),
-        )?;
+            remove_incomplete_sparse_repo_set_up(&tmp_sparse_repo_path, app.clone())?;
+            if let Some(bundle_path) = &checkpoint.bundle {
+                // Seed the index so that the initial sync doesn't need to
+                // resolve the bundled selection again.
+                let entries = BundleSidecar::load(bundle_path)?.index_entries()?;
+                let count = index::import_entries(&tmp_sparse_repo_path, entries)
+                    .context("Importing the bundled index")?;
+                info!(count, "Imported index entries from bundle");
+            }
+            set_up_sparse_repo(
+                &tmp_sparse_repo_path,
+                checkpoint.projects_and_targets.clone(),
+                template,
+                checkpoint.sync_mode(),
+                app.clone(),
+            )?;
+            checkpoint.complete(CloneStage::SparseRepoSetUp, staging_dir)?;
+        }
 
-        if do_post_clone_fetch {
-            fetch_default_remote(&tmp_sparse_repo_path, app.clone())
-                .context("Could not complete post clone fetch")?;
+        if checkpoint.completed < CloneStage::Fetched {
+            if checkpoint.do_post_clone_fetch {
+                fetch_default_remote(&tmp_sparse_repo_path, app.clone())
+                    .context("Could not complete post clone fetch")?;
+            }
+            checkpoint.complete(CloneStage::Fetched, staging_dir)?;
         }
 
-        set_up_hooks(&tmp_sparse_repo_path)?;
+        if checkpoint.completed < CloneStage::HooksSetUp {
+            set_up_hooks(&tmp_sparse_repo_path)?;
+            checkpoint.complete(CloneStage::HooksSetUp, staging_dir)?;
+        }
 
-        move_repo(
-            &tmp_sparse_repo_path,
-            &sparse_repo_path,
-            tracker,
-            app.clone(),
-        )
-        .context("Could not move repo into place")?;
+        if checkpoint.completed < CloneStage::Moved {
+            std::fs::rename(&tmp_sparse_repo_path, sparse_repo_path)
+                .context("Could not move repo into place")?;
+            checkpoint.complete(CloneStage::Moved, staging_dir)?;
+        }
+
+        register_moved_repo(sparse_repo_path, tracker, app.clone())
+            .context("Could not register repo")?;
+
+        std::fs::remove_dir_all(staging_dir).context("Failed to remove staging directory")?;
 
         Ok(())
     };
 
-    if let err @ Err(_) = configure_repo_then_move_in_place() {
-        //cleanup, will rely on maintanence sandbox cleanup for tmp_sparse_repo
-        if std::fs::remove_dir_all(sparse_repo_path).is_err() {
-            return err.context("Failed to cleanup repo");
-        };
+    if let Err(err) = configure_repo_then_move_in_place() {
+        // Keep the staging directory so that the clone can be resumed. The
+        // sparse repo dir is only removed if nothing has been moved into it.
+        if checkpoint.completed < CloneStage::Moved {
+            if let Err(cleanup_err) = std::fs::remove_dir(sparse_repo_path) {
+                warn!(?cleanup_err, "Failed to clean up repo directory");
+            }
+        }
+
+        return Err(err.context(format!(
+            "Clone failed; run `focus new --resume {}` to continue it",
+            sparse_repo_path.display()
+        )));
+    }
+
+    Ok(())
+}
+
+/// Choose the template for a clone before cloning. Templates committed to a
+/// remote origin, or to the origin of a bundle, can only be read once it is
+/// cloned, so if no other template matches the origin the choice is left until
+/// then. A template named by the user must be one that can be read now, so
+/// that an unknown name fails before anything is fetched.
+fn resolve_initial_template(
+    origin: &Origin,
+    name: Option<&str>,
+    from_bundle: bool,
+) -> Result<TemplateChoice> {
+    let global = ClonedRepoTemplates::load(&ClonedRepoTemplates::global_path())?;
+    match origin {
+        Origin::Local(dense_repo_path) if !from_bundle => {
+            let dense_repo = Repository::open(dense_repo_path)
+                .with_context(|| format!("Opening {}", dense_repo_path.display()))?;
+            let sources = [ClonedRepoTemplates::load_from_head(&dense_repo)?, global];
+            let template =
+                clone_template::resolve(name, &dense_repo_path.display().to_string(), &sources)?;
+            Ok(TemplateChoice::Chosen(template))
+        }
+        _ => {
+            let origin = match origin {
+                Origin::Local(path) => path.display().to_string(),
+                Origin::Remote(url) => url.to_string(),
+            };
+            match clone_template::resolve(name, &origin, &[global])? {
+                Some(template) => Ok(TemplateChoice::Chosen(Some(template))),
+                None => Ok(TemplateChoice::Pending),
+            }
+        }
+    }
+}
+
+/// Choose the template for a clone whose choice was left until the templates
+/// committed to its origin could be read from the clone. Since the clone is
+/// done by then, only the projects and targets of the template are used.
+fn resolve_template_after_cloning(
+    cloned_repo_path: &Path,
+    name: Option<&str>,
+    origin: &str,
+) -> Result<Option<ClonedRepoTemplate>> {
+    let cloned_repo = Repository::open(cloned_repo_path).context("Opening cloned repo")?;
+    let sources = [ClonedRepoTemplates::load_from_head(&cloned_repo)?];
+    let template = clone_template::resolve(name, origin, &sources)?;
+    if let Some(template) = &template {
+        if template.branch.is_some() || template.days_of_history.is_some() {
+            warn!(
+                template = %template.name,
+                "The branch and days of history of a clone template committed to a remote origin are not used"
+            );
+        }
+    }
+    Ok(template)
+}
+
+/// Settings passed to `git clone` whatever is cloned from.
+#[derive(Debug)]
+struct CommonCloneSettings<'a> {
+    /// Git config from a template in the user's own templates file.
+    repo_config: &'a BTreeMap<String, String>,
+    /// A repo to borrow objects from.
+    reference: Option<&'a Path>,
+}
+
+/// Clone `branch` (or every branch unless `single_branch` is set) from
+/// `source_url` into `destination_path` without checking it out.
+///
+/// Rather than running `git clone`, which refuses to clone into an existing
+/// directory, this initializes a repo and fetches into it, so the clone stage
+/// can be restarted idempotently after an interruption. A killed fetch
+/// discards the pack it was receiving, so restarting it fetches those objects
+/// again; only the objects of fetches which completed are kept.
+fn init_and_fetch(
+    source_url: &str,
+    destination_path: &Path,
+    branch: &str,
+    single_branch: bool,
+    fetch_args: &[String],
+    settings: &CommonCloneSettings,
+    app: Arc<App>,
+) -> Result<()> {
+    std::fs::create_dir_all(destination_path)
+        .with_context(|| format!("Creating {}", destination_path.display()))?;
+    let git = |args: &[&str]| -> Result<()> {
+        let (mut cmd, scmd) = git_helper::git_command(app.clone())?;
+        scmd.ensure_success_or_log(
+            cmd.current_dir(destination_path).args(args),
+            SandboxCommandOutput::Stderr,
+        )
+        .with_context(|| format!("git {} failed", args.join(" ")))?;
+        Ok(())
+    };
+
+    // Initializing an existing repo is harmless, so this is safe to repeat.
+    git(&["init", "--quiet"])?;
+    for (key, value) in settings.repo_config.iter() {
+        git(&["config", key, value])?;
+    }
+    git(&["config", "remote.origin.url", source_url])?;
+    let refspec = if single_branch {
+        format!("+refs/heads/{branch}:refs/remotes/origin/{branch}")
+    } else {
+        String::from("+refs/heads/*:refs/remotes/origin/*")
+    };
+    git(&["config", "--replace-all", "remote.origin.fetch", &refspec])?;
+    git(&["config", "remote.origin.tagOpt", "--no-tags"])?;
 
-        return err;
+    if let Some(reference) = settings.reference {
+        let reference_objects = match reference.join(".git").join("objects") {
+            objects if objects.is_dir() => objects,
+            _ => reference.join("objects"),
+        };
+        let info_dir = destination_path.join(".git").join("objects").join("info");
+        std::fs::create_dir_all(&info_dir).context("Creating .git/objects/info")?;
+        std::fs::write(
+            info_dir.join("alternates"),
+            format!("{}\n", reference_objects.display()),
+        )
+        .context("Writing .git/objects/info/alternates")?;
     }
 
+    let mut fetch = vec!["fetch", "--progress", "--no-tags"];
+    fetch.extend(fetch_args.iter().map(String::as_str));
+    fetch.push("origin");
+    git(&fetch)?;
+
+    let local_branch = format!("refs/heads/{branch}");
+    git(&[
+        "update-ref",
+        &local_branch,
+        &format!("refs/remotes/origin/{branch}"),
+    ])?;
+    git(&["symbolic-ref", "HEAD", &local_branch])?;
+    git(&["config", &format!("branch.{branch}.remote"), "origin"])?;
+    git(&["config", &format!("branch.{branch}.merge"), &local_branch])?;
     Ok(())
 }
 
-fn move_repo(from_path: &Path, to_path: &Path, tracker: &Tracker, app: Arc<App>) -> Result<()> {
-    std::fs::rename(from_path, to_path)?;
+/// Clone from a bundle written by `focus bundle create`. The bundle is only
+/// used for the initial history; `origin` is set up as the remote to fetch the
+/// rest from.
+fn clone_bundle(
+    bundle_path: &Path,
+    sparse_repo_path: &Path,
+    branch: &str,
+    origin: &str,
+    settings: &CommonCloneSettings,
+    app: Arc<App>,
+) -> Result<()> {
+    init_and_fetch(
+        &bundle_path.display().to_string(),
+        sparse_repo_path,
+        branch,
+        true,
+        &[],
+        settings,
+        app.clone(),
+    )
+    .context("Failed to clone the bundle")?;
 
+    let (mut cmd, scmd) = git_helper::git_command(app)?;
+    scmd.ensure_success_or_log(
+        cmd.current_dir(sparse_repo_path)
+            .arg("remote")
+            .arg("set-url")
+            .arg("origin")
+            .arg(origin),
+        SandboxCommandOutput::Stderr,
+    )
+    .context("Failed to set the origin of the bundled repo")?;
+    Ok(())
+}
+
+/// Remove what an interrupted attempt to set up the sparse repo left behind, so
+/// that it can be set up again.
+fn remove_incomplete_sparse_repo_set_up(sparse_repo_path: &Path, app: Arc<App>) -> Result<()> {
+    let outlining_tree_path = Repo::outlining_tree_path(&sparse_repo_path.join(".git"));
+    if !outlining_tree_path.exists() {
+        return Ok(());
+    }
+
+    info!(path = ?outlining_tree_path, "Removing incomplete outlining tree");
+    std::fs::remove_dir_all(&outlining_tree_path)
+        .context("Failed to remove incomplete outlining tree")?;
+    let (mut cmd, scmd) = git_helper::git_command(app)?;
+    scmd.ensure_success_or_log(
+        cmd.current_dir(sparse_repo_path)
+            .arg("worktree")
+            .arg("prune"),
+        SandboxCommandOutput::Stderr,
+    )
+    .context("git worktree prune failed")?;
+    Ok(())
+}
+
+/// Finish setting up a repo once it has been moved into place.
+fn register_moved_repo(path: &Path, tracker: &Tracker, app: Arc<App>) -> Result<()> {
     // The outlining tree needs to be updated
-    let repo = Repo::open(to_path, app.clone()).context("Failed to open repo")?;
+    let repo = Repo::open(path, app.clone()).context("Failed to open repo")?;
     repo.repair_outlining_tree()
         .context("Failed to repair the outlining tree after move")?;
 
     //register the repo
     tracker
-        .ensure_registered(to_path, app)
+        .ensure_registered(path, app)
         .context("Registering repo")?;
 
     Ok(())
@@ -449,6 +675,7 @@ fn clone_local(
     branch: &str,
     copy_branches: bool,
     days_of_history: u64,
+    settings: &CommonCloneSettings,
     app: Arc<App>,
 ) -> Result<()> {
     info!("Dense repo path: {}", dense_repo_path.display());
@@ -464,10 +691,6 @@ fn clone_local(
         bail!("Dense repo path must be absolute");
     }
 
-    if sparse_repo_path.is_dir() {
-        bail!("Sparse repo directory already exists");
-    }
-
     enable_filtering(&dense_repo_path)
         .context("setting configuration options in the dense repo")?;
 
@@ -483,6 +706,7 @@ fn clone_local(
             branch,
             copy_branches,
             days_of_history,
+            settings,
             app.clone(),
         )
         .context("Failed to clone the repository")?;
@@ -538,12 +762,9 @@ fn clone_remote(
     sparse_repo_path: &Path,
     branch: &str,
     days_of_history: u64,
+    settings: &CommonCloneSettings,
     app: Arc<App>,
 ) -> Result<()> {
-    if sparse_repo_path.is_dir() {
-        bail!("Sparse repo directory already exists");
-    }
-
     info!(
         "Cloning {} to {}",
         &dense_repo_url,
@@ -557,12 +778,13 @@ fn clone_remote(
         branch,
         false,
         days_of_history,
+        settings,
         app,
     )
     .context("Failed to clone the repository")
 }
 
-fn set_up_sparse_repo(
+pub(crate) fn set_up_sparse_repo(
     sparse_repo_path: &Path,
     projects_and_targets: Vec<String>,
     template: Option<ClonedRepoTemplate>,
@@ -594,7 +816,7 @@ fn set_up_sparse_repo(
     let odb = if repo.get_bazel_oneshot_resolution()? {
         None
     } else {
-        Some(RocksDBCache::new(repo.underlying()))
+        Some(RocksDBCache::new(repo.underlying())?)
     };
     repo.sync(
         head_commit.id(),
@@ -638,8 +860,8 @@ fn compute_and_store_initial_selection(
             .into_iter()
             .chain(
                 template
-                    .entries()
-                    .into_iter()
+                    .projects_and_targets
+                    .iter()
                     .map(|entry| Operation::new(OperationAction::default_add(), entry)),
             )
             .collect(),
@@ -659,14 +881,24 @@ fn compute_and_store_initial_selection(
     // their working tree and not ignored by default, which means that the next
     // `focus add`/`focus sync` attempt will fail because the working tree is
     // not clean. Improve the experience by ignoring `.focus` by default.
-    let info_dir = repo.git_dir().join("info");
+    // Git reads the excludes of linked worktrees from the repo they were added
+    // to, so they may already be set up.
+    let info_dir = repo.underlying().commondir().join("info");
     std::fs::create_dir_all(&info_dir).context("Creating .git/info")?;
     let exclude_path = info_dir.join("exclude");
-    let mut exclude_file = OpenOptions::new()
-        .append(true)
-        .open(exclude_path)
-        .context("Opening .git/info/exclude")?;
-    writeln!(exclude_file, "/.focus/").context("Writing initial .git/info/exclude")?;
+    let excludes = match std::fs::read_to_string(&exclude_path) {
+        Ok(excludes) => excludes,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e).context("Reading .git/info/exclude"),
+    };
+    if !excludes.lines().any(|line| line == "/.focus/") {
+        let mut exclude_file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(exclude_path)
+            .context("Opening .git/info/exclude")?;
+        writeln!(exclude_file, "/.focus/").context("Writing initial .git/info/exclude")?;
+    }
 
     Ok(target_set)
 }
@@ -677,6 +909,7 @@ fn clone_shallow(
     branch: &str,
     copy_branches: bool,
     days_of_history: u64,
+    settings: &CommonCloneSettings,
     app: Arc<App>,
 ) -> Result<()> {
     // Unfortunately time::duration is signed
@@ -711,22 +944,20 @@ fn clone_shallow(
         }
     }
 
-    let mut builder = CloneBuilder::new(destination_path.into());
-    builder
-        .fetch_url(source_url.as_str().into())
-        .no_checkout(true)
-        .follow_tags(false)
-        .branch(branch.into());
-
+    let mut fetch_args = Vec::new();
     if days_of_history > 0 {
-        builder.add_clone_arg(format!("--shallow-since={}", shallow_since_datestamp));
+        fetch_args.push(format!("--shallow-since={}", shallow_since_datestamp));
     }
 
-    if !copy_branches {
-        builder.add_clone_arg("--single-branch");
-    }
-    run_clone(builder, app)?;
-    Ok(())
+    init_and_fetch(
+        source_url.as_str(),
+        destination_path,
+        branch,
+        !copy_branches,
+        &fetch_args,
+        settings,
+        app,
+    )
 }
 
 fn set_up_remotes(
@@ -872,8 +1103,7 @@ fn copy_dense_config(dense_repo: &Repository, sparse_repo: &Repository) -> Resul
 }
 
 fn set_up_hooks(sparse_repo: &Path) -> Result<()> {
-    event::init(sparse_repo)?;
-    Ok(())
+    event::init_or_warn(sparse_repo)
 }
 
 /// Issues a git command to fetch from the default remote.
@@ -1027,63 +1257,15 @@ fn set_up_bazel_preflight_script(sparse_repo: &Path) -> Result<()> {
     Ok(())
 }
 
-#[derive(
-    Debug,
-    Copy,
-    Clone,
-    PartialEq,
-    Eq,
-    Hash,
-    strum_macros::Display,
-    strum_macros::EnumString,
-    strum_macros::EnumVariantNames,
-    strum_macros::IntoStaticStr,
-    strum_macros::EnumIter,
-)]
-#[strum(serialize_all = "snake_case")]
-pub enum ClonedRepoTemplate {
-    Bazel,
-    Envoy,
-    None,
-}
-
-impl ClonedRepoTemplate {
-    pub fn from_url(url: Url) -> Option<Self> {
-        match url.domain() {
-            Some("github.com") => {
-                let path = url.path();
-                let path = path.strip_suffix('/').unwrap_or(path);
-                match path {
-                    "/bazelbuild/bazel" => Some(Self::Bazel),
-                    "/envoyproxy/envoy" => Some(Self::Envoy),
-                    _ => None,
-                }
-            }
-            _ => None,
-        }
-    }
-
-    pub fn entries(&self) -> impl IntoIterator<Item = &'static str> {
-        match self {
-            ClonedRepoTemplate::Bazel => {
-                vec!["directory:third_party", "directory:tools"]
-            }
-            ClonedRepoTemplate::Envoy => {
-                vec!["directory:bazel", "directory:api", "directory:tools"]
-            }
-            ClonedRepoTemplate::None => vec![],
-        }
-    }
-}
-
 #[cfg(test)]
 mod test {
-    use crate::{clone::ClonedRepoTemplate, testing::integration::RepoPairFixture};
+    use super::*;
+    use crate::testing::integration::RepoPairFixture;
+    use assert_cmd::prelude::OutputAssertExt;
     use focus_internals::target::Target;
     use focus_testing::init_logging;
 
     use anyhow::Result;
-    use url::Url;
 
     #[test]
     fn clone_contains_an_initial_layer_set() -> Result<()> {
@@ -1117,26 +1299,221 @@ mod test {
     }
 
     #[test]
-    fn test_template_from_url() -> Result<()> {
-        assert_eq!(
-            ClonedRepoTemplate::from_url(Url::parse("https://github.com/envoyproxy/envoy")?),
-            Some(ClonedRepoTemplate::Envoy)
-        );
-        assert_eq!(
-            ClonedRepoTemplate::from_url(Url::parse("https://github.com/envoyproxy/envoy/")?),
-            Some(ClonedRepoTemplate::Envoy)
-        );
-        assert_eq!(
-            ClonedRepoTemplate::from_url(Url::parse("https://gitlab.com/envoyproxy/envoy/")?),
-            None
-        );
+    fn clone_uses_template_committed_to_origin() -> Result<()> {
+        init_logging();
+
+        let fixture = RepoPairFixture::new()?;
+        fixture.dense_repo.write_and_commit_file(
+            ClonedRepoTemplates::repo_path(),
+            r#"{
+                "templates": [
+                    {
+                        "name": "fixture",
+                        "origins": [".*"],
+                        "projects_and_targets": ["team_zissou/project_b"],
+                        "repo_config": {"focus.test.template": "fixture"}
+                    }
+                ]
+            }"#,
+            "Add clone templates",
+        )?;
+
+        fixture.perform_clone()?;
+
+        let repo = fixture.sparse_repo()?;
+        let selection = repo.selection_manager()?.computed_selection()?;
+        assert_eq!(selection.projects.len(), 1);
+        // Git config from a template committed to the origin is never applied,
+        // since it could run arbitrary commands.
+        let config = repo.underlying().config()?.snapshot()?;
+        assert!(config.get_str("focus.test.template").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn unknown_template_name_fails_before_cloning_a_remote_origin() -> Result<()> {
+        let origin = Origin::Remote(Url::parse("https://git.example.com/source")?);
+
+        let error = resolve_initial_template(&origin, Some("no_such_template"), false)
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("no_such_template"), "{}", error);
+
+        // Templates committed to the origin are only matched once it is cloned.
+        assert!(matches!(
+            resolve_initial_template(&origin, None, false)?,
+            TemplateChoice::Pending
+        ));
+        assert!(matches!(
+            resolve_initial_template(&origin, Some("envoy"), false)?,
+            TemplateChoice::Chosen(Some(_))
+        ));
+
+        Ok(())
+    }
+
+    fn clone_args(fixture: &RepoPairFixture) -> CloneArgs {
+        CloneArgs {
+            origin: Some(Origin::Local(fixture.dense_repo_path.clone())),
+            branch: Some(fixture.branch.clone()),
+            projects_and_targets: fixture.projects_and_targets.clone(),
+            do_post_clone_fetch: false,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn clone_borrows_objects_from_reference() -> Result<()> {
+        init_logging();
+
+        let fixture = RepoPairFixture::new()?;
+        let clone_args = CloneArgs {
+            reference: Some(fixture.dense_repo_path.clone()),
+            ..clone_args(&fixture)
+        };
+        run(
+            fixture.sparse_repo_path.clone(),
+            clone_args,
+            None,
+            &fixture.tracker,
+            fixture.app.clone(),
+        )?;
+
+        let alternates = std::fs::read_to_string(
+            fixture
+                .sparse_repo_path
+                .join(".git")
+                .join("objects")
+                .join("info")
+                .join("alternates"),
+        )?;
+        assert!(alternates.contains(fixture.dense_repo_path.to_str().unwrap()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn clone_from_bundle() -> Result<()> {
+        init_logging();
+
+        let fixture = RepoPairFixture::new()?;
+        let bundle_path = fixture.dir.path().join("dense.bundle");
+        fixture
+            .app
+            .git_binary()
+            .command()
+            .arg("bundle")
+            .arg("create")
+            .arg(&bundle_path)
+            .arg(&fixture.branch)
+            .current_dir(&fixture.dense_repo_path)
+            .assert()
+            .try_success()?;
+        BundleSidecar {
+            origin: fixture.dense_repo_path.display().to_string(),
+            branch: fixture.branch.clone(),
+            projects_and_targets: vec![String::from("team_zissou/project_b")],
+            index: Default::default(),
+        }
+        .save(&bundle_path)?;
+
+        let clone_args = CloneArgs {
+            origin: None,
+            branch: None,
+            bundle: Some(bundle_path),
+            ..clone_args(&fixture)
+        };
+        run(
+            fixture.sparse_repo_path.clone(),
+            clone_args,
+            None,
+            &fixture.tracker,
+            fixture.app.clone(),
+        )?;
+
+        let repo = fixture.sparse_repo()?;
+        let selection = repo.selection_manager()?.computed_selection()?;
+        assert_eq!(selection.projects.len(), 1);
+        let origin = repo.underlying().find_remote("origin")?;
         assert_eq!(
-            ClonedRepoTemplate::from_url(Url::parse("https://github.com/kubernetes/kubernetes")?),
-            None
+            origin.url(),
+            Some(fixture.dense_repo_path.display().to_string().as_str())
         );
 
         Ok(())
     }
+
+    #[test]
+    fn clone_stage_can_be_restarted_in_the_staged_repo() -> Result<()> {
+        init_logging();
+
+        let mut fixture = RepoPairFixture::new()?;
+        fixture
+            .projects_and_targets
+            .push(String::from("team_zissou/no_such_project"));
+        assert!(fixture.perform_clone().is_err());
+
+        // Pretend the clone stage itself was killed, leaving the staged repo
+        // behind.
+        let staging_dir = staging_dir_for(&fixture.sparse_repo_path)?;
+        let staged_git_dir = staging_dir.join(STAGED_REPO_DIR_NAME).join(".git");
+        let mut checkpoint = CloneCheckpoint::load(&staging_dir)?;
+        checkpoint.completed = CloneStage::Started;
+        checkpoint.projects_and_targets = vec![String::from("team_zissou/project_b")];
+        checkpoint.save(&staging_dir)?;
+        std::fs::write(staged_git_dir.join("interrupted-clone-marker"), "")?;
+
+        resume(
+            fixture.sparse_repo_path.clone(),
+            &fixture.tracker,
+            fixture.app.clone(),
+        )?;
+
+        // The clone stage ran again in the staged repo rather than replacing it.
+        let git_dir = fixture.sparse_repo_path.join(".git");
+        assert!(git_dir.join("interrupted-clone-marker").is_file());
+        assert!(!staging_dir.exists());
+        let selections = fixture.sparse_repo()?.selection_manager()?;
+        assert_eq!(selections.computed_selection()?.projects.len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn clone_can_be_resumed_after_failing() -> Result<()> {
+        init_logging();
+
+        let mut fixture = RepoPairFixture::new()?;
+        fixture
+            .projects_and_targets
+            .push(String::from("team_zissou/no_such_project"));
+
+        assert!(fixture.perform_clone().is_err());
+
+        let staging_dir = staging_dir_for(&fixture.sparse_repo_path)?;
+        let mut checkpoint = CloneCheckpoint::load(&staging_dir)?;
+        assert_eq!(checkpoint.completed, CloneStage::Cloned);
+        assert!(staging_dir.join(STAGED_REPO_DIR_NAME).join(".git").is_dir());
+        assert!(!fixture.sparse_repo_path.exists());
+
+        // A new clone must not clobber the interrupted one.
+        assert!(fixture.perform_clone().is_err());
+
+        checkpoint.projects_and_targets = vec![String::from("team_zissou/project_b")];
+        checkpoint.save(&staging_dir)?;
+        resume(
+            fixture.sparse_repo_path.clone(),
+            &fixture.tracker,
+            fixture.app.clone(),
+        )?;
+
+        assert!(!staging_dir.exists());
+        let selections = fixture.sparse_repo()?.selection_manager()?;
+        assert_eq!(selections.computed_selection()?.projects.len(), 1);
+
+        Ok(())
+    }
 }
 
 #[cfg(feature = "twttr")]
@@ -1268,9 +1645,8 @@ mod twttr_test {
         let post_merge_hook_contents =
             std::fs::read_to_string(git_repo.path().join("hooks").join("post-merge"))
                 .expect("Something went wrong reading the file");
-        assert_eq!(
-            post_merge_hook_contents,
-            format!("{} event post-merge\n", focus_exe_path)
+        assert!(
+            post_merge_hook_contents.contains(&format!("{} event post-merge\n", focus_exe_path))
         );
 
         // TODO: Test refspecs from remote config
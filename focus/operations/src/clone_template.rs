@@ -0,0 +1,280 @@
+// Copyright 2022 Twitter, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use focus_internals::model::configuration::Configuration;
+use focus_util::paths::focus_config_dir;
+use regex::Regex;
+use serde_derive::{Deserialize, Serialize};
+use tracing::{debug, warn};
+
+/// The name of the file templates are read from, both in the focus config
+/// directory and in the `.focus/config` directory of the origin repo.
+pub const CLONE_TEMPLATES_FILE_NAME: &str = "clone-templates.json";
+
+/// The name of the built-in template which adds nothing, to turn off the
+/// automatic choice of a template.
+pub const NO_TEMPLATE_NAME: &str = "none";
+
+/// Defaults for `focus new` for the repos whose origin it matches.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ClonedRepoTemplate {
+    /// The name to choose the template by with `focus new --template`.
+    pub name: String,
+
+    /// Regular expressions matched against the URL or path of the origin to
+    /// choose the template automatically.
+    pub origins: Vec<String>,
+
+    /// Projects and targets added to the initial selection.
+    pub projects_and_targets: Vec<String>,
+
+    /// The branch to clone if none is given.
+    pub branch: Option<String>,
+
+    /// The days of history to clone if none is given.
+    pub days_of_history: Option<u64>,
+
+    /// Git config to set in the new repo. Only honoured in the user's own
+    /// templates file, since settings like `core.fsmonitor` run commands.
+    pub repo_config: BTreeMap<String, String>,
+}
+
+impl ClonedRepoTemplate {
+    /// Whether the template should be used for `origin`.
+    pub fn matches(&self, origin: &str) -> Result<bool> {
+        for pattern in self.origins.iter() {
+            let regex = Regex::new(pattern).with_context(|| {
+                format!(
+                    "Invalid origin pattern {:?} in clone template {}",
+                    pattern, self.name
+                )
+            })?;
+            if regex.is_match(origin) {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// The templates which ship with focus, which are used if no configured
+    /// template applies.
+    pub fn builtin() -> Vec<Self> {
+        let github = |name: &str, path: &str, projects_and_targets: &[&str]| Self {
+            name: name.to_owned(),
+            origins: vec![format!(
+                r"^https://github\.com/{}(\.git)?/?$",
+                regex::escape(path)
+            )],
+            projects_and_targets: projects_and_targets
+                .iter()
+                .map(|entry| entry.to_string())
+                .collect(),
+            ..Default::default()
+        };
+        vec![
+            github(
+                "bazel",
+                "bazelbuild/bazel",
+                &["directory:third_party", "directory:tools"],
+            ),
+            github(
+                "envoy",
+                "envoyproxy/envoy",
+                &["directory:bazel", "directory:api", "directory:tools"],
+            ),
+            Self {
+                name: NO_TEMPLATE_NAME.to_owned(),
+                ..Default::default()
+            },
+        ]
+    }
+}
+
+/// A file of templates.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ClonedRepoTemplates {
+    pub templates: Vec<ClonedRepoTemplate>,
+}
+
+impl ClonedRepoTemplates {
+    /// The path of the templates which apply to every clone.
+    pub fn global_path() -> PathBuf {
+        focus_config_dir().join(CLONE_TEMPLATES_FILE_NAME)
+    }
+
+    /// The path of the templates kept in the repo itself.
+    pub fn repo_path() -> PathBuf {
+        Configuration::config_dir("").join(CLONE_TEMPLATES_FILE_NAME)
+    }
+
+    /// Read templates from a file, if it exists.
+    pub fn load(path: &Path) -> Result<Self> {
+        match std::fs::read(path) {
+            Ok(contents) => serde_json::from_slice(&contents)
+                .with_context(|| format!("Parsing clone templates in {}", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Default::default()),
+            Err(e) => {
+                Err(e).with_context(|| format!("Reading clone templates in {}", path.display()))
+            }
+        }
+    }
+
+    /// Read the templates committed at HEAD in a repo, if any. Anyone who can
+    /// commit to the repo can change these, so their git config is dropped.
+    pub fn load_from_head(repo: &git2::Repository) -> Result<Self> {
+        let head_tree = repo
+            .head()
+            .and_then(|head| head.peel_to_tree())
+            .context("Resolving HEAD tree")?;
+        let path = Self::repo_path();
+        let entry = match head_tree.get_path(&path) {
+            Ok(entry) => entry,
+            Err(e) if e.code() == git2::ErrorCode::NotFound => return Ok(Default::default()),
+            Err(e) => return Err(e).with_context(|| format!("Reading {}", path.display())),
+        };
+        let blob = entry
+            .to_object(repo)
+            .and_then(|object| object.peel_to_blob())
+            .with_context(|| format!("Reading {}", path.display()))?;
+        let mut templates: Self = serde_json::from_slice(blob.content())
+            .with_context(|| format!("Parsing clone templates in {}", path.display()))?;
+        for template in templates.templates.iter_mut() {
+            if !template.repo_config.is_empty() {
+                warn!(
+                    template = %template.name,
+                    keys = ?template.repo_config.keys().collect::<Vec<_>>(),
+                    "Ignoring git config in a clone template committed to the repo"
+                );
+                template.repo_config.clear();
+            }
+        }
+        Ok(templates)
+    }
+}
+
+/// Choose the template for a clone of `origin` from `sources`, in order of
+/// precedence, followed by the built-in templates. A template `name` given by
+/// the user must exist; otherwise the first template whose origin patterns
+/// match is chosen, if any.
+pub fn resolve(
+    name: Option<&str>,
+    origin: &str,
+    sources: &[ClonedRepoTemplates],
+) -> Result<Option<ClonedRepoTemplate>> {
+    let builtin = ClonedRepoTemplate::builtin();
+    let mut candidates = sources
+        .iter()
+        .flat_map(|source| source.templates.iter())
+        .chain(builtin.iter());
+    let template = match name {
+        Some(name) => match candidates.find(|template| template.name == name) {
+            Some(template) => Some(template),
+            None => bail!("There is no clone template named {:?}", name),
+        },
+        None => {
+            let mut found = None;
+            for template in candidates {
+                if template.matches(origin)? {
+                    found = Some(template);
+                    break;
+                }
+            }
+            found
+        }
+    };
+    debug!(?template, %origin, "Resolved clone template");
+    Ok(template.cloned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resolve_builtin(origin: &str) -> Result<Option<String>> {
+        Ok(resolve(None, origin, &[])?.map(|template| template.name))
+    }
+
+    #[test]
+    fn builtin_templates_match_their_origins() -> Result<()> {
+        assert_eq!(
+            resolve_builtin("https://github.com/envoyproxy/envoy")?,
+            Some(String::from("envoy"))
+        );
+        assert_eq!(
+            resolve_builtin("https://github.com/envoyproxy/envoy/")?,
+            Some(String::from("envoy"))
+        );
+        assert_eq!(
+            resolve_builtin("https://gitlab.com/envoyproxy/envoy/")?,
+            None
+        );
+        assert_eq!(
+            resolve_builtin("https://github.com/kubernetes/kubernetes")?,
+            None
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn configured_templates_take_precedence() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join(CLONE_TEMPLATES_FILE_NAME);
+        std::fs::write(
+            &path,
+            r#"{
+                "templates": [
+                    {
+                        "name": "monorepo",
+                        "origins": ["^https://git\\.example\\.com/source"],
+                        "projects_and_targets": ["team/base"],
+                        "branch": "main",
+                        "days_of_history": 30,
+                        "repo_config": {"fetch.writeCommitGraph": "true"}
+                    },
+                    {
+                        "name": "envoy",
+                        "projects_and_targets": ["directory:api"]
+                    }
+                ]
+            }"#,
+        )?;
+        let origin = ClonedRepoTemplates::load(&path)?;
+        let global = ClonedRepoTemplates {
+            templates: vec![ClonedRepoTemplate {
+                name: String::from("everything"),
+                origins: vec![String::from(".*")],
+                ..Default::default()
+            }],
+        };
+        let sources = [origin, global];
+
+        let template = resolve(None, "https://git.example.com/source.git", &sources)?.unwrap();
+        assert_eq!(template.name, "monorepo");
+        assert_eq!(template.branch.as_deref(), Some("main"));
+        assert_eq!(template.days_of_history, Some(30));
+        assert_eq!(template.repo_config["fetch.writeCommitGraph"], "true");
+
+        // The configured template overrides the built-in one of the same name.
+        let template = resolve(Some("envoy"), "/src/envoy", &sources)?.unwrap();
+        assert_eq!(template.projects_and_targets, vec!["directory:api"]);
+
+        assert_eq!(
+            resolve(None, "https://github.com/envoyproxy/envoy", &sources)?
+                .unwrap()
+                .name,
+            "everything"
+        );
+        assert!(resolve(Some("missing"), "/src/envoy", &sources).is_err());
+        assert_eq!(
+            ClonedRepoTemplates::load(&dir.path().join("missing.json"))?,
+            Default::default()
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,612 @@
+// Copyright 2022 Twitter, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt::Display;
+use std::io::{ErrorKind, Read, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Local, Utc};
+use focus_internals::model::repo::Repo;
+use focus_internals::tracker::Tracker;
+use focus_util::app::{App, ExitCode};
+use focus_util::paths::focus_config_dir;
+use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use serde_derive::{Deserialize, Serialize};
+use tracing::{debug, info, warn};
+
+use crate::sync::{SyncMode, SyncRequest};
+
+/// How often the daemon checks for repos which were added or removed.
+const RESCAN_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How often the daemon checks whether a repo has settled.
+const TICK: Duration = Duration::from_millis(250);
+
+/// How long `focus status` waits for the daemon to answer.
+const QUERY_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// The path of the socket the daemon answers status queries on.
+pub fn default_socket_path() -> PathBuf {
+    dirs::runtime_dir()
+        .unwrap_or_else(focus_config_dir)
+        .join("focus-daemon.sock")
+}
+
+/// What caused the daemon to sync a repo.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Trigger {
+    /// HEAD moved to another commit, so the working tree is synced.
+    Head,
+
+    /// Commits were fetched, so they are synced preemptively.
+    Refs,
+}
+
+impl Trigger {
+    fn sync_mode(&self) -> SyncMode {
+        match self {
+            Trigger::Head => SyncMode::Incremental,
+            Trigger::Refs => SyncMode::Preemptive { force: false },
+        }
+    }
+}
+
+/// The outcome of a sync run by the daemon.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SyncRecord {
+    pub trigger: Trigger,
+    pub finished_at: DateTime<Utc>,
+    pub outcome: String,
+}
+
+/// What the daemon knows about a repo it watches.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RepoStatus {
+    pub path: PathBuf,
+
+    /// Whether changes are waiting to settle before a sync.
+    pub pending: bool,
+
+    pub last_sync: Option<SyncRecord>,
+}
+
+impl Display for RepoStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "--- Background sync ---")?;
+        match self.last_sync.as_ref() {
+            Some(record) => writeln!(
+                f,
+                "Last synced at {} after {} changed: {}",
+                record
+                    .finished_at
+                    .with_timezone(&Local)
+                    .format("%Y-%m-%d %H:%M:%S"),
+                match record.trigger {
+                    Trigger::Head => "HEAD",
+                    Trigger::Refs => "fetched refs",
+                },
+                record.outcome
+            )?,
+            None => writeln!(f, "Not synced since the daemon started.")?,
+        }
+        if self.pending {
+            writeln!(f, "Changes are waiting to be synced.")?;
+        }
+        Ok(())
+    }
+}
+
+/// The state of the daemon, as reported over its socket.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DaemonStatus {
+    pub pid: u32,
+    pub started_at: DateTime<Utc>,
+    pub repos: Vec<RepoStatus>,
+}
+
+impl DaemonStatus {
+    /// The status of the repo at `path`, if the daemon watches it.
+    pub fn repo(&self, path: &Path) -> Option<&RepoStatus> {
+        let path = path.canonicalize().ok()?;
+        self.repos
+            .iter()
+            .find(|repo| repo.path.canonicalize().ok().as_ref() == Some(&path))
+    }
+
+    fn repo_mut(&mut self, path: &Path) -> Option<&mut RepoStatus> {
+        self.repos.iter_mut().find(|repo| repo.path == path)
+    }
+}
+
+/// Ask the daemon listening on `socket_path` for its status. Returns `None` if
+/// no daemon is running.
+pub fn query_status(socket_path: &Path) -> Result<Option<DaemonStatus>> {
+    let mut stream = match UnixStream::connect(socket_path) {
+        Ok(stream) => stream,
+        Err(e) if matches!(e.kind(), ErrorKind::NotFound | ErrorKind::ConnectionRefused) => {
+            return Ok(None)
+        }
+        Err(e) => {
+            return Err(e).with_context(|| format!("Connecting to {}", socket_path.display()))
+        }
+    };
+    stream.set_read_timeout(Some(QUERY_TIMEOUT))?;
+    let mut contents = Vec::new();
+    stream
+        .read_to_end(&mut contents)
+        .context("Reading the daemon status")?;
+    let status = serde_json::from_slice(&contents).context("Parsing the daemon status")?;
+    Ok(Some(status))
+}
+
+/// Listen on `socket_path`, replacing the socket of a daemon which is no
+/// longer running.
+fn bind(socket_path: &Path) -> Result<UnixListener> {
+    if socket_path.exists() {
+        if UnixStream::connect(socket_path).is_ok() {
+            bail!(
+                "The daemon is already running (listening on {})",
+                socket_path.display()
+            );
+        }
+        std::fs::remove_file(socket_path)
+            .with_context(|| format!("Removing stale socket {}", socket_path.display()))?;
+    }
+    if let Some(parent) = socket_path.parent() {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("Creating {}", parent.display()))?;
+    }
+    UnixListener::bind(socket_path).with_context(|| format!("Binding {}", socket_path.display()))
+}
+
+/// Answer every connection to `listener` with the current status.
+fn serve_status(listener: UnixListener, status: Arc<Mutex<DaemonStatus>>) {
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let result = stream.map_err(anyhow::Error::from).and_then(|mut stream| {
+                let status = status.lock().unwrap().clone();
+                stream.write_all(&serde_json::to_vec(&status)?)?;
+                Ok(())
+            });
+            if let Err(e) = result {
+                debug!(?e, "Failed to answer a status query");
+            }
+        }
+    });
+}
+
+/// The paths of a tracked repo that the daemon watches.
+#[derive(Clone, Debug)]
+struct WatchedRepo {
+    work_dir: PathBuf,
+    git_dir: PathBuf,
+    common_dir: PathBuf,
+}
+
+impl WatchedRepo {
+    fn open(path: &Path) -> Result<Self> {
+        let repo = git2::Repository::open(path)
+            .with_context(|| format!("Opening repo at {}", path.display()))?;
+        let work_dir = repo
+            .workdir()
+            .context("The repo has no working tree")?
+            .canonicalize()?;
+        Ok(Self {
+            work_dir,
+            git_dir: repo.path().canonicalize()?,
+            common_dir: repo.commondir().canonicalize()?,
+        })
+    }
+
+    fn prefetch_refs_dir(&self) -> PathBuf {
+        self.common_dir.join("refs").join("prefetch")
+    }
+
+    fn branches_dir(&self) -> PathBuf {
+        self.common_dir.join("refs").join("heads")
+    }
+
+    /// The paths to watch. The working tree is not watched, since it is only
+    /// synced once its changes are committed; the git dir is not watched
+    /// recursively, since it holds the outlining tree.
+    fn watches(&self) -> HashSet<(PathBuf, RecursiveMode)> {
+        HashSet::from([
+            (self.git_dir.clone(), RecursiveMode::NonRecursive),
+            (self.common_dir.join("refs"), RecursiveMode::Recursive),
+        ])
+    }
+
+    /// Whether a change to `path` should cause a sync, and which.
+    fn classify(&self, path: &Path) -> Option<Trigger> {
+        if path.starts_with(self.prefetch_refs_dir()) || path == self.git_dir.join("FETCH_HEAD") {
+            Some(Trigger::Refs)
+        } else if path.starts_with(self.branches_dir()) || path == self.git_dir.join("HEAD") {
+            Some(Trigger::Head)
+        } else {
+            None
+        }
+    }
+
+    /// Where HEAD is relative to the sync point of the working tree.
+    fn head_state(&self, app: Arc<App>) -> Result<HeadState> {
+        let repo = Repo::open(&self.work_dir, app.clone())?;
+        let working_tree = repo.working_tree()?;
+        let head = repo.get_head_commit()?.id();
+        if working_tree.read_sparse_sync_point_ref()? == Some(head) {
+            Ok(HeadState::Synced)
+        } else if working_tree.is_clean(app)? {
+            Ok(HeadState::Moved)
+        } else {
+            Ok(HeadState::Dirty)
+        }
+    }
+}
+
+/// Where HEAD is relative to the sync point.
+#[derive(Debug, PartialEq, Eq)]
+enum HeadState {
+    /// HEAD is at the sync point.
+    Synced,
+
+    /// HEAD moved and the working tree is clean, so it can be synced.
+    Moved,
+
+    /// HEAD moved, but the working tree has uncommitted changes.
+    Dirty,
+}
+
+/// Changes to a repo which have yet to settle.
+struct Pending {
+    /// When the changes have settled, unless more arrive before then.
+    deadline: Instant,
+    triggers: BTreeSet<Trigger>,
+}
+
+struct Daemon<'a> {
+    app: Arc<App>,
+    tracker: &'a Tracker,
+    debounce: Duration,
+    watcher: RecommendedWatcher,
+    repos: HashMap<PathBuf, WatchedRepo>,
+    watching: HashMap<PathBuf, HashSet<(PathBuf, RecursiveMode)>>,
+    pending: HashMap<PathBuf, Pending>,
+    status: Arc<Mutex<DaemonStatus>>,
+}
+
+impl Daemon<'_> {
+    /// Start watching newly tracked repos and stop watching those which are
+    /// gone.
+    fn rescan(&mut self) -> Result<()> {
+        let snapshot = self.tracker.scan().context("Scanning repositories")?;
+        let mut found = HashMap::new();
+        for tracked in snapshot.repos() {
+            match WatchedRepo::open(tracked.location()) {
+                Ok(repo) => {
+                    found.insert(repo.work_dir.clone(), repo);
+                }
+                Err(e) => warn!(path = ?tracked.location(), ?e, "Not watching repo"),
+            }
+        }
+
+        let gone: Vec<PathBuf> = self
+            .repos
+            .keys()
+            .filter(|path| !found.contains_key(*path))
+            .cloned()
+            .collect();
+        for path in gone {
+            info!(?path, "No longer watching repo");
+            for (watch, _) in self.watching.remove(&path).unwrap_or_default() {
+                self.watcher.unwatch(&watch).ok();
+            }
+            self.pending.remove(&path);
+        }
+        for path in found.keys() {
+            if !self.repos.contains_key(path) {
+                info!(?path, "Watching repo");
+            }
+        }
+        self.repos = found;
+        let paths: Vec<PathBuf> = self.repos.keys().cloned().collect();
+        for path in paths {
+            self.update_watches(&path);
+        }
+
+        let mut status = self.status.lock().unwrap();
+        let previous = std::mem::take(&mut status.repos);
+        status.repos = self
+            .repos
+            .keys()
+            .map(|path| {
+                previous
+                    .iter()
+                    .find(|repo| &repo.path == path)
+                    .cloned()
+                    .unwrap_or_else(|| RepoStatus {
+                        path: path.clone(),
+                        pending: false,
+                        last_sync: None,
+                    })
+            })
+            .collect();
+        status.repos.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(())
+    }
+
+    /// Watch what the repo at `path` needs watched, if not already watched.
+    fn update_watches(&mut self, path: &Path) {
+        let desired = match self.repos.get(path) {
+            Some(repo) => repo.watches(),
+            None => return,
+        };
+        let current = self.watching.entry(path.to_owned()).or_default();
+        for (watch, mode) in current.difference(&desired) {
+            debug!(?watch, ?mode, "Unwatching");
+            self.watcher.unwatch(watch).ok();
+        }
+        let mut watched = HashSet::new();
+        for (watch, mode) in desired {
+            if current.contains(&(watch.clone(), mode)) {
+                watched.insert((watch, mode));
+                continue;
+            }
+            match self.watcher.watch(&watch, mode) {
+                Ok(()) => {
+                    watched.insert((watch, mode));
+                }
+                // Git creates the refs directory lazily, so it may not exist yet.
+                Err(e) => debug!(?watch, ?e, "Failed to watch"),
+            }
+        }
+        *current = watched;
+    }
+
+    /// The repo and trigger for each path of `event`.
+    fn classify(&self, event: &Event) -> Vec<(PathBuf, Trigger)> {
+        if !matches!(
+            event.kind,
+            EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)
+        ) {
+            return Vec::new();
+        }
+        event
+            .paths
+            .iter()
+            .flat_map(|path| {
+                self.repos.iter().filter_map(move |(repo_path, repo)| {
+                    repo.classify(path)
+                        .map(|trigger| (repo_path.clone(), trigger))
+                })
+            })
+            .collect()
+    }
+
+    fn note(&mut self, event: &Event) {
+        for (path, trigger) in self.classify(event) {
+            debug!(?path, ?trigger, paths = ?event.paths, "Change");
+            let deadline = Instant::now() + self.debounce;
+            let pending = self.pending.entry(path.clone()).or_insert_with(|| Pending {
+                deadline,
+                triggers: BTreeSet::new(),
+            });
+            pending.deadline = deadline;
+            pending.triggers.insert(trigger);
+            if let Some(repo) = self.status.lock().unwrap().repo_mut(&path) {
+                repo.pending = true;
+            }
+        }
+    }
+
+    /// Sync the repos whose changes have settled.
+    fn sync_settled(&mut self) {
+        let now = Instant::now();
+        let settled: Vec<PathBuf> = self
+            .pending
+            .iter()
+            .filter(|(_, pending)| pending.deadline <= now)
+            .map(|(path, _)| path.clone())
+            .collect();
+        for path in settled {
+            let pending = self.pending.remove(&path).unwrap();
+            if let Some(repo) = self.status.lock().unwrap().repo_mut(&path) {
+                repo.pending = false;
+            }
+            for trigger in pending.triggers {
+                let outcome = match self.sync(&path, trigger) {
+                    Ok(Some(outcome)) => outcome,
+                    Ok(None) => continue,
+                    Err(e) => {
+                        warn!(?path, ?trigger, ?e, "Sync failed");
+                        format!("Failed: {:#}", e)
+                    }
+                };
+                info!(?path, ?trigger, %outcome, "Synced");
+                if let Some(repo) = self.status.lock().unwrap().repo_mut(&path) {
+                    repo.last_sync = Some(SyncRecord {
+                        trigger,
+                        finished_at: Utc::now(),
+                        outcome,
+                    });
+                }
+            }
+        }
+    }
+
+    /// Sync the repo at `path` for `trigger`, returning the outcome, or `None`
+    /// if HEAD is already synced. The working tree is never snapshotted, since
+    /// that cleans and resets it while the user may be editing it, so a
+    /// working tree with uncommitted changes is left for the user to sync.
+    fn sync(&self, path: &Path, trigger: Trigger) -> Result<Option<String>> {
+        if trigger == Trigger::Head {
+            match self.repos[path].head_state(self.app.clone())? {
+                HeadState::Synced => return Ok(None),
+                HeadState::Moved => (),
+                HeadState::Dirty => {
+                    return Ok(Some(String::from(
+                        "Skipped because the working tree has uncommitted changes",
+                    )))
+                }
+            }
+        }
+        let request = SyncRequest::new(path, trigger.sync_mode()).without_snapshot();
+        let result = crate::sync::run(&request, self.app.clone())?;
+        Ok(Some(format!("{:?}", result.status)))
+    }
+}
+
+/// Watch the repos registered with `tracker` and sync them when commits are
+/// fetched or checked out, once changes have stopped for `debounce`.
+/// The daemon runs until it is killed, answering status queries on
+/// `socket_path`.
+pub fn run(
+    app: Arc<App>,
+    tracker: &Tracker,
+    socket_path: &Path,
+    debounce: Duration,
+) -> Result<ExitCode> {
+    let listener = bind(socket_path)?;
+    let status = Arc::new(Mutex::new(DaemonStatus {
+        pid: std::process::id(),
+        started_at: Utc::now(),
+        repos: Vec::new(),
+    }));
+    serve_status(listener, status.clone());
+
+    let (sender, events) = mpsc::channel();
+    let watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+        sender.send(event).ok();
+    })
+    .context("Creating a file system watcher")?;
+    let mut daemon = Daemon {
+        app,
+        tracker,
+        debounce,
+        watcher,
+        repos: HashMap::new(),
+        watching: HashMap::new(),
+        pending: HashMap::new(),
+        status,
+    };
+
+    info!(socket = ?socket_path, ?debounce, "Daemon started");
+    let mut last_scan: Option<Instant> = None;
+    loop {
+        if last_scan.map_or(true, |time| time.elapsed() >= RESCAN_INTERVAL) {
+            if let Err(e) = daemon.rescan() {
+                warn!(?e, "Failed to update the watched repos");
+            }
+            last_scan = Some(Instant::now());
+        }
+
+        match events.recv_timeout(TICK) {
+            Ok(Ok(event)) => daemon.note(&event),
+            Ok(Err(e)) => warn!(?e, "File system watcher error"),
+            Err(RecvTimeoutError::Timeout) => (),
+            Err(RecvTimeoutError::Disconnected) => bail!("The file system watcher stopped"),
+        }
+
+        daemon.sync_settled();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn changes_are_classified_by_what_they_touch() -> Result<()> {
+        let repo = WatchedRepo {
+            work_dir: PathBuf::from("/src/repo"),
+            git_dir: PathBuf::from("/src/repo/.git"),
+            common_dir: PathBuf::from("/src/repo/.git"),
+        };
+        let classify = |path: &str| repo.classify(Path::new(path));
+        assert_eq!(classify("/src/repo/library/BUILD"), None);
+        assert_eq!(classify("/src/repo/tools/defs.bzl"), None);
+        assert_eq!(classify("/src/repo/.git/index"), None);
+        assert_eq!(classify("/src/repo/.git/HEAD"), Some(Trigger::Head));
+        assert_eq!(
+            classify("/src/repo/.git/refs/heads/main"),
+            Some(Trigger::Head)
+        );
+        assert_eq!(
+            classify("/src/repo/.git/refs/prefetch/remotes/origin/main"),
+            Some(Trigger::Refs)
+        );
+        assert_eq!(classify("/src/repo/.git/FETCH_HEAD"), Some(Trigger::Refs));
+        assert_eq!(classify("/src/repo/.git/refs/focus/sync"), None);
+        assert_eq!(classify("/src/other/.git/HEAD"), None);
+
+        let worktree = WatchedRepo {
+            work_dir: PathBuf::from("/src/feature"),
+            git_dir: PathBuf::from("/src/repo/.git/worktrees/feature"),
+            common_dir: PathBuf::from("/src/repo/.git"),
+        };
+        assert_eq!(
+            worktree.classify(Path::new("/src/repo/.git/worktrees/feature/FETCH_HEAD")),
+            Some(Trigger::Refs)
+        );
+        assert_eq!(
+            worktree.classify(Path::new("/src/repo/.git/worktrees/feature/HEAD")),
+            Some(Trigger::Head)
+        );
+        assert_eq!(worktree.classify(Path::new("/src/repo/.git/HEAD")), None);
+        Ok(())
+    }
+
+    #[test]
+    fn only_git_dir_and_refs_are_watched() -> Result<()> {
+        let repo = WatchedRepo {
+            work_dir: PathBuf::from("/src/feature"),
+            git_dir: PathBuf::from("/src/repo/.git/worktrees/feature"),
+            common_dir: PathBuf::from("/src/repo/.git"),
+        };
+        assert_eq!(
+            repo.watches(),
+            HashSet::from([
+                (
+                    PathBuf::from("/src/repo/.git/worktrees/feature"),
+                    RecursiveMode::NonRecursive
+                ),
+                (
+                    PathBuf::from("/src/repo/.git/refs"),
+                    RecursiveMode::Recursive
+                ),
+            ])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn status_is_served_over_the_socket() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let socket_path = dir.path().join("focus-daemon.sock");
+        assert_eq!(query_status(&socket_path)?, None);
+
+        let repo_path = dir.path().join("repo");
+        std::fs::create_dir(&repo_path)?;
+        let status = DaemonStatus {
+            pid: std::process::id(),
+            started_at: Utc::now(),
+            repos: vec![RepoStatus {
+                path: repo_path.clone(),
+                pending: true,
+                last_sync: None,
+            }],
+        };
+        serve_status(bind(&socket_path)?, Arc::new(Mutex::new(status.clone())));
+        assert!(bind(&socket_path).is_err());
+
+        let served = query_status(&socket_path)?.unwrap();
+        assert_eq!(served, status);
+        assert!(served.repo(&repo_path).unwrap().pending);
+        assert!(served.repo(dir.path()).is_none());
+        Ok(())
+    }
+}
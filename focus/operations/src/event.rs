@@ -1,47 +1,288 @@
 // Copyright 2022 Twitter, Inc.
 // SPDX-License-Identifier: Apache-2.0
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use focus_util::app::{App, ExitCode};
+use focus_util::git_helper::{get_changed_paths_between_trees, ConfigExt, INSIDE_FOCUS_ENV_VAR};
+use focus_util::paths::is_relevant_to_build_graph;
+use git2::{ConfigLevel, Oid, Repository};
 use std::io::Write;
+use std::os::unix::ffi::OsStrExt;
 use std::os::unix::fs::OpenOptionsExt;
-use std::path::PathBuf;
+use std::path::{Component, PathBuf};
 use std::{fs::File, path::Path, sync::Arc};
-use tracing::debug;
+use tracing::{debug, info, warn};
 
 use crate::sync::{SyncMode, SyncRequest};
 
+/// The hooks focus installs.
+const HOOKS: &[&str] = &["post-merge", "post-commit", "post-checkout"];
+
+/// The hooks which pass their arguments on to focus.
+const HOOKS_WITH_ARGUMENTS: &[&str] = &["post-checkout"];
+
+/// Whether to sync after checking out a commit whose build graph differs, or
+/// only to suggest doing so.
+const SYNC_ON_CHECKOUT_KEY: &str = "focus.hooks.synconcheckout";
+
+/// Marks the hook scripts written by focus, so they can be told apart from
+/// those written by other tools.
+const FOCUS_HOOK_MARKER: &str = "# Installed by focus";
+
+/// The suffix given to a pre-existing hook which the focus hook chains to.
+const CHAINED_HOOK_SUFFIX: &str = "pre-focus";
+
+/// Returned when `core.hooksPath` names a directory which may be shared with
+/// other repositories, where focus won't write its hooks.
+#[derive(thiserror::Error, Debug)]
+#[error(
+    "core.hooksPath is set to {} in the {level:?} git config, outside of this repository; \
+     focus won't install its hooks in a directory that other repositories may use. \
+     Set core.hooksPath with `git config --local` or unset it to install them.",
+    .path.display()
+)]
+pub struct SharedHooksDir {
+    pub path: PathBuf,
+    pub level: ConfigLevel,
+}
+
+/// Returned when `core.hooksPath` names a directory in the working tree which
+/// is tracked by the repository, such as one managed by husky, where the hooks
+/// focus writes would show up as changes to commit.
+#[derive(thiserror::Error, Debug)]
+#[error(
+    "core.hooksPath is set to {}, which is tracked by this repository; \
+     focus won't overwrite the hooks committed there. \
+     Run `focus event <hook>` from those hooks to get focus's behaviour.",
+    .path.display()
+)]
+pub struct TrackedHooksDir {
+    pub path: PathBuf,
+}
+
 /// Initializes hooks in passed in repo
 pub fn init(repo_path: &Path) -> Result<()> {
-    debug!("Writing hooks to {}", repo_path.display());
+    let hooks_dir = hooks_dir(repo_path)?;
+    debug!("Writing hooks to {}", hooks_dir.display());
+    std::fs::create_dir_all(&hooks_dir)
+        .with_context(|| format!("creating {}", hooks_dir.display()))?;
+    write_hooks_to_dir(HOOKS, &hooks_dir)?;
 
-    let hooks = vec!["post-merge", "post-commit"];
-    let hooks_dir = repo_path.join(".git").join("hooks");
-    write_hooks_to_dir(&hooks, &hooks_dir)?;
+    // Focus used to write its hooks to the default directory regardless of
+    // `core.hooksPath`, where git would never run them.
+    let default_hooks_dir = default_hooks_dir(repo_path)?;
+    if default_hooks_dir != hooks_dir {
+        remove_hooks_from_dir(HOOKS, &default_hooks_dir)?;
+    }
 
     Ok(())
 }
 
+/// Initializes hooks in passed in repo like [init], but only warns if
+/// `core.hooksPath` is shared with other repositories or tracked by this one.
+pub fn init_or_warn(repo_path: &Path) -> Result<()> {
+    match init(repo_path) {
+        Err(e)
+            if e.downcast_ref::<SharedHooksDir>().is_some()
+                || e.downcast_ref::<TrackedHooksDir>().is_some() =>
+        {
+            warn!("Not installing hooks: {}", e);
+            Ok(())
+        }
+        result => result,
+    }
+}
+
+/// Removes the hooks installed by focus from the passed in repo, restoring any
+/// hooks they chained to.
+pub fn uninstall(repo_path: &Path) -> Result<()> {
+    let hooks_dir = hooks_dir(repo_path)?;
+    debug!("Removing hooks from {}", hooks_dir.display());
+    remove_hooks_from_dir(HOOKS, &hooks_dir)
+}
+
+/// The directory git runs the hooks of the passed in repo from, honouring
+/// `core.hooksPath`. Fails with [SharedHooksDir] unless `core.hooksPath` is
+/// set in the repo's own config or points inside the repo, and with
+/// [TrackedHooksDir] if it points to a directory tracked by the repo.
+pub fn hooks_dir(repo_path: &Path) -> Result<PathBuf> {
+    let repo = Repository::open(repo_path)
+        .with_context(|| format!("opening repo at {}", repo_path.display()))?;
+    let config = repo.config().context("reading repo config")?;
+    configured_hooks_dir(&repo, &config)
+}
+
+fn configured_hooks_dir(repo: &Repository, config: &git2::Config) -> Result<PathBuf> {
+    let level = match config.get_entry("core.hooksPath") {
+        Ok(entry) => entry.level(),
+        Err(e) if e.code() == git2::ErrorCode::NotFound => return Ok(repo.path().join("hooks")),
+        Err(e) => return Err(e).context("reading core.hooksPath"),
+    };
+    let path = config
+        .get_path("core.hooksPath")
+        .context("reading core.hooksPath")?;
+    // Relative paths are relative to where git runs hooks, which is the top of
+    // the working tree.
+    let top = repo.workdir().unwrap_or_else(|| repo.path());
+    let path = top.join(path);
+    if is_tracked_dir(repo, &path)? {
+        Err(TrackedHooksDir { path }.into())
+    } else if level == ConfigLevel::Local || is_within(&path, top) || is_within(&path, repo.path())
+    {
+        Ok(path)
+    } else {
+        Err(SharedHooksDir { path, level }.into())
+    }
+}
+
+/// Whether any file in `path` is in the index of the repo.
+fn is_tracked_dir(repo: &Repository, path: &Path) -> Result<bool> {
+    let relative_path = match repo.workdir() {
+        Some(workdir) if is_within(path, workdir) => path.strip_prefix(workdir)?,
+        _ => return Ok(false),
+    };
+    let mut prefix = relative_path.as_os_str().as_bytes().to_vec();
+    if !prefix.is_empty() {
+        prefix.push(b'/');
+    }
+    let index = repo.index().context("reading the index")?;
+    let is_tracked = index.iter().any(|entry| entry.path.starts_with(&prefix));
+    Ok(is_tracked)
+}
+
+fn is_within(path: &Path, dir: &Path) -> bool {
+    path.starts_with(dir)
+        && !path
+            .components()
+            .any(|component| component == Component::ParentDir)
+}
+
+fn default_hooks_dir(repo_path: &Path) -> Result<PathBuf> {
+    let repo = Repository::open(repo_path)
+        .with_context(|| format!("opening repo at {}", repo_path.display()))?;
+    Ok(repo.path().join("hooks"))
+}
+
+fn chained_hook_path(dir: &Path, hook: &str) -> PathBuf {
+    dir.join(format!("{}.{}", hook, CHAINED_HOOK_SUFFIX))
+}
+
+/// Whether the hook script was written by focus, including the single line
+/// scripts written by earlier versions.
+fn is_focus_hook(contents: &str, hook: &str) -> bool {
+    if contents
+        .lines()
+        .any(|line| line.starts_with(FOCUS_HOOK_MARKER))
+    {
+        return true;
+    }
+    let mut lines = contents.lines();
+    match (lines.next(), lines.next()) {
+        (Some(line), None) => line.ends_with(&format!(" event {}", hook)),
+        _ => false,
+    }
+}
+
+fn hook_script(focus_exe_path: &str, hook: &str) -> String {
+    format!(
+        r#"#!/bin/sh
+{marker}. Remove with `focus hooks uninstall`.
+# Runs the hook this replaced, if any, and then notifies focus.
+chained_hook="$(dirname "$0")/{hook}.{suffix}"
+status=0
+if [ -x "$chained_hook" ]; then
+    "$chained_hook" "$@"
+    status=$?
+fi
+{focus} event {hook}{args}
+exit $status
+"#,
+        marker = FOCUS_HOOK_MARKER,
+        hook = hook,
+        suffix = CHAINED_HOOK_SUFFIX,
+        focus = focus_exe_path,
+        args = if HOOKS_WITH_ARGUMENTS.contains(&hook) {
+            r#" "$@""#
+        } else {
+            ""
+        },
+    )
+}
+
 fn write_hooks_to_dir(hooks: &[&str], dir: &Path) -> Result<()> {
+    let focus_exe = &std::env::current_exe().unwrap_or_else(|_| PathBuf::from("focus"));
+    let focus_exe_path = focus_exe.file_name().unwrap().to_string_lossy();
     for hook in hooks {
-        let focus_exe = &std::env::current_exe().unwrap_or_else(|_| PathBuf::from("focus"));
-        let focus_exe_path = focus_exe.file_name().unwrap().to_string_lossy();
-        let contents = format!("{} event {}", focus_exe_path, hook);
-
         let file_path = dir.join(hook);
+        match std::fs::read_to_string(&file_path) {
+            Ok(contents) if is_focus_hook(&contents, hook) => {}
+            Ok(_) => {
+                let chained_path = chained_hook_path(dir, hook);
+                if chained_path.exists() {
+                    anyhow::bail!(
+                        "Cannot install the {} hook: both {} and {} already exist",
+                        hook,
+                        file_path.display(),
+                        chained_path.display()
+                    );
+                }
+                debug!(?file_path, ?chained_path, "Chaining to existing hook");
+                std::fs::rename(&file_path, &chained_path).with_context(|| {
+                    format!(
+                        "moving {} to {}",
+                        file_path.display(),
+                        chained_path.display()
+                    )
+                })?;
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e).context(format!("reading {}", file_path.display())),
+        }
+
         let mut file = File::options()
             .write(true)
             .create(true)
+            .truncate(true)
             .mode(0o755)
             .open(&file_path)
             .context(format!("opening/creating {}", hook))?;
-        writeln!(file, "{}", contents)
+        file.write_all(hook_script(&focus_exe_path, hook).as_bytes())
             .context(format!("writing contents to {}", file_path.display()))?;
     }
 
     Ok(())
 }
 
+fn remove_hooks_from_dir(hooks: &[&str], dir: &Path) -> Result<()> {
+    for hook in hooks {
+        let file_path = dir.join(hook);
+        match std::fs::read_to_string(&file_path) {
+            Ok(contents) if is_focus_hook(&contents, hook) => {
+                std::fs::remove_file(&file_path)
+                    .with_context(|| format!("removing {}", file_path.display()))?;
+            }
+            // Leave hooks written by others alone.
+            Ok(_) => continue,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e).context(format!("reading {}", file_path.display())),
+        }
+
+        let chained_path = chained_hook_path(dir, hook);
+        if chained_path.exists() {
+            debug!(?chained_path, ?file_path, "Restoring chained hook");
+            std::fs::rename(&chained_path, &file_path).with_context(|| {
+                format!(
+                    "moving {} to {}",
+                    chained_path.display(),
+                    file_path.display()
+                )
+            })?;
+        }
+    }
+
+    Ok(())
+}
+
 pub fn post_merge(app: Arc<App>) -> Result<ExitCode> {
     let current_dir = std::env::current_dir().context("Failed to obtain current directory")?;
     debug!(sparse_repo = ?current_dir.display(), "Running post-merge hook");
@@ -49,12 +290,79 @@ pub fn post_merge(app: Arc<App>) -> Result<ExitCode> {
     Ok(ExitCode(0))
 }
 
-pub fn post_checkout(_app: Arc<App>) -> Result<ExitCode> {
+/// Handles the post-checkout hook, which git runs with the previous and new
+/// HEAD and a flag which is 1 for a branch checkout and 0 for a file checkout.
+/// Syncs if the build graph differs between the two commits.
+pub fn post_checkout(app: Arc<App>, args: &[String]) -> Result<ExitCode> {
     let current_dir = std::env::current_dir().context("Failed to obtain current directory")?;
-    debug!(sparse_repo = ?current_dir.display(), "Running post-checkout hook");
+    debug!(sparse_repo = ?current_dir.display(), ?args, "Running post-checkout hook");
+
+    if std::env::var_os(INSIDE_FOCUS_ENV_VAR).is_some() {
+        debug!("Skipping post-checkout hook run by focus");
+        return Ok(ExitCode(0));
+    }
+
+    let (prev_head, new_head) = match args {
+        [prev_head, new_head, flag] => {
+            if flag != "1" {
+                debug!("Skipping file checkout");
+                return Ok(ExitCode(0));
+            }
+            (
+                Oid::from_str(prev_head).context("Parsing previous HEAD")?,
+                Oid::from_str(new_head).context("Parsing new HEAD")?,
+            )
+        }
+        _ => bail!(
+            "Expected the previous HEAD, new HEAD and checkout flag, got {:?}",
+            args
+        ),
+    };
+
+    let repo = Repository::open(&current_dir).context("Opening repo")?;
+    if !build_graph_changed(&repo, prev_head, new_head)? {
+        debug!("Build graph is unchanged, not syncing");
+        return Ok(ExitCode(0));
+    }
+
+    let sync_on_checkout = repo
+        .config()
+        .context("Reading repo config")?
+        .get_bool_with_default(SYNC_ON_CHECKOUT_KEY, true)?;
+    if sync_on_checkout {
+        info!("The build graph changed, syncing");
+        crate::sync::run(&SyncRequest::new(&current_dir, SyncMode::Incremental), app)?;
+    } else {
+        eprintln!(
+            "The build graph changed since the previous checkout, run `focus sync` to update the outline"
+        );
+    }
     Ok(ExitCode(0))
 }
 
+/// Whether any file relevant to the build graph differs between the two
+/// commits. A zero ID, as passed when there was no previous HEAD, counts as an
+/// empty tree.
+fn build_graph_changed(repo: &Repository, prev_head: Oid, new_head: Oid) -> Result<bool> {
+    if prev_head == new_head {
+        return Ok(false);
+    }
+    let tree_of = |oid: Oid| -> Result<Option<git2::Tree>> {
+        if oid.is_zero() {
+            return Ok(None);
+        }
+        let commit = repo
+            .find_commit(oid)
+            .with_context(|| format!("Finding commit {}", oid))?;
+        Ok(Some(commit.tree().context("Getting commit tree")?))
+    };
+    let prev_tree = tree_of(prev_head)?;
+    let new_tree = tree_of(new_head)?;
+    let changed_paths =
+        get_changed_paths_between_trees(repo, prev_tree.as_ref(), new_tree.as_ref())?;
+    Ok(changed_paths.iter().any(is_relevant_to_build_graph))
+}
+
 pub fn post_commit(_app: Arc<App>) -> Result<ExitCode> {
     let current_dir = std::env::current_dir().context("Failed to obtain current directory")?;
     debug!(sparse_repo = ?current_dir.display(), "Running post-commit hook");
@@ -79,11 +387,178 @@ pub(crate) mod testing {
         write_hooks_to_dir(&hook_names, temp_dir_path)?;
 
         for hook in hook_names {
-            let expected_content = format!("{} event {}\n", focus_exe_path, hook);
             let content = fs::read_to_string(temp_dir_path.join(hook))
                 .context(format!("Could not read hook {}", hook))?;
-            assert_eq!(content, expected_content);
+            assert_eq!(content, hook_script(&focus_exe_path, hook));
+            assert!(content.contains(&format!("{} event {}\n", focus_exe_path, hook)));
+            assert!(is_focus_hook(&content, hook));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn install_chains_existing_hooks_and_uninstall_restores_them() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let repo = Repository::init(temp_dir.path())?;
+        let hooks_dir = repo.path().join("hooks");
+        fs::create_dir_all(&hooks_dir)?;
+        let team_hook = "#!/bin/sh\necho team hook\n";
+        fs::write(hooks_dir.join("post-merge"), team_hook)?;
+
+        init(temp_dir.path())?;
+        // Installing again must not chain focus's hook to itself.
+        init(temp_dir.path())?;
+
+        assert!(is_focus_hook(
+            &fs::read_to_string(hooks_dir.join("post-merge"))?,
+            "post-merge"
+        ));
+        assert_eq!(
+            fs::read_to_string(hooks_dir.join("post-merge.pre-focus"))?,
+            team_hook
+        );
+        assert!(!hooks_dir.join("post-commit.pre-focus").exists());
+
+        uninstall(temp_dir.path())?;
+
+        assert_eq!(fs::read_to_string(hooks_dir.join("post-merge"))?, team_hook);
+        assert!(!hooks_dir.join("post-merge.pre-focus").exists());
+        assert!(!hooks_dir.join("post-commit").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn post_checkout_hook_passes_arguments() {
+        assert!(
+            hook_script("focus", "post-checkout").contains("focus event post-checkout \"$@\"\n")
+        );
+        assert!(hook_script("focus", "post-merge").contains("focus event post-merge\n"));
+    }
+
+    #[test]
+    fn build_graph_changed_detects_build_files() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let repo = Repository::init(temp_dir.path())?;
+        let signature = git2::Signature::now("Focus", "focus@example.com")?;
+        let commit = |path: &str, contents: &str, parent: Option<Oid>| -> Result<Oid> {
+            let full_path = temp_dir.path().join(path);
+            fs::create_dir_all(full_path.parent().unwrap())?;
+            fs::write(&full_path, contents)?;
+            let mut index = repo.index()?;
+            index.add_path(Path::new(path))?;
+            let tree = repo.find_tree(index.write_tree()?)?;
+            index.write()?;
+            let parents = parent.map(|oid| repo.find_commit(oid)).transpose()?;
+            let parents: Vec<&git2::Commit> = parents.iter().collect();
+            Ok(repo.commit(None, &signature, &signature, "commit", &tree, &parents)?)
+        };
+
+        let base = commit("library/BUILD", "java_library()", None)?;
+        let source_change = commit("library/Main.java", "class Main {}", Some(base))?;
+        let build_change = commit(
+            "library/BUILD",
+            "java_library(deps = [])",
+            Some(source_change),
+        )?;
+
+        assert!(!build_graph_changed(&repo, base, base)?);
+        assert!(!build_graph_changed(&repo, base, source_change)?);
+        assert!(build_graph_changed(&repo, source_change, build_change)?);
+        assert!(build_graph_changed(&repo, build_change, base)?);
+        assert!(build_graph_changed(&repo, Oid::zero(), base)?);
+        Ok(())
+    }
+
+    #[test]
+    fn install_refuses_shared_core_hooks_path() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let repo_dir = temp_dir.path().join("repo");
+        let repo = Repository::init(&repo_dir)?;
+        let shared_hooks_dir = temp_dir.path().join("shared-hooks");
+        let global_config_path = temp_dir.path().join("gitconfig");
+        fs::write(
+            &global_config_path,
+            format!("[core]\n\thooksPath = {}\n", shared_hooks_dir.display()),
+        )?;
+        let mut config = repo.config()?;
+        config.add_file(&global_config_path, ConfigLevel::Global, false)?;
+
+        let error = configured_hooks_dir(&repo, &config).unwrap_err();
+        assert!(error.downcast_ref::<SharedHooksDir>().is_some());
+
+        // An untracked hooks directory inside the repo is fine wherever it is
+        // configured.
+        let inside_hooks_dir = repo_dir.join(".husky");
+        fs::write(
+            &global_config_path,
+            format!("[core]\n\thooksPath = {}\n", inside_hooks_dir.display()),
+        )?;
+        let mut config = repo.config()?;
+        config.add_file(&global_config_path, ConfigLevel::Global, false)?;
+        assert_eq!(configured_hooks_dir(&repo, &config)?, inside_hooks_dir);
+
+        // But once the repo tracks it, as with husky, its hooks are left alone.
+        fs::create_dir_all(&inside_hooks_dir)?;
+        fs::write(inside_hooks_dir.join("post-merge"), "#!/bin/sh\nnpm test\n")?;
+        let mut index = repo.index()?;
+        index.add_path(Path::new(".husky/post-merge"))?;
+        index.write()?;
+        let error = configured_hooks_dir(&repo, &config).unwrap_err();
+        assert!(error.downcast_ref::<TrackedHooksDir>().is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn install_honours_core_hooks_path() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let repo = Repository::init(temp_dir.path())?;
+        repo.config()?.set_str("core.hooksPath", ".husky")?;
+        let default_hooks_dir = repo.path().join("hooks");
+        fs::create_dir_all(&default_hooks_dir)?;
+        fs::write(
+            default_hooks_dir.join("post-merge"),
+            "focus event post-merge\n",
+        )?;
+
+        init(temp_dir.path())?;
+
+        let husky_dir = temp_dir.path().join(".husky");
+        assert_eq!(hooks_dir(temp_dir.path())?, husky_dir);
+        for hook in HOOKS {
+            assert!(is_focus_hook(
+                &fs::read_to_string(husky_dir.join(hook))?,
+                hook
+            ));
         }
+        // The hook written by an earlier version to the wrong directory is
+        // removed.
+        assert!(!default_hooks_dir.join("post-merge").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn install_skips_tracked_core_hooks_path() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let repo = Repository::init(temp_dir.path())?;
+        repo.config()?.set_str("core.hooksPath", ".husky")?;
+        let husky_dir = temp_dir.path().join(".husky");
+        fs::create_dir_all(&husky_dir)?;
+        let husky_hook = "#!/bin/sh\nnpm test\n";
+        fs::write(husky_dir.join("post-merge"), husky_hook)?;
+        let mut index = repo.index()?;
+        index.add_path(Path::new(".husky/post-merge"))?;
+        index.write()?;
+
+        let error = init(temp_dir.path()).unwrap_err();
+        assert!(error.downcast_ref::<TrackedHooksDir>().is_some());
+        init_or_warn(temp_dir.path())?;
+
+        assert_eq!(
+            fs::read_to_string(husky_dir.join("post-merge"))?,
+            husky_hook
+        );
+        assert!(!husky_dir.join("post-merge.pre-focus").exists());
+        assert!(!husky_dir.join("post-commit").exists());
         Ok(())
     }
 }
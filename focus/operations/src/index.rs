@@ -6,33 +6,44 @@ use std::collections::{BTreeSet, HashSet};
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use anyhow::Context;
-use content_addressed_cache::{Cache, CacheSynchronizer, GitBackedCacheSynchronizer, KeysetID};
+use content_addressed_cache::{
+    Cache, CacheKey, CacheKeyKind, CacheSynchronizer, GitBackedCacheSynchronizer, Keyset, KeysetID,
+    KeysetNotFound, ObjectStoreCacheSynchronizer,
+};
 use focus_util::app::{App, ExitCode};
 use focus_util::git_helper;
 use focus_util::paths::assert_focused_repo;
-use tracing::{debug, debug_span, info};
+use rand::seq::IteratorRandom;
+use tracing::{debug, debug_span, info, warn};
 
 use focus_internals::index::{
-    self, content_hash, get_files_to_materialize, ContentHash, DependencyKey, HashContext,
-    ObjectDatabase, PathsToMaterializeResult, RocksDBCache, RocksDBMemoizationCacheExt,
+    self, content_hash, find_dependent_projects, find_stored_keys, get_files_to_materialize,
+    rdeps_query_path, verify_object_database, ContentHash, DependencyGraph, DependencyKey,
+    DependentProjects, GraphFormat, HashContext, LoadGraph, ObjectDatabase,
+    PathsToMaterializeResult, RocksDBCache, RocksDBMemoizationCacheExt, VerificationMismatch,
     FUNCTION_ID,
 };
 use focus_internals::model::configuration::IndexConfig;
-use focus_internals::model::repo::Repo;
+use focus_internals::model::repo::{Outliner, Repo};
 use focus_internals::model::selection::OperationAction;
-use focus_internals::target::{Target, TargetSet};
-
-const PARENTS_TO_TRY_IN_FETCH: u32 = 100;
+use focus_internals::target::{Label, Target, TargetSet};
+use focus_internals::target_resolver::ResolutionOptions;
+
+const PARENTS_TO_TRY_IN_FETCH: usize = 100;
+/// If the keysets near `HEAD` were pruned from the remote, fetching falls back to keysets this far back.
+const PARENTS_TO_TRY_IN_FETCH_FALLBACK: usize = 1000;
+/// How many keysets to try fetching before giving up when the nearest ones have been pruned.
+const MAX_FETCH_ATTEMPTS: usize = 5;
 const TAG_NAMESPACE: &str = "focus";
 const COMMIT_USER_NAME: &str = "focus";
 const COMMIT_USER_EMAIL: &str = "focus@example.com";
 
 pub fn clear(sparse_repo_path: PathBuf) -> anyhow::Result<()> {
     let repo = git2::Repository::open(sparse_repo_path).context("opening sparse repo")?;
-    let odb = RocksDBCache::new(&repo);
+    let odb = RocksDBCache::new(&repo)?;
     let cache: &dyn Cache = &odb;
     cache.clear()?;
     Ok(())
@@ -76,7 +87,7 @@ fn resolve_targets(
     let head_commit = git_helper::get_head_commit(&repo).context("Resolving head commit")?;
     let tree = head_commit.tree().context("Resolving tree")?;
     let ctx = HashContext::new(&repo, &tree)?;
-    let odb = RocksDBCache::new(&repo);
+    let odb = RocksDBCache::new(&repo)?;
 
     let borrowed_odb = odb.borrow();
     let materialize_result = get_files_to_materialize(&ctx, borrowed_odb, dep_keys.clone())?;
@@ -165,6 +176,104 @@ pub fn resolve(
     Ok(ExitCode(0))
 }
 
+pub fn graph(
+    app: Arc<App>,
+    sparse_repo_path: &Path,
+    targets: Vec<String>,
+    format: GraphFormat,
+    max_depth: Option<usize>,
+) -> anyhow::Result<ExitCode> {
+    assert_focused_repo(sparse_repo_path)?;
+    let targets = if targets.is_empty() {
+        let repo = Repo::open(sparse_repo_path, app.clone())?;
+        let selections = repo.selection_manager()?;
+        selections.compute_complete_target_set()?
+    } else {
+        targets
+            .iter()
+            .map(|target| Target::try_from(target.as_str()))
+            .collect::<Result<TargetSet, _>>()?
+    };
+    let dep_keys: HashSet<DependencyKey> = targets
+        .iter()
+        .map(|target| DependencyKey::from(target.clone()))
+        .collect();
+
+    // Make sure that the index has entries for the targets before reading them.
+    if let Err(exit_code) = resolve_targets(app, sparse_repo_path, targets, false)? {
+        return Ok(exit_code);
+    }
+
+    let repo = git2::Repository::open(sparse_repo_path).context("opening sparse repo")?;
+    let head_commit = git_helper::get_head_commit(&repo).context("Resolving head commit")?;
+    let tree = head_commit.tree().context("Resolving tree")?;
+    let ctx = HashContext::new(&repo, &tree)?;
+    let odb = RocksDBCache::new(&repo)?;
+    let graph = DependencyGraph::collect(&ctx, &odb, dep_keys, max_depth)?;
+    if !graph.unresolved_keys().is_empty() {
+        warn!(
+            count = graph.unresolved_keys().len(),
+            "Some keys had no index entries; their dependencies are not included"
+        );
+    }
+
+    let stdout = std::io::stdout();
+    let mut stdout = stdout.lock();
+    graph.write(format, &mut stdout)?;
+    Ok(ExitCode(0))
+}
+
+pub fn rdeps(app: Arc<App>, sparse_repo_path: &Path, query: &str) -> anyhow::Result<ExitCode> {
+    assert_focused_repo(sparse_repo_path)?;
+    let path = rdeps_query_path(query)?;
+    let repo = Repo::open(sparse_repo_path, app.clone())?;
+    let selections = repo.selection_manager()?;
+    let catalog = selections.project_catalog();
+
+    // Make sure that the index has entries for every project before reading them.
+    let all_targets = all_project_targets(&repo)?;
+    if let Err(exit_code) = resolve_targets(app, sparse_repo_path, all_targets, false)? {
+        return Ok(exit_code);
+    }
+
+    let git_repo = repo.underlying();
+    let head_commit = git_helper::get_head_commit(git_repo).context("Resolving head commit")?;
+    let tree = head_commit.tree().context("Resolving tree")?;
+    let ctx = HashContext::new(git_repo, &tree)?;
+    let odb = RocksDBCache::new(git_repo)?;
+    let DependentProjects {
+        projects,
+        unresolved_projects,
+    } = find_dependent_projects(
+        &ctx,
+        &odb,
+        catalog
+            .mandatory_projects
+            .underlying
+            .values()
+            .chain(catalog.optional_projects.underlying.values()),
+        &catalog.optional_projects.underlying,
+        &path,
+    )?;
+
+    for name in projects.iter() {
+        if catalog.mandatory_projects.underlying.contains_key(name) {
+            println!("{name} (mandatory)");
+        } else {
+            println!("{name}");
+        }
+    }
+
+    if unresolved_projects.is_empty() {
+        Ok(ExitCode(0))
+    } else {
+        for name in unresolved_projects.iter() {
+            eprintln!("Could not resolve project: {name}");
+        }
+        Ok(ExitCode(1))
+    }
+}
+
 pub fn hash(
     _app: Arc<App>,
     sparse_repo_path: &Path,
@@ -192,10 +301,52 @@ pub fn hash(
     Ok(ExitCode(0))
 }
 
+pub fn load_graph(
+    _app: Arc<App>,
+    sparse_repo_path: &Path,
+    commit: String,
+    files: &[String],
+) -> anyhow::Result<ExitCode> {
+    let repo = git2::Repository::open(sparse_repo_path)?;
+    let object = repo
+        .revparse_single(&commit)
+        .with_context(|| format!("Resolving commit {commit}"))?;
+    let commit = object
+        .peel_to_commit()
+        .with_context(|| format!("Resolving {commit} to a commit"))?;
+    let tree = commit.tree()?;
+
+    let load_graph = if files.is_empty() {
+        LoadGraph::for_tree(&repo, &tree)?
+    } else {
+        let roots = files
+            .iter()
+            .map(|file| Label::from_str(file).with_context(|| format!("Parsing label {file}")))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        LoadGraph::from_roots(&repo, &tree, roots)?
+    };
+
+    for (file, loads) in load_graph.edges() {
+        for load in loads {
+            println!("{file} -> {load}");
+        }
+    }
+
+    let missing_files = load_graph.missing_files();
+    if missing_files.is_empty() {
+        Ok(ExitCode(0))
+    } else {
+        for file in missing_files {
+            eprintln!("Missing file: {file}");
+        }
+        Ok(ExitCode(1))
+    }
+}
+
 pub fn get(_app: Arc<App>, sparse_repo_path: &Path, hash: &str) -> anyhow::Result<ExitCode> {
     let repo = git2::Repository::open(sparse_repo_path)?;
     let hash = ContentHash::from_str(hash)?;
-    let odb = RocksDBCache::new(&repo);
+    let odb = RocksDBCache::new(&repo)?;
     let value = odb.get_direct(&hash)?;
     match value {
         Some(value) => {
@@ -216,22 +367,118 @@ pub fn generate(
     break_on_missing_keys: bool,
 ) -> anyhow::Result<ExitCode> {
     let repo = Repo::open(&sparse_repo_path, app.clone())?;
-    let selections = repo.selection_manager()?;
-    let all_targets = {
-        let mut targets = TargetSet::try_from(&selections.project_catalog().mandatory_projects)?;
-        targets.extend(TargetSet::try_from(
-            &selections.project_catalog().optional_projects,
-        )?);
-        targets
-    };
+    let all_targets = all_project_targets(&repo)?;
     match resolve_targets(app, &sparse_repo_path, all_targets, break_on_missing_keys)? {
         Ok(_result) => Ok(ExitCode(0)),
         Err(exit_code) => Ok(exit_code),
     }
 }
 
-fn index_repo_dir(sparse_repo_path: &Path) -> PathBuf {
-    sparse_repo_path.join(".git").join("focus").join("index")
+/// The targets of all mandatory and optional projects in the repository.
+fn all_project_targets(repo: &Repo) -> anyhow::Result<TargetSet> {
+    let selections = repo.selection_manager()?;
+    let mut targets = TargetSet::try_from(&selections.project_catalog().mandatory_projects)?;
+    targets.extend(TargetSet::try_from(
+        &selections.project_catalog().optional_projects,
+    )?);
+    Ok(targets)
+}
+
+pub fn verify(
+    app: Arc<App>,
+    sparse_repo_path: PathBuf,
+    sample: Option<usize>,
+    evict: bool,
+) -> anyhow::Result<ExitCode> {
+    let repo = Repo::open(&sparse_repo_path, app.clone())?;
+    let outliner = repo
+        .outliner()
+        .context("Verifying the index requires an outlining tree")?;
+    let all_targets = all_project_targets(&repo)?;
+
+    let git_repo = repo.underlying();
+    let head_commit = git_helper::get_head_commit(git_repo).context("Resolving head commit")?;
+    let tree = head_commit.tree().context("Resolving tree")?;
+    let ctx = HashContext::new(git_repo, &tree)?;
+    let odb = RocksDBCache::new(git_repo)?;
+
+    let dep_keys: HashSet<DependencyKey> =
+        all_targets.into_iter().map(DependencyKey::from).collect();
+    let stored_keys = find_stored_keys(&ctx, &odb, dep_keys)?;
+    let keys_to_verify: BTreeSet<DependencyKey> = match sample {
+        Some(sample_size) => stored_keys
+            .into_iter()
+            .choose_multiple(&mut rand::thread_rng(), sample_size)
+            .into_iter()
+            .collect(),
+        None => stored_keys,
+    };
+    if keys_to_verify.is_empty() {
+        println!("No index entries to verify at HEAD");
+        return Ok(ExitCode(0));
+    }
+
+    let targets = keys_to_verify
+        .iter()
+        .map(|dep_key| Target::try_from(dep_key_to_target(dep_key).as_str()))
+        .collect::<Result<TargetSet, _>>()?;
+    info!(count = targets.len(), "Resolving index entries again");
+    let (_patterns, resolution_result) = outliner
+        .outline(
+            head_commit.id(),
+            &targets,
+            &ResolutionOptions::default(),
+            None,
+            app,
+        )
+        .context("Failed to outline")?;
+
+    let mismatches = verify_object_database(&ctx, &odb, &keys_to_verify, &resolution_result)?;
+    for VerificationMismatch {
+        key,
+        hash,
+        stored_value,
+        resolved_value,
+        hash_inputs,
+    } in mismatches.iter()
+    {
+        println!("Mismatch: {hash} {}", dep_key_to_target(key));
+        println!("Stored value: {stored_value:#?}");
+        match resolved_value {
+            Some(resolved_value) => println!("Resolved value: {resolved_value:#?}"),
+            None => println!("Resolved value: <not produced>"),
+        }
+        println!("Hash inputs:");
+        for (input_key, input_hash) in hash_inputs {
+            println!("  {input_hash} {input_key:?}");
+        }
+        if evict {
+            odb.remove(hash)
+                .with_context(|| format!("Evicting {hash}"))?;
+        }
+    }
+
+    println!(
+        "Verified {} entries; {} mismatched{}",
+        keys_to_verify.len(),
+        mismatches.len(),
+        if evict && !mismatches.is_empty() {
+            " and were evicted"
+        } else {
+            ""
+        }
+    );
+    if mismatches.is_empty() {
+        Ok(ExitCode(0))
+    } else {
+        Ok(ExitCode(1))
+    }
+}
+
+fn index_repo_dir(sparse_repo_path: &Path) -> anyhow::Result<PathBuf> {
+    // Linked worktrees share the index with the repo they were added to.
+    let repo = git2::Repository::open(sparse_repo_path).context("opening sparse repo")?;
+    Ok(repo.commondir().join("focus").join("index"))
 }
 
 pub const INDEX_DEFAULT_REMOTE: &str = "https://git.example.com/focus-index";
@@ -244,7 +491,7 @@ pub fn fetch(
 ) -> anyhow::Result<ExitCode> {
     let repo = Repo::open(&sparse_repo_path, app.clone())
         .with_context(|| format!("Opening repository at {}", &sparse_repo_path.display()))?;
-    let cache = RocksDBCache::new(repo.underlying());
+    let cache = RocksDBCache::new(repo.underlying())?;
 
     let index_config = repo.config().index.clone();
     let index_config = if force {
@@ -272,49 +519,98 @@ pub fn fetch(
     }
 }
 
+/// Create the synchronizer appropriate for the given remote. Remotes prefixed with `objects+` are plain object stores; anything else is a Git remote.
+fn make_synchronizer(
+    index_dir: PathBuf,
+    remote: String,
+    app: Arc<App>,
+) -> anyhow::Result<Box<dyn CacheSynchronizer>> {
+    if ObjectStoreCacheSynchronizer::handles_remote(&remote) {
+        Ok(Box::new(ObjectStoreCacheSynchronizer::create(
+            index_dir,
+            &remote,
+            TAG_NAMESPACE.to_string(),
+        )?))
+    } else {
+        Ok(Box::new(GitBackedCacheSynchronizer::create(
+            index_dir,
+            remote,
+            app,
+            TAG_NAMESPACE.to_string(),
+            COMMIT_USER_EMAIL.to_string(),
+            COMMIT_USER_NAME.to_string(),
+        )?))
+    }
+}
+
+/// The keysets in `available_keysets` for `commit` and up to `max_commits` of its first-parent ancestors, nearest first, along with how many commits back each was found.
+fn nearest_keysets(
+    mut commit: git2::Commit,
+    available_keysets: &HashSet<KeysetID>,
+    max_commits: usize,
+) -> anyhow::Result<Vec<(KeysetID, usize)>> {
+    let mut result = Vec::new();
+    for distance in 0..max_commits {
+        let keyset_id = commit.tree()?.id();
+
+        if available_keysets.contains(&keyset_id) {
+            result.push((keyset_id, distance));
+        }
+        if commit.parent_count() == 0 {
+            break;
+        }
+        commit = commit.parent(0)?;
+    }
+    Ok(result)
+}
+
 fn fetch_internal(
     app: Arc<App>,
     cache: &RocksDBCache,
     sparse_repo_path: PathBuf,
     index_config: &IndexConfig,
 ) -> anyhow::Result<ExitCode> {
-    let index_dir = index_repo_dir(&sparse_repo_path);
-    let synchronizer = GitBackedCacheSynchronizer::create(
-        index_dir,
-        index_config.remote.clone(),
-        app.clone(),
-        TAG_NAMESPACE.to_string(),
-        COMMIT_USER_EMAIL.to_string(),
-        COMMIT_USER_NAME.to_string(),
-    )?;
+    let index_dir = index_repo_dir(&sparse_repo_path)?;
+    let synchronizer = make_synchronizer(index_dir, index_config.remote.clone(), app.clone())?;
     let repo = Repo::open(sparse_repo_path.as_path(), app).context("Failed to open repo")?;
-    let mut commit = repo.get_head_commit()?;
+    let commit = repo.get_head_commit()?;
 
     let available_keysets = synchronizer.available_remote_keysets()?;
 
-    let mut found_keyset: Option<KeysetID> = None;
-    for _ in 0..PARENTS_TO_TRY_IN_FETCH {
-        let keyset_id = commit.tree()?.id();
-
-        if available_keysets.contains(&keyset_id) {
-            found_keyset = Some(keyset_id);
-            break;
+    // Keysets may be pruned between listing and fetching them, so try older ones if they have gone missing.
+    let mut last_error = None;
+    for (keyset_id, distance) in
+        nearest_keysets(commit, &available_keysets, PARENTS_TO_TRY_IN_FETCH_FALLBACK)?
+            .into_iter()
+            .take(MAX_FETCH_ATTEMPTS)
+    {
+        if distance >= PARENTS_TO_TRY_IN_FETCH {
+            info!(
+                distance,
+                "No index is available for recent commits; falling back to an older one"
+            );
         }
-        commit = commit.parent(0)?;
-    }
-    if let Some(keyset_id) = found_keyset {
         let keyset_id_str = keyset_id.to_string();
         let span = debug_span!("Fetching index");
         info!(tag = %keyset_id_str, "Fetching index");
         let _guard = span.enter();
-        synchronizer
-            .fetch_and_populate(keyset_id, cache)
-            .context("Fetching index data")?;
-    } else {
-        info!("No index matches the current commit");
+        match synchronizer.fetch_and_populate(keyset_id, cache) {
+            Ok(_) => return Ok(ExitCode(0)),
+            Err(e) if e.downcast_ref::<KeysetNotFound>().is_some() => {
+                warn!(tag = %keyset_id_str, "Index was pruned from the remote; trying an older one");
+                last_error = Some(e);
+            }
+            Err(e) => return Err(e).context("Fetching index data"),
+        }
     }
 
-    Ok(ExitCode(0))
+    match last_error {
+        Some(e) => Err(e).context("Fetching index data"),
+        None => {
+            info!("No index matches the current commit");
+            Ok(ExitCode(0))
+        }
+    }
 }
 
 pub fn push(
@@ -334,16 +630,9 @@ pub fn push(
         targets
     };
 
-    let index_dir = index_repo_dir(&sparse_repo_path);
+    let index_dir = index_repo_dir(&sparse_repo_path)?;
     std::fs::create_dir_all(&index_dir).context("creating index directory")?;
-    let synchronizer = GitBackedCacheSynchronizer::create(
-        index_dir,
-        remote,
-        app.clone(),
-        TAG_NAMESPACE.to_string(),
-        COMMIT_USER_EMAIL.to_string(),
-        COMMIT_USER_NAME.to_string(),
-    )?;
+    let synchronizer = make_synchronizer(index_dir, remote, app.clone())?;
 
     let head_commit = repo.get_head_commit()?;
     let head_tree = head_commit.tree().context("finding HEAD tree")?;
@@ -361,45 +650,196 @@ pub fn push(
         "Number of keys resolved"
     );
 
-    let odb = RocksDBCache::new(repo.underlying());
-    let cache: &dyn ObjectDatabase = &odb;
-
-    let keyset = {
-        let mut result = HashSet::new();
-        for key in seen_keys {
-            match key {
-                key @ DependencyKey::BazelPackage(_) => {
-                    let (hash, value) = cache.get(&ctx, &key)?;
-                    if value.is_none() {
-                        panic!("Failed to find value associated with this key, which we should have previously generated and cached: {key:?}");
-                    }
-                    result.insert((*FUNCTION_ID, git2::Oid::from(hash)));
-                }
+    let odb = RocksDBCache::new(repo.underlying())?;
+    let keyset = keyset_for_keys(&ctx, &odb, seen_keys)?;
 
-                DependencyKey::BazelBuildFile(_) | DependencyKey::Path(_) => {
-                    // The paths to materialize for these kinds of dependencies
-                    // are known statically, so we don't need to insert or
-                    // propagate cache entries.
-                }
+    if !dry_run {
+        // Share only what changed since the nearest ancestor with an index on the remote.
+        let keyset_id = ctx.head_tree().id();
+        let mut available_keysets = synchronizer.available_remote_keysets()?;
+        available_keysets.remove(&keyset_id);
+        let previous_keyset_id = match head_commit.parent(0) {
+            Ok(parent) => nearest_keysets(parent, &available_keysets, PARENTS_TO_TRY_IN_FETCH)?
+                .first()
+                .map(|(keyset_id, _)| *keyset_id),
+            Err(_) => None,
+        };
+        info!(?previous_keyset_id, "Pushing index");
+        synchronizer.share(keyset_id, &keyset, &odb, previous_keyset_id)?;
+    } else {
+        info!("This is a dry run, so not pushing index");
+    }
+
+    Ok(ExitCode(0))
+}
 
-                key @ DependencyKey::DummyForTesting(_) => {
-                    panic!("Encountered dummy testing value; this should not appear in real-world data: {key:?}");
+/// The cache entries for `seen_keys` which are shared through an index remote.
+fn keyset_for_keys(
+    ctx: &HashContext,
+    cache: &dyn ObjectDatabase,
+    seen_keys: impl IntoIterator<Item = DependencyKey>,
+) -> anyhow::Result<Keyset> {
+    let mut result = HashSet::new();
+    for key in seen_keys {
+        match key {
+            key @ DependencyKey::BazelPackage(_) => {
+                let (hash, value) = cache.get(ctx, &key)?;
+                if value.is_none() {
+                    panic!("Failed to find value associated with this key, which we should have previously generated and cached: {key:?}");
                 }
+                result.insert((*FUNCTION_ID, git2::Oid::from(hash)));
+            }
+
+            DependencyKey::BazelBuildFile(_) | DependencyKey::Path(_) => {
+                // The paths to materialize for these kinds of dependencies
+                // are known statically, so we don't need to insert or
+                // propagate cache entries.
+            }
+
+            key @ DependencyKey::DummyForTesting(_) => {
+                panic!("Encountered dummy testing value; this should not appear in real-world data: {key:?}");
             }
         }
-        result
+    }
+    Ok(result)
+}
+
+/// The index entries needed to resolve `targets` at `HEAD`, resolving the
+/// targets first if any are missing from the index.
+pub fn export_entries(
+    app: Arc<App>,
+    sparse_repo_path: &Path,
+    targets: HashSet<Target>,
+) -> anyhow::Result<Vec<(CacheKeyKind, CacheKey, Vec<u8>)>> {
+    let ResolveTargetResult {
+        seen_keys,
+        paths: _,
+    } = match resolve_targets(app, sparse_repo_path, targets, false)? {
+        Ok(result) => result,
+        Err(exit_code) => anyhow::bail!("Resolving targets failed ({:?})", exit_code),
     };
 
-    if !dry_run {
-        info!("Pushing index");
-        synchronizer.share(ctx.head_tree().id(), &keyset, &odb, None)?;
-    } else {
-        info!("This is a dry run, so not pushing index");
+    let repo = git2::Repository::open(sparse_repo_path).context("opening sparse repo")?;
+    let head_tree = git_helper::get_head_commit(&repo)
+        .context("Resolving head commit")?
+        .tree()
+        .context("Resolving tree")?;
+    let ctx = HashContext::new(&repo, &head_tree)?;
+    let odb = RocksDBCache::new(&repo)?;
+    let keyset = keyset_for_keys(&ctx, &odb, seen_keys)?;
+
+    let cache: &dyn Cache = &odb;
+    let mut entries = Vec::with_capacity(keyset.len());
+    for (kind, key) in keyset {
+        let value = cache
+            .get(kind, key)?
+            .with_context(|| format!("Index entry {} is missing", key))?;
+        entries.push((kind, key, value));
+    }
+    Ok(entries)
+}
+
+/// Store index entries exported by [`export_entries`] in the repo's index.
+/// Returns the number of entries stored.
+pub fn import_entries(
+    sparse_repo_path: &Path,
+    entries: impl IntoIterator<Item = (CacheKeyKind, CacheKey, Vec<u8>)>,
+) -> anyhow::Result<usize> {
+    let repo = git2::Repository::open(sparse_repo_path).context("opening sparse repo")?;
+    let odb = RocksDBCache::new(&repo)?;
+    let cache: &dyn Cache = &odb;
+    let mut count = 0;
+    for (kind, key, value) in entries {
+        cache.put(kind, key, &value)?;
+        count += 1;
+    }
+    debug!(count, "Imported index entries");
+    Ok(count)
+}
+
+/// Remove keysets from the remote that belong to first-parent ancestors of `HEAD`, other than those of the `keep_last` most recent such ancestors and those of commits made within `older_than`. Keysets that are not found in the first-parent history of `HEAD` (for example, those of other branches) are kept.
+pub fn prune(
+    app: Arc<App>,
+    sparse_repo_path: PathBuf,
+    remote: String,
+    keep_last: usize,
+    older_than: Duration,
+    dry_run: bool,
+) -> anyhow::Result<ExitCode> {
+    let repo = Repo::open(&sparse_repo_path, app.clone())?;
+    let index_dir = index_repo_dir(&sparse_repo_path)?;
+    std::fs::create_dir_all(&index_dir).context("creating index directory")?;
+    let synchronizer = make_synchronizer(index_dir, remote, app)?;
+    let available_keysets = synchronizer.available_remote_keysets()?;
+
+    let cutoff = SystemTime::now()
+        .checked_sub(older_than)
+        .unwrap_or(UNIX_EPOCH)
+        .duration_since(UNIX_EPOCH)
+        .context("Computing retention cutoff")?
+        .as_secs() as i64;
+
+    let head_commit_id = repo.get_head_commit()?.id();
+    let stale = find_stale_keysets(
+        repo.underlying(),
+        head_commit_id,
+        &available_keysets,
+        keep_last,
+        cutoff,
+    )?;
+
+    println!(
+        "{} keysets available, {} retained, {} to prune",
+        available_keysets.len(),
+        available_keysets.len() - stale.len(),
+        stale.len()
+    );
+    if dry_run {
+        let mut stale: Vec<String> = stale.iter().map(|id| id.to_string()).collect();
+        stale.sort();
+        for keyset_id in stale {
+            println!("{}", keyset_id);
+        }
+        info!("This is a dry run, so not pruning the index");
+    } else if !stale.is_empty() {
+        synchronizer
+            .remove_remote_keysets(&stale)
+            .context("Removing stale keysets")?;
     }
 
     Ok(ExitCode(0))
 }
 
+/// Walk the first-parent history of `head_commit_id` and return the keysets in `available_keysets` that are found there and fall outside both retention limits: they belong neither to one of the `keep_last` most recent commits with a keyset nor to a commit made at or after `cutoff` (in seconds since the epoch).
+fn find_stale_keysets(
+    repo: &git2::Repository,
+    head_commit_id: git2::Oid,
+    available_keysets: &HashSet<KeysetID>,
+    keep_last: usize,
+    cutoff: i64,
+) -> anyhow::Result<HashSet<KeysetID>> {
+    let mut found = HashSet::<KeysetID>::new();
+    let mut stale = HashSet::<KeysetID>::new();
+    let mut revwalk = repo.revwalk().context("Creating revwalk")?;
+    revwalk.simplify_first_parent()?;
+    revwalk.push(head_commit_id)?;
+    for oid in revwalk {
+        if found.len() == available_keysets.len() {
+            break;
+        }
+        let commit = repo.find_commit(oid?)?;
+        let keyset_id = commit.tree_id();
+        if !available_keysets.contains(&keyset_id) || !found.insert(keyset_id) {
+            continue;
+        }
+        // Commit times are not monotonic along the walk, so every commit is considered on its own.
+        if found.len() > keep_last && commit.time().seconds() < cutoff {
+            stale.insert(keyset_id);
+        }
+    }
+    Ok(stale)
+}
+
 pub fn print_churn_stats(
     app: Arc<App>,
     sparse_repo: PathBuf,
@@ -461,7 +901,7 @@ mod tests {
 
         // Try to materialize files -- this should be a cache miss.
         {
-            let odb = RocksDBCache::new(repo);
+            let odb = RocksDBCache::new(repo)?;
             let materialize_result = get_files_to_materialize(
                 &ctx,
                 odb.borrow(),
@@ -475,7 +915,7 @@ mod tests {
                             Label("//project_a/src/main/java/com/example/cmdline:runner"),
                         ),
                         ContentHash(
-                            03ef8e36eece908c6ac3ff6ec3ce571b1a1336e5,
+                            46bb7e7d56d3bed36c793ee95e15b281bb939374,
                         ),
                     ),
                 },
@@ -502,7 +942,7 @@ mod tests {
 
         // Try to materialize files again -- this should be a cache hit.
         {
-            let odb = RocksDBCache::new(repo);
+            let odb = RocksDBCache::new(repo)?;
             let materialize_result = get_files_to_materialize(
                 &ctx,
                 odb.borrow(),
@@ -542,4 +982,33 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn test_find_stale_keysets() -> anyhow::Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let scratch_repo = ScratchGitRepo::new_static_fixture(temp_dir.path())?;
+        let repo = scratch_repo.repo()?;
+        let tree_of = |commit_id: git2::Oid| -> anyhow::Result<KeysetID> {
+            Ok(repo.find_commit(commit_id)?.tree_id())
+        };
+
+        let initial = repo.head()?.peel_to_commit()?.tree_id();
+        let first = tree_of(scratch_repo.write_and_commit_file("a.txt", "1", "first")?)?;
+        let second = tree_of(scratch_repo.write_and_commit_file("a.txt", "2", "second")?)?;
+        let third = tree_of(scratch_repo.write_and_commit_file("a.txt", "3", "third")?)?;
+        let head = repo.head()?.peel_to_commit()?.id();
+        // A keyset for a commit that is not in the first-parent history of `HEAD`.
+        let foreign = git2::Oid::hash_object(git2::ObjectType::Tree, b"elsewhere")?;
+        let available = hashset! {initial, first, second, third, foreign};
+
+        // Every commit is older than the cutoff, so only `keep_last` limits retention.
+        let stale = find_stale_keysets(&repo, head, &available, 2, i64::MAX)?;
+        assert_eq!(stale, hashset! {initial, first});
+
+        // Every commit is newer than the cutoff, so nothing is pruned.
+        let stale = find_stale_keysets(&repo, head, &available, 0, 0)?;
+        assert!(stale.is_empty());
+
+        Ok(())
+    }
 }
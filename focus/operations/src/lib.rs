@@ -5,13 +5,17 @@
 
 pub mod background;
 pub mod branch;
+pub mod bundle;
 pub mod clone;
+pub mod clone_template;
+pub mod daemon;
 pub mod detect_build_graph_changes;
 pub mod ensure_clean;
 pub mod event;
 pub mod filter;
 pub mod index;
 pub mod maintenance;
+pub mod peek;
 pub mod project;
 pub mod project_cache;
 pub mod pull;
@@ -22,3 +26,4 @@ pub mod status;
 pub mod sync;
 pub(crate) mod testing;
 pub mod util;
+pub mod worktree;
@@ -181,6 +181,43 @@ impl Launchctl {
         Ok(output_path)
     }
 
+    /// The calendar intervals of the installed job for `time_period`, or None
+    /// if no job is installed and loaded.
+    pub fn scheduled_intervals(
+        &self,
+        time_period: TimePeriod,
+    ) -> Result<Option<Vec<CalendarInterval>>> {
+        let label = ScheduledJobOpts {
+            time_period,
+            ..Default::default()
+        }
+        .label();
+        let path = self.plist_path(&label);
+        let value = match PlistValue::from_file(&path) {
+            Ok(value) => value,
+            Err(e) if e.as_io().map(|e| e.kind()) == Some(ErrorKind::NotFound) => return Ok(None),
+            Err(e) => return Err(e).with_context(|| format!("reading {:?}", path)),
+        };
+        let dict = value
+            .as_dictionary()
+            .with_context(|| format!("{:?} was not a dictionary", path))?;
+        if dict.get(DISABLED).and_then(PlistValue::as_boolean) == Some(true)
+            || !self.is_service_loaded(&label)?
+        {
+            return Ok(None);
+        }
+
+        let intervals = dict
+            .get(START_CALENDAR_INTERVAL)
+            .and_then(PlistValue::as_array)
+            .with_context(|| format!("{:?} had no {}", path, START_CALENDAR_INTERVAL))?
+            .iter()
+            .map(CalendarInterval::try_from)
+            .collect::<Result<Vec<_>>>()
+            .with_context(|| format!("parsing {:?}", path))?;
+        Ok(Some(intervals))
+    }
+
     pub fn delete_plist(&self, opts: &ScheduledJobOpts) -> Result<()> {
         let path = self.plist_path(&opts.label());
         let res = std::fs::remove_file(&path);
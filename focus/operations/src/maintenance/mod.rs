@@ -3,6 +3,7 @@
 
 pub mod launchd;
 pub mod scheduling;
+pub mod state;
 
 use std::{
     collections::HashMap,
@@ -19,15 +20,22 @@ use content_addressed_cache::RocksDBCache;
 use focus_internals::{index::RocksDBMemoizationCacheExt, locking, tracker::Tracker};
 
 use anyhow::{bail, Context, Result};
+use chrono::{Local, Utc};
 use focus_util::git_helper::{git_command_with_git_binary, GitBinary};
-use focus_util::{app::App, git_helper::ConfigExt, sandbox_command::SandboxCommandOutput};
+use focus_util::{
+    app::{App, ExitCode},
+    git_helper::ConfigExt,
+    sandbox_command::SandboxCommandOutput,
+};
 use maplit::hashmap;
+use strum::IntoEnumIterator;
 use strum_macros;
 use tracing::{debug, error, info, warn};
 
 use crate::sync::SyncRequest;
 
 pub use self::launchd::{schedule_disable, schedule_enable, Launchctl, ScheduleOpts};
+use self::state::{MaintenanceState, RunRecord};
 
 pub(crate) const DEFAULT_FOCUS_PATH: &str = "/opt/twitter_mde/bin/focus";
 pub const DEFAULT_GIT_BINARY_PATH_FOR_SCHEDULED_JOBS: &str = "/opt/twitter_mde/bin/git";
@@ -209,6 +217,8 @@ pub struct Runner<'a> {
     pub tracker: &'a Tracker,
     /// if true, use the focus Tracker to discover repos
     pub tracked_repos: bool,
+    /// where the outcome of each run is recorded
+    pub state_path: PathBuf,
     pub app: Arc<App>,
 }
 
@@ -228,6 +238,7 @@ impl Runner<'_> {
             git_config_key: config_key,
             git_config_path: config_path,
             tracked,
+            state_path,
         } = opts;
 
         let git_binary = match git_binary {
@@ -241,6 +252,7 @@ impl Runner<'_> {
             config: use_config_path_or_default_global(config_path.as_deref())?,
             tracker,
             tracked_repos: tracked,
+            state_path,
             app,
         })
     }
@@ -265,7 +277,7 @@ impl Runner<'_> {
     // Opening a rocksDB connection should run a compaction, if it's necessary.
     fn run_rocksdb_compaction(&self, repo_path: &Path) -> Result<()> {
         let repo = git2::Repository::open(repo_path).context("opening repo")?;
-        RocksDBCache::new(&repo);
+        RocksDBCache::new(&repo)?;
         Ok(())
     }
 
@@ -290,12 +302,16 @@ impl Runner<'_> {
     }
 
     #[tracing::instrument]
-    fn run_maint(&self, time_period: TimePeriod, repo_path: &Path) -> Result<MaintResult> {
+    fn run_maint(
+        &self,
+        time_period: TimePeriod,
+        repo_path: &Path,
+    ) -> Result<(MaintResult, Option<crate::sync::SyncStatus>)> {
         let _lock = match locking::hold_lock(repo_path, Path::new("maint.lock"), self.app.clone()) {
             Ok(lock) => lock,
             Err(e) => {
                 error!(?e, "failed to acquire lock");
-                return Ok(MaintResult::LockFailed);
+                return Ok((MaintResult::LockFailed, None));
             }
         };
 
@@ -318,7 +334,7 @@ impl Runner<'_> {
             sync_maint_runtime,
         );
 
-        Ok(git_maint_result)
+        Ok((git_maint_result, Some(sync_result)))
     }
 
     #[tracing::instrument]
@@ -374,25 +390,47 @@ impl Runner<'_> {
         info!(?time_period, ?path, "running tasks");
         set_default_git_maintenance_config(path)?;
 
+        let started_at = Utc::now();
+        let started_instant = Instant::now();
+        let mut record = RunRecord {
+            time_period: time_period.to_string(),
+            started_at,
+            duration_secs: 0.0,
+            maint_result: "git_error".to_string(),
+            sync_status: None,
+            error: None,
+        };
+
         let maint_result = match self.run_maint(time_period, path) {
-            Ok(MaintResult::Success(status)) => {
+            Ok((MaintResult::Success(status), sync_status)) => {
+                record.maint_result = maint_exit_status_metric_helper(status);
+                record.sync_status = sync_status.map(sync_status_metric_helper);
                 if status.success() {
                     debug!(?time_period, ?path, "completed maintenance",);
                     None
                 } else {
                     warn!(?path, exit_status = ?status, "maintenance failed");
+                    record.error = Some(format!("git maintenance exited with {}", status));
                     Some(maint_exit_status_metric_helper(status))
                 }
             }
-            Ok(MaintResult::LockFailed) => {
+            Ok((MaintResult::LockFailed, _)) => {
                 warn!(?path, "failed to acquire lock");
+                record.maint_result = "lock_failed".to_string();
+                record.error = Some("Failed to acquire the maintenance lock".to_string());
                 Some("lock_failed".to_string())
             }
             Err(e) => {
                 warn!(?path, ?e, "failed running git-maintenance");
+                record.error = Some(format!("{:#}", e));
                 Some("git_error".to_string())
             }
         };
+        record.duration_secs = started_instant.elapsed().as_secs_f64();
+
+        if let Err(e) = MaintenanceState::record_in_file(&self.state_path, path, record) {
+            warn!(?path, ?e, "failed recording maintenance state");
+        }
 
         if let Some(maint_result) = maint_result {
             self.add_ti_invocation_message(&hashmap! { "maint_result".to_string() => maint_result })
@@ -461,6 +499,7 @@ pub struct RunOptions {
     pub git_config_key: String,
     pub git_config_path: Option<PathBuf>,
     pub tracked: bool,
+    pub state_path: PathBuf,
 }
 
 impl Default for RunOptions {
@@ -470,6 +509,7 @@ impl Default for RunOptions {
             git_config_key: DEFAULT_CONFIG_KEY.to_owned(),
             git_config_path: None,
             tracked: false,
+            state_path: MaintenanceState::default_path(),
         }
     }
 }
@@ -489,6 +529,43 @@ pub fn run(
     Ok(())
 }
 
+/// When each scheduled maintenance job next runs. Jobs which aren't scheduled,
+/// including all of them on platforms without launchd, are left out.
+fn next_scheduled_runs() -> Result<Vec<(TimePeriod, chrono::DateTime<Local>)>> {
+    if !cfg!(target_os = "macos") {
+        return Ok(Vec::new());
+    }
+
+    let launchctl = Launchctl::default();
+    let now = Local::now();
+    let mut next_runs = Vec::new();
+    for time_period in TimePeriod::iter() {
+        if let Some(intervals) = launchctl.scheduled_intervals(time_period)? {
+            if let Some(next_run) = scheduling::CalendarInterval::next_after(&intervals, now) {
+                next_runs.push((time_period, next_run));
+            }
+        }
+    }
+    Ok(next_runs)
+}
+
+/// Print when maintenance last ran and succeeded in each tracked repo, and
+/// when it runs next.
+pub fn status(tracker: &Tracker, state_path: &Path) -> Result<ExitCode> {
+    let state = MaintenanceState::load(state_path).context("Loading maintenance state")?;
+    let repo_paths: Vec<PathBuf> = tracker
+        .scan()
+        .context("scanning repositories")?
+        .repos()
+        .iter()
+        .map(|repo| repo.location().to_path_buf())
+        .collect();
+    let next_runs = next_scheduled_runs().context("Reading the maintenance schedule")?;
+
+    print!("{}", state::render(&state, &repo_paths, &next_runs));
+    Ok(ExitCode(0))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -633,6 +710,7 @@ mod tests {
             git_config_key: config_key.into(),
             git_config_path: Some(config_path),
             tracked: false,
+            ..Default::default()
         };
 
         let runner = Runner::new(opts, &tracker, fix.app)?;
@@ -3,6 +3,8 @@
 
 use std::{fmt::Debug, path::PathBuf};
 
+use chrono::{DateTime, Datelike, Local, Timelike};
+
 use super::*;
 
 pub(crate) type PlistValue = plist::Value;
@@ -179,6 +181,32 @@ impl CalendarInterval {
         }]
     }
 
+    /// Whether a job scheduled with this interval starts at `time`, to the
+    /// minute. Unset fields match any value, as with launchd.
+    pub fn matches(&self, time: &DateTime<Local>) -> bool {
+        let field_matches = |field: Option<u32>, value: u32| field.map_or(true, |f| f == value);
+        field_matches(self.day, time.day())
+            && field_matches(self.hour, time.hour())
+            && field_matches(self.minute, time.minute())
+            && field_matches(
+                self.weekday.map(|weekday| weekday % 7),
+                time.weekday().num_days_from_sunday(),
+            )
+    }
+
+    /// The first minute after `after` at which any of `intervals` starts a job.
+    /// Only the next eight days are considered, which covers all of the
+    /// intervals used by the time periods.
+    pub fn next_after(
+        intervals: &[CalendarInterval],
+        after: DateTime<Local>,
+    ) -> Option<DateTime<Local>> {
+        let start = after.with_second(0)?.with_nanosecond(0)?;
+        (1..=8 * 24 * 60)
+            .map(|minutes| start + chrono::Duration::minutes(minutes))
+            .find(|time| intervals.iter().any(|interval| interval.matches(time)))
+    }
+
     pub(crate) fn for_time_period(
         tp: TimePeriod,
         defaults: CalendarInterval,
@@ -248,6 +276,34 @@ impl CalendarInterval {
     }
 }
 
+impl TryFrom<&PlistValue> for CalendarInterval {
+    type Error = anyhow::Error;
+
+    fn try_from(value: &PlistValue) -> Result<Self> {
+        let dict = value
+            .as_dictionary()
+            .context("Calendar interval was not a dictionary")?;
+        let field = |key: &str| -> Result<Option<u32>> {
+            dict.get(key)
+                .map(|value| {
+                    value
+                        .as_unsigned_integer()
+                        .and_then(|n| u32::try_from(n).ok())
+                        .with_context(|| format!("Calendar interval field {} was invalid", key))
+                })
+                .transpose()
+        };
+
+        Ok(CalendarInterval {
+            day: field("Day")?,
+            hour: field("Hour")?,
+            minute: field("Minute")?,
+            weekday: field("Weekday")?,
+            every_n_minutes: None,
+        })
+    }
+}
+
 impl From<CalendarInterval> for PlistValue {
     fn from(ci: CalendarInterval) -> Self {
         (&ci).into()
@@ -285,3 +341,47 @@ impl From<&CalendarInterval> for PlistValue {
         dict.into()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    #[test]
+    fn next_after_finds_the_first_matching_minute() -> Result<()> {
+        // A Saturday.
+        let now = Local.ymd(2022, 9, 3).and_hms(10, 30, 15);
+
+        let hourly = CalendarInterval::every_n_minutes(5, 2);
+        assert_eq!(
+            CalendarInterval::next_after(&hourly, now),
+            Some(Local.ymd(2022, 9, 3).and_hms(10, 32, 0))
+        );
+
+        let daily = CalendarInterval::daily(7, 4);
+        assert_eq!(
+            CalendarInterval::next_after(&daily, now),
+            Some(Local.ymd(2022, 9, 4).and_hms(4, 7, 0))
+        );
+
+        // Sunday may be given as 7, as it is in plists.
+        let weekly: Vec<CalendarInterval> = PlistValue::Array(vec![{
+            let mut dict = PlistDictionary::new();
+            dict.insert("Hour".into(), 4u32.into());
+            dict.insert("Minute".into(), 0u32.into());
+            dict.insert("Weekday".into(), 7u32.into());
+            dict.into()
+        }])
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(CalendarInterval::try_from)
+        .collect::<Result<_>>()?;
+        assert_eq!(
+            CalendarInterval::next_after(&weekly, now),
+            Some(Local.ymd(2022, 9, 4).and_hms(4, 0, 0))
+        );
+        Ok(())
+    }
+}
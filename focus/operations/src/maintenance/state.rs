@@ -0,0 +1,255 @@
+// Copyright 2022 Twitter, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local, Utc};
+use focus_util::lock_file::WaitingLock;
+use focus_util::paths::focus_config_dir;
+use serde_derive::{Deserialize, Serialize};
+
+use super::TimePeriod;
+
+const STATE_FILE_NAME: &str = "maintenance-state.json";
+
+/// The outcome of maintenance in one repo.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct RunRecord {
+    pub time_period: String,
+    pub started_at: DateTime<Utc>,
+    pub duration_secs: f64,
+
+    /// The result of `git maintenance`, as reported to tool insights.
+    pub maint_result: String,
+
+    /// The status of the preemptive sync, if it ran.
+    pub sync_status: Option<String>,
+
+    pub error: Option<String>,
+}
+
+impl RunRecord {
+    pub fn succeeded(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// What is known about maintenance in one repo.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RepoState {
+    pub last_run: Option<RunRecord>,
+    pub last_success: Option<DateTime<Utc>>,
+
+    /// The number of runs which have failed since the last success.
+    pub consecutive_failures: u32,
+}
+
+/// The results of maintenance runs, kept so that `focus maintenance status`
+/// can report on them.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MaintenanceState {
+    pub repos: BTreeMap<PathBuf, RepoState>,
+}
+
+impl MaintenanceState {
+    pub fn default_path() -> PathBuf {
+        focus_config_dir().join(STATE_FILE_NAME)
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        match std::fs::read(path) {
+            Ok(contents) => serde_json::from_slice(&contents)
+                .with_context(|| format!("Parsing {}", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Default::default()),
+            Err(e) => Err(e).with_context(|| format!("Reading {}", path.display())),
+        }
+    }
+
+    /// Write the state, replacing the file at once so that readers never see
+    /// a partial one.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let dir = path
+            .parent()
+            .context("The maintenance state path has no parent")?;
+        std::fs::create_dir_all(dir).with_context(|| format!("Creating {}", dir.display()))?;
+        let mut temp = tempfile::NamedTempFile::new_in(dir)?;
+        serde_json::to_writer_pretty(&mut temp, self)?;
+        temp.persist(path)
+            .with_context(|| format!("Writing {}", path.display()))?;
+        Ok(())
+    }
+
+    pub fn record(&mut self, repo_path: &Path, run: RunRecord) {
+        let state = self.repos.entry(repo_path.to_owned()).or_default();
+        if run.succeeded() {
+            state.last_success = Some(run.started_at);
+            state.consecutive_failures = 0;
+        } else {
+            state.consecutive_failures += 1;
+        }
+        state.last_run = Some(run);
+    }
+
+    /// Record the outcome of a run in the file at `path`. Maintenance for
+    /// other time periods and repos may run concurrently, so the file is read
+    /// again and written while holding a lock on a file next to it.
+    pub fn record_in_file(path: &Path, repo_path: &Path, run: RunRecord) -> Result<()> {
+        let dir = path
+            .parent()
+            .context("The maintenance state path has no parent")?;
+        std::fs::create_dir_all(dir).with_context(|| format!("Creating {}", dir.display()))?;
+        let _lock = WaitingLock::acquire(&path.with_extension("lock"))?;
+
+        let mut state = Self::load(path)?;
+        state.record(repo_path, run);
+        state.save(path)
+    }
+}
+
+fn format_time(time: DateTime<Local>) -> String {
+    time.format("%Y-%m-%d %H:%M").to_string()
+}
+
+/// Describe the state of maintenance in each of `repo_paths`, given when each
+/// scheduled job runs next.
+pub fn render(
+    state: &MaintenanceState,
+    repo_paths: &[PathBuf],
+    next_runs: &[(TimePeriod, DateTime<Local>)],
+) -> String {
+    let next_run = next_runs
+        .iter()
+        .min_by_key(|(_, time)| *time)
+        .map(|(time_period, time)| format!("{} ({})", format_time(*time), time_period))
+        .unwrap_or_else(|| String::from("not scheduled"));
+
+    let mut output = String::new();
+    for repo_path in repo_paths {
+        let repo_state = state.repos.get(repo_path).cloned().unwrap_or_default();
+        writeln!(output, "{}", repo_path.display()).unwrap();
+        let last_run = match repo_state.last_run.as_ref() {
+            Some(run) => format!(
+                "{} ({}, {:.1}s): {}",
+                format_time(run.started_at.with_timezone(&Local)),
+                run.time_period,
+                run.duration_secs,
+                match (run.error.as_ref(), run.sync_status.as_ref()) {
+                    (Some(error), _) => format!("failed: {}", error),
+                    (None, Some(sync_status)) => format!("succeeded, sync {}", sync_status),
+                    (None, None) => String::from("succeeded"),
+                }
+            ),
+            None => String::from("never"),
+        };
+        writeln!(output, "  Last run:     {}", last_run).unwrap();
+        writeln!(
+            output,
+            "  Last success: {}",
+            repo_state
+                .last_success
+                .map(|time| format_time(time.with_timezone(&Local)))
+                .unwrap_or_else(|| String::from("never"))
+        )
+        .unwrap();
+        if repo_state.consecutive_failures > 0 {
+            writeln!(
+                output,
+                "  Failures:     {} in a row",
+                repo_state.consecutive_failures
+            )
+            .unwrap();
+        }
+        writeln!(output, "  Next run:     {}", next_run).unwrap();
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn run(started_at: DateTime<Utc>, error: Option<&str>) -> RunRecord {
+        RunRecord {
+            time_period: TimePeriod::Hourly.to_string(),
+            started_at,
+            duration_secs: 1.5,
+            maint_result: String::from(if error.is_some() { "git_error" } else { "0" }),
+            sync_status: error.is_none().then(|| String::from("success")),
+            error: error.map(String::from),
+        }
+    }
+
+    #[test]
+    fn failures_are_counted_until_a_success() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("config").join(STATE_FILE_NAME);
+        let repo = PathBuf::from("/src/repo");
+        let first = Utc.ymd(2022, 9, 1).and_hms(10, 0, 0);
+
+        MaintenanceState::record_in_file(&path, &repo, run(first, None))?;
+        for hours in 1..=2 {
+            MaintenanceState::record_in_file(
+                &path,
+                &repo,
+                run(first + chrono::Duration::hours(hours), Some("lock held")),
+            )?;
+        }
+        let state = MaintenanceState::load(&path)?;
+        let repo_state = &state.repos[&repo];
+        assert_eq!(repo_state.last_success, Some(first));
+        assert_eq!(repo_state.consecutive_failures, 2);
+        assert_eq!(
+            repo_state.last_run.as_ref().unwrap().error.as_deref(),
+            Some("lock held")
+        );
+
+        let output = render(
+            &state,
+            &[repo.clone(), PathBuf::from("/src/other")],
+            &[(TimePeriod::Daily, Local.ymd(2022, 9, 2).and_hms(4, 7, 0))],
+        );
+        assert!(output.contains("Failures:     2 in a row"));
+        assert!(output.contains("failed: lock held"));
+        assert!(output.contains("/src/other\n  Last run:     never\n  Last success: never\n"));
+        assert!(output.contains("Next run:     2022-09-02 04:07 (daily)"));
+
+        MaintenanceState::record_in_file(
+            &path,
+            &repo,
+            run(first + chrono::Duration::hours(3), None),
+        )?;
+        let state = MaintenanceState::load(&path)?;
+        assert_eq!(state.repos[&repo].consecutive_failures, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn concurrent_records_are_not_lost() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join(STATE_FILE_NAME);
+        let started_at = Utc.ymd(2022, 9, 1).and_hms(10, 0, 0);
+
+        let handles = (0..8)
+            .map(|n| {
+                let path = path.clone();
+                std::thread::spawn(move || {
+                    let repo = PathBuf::from(format!("/src/repo-{}", n));
+                    MaintenanceState::record_in_file(&path, &repo, run(started_at, None))
+                })
+            })
+            .collect::<Vec<_>>();
+        for handle in handles {
+            handle.join().unwrap()?;
+        }
+
+        assert_eq!(MaintenanceState::load(&path)?.repos.len(), 8);
+        Ok(())
+    }
+}
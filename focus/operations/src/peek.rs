@@ -0,0 +1,143 @@
+// Copyright 2022 Twitter, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+use chrono::Utc;
+use focus_internals::model::{data_paths::DataPaths, peek::Peeks, repo::Repo};
+use focus_util::app::App;
+use tracing::info;
+
+use crate::sync::{SyncMode, SyncRequest};
+
+/// Read the directories peeked at in the repo.
+pub fn load(repo: &Repo) -> Result<Peeks> {
+    let paths = DataPaths::from_working_tree(repo.working_tree()?)?;
+    Peeks::load(&paths.peek_file).context("Loading peeked directories")
+}
+
+pub(crate) fn save(repo: &Repo, peeks: &Peeks) -> Result<()> {
+    let paths = DataPaths::from_working_tree(repo.working_tree()?)?;
+    peeks
+        .save(&paths.peek_file)
+        .context("Saving peeked directories")
+}
+
+/// Temporarily add `directories` to the outline for `duration`, then sync so
+/// that they are checked out. They are removed by the first sync after they
+/// expire.
+pub fn add(
+    sparse_repo: impl AsRef<Path>,
+    directories: Vec<String>,
+    duration: Duration,
+    app: Arc<App>,
+) -> Result<bool> {
+    let sparse_repo = sparse_repo.as_ref();
+    let repo = Repo::open(sparse_repo, app.clone())?;
+    let head_tree = repo
+        .underlying()
+        .head()
+        .and_then(|head| head.peel_to_tree())
+        .context("Resolving HEAD tree")?;
+    let expires_at = Utc::now()
+        + chrono::Duration::from_std(duration).context("The duration is out of range")?;
+
+    let mut added = Peeks::default();
+    for directory in directories.iter() {
+        added.add(directory, expires_at)?;
+    }
+    for peek in added.peeks.iter() {
+        let is_directory = head_tree
+            .get_path(Path::new(&peek.directory))
+            .map(|entry| entry.kind() == Some(git2::ObjectType::Tree))
+            .unwrap_or(false);
+        if !is_directory {
+            bail!("There is no directory {} at HEAD", peek.directory);
+        }
+    }
+
+    let mut peeks = load(&repo)?;
+    for peek in added.peeks {
+        peeks.add(&peek.directory, peek.expires_at)?;
+    }
+    save(&repo, &peeks)?;
+
+    info!(?directories, %expires_at, "Peeking");
+    sync(sparse_repo, app)
+}
+
+/// Remove all peeked directories from the outline and sync.
+pub fn clear(sparse_repo: impl AsRef<Path>, app: Arc<App>) -> Result<bool> {
+    let sparse_repo = sparse_repo.as_ref();
+    let repo = Repo::open(sparse_repo, app.clone())?;
+    if load(&repo)?.is_empty() {
+        return Ok(false);
+    }
+    save(&repo, &Peeks::default())?;
+    sync(sparse_repo, app)
+}
+
+fn sync(sparse_repo: &Path, app: Arc<App>) -> Result<bool> {
+    let result = crate::sync::run(&SyncRequest::new(sparse_repo, SyncMode::Incremental), app)
+        .context("Synchronizing changes")?;
+    Ok(result.status == crate::sync::SyncStatus::Success)
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use focus_testing::init_logging;
+
+    use crate::testing::integration::RepoPairFixture;
+
+    use super::*;
+
+    #[test]
+    fn peek_checks_out_directories_until_they_expire() -> Result<()> {
+        init_logging();
+        let fixture = RepoPairFixture::new()?;
+        fixture.perform_clone()?;
+        let library_path = fixture.sparse_repo_path.join("library_b");
+        assert!(!library_path.is_dir());
+
+        assert!(add(
+            &fixture.sparse_repo_path,
+            vec![String::from("library_b")],
+            Duration::from_secs(3600),
+            fixture.app.clone(),
+        )?);
+        assert!(library_path.is_dir());
+        assert!(add(
+            &fixture.sparse_repo_path,
+            vec![String::from("no_such_directory")],
+            Duration::from_secs(3600),
+            fixture.app.clone(),
+        )
+        .is_err());
+
+        // Expire the peek and sync again.
+        let repo = fixture.sparse_repo()?;
+        let mut peeks = load(&repo)?;
+        peeks.peeks[0].expires_at = Utc::now();
+        save(&repo, &peeks)?;
+        fixture.perform_sync()?;
+        assert!(!library_path.is_dir());
+        assert!(load(&repo)?.is_empty());
+
+        add(
+            &fixture.sparse_repo_path,
+            vec![String::from("library_b")],
+            Duration::from_secs(3600),
+            fixture.app.clone(),
+        )?;
+        assert!(library_path.is_dir());
+        assert!(clear(&fixture.sparse_repo_path, fixture.app.clone())?);
+        assert!(!library_path.is_dir());
+        assert!(load(&repo)?.is_empty());
+
+        Ok(())
+    }
+}
@@ -1,11 +1,15 @@
 // Copyright 2022 Twitter, Inc.
 // SPDX-License-Identifier: Apache-2.0
 
-use std::{path::Path, sync::Arc};
+use std::{ffi::OsString, path::Path, sync::Arc, time::Duration};
 
 use anyhow::{Context, Result};
-use focus_internals::{model::repo::Repo, project_cache::ProjectCache};
+use focus_internals::{
+    model::repo::Repo,
+    project_cache::{LocalCacheBackend, ProjectCache},
+};
 use focus_util::app::{App, ExitCode};
+use tracing::info;
 
 pub fn push(
     app: Arc<App>,
@@ -43,3 +47,147 @@ pub fn push(
 
     Ok(ExitCode(0))
 }
+
+/// Open the project cache for the repo, connecting it to the remote if one is configured.
+fn open_cache(repo: &Repo, app: Arc<App>) -> Result<ProjectCache> {
+    match repo.get_project_cache_remote_endpoint()? {
+        Some(endpoint) => ProjectCache::new(repo, endpoint, app),
+        None => ProjectCache::local(repo, app),
+    }
+}
+
+fn resolve_build_graph_hash(
+    cache: &ProjectCache,
+    repo: &Repo,
+    build_graph_hash: Option<String>,
+    commit: &str,
+) -> Result<Vec<u8>> {
+    match build_graph_hash {
+        Some(build_graph_hash) => hex::decode(&build_graph_hash)
+            .with_context(|| format!("Parsing build graph hash '{}'", build_graph_hash)),
+        None => {
+            let object = repo
+                .underlying()
+                .revparse_single(commit)
+                .with_context(|| format!("Resolving commit {commit}"))?;
+            let commit = object.peel_to_commit().context("Object was not a commit")?;
+            let (_, build_graph_hash) = cache.get_build_graph_hash(commit.id(), true)?;
+            Ok(build_graph_hash)
+        }
+    }
+}
+
+/// List the build graphs present in the local project cache.
+pub fn list(app: Arc<App>, sparse_repo: impl AsRef<Path>, remote: bool) -> Result<ExitCode> {
+    let repo = Repo::open(sparse_repo.as_ref(), app.clone())?;
+    let cache = open_cache(&repo, app)?;
+
+    for summary in cache.list(remote)? {
+        println!(
+            "{} commits={} mandatory={} projects={} shards={} imported={} bytes={}",
+            hex::encode(&summary.build_graph_hash),
+            summary.commit_count,
+            summary.has_mandatory_patterns,
+            summary.optional_project_count,
+            summary
+                .shard_count
+                .map(|count| count.to_string())
+                .unwrap_or_else(|| String::from("-")),
+            summary.imported,
+            summary.size,
+        );
+    }
+
+    Ok(ExitCode(0))
+}
+
+/// Print the patterns cached for the mandatory projects or the given optional project.
+pub fn show(
+    app: Arc<App>,
+    sparse_repo: impl AsRef<Path>,
+    build_graph_hash: String,
+    project_name: Option<String>,
+    remote: bool,
+) -> Result<ExitCode> {
+    let repo = Repo::open(sparse_repo.as_ref(), app.clone())?;
+    let cache = open_cache(&repo, app)?;
+    let build_graph_hash = hex::decode(&build_graph_hash)
+        .with_context(|| format!("Parsing build graph hash '{}'", build_graph_hash))?;
+
+    match cache.show(&build_graph_hash, project_name.as_deref(), remote)? {
+        Some(patterns) => {
+            for pattern in patterns {
+                for line in Vec::<OsString>::from(pattern) {
+                    println!("{}", line.to_string_lossy());
+                }
+            }
+            Ok(ExitCode(0))
+        }
+        None => {
+            eprintln!("No patterns are cached for this build graph");
+            Ok(ExitCode(1))
+        }
+    }
+}
+
+/// Check that the remote manifest for a build graph agrees with its shards. If no build graph hash is given, the one for `commit` is used.
+pub fn verify(
+    app: Arc<App>,
+    sparse_repo: impl AsRef<Path>,
+    build_graph_hash: Option<String>,
+    commit: String,
+) -> Result<ExitCode> {
+    let repo = Repo::open(sparse_repo.as_ref(), app.clone())?;
+    let cache = open_cache(&repo, app)?;
+    let build_graph_hash = resolve_build_graph_hash(&cache, &repo, build_graph_hash, &commit)?;
+
+    let report = cache.verify(&build_graph_hash)?;
+    println!(
+        "{} version={} shards={} mandatory_items={} optional_items={}",
+        hex::encode(&build_graph_hash),
+        report.version,
+        report.shard_count,
+        report.mandatory_item_count,
+        report.optional_item_count
+    );
+    for problem in report.problems.iter() {
+        println!("Problem: {}", problem);
+    }
+
+    if report.is_ok() {
+        Ok(ExitCode(0))
+    } else {
+        Ok(ExitCode(1))
+    }
+}
+
+/// Remove project cache content older than `older_than` from the local database and, if the configured endpoint is a local database, from it as well.
+pub fn gc(app: Arc<App>, sparse_repo: impl AsRef<Path>, older_than: Duration) -> Result<ExitCode> {
+    if older_than.as_secs() == 0 {
+        // RocksDB treats a TTL of zero as no TTL at all.
+        anyhow::bail!("The retention period must be at least one second");
+    }
+
+    let repo = Repo::open(sparse_repo.as_ref(), app)?;
+    let result = ProjectCache::collect_garbage(&repo, older_than)
+        .context("Collecting garbage in the local project cache failed")?;
+    println!(
+        "Local: removed {} expired entries and {} orphaned import receipts",
+        result.expired_entries, result.orphaned_receipts
+    );
+
+    if let Some(endpoint) = repo.get_project_cache_remote_endpoint()? {
+        if endpoint.scheme().eq_ignore_ascii_case("file") {
+            let expired_entries = LocalCacheBackend::collect_garbage(&endpoint, older_than)
+                .context("Collecting garbage in the local cache backend failed")?;
+            println!(
+                "Backend {}: removed {} expired entries",
+                endpoint, expired_entries
+            );
+        } else {
+            info!(%endpoint, "Skipping garbage collection of remote endpoint");
+        }
+    }
+
+    Ok(ExitCode(0))
+}
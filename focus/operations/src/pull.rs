@@ -2,13 +2,13 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use anyhow::{bail, Context, Result};
+use focus_internals::model::repo::WorkingTree;
 use focus_util::app::{App, ExitCode};
 use focus_util::git_helper;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use tracing::info;
 
-const FOCUS_SYNC_REF: &str = "refs/focus/sync";
 // This is correct for `source`
 const PREFETCH_DEFAULT_REF: &str = "refs/prefetch/remotes/origin/master";
 
@@ -25,9 +25,16 @@ pub fn run(app: Arc<App>, repo_path: PathBuf) -> Result<ExitCode> {
     Ok(ExitCode(0))
 }
 
+/// The name of the sync ref of the working tree at `repo_path`, which differs
+/// in linked worktrees.
+fn focus_sync_ref(repo_path: &Path) -> Result<&'static str> {
+    let repo = git2::Repository::open(repo_path).context("Opening repo")?;
+    Ok(WorkingTree::new(repo)?.sparse_sync_ref_name())
+}
+
 /// Validate existence of refs that `focus pull` operates on
 fn validation_ref_existence(app: Arc<App>, repo_path: &Path) -> Result<()> {
-    let focus_sync_ref = git_helper::parse_ref(app.clone(), repo_path, FOCUS_SYNC_REF);
+    let focus_sync_ref = git_helper::parse_ref(app.clone(), repo_path, focus_sync_ref(repo_path)?);
     let prefetch_default_ref = git_helper::parse_ref(app, repo_path, PREFETCH_DEFAULT_REF);
 
     if focus_sync_ref.is_err() || focus_sync_ref.unwrap().is_empty() {
@@ -46,8 +53,9 @@ fn validate_merge_base(app: Arc<App>, repo_path: &Path) -> Result<()> {
     let default_prefetch_ref_sha =
         git_helper::parse_ref(app.clone(), repo_path, PREFETCH_DEFAULT_REF)
             .expect("Could not parse default prefetch ref");
-    let focus_sync_ref_sha = git_helper::parse_ref(app.clone(), repo_path, FOCUS_SYNC_REF)
-        .expect("Could not parse `refs/focus/sync`");
+    let sync_ref = focus_sync_ref(repo_path)?;
+    let focus_sync_ref_sha = git_helper::parse_ref(app.clone(), repo_path, sync_ref)
+        .with_context(|| format!("Could not parse `{}`", sync_ref))?;
     let current_head = git_helper::get_current_revision(app.clone(), repo_path)?;
 
     // If prefetch refs and HEAD are equal then we can exit early
@@ -74,12 +82,17 @@ fn validate_merge_base(app: Arc<App>, repo_path: &Path) -> Result<()> {
     // Tests fail if I take out the redundant clone here
     #[allow(clippy::redundant_clone)]
     let merge_base_focus_sync_and_head =
-        git_helper::get_merge_base(app.clone(), repo_path, &current_head, FOCUS_SYNC_REF, None)
-            .context("Could not get merge-base between current HEAD and 'refs/focus/sync'")?;
+        git_helper::get_merge_base(app.clone(), repo_path, &current_head, sync_ref, None)
+            .with_context(|| {
+                format!(
+                    "Could not get merge-base between current HEAD and '{}'",
+                    sync_ref
+                )
+            })?;
 
     // If focus sync is behind current HEAD, then exit early
     if merge_base_focus_sync_and_head == focus_sync_ref_sha {
-        bail!("Exiting: refs/focus/sync is behind HEAD");
+        bail!("Exiting: {} is behind HEAD", sync_ref);
     }
 
     Ok(())
@@ -114,10 +127,11 @@ fn update_current_branch(app: Arc<App>, repo_path: &Path) -> Result<()> {
     if current_branch.is_empty() {
         bail!("HEAD does not point to a branch");
     }
-    info!("Updating current branch from `refs/focus/sync`");
+    let sync_ref = focus_sync_ref(repo_path)?;
+    info!("Updating current branch from `{}`", sync_ref);
     git_helper::pull(
         repo_path,
-        [format!("refs/focus/sync:refs/{}", current_branch)].iter(),
+        [format!("{}:refs/{}", sync_ref, current_branch)].iter(),
         ".",
         app,
         None,
@@ -5,6 +5,9 @@ use anyhow::Result;
 use focus_internals::{model::repo::Repo, target::TargetTypes};
 use focus_util::app::{App, ExitCode};
 use std::{collections::HashSet, path::Path, sync::Arc};
+use tracing::debug;
+
+use crate::daemon;
 
 pub fn run(
     sparse_repo: impl AsRef<Path>,
@@ -29,6 +32,19 @@ pub fn run(
 
     if target_types.is_empty() && !targets_flag {
         println!("{}", selection);
+        let peeks = crate::peek::load(&repo)?;
+        if !peeks.is_empty() {
+            println!("{}", peeks);
+        }
+        match daemon::query_status(&daemon::default_socket_path()) {
+            Ok(Some(status)) => {
+                if let Some(repo_status) = status.repo(sparse_repo.as_ref()) {
+                    println!("{}", repo_status);
+                }
+            }
+            Ok(None) => (),
+            Err(e) => debug!(?e, "Failed to query the background sync daemon"),
+        }
     } else {
         let mut targets = selection.targets;
         if !targets_flag {
@@ -7,7 +7,9 @@ use focus_internals::index::RocksDBMemoizationCacheExt;
 use focus_internals::{locking, model::repo::Repo};
 use focus_util::git;
 
+use crate::peek;
 use crate::util::perform;
+use chrono::Utc;
 use content_addressed_cache::RocksDBCache;
 use focus_util::app::App;
 use focus_util::backed_up_file::BackedUpFile;
@@ -62,7 +64,7 @@ pub enum SyncMode {
 }
 
 /// An enumeration capturing that the sync was peformed or a reason it was skipped.
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum SyncStatus {
     /// The sync was performed.
     Success,
@@ -112,6 +114,10 @@ pub struct SyncRequest {
 
     /// Which sync mechanism to use.
     mode: SyncMode,
+
+    /// Whether uncommitted changes are snapshotted and reapplied around the
+    /// sync. Otherwise, the working tree must be clean.
+    snapshot: bool,
 }
 
 impl SyncRequest {
@@ -119,6 +125,16 @@ impl SyncRequest {
         Self {
             sparse_repo: sparse_repo.as_ref().to_owned(),
             mode,
+            snapshot: true,
+        }
+    }
+
+    /// Refuse to sync a working tree with uncommitted changes rather than
+    /// snapshotting them, for syncs nobody asked for.
+    pub fn without_snapshot(self) -> Self {
+        Self {
+            snapshot: false,
+            ..self
         }
     }
 
@@ -216,20 +232,26 @@ pub fn run(request: &SyncRequest, app: Arc<App>) -> Result<SyncResult> {
         bail!("This does not appear to be a focused repo -- it is missing a sparse checkout file");
     }
 
-    // Take a snapshot of the sparse repo state.
-    let snapshot =
+    // Take a snapshot of the sparse repo state. Preemptive syncs leave the
+    // working tree alone, so they need no snapshot of it.
+    let snapshot = if request.snapshot {
         git::snapshot::create(request.sparse_repo_path(), app.clone()).with_context(|| {
             format!(
                 "Creating a snapshot in {} failed",
                 request.sparse_repo_path().display()
             )
-        })?;
+        })?
+    } else if preemptive || working_tree.is_clean(app.clone())? {
+        None
+    } else {
+        bail!("The working tree has uncommitted changes");
+    };
     let _snapshot_guard =
         git::snapshot::ReapplyGuard::new(request.sparse_repo_path(), snapshot.clone(), app.clone());
 
     let selections = repo.selection_manager()?;
-    let selection = selections.computed_selection()?;
-    let targets = selections.compute_complete_target_set()?;
+    let mut selection = selections.computed_selection()?;
+    let mut targets = selections.compute_complete_target_set()?;
 
     let mut mechanism = SyncMechanism::IncrementalOutline;
 
@@ -277,6 +299,15 @@ pub fn run(request: &SyncRequest, app: Arc<App>) -> Result<SyncResult> {
         Some(BackedUpFile::new(&sparse_profile_path)?)
     };
 
+    // Peeked directories are outlined along with the selection until the first
+    // sync after they expire. They count as ad-hoc targets, so the project
+    // cache is not used while there are any.
+    let mut peeks = peek::load(&repo)?;
+    let expired_peeks = peeks.remove_expired(Utc::now());
+    let peeked_targets = peeks.targets();
+    selection.targets.extend(peeked_targets.iter().cloned());
+    targets.extend(peeked_targets);
+
     let head_commit = repo.get_head_commit().context("Resolving head commit")?;
 
     // Figure out if this repo has a "master" branch or "main" branch.
@@ -364,7 +395,7 @@ pub fn run(request: &SyncRequest, app: Arc<App>) -> Result<SyncResult> {
                 let cache: Option<RocksDBCache> = if one_shot {
                     None
                 } else {
-                    Some(RocksDBCache::new(repo.underlying()))
+                    Some(RocksDBCache::new(repo.underlying())?)
                 };
 
                 repo.sync(
@@ -398,6 +429,13 @@ pub fn run(request: &SyncRequest, app: Arc<App>) -> Result<SyncResult> {
             working_tree.write_sync_point_ref()
         })?;
 
+        if !expired_peeks.is_empty() {
+            let directories: Vec<&String> =
+                expired_peeks.iter().map(|peek| &peek.directory).collect();
+            info!(?directories, "Removed expired peeked directories");
+            peek::save(&repo, &peeks)?;
+        }
+
         // The profile was successfully applied, so do not restore the backup.
         backed_up_sparse_profile.unwrap().set_restore(false);
     }
@@ -109,12 +109,14 @@ impl RepoPairFixture {
     pub fn perform_clone(&self) -> Result<()> {
         let clone_args = CloneArgs {
             origin: Some(crate::clone::Origin::Local(self.dense_repo_path.clone())),
-            branch: self.branch.clone(),
+            branch: Some(self.branch.clone()),
             projects_and_targets: self.projects_and_targets.clone(),
             copy_branches: true,
-            days_of_history: 90,
+            days_of_history: Some(90),
             do_post_clone_fetch: false,
             sync_mode: self.sync_mode.get(),
+            bundle: None,
+            reference: None,
         };
 
         crate::clone::run(
@@ -192,6 +192,28 @@ fn sync_with_pending_changes_internal(sync_mode: SyncMode) -> Result<SyncMechani
     Ok(sync_result.mechanism)
 }
 
+#[test]
+fn sync_without_snapshot_refuses_pending_changes() -> Result<()> {
+    init_logging();
+
+    let fixture = RepoPairFixture::new()?;
+    fixture.perform_clone()?;
+
+    let untracked_file_path = fixture.sparse_repo_path.join("untracked-file.txt");
+    std::fs::write(&untracked_file_path, "Howdy!\n")?;
+
+    let request =
+        SyncRequest::new(&fixture.sparse_repo_path, SyncMode::Incremental).without_snapshot();
+    assert!(crate::sync::run(&request, fixture.app.clone()).is_err());
+    assert_eq!(std::fs::read_to_string(&untracked_file_path)?, "Howdy!\n");
+
+    std::fs::remove_file(&untracked_file_path)?;
+    let sync_result = crate::sync::run(&request, fixture.app.clone())?;
+    assert_eq!(sync_result.status, SyncStatus::Success);
+
+    Ok(())
+}
+
 #[test]
 fn sync_layer_manipulation_with_incremental_sync() -> Result<()> {
     sync_layer_manipulation_internal(SyncMode::Incremental)
@@ -1,10 +1,12 @@
 // Copyright 2022 Twitter, Inc.
 // SPDX-License-Identifier: Apache-2.0
 
-use std::path::Path;
+use std::{path::Path, time::Duration};
 
 use anyhow::{bail, Context, Result};
-use focus_internals::model::repo::PROJECT_CACHE_ENDPOINT_CONFIG_KEY;
+use focus_internals::{
+    model::repo::PROJECT_CACHE_ENDPOINT_CONFIG_KEY, project_cache::ProjectCache,
+};
 use focus_testing::{init_logging, GitBinary};
 use focus_util::{app::ExitCode, git_helper};
 
@@ -130,6 +132,48 @@ fn generation() -> Result<()> {
     Ok(())
 }
 
+#[test]
+fn inspection_of_generated_content() -> Result<()> {
+    init_logging();
+
+    let fixture = Fixture::new(Location::Sparse)?;
+    let app = fixture.underlying.app.clone();
+    fixture.configure_endpoint(Location::Sparse)?;
+    fixture.generate_content(2)?;
+
+    let exit_code =
+        project_cache::verify(app.clone(), fixture.repo_path(), None, String::from("HEAD"))?;
+    assert_eq!(exit_code, ExitCode(0));
+
+    // Fresh content survives garbage collection.
+    project_cache::gc(app.clone(), fixture.repo_path(), Duration::from_secs(3600))?;
+
+    let repo = fixture.underlying.sparse_repo()?;
+    let project_count = repo
+        .selection_manager()?
+        .project_catalog()
+        .optional_projects
+        .underlying
+        .len();
+    let endpoint = repo
+        .get_project_cache_remote_endpoint()?
+        .expect("Endpoint is configured");
+    let cache = ProjectCache::new(&repo, endpoint, app)?;
+    let summaries = cache.list(true)?;
+    assert_eq!(summaries.len(), 1);
+    let summary = &summaries[0];
+    assert_eq!(summary.commit_count, 1);
+    assert!(summary.has_mandatory_patterns);
+    assert_eq!(summary.optional_project_count, project_count);
+    assert_eq!(summary.shard_count, Some(2));
+    assert!(!summary.imported);
+
+    let patterns = cache.show(&summary.build_graph_hash, None, true)?;
+    assert!(!patterns.unwrap_or_default().is_empty());
+
+    Ok(())
+}
+
 #[test]
 fn project_cache_falls_back_with_non_project_targets_selected() -> Result<()> {
     init_logging();
@@ -0,0 +1,175 @@
+// Copyright 2022 Twitter, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::{bail, Context, Result};
+use focus_internals::{model::repo::Repo, tracker::Tracker};
+use focus_util::{app::App, git_helper, sandbox_command::SandboxCommandOutput};
+use tracing::{info, warn};
+
+use crate::clone;
+use crate::sync::SyncMode;
+
+/// Add a linked worktree of the focused repo at `repo_path` at `path`, with
+/// `branch` checked out. Git creates a branch named after the directory if none
+/// is given. The worktree starts with the selection of the repo it was added
+/// to and gets its own outlining tree, but shares the object store, index, and
+/// project cache with it.
+pub fn add(
+    repo_path: &Path,
+    path: &Path,
+    branch: Option<String>,
+    tracker: &Tracker,
+    app: Arc<App>,
+) -> Result<()> {
+    let path = std::env::current_dir()
+        .context("Failed to obtain current directory")?
+        .join(path);
+    if path.exists() {
+        bail!("{} already exists", path.display());
+    }
+
+    let repo = Repo::open(repo_path, app.clone()).context("Failed to open repo")?;
+    let selection = repo.selection_manager()?.selection()?;
+    let projects_and_targets: Vec<String> = selection
+        .projects
+        .into_iter()
+        .map(|project| project.name)
+        .chain(
+            selection
+                .targets
+                .into_iter()
+                .map(|target| target.to_string()),
+        )
+        .collect();
+    let sync_mode = if repo.get_bazel_oneshot_resolution()? {
+        SyncMode::OneShot
+    } else {
+        SyncMode::Incremental
+    };
+
+    // Git names the administrative directory of a worktree after its
+    // directory, which also names its outlining tree, so clear out those of
+    // worktrees which were deleted without `git worktree remove`.
+    prune(repo_path, app.clone())?;
+
+    info!(path = ?path, ?branch, "Adding worktree");
+    {
+        let (mut cmd, scmd) = git_helper::git_command(app.clone())?;
+        let cmd = cmd
+            .current_dir(repo_path)
+            .arg("worktree")
+            .arg("add")
+            .arg("--no-checkout")
+            .arg(&path);
+        if let Some(branch) = branch.as_ref() {
+            cmd.arg(branch);
+        }
+        scmd.ensure_success_or_log(cmd, SandboxCommandOutput::Stderr)
+            .context("git worktree add failed")?;
+    }
+
+    let set_up = || -> Result<()> {
+        clone::set_up_sparse_repo(&path, projects_and_targets, None, sync_mode, app.clone())?;
+        tracker
+            .ensure_registered(&path, app.clone())
+            .context("Registering the worktree with the tracker")
+    };
+    if let Err(e) = set_up() {
+        // Leave nothing behind so that the worktree can be added again.
+        if let Err(e) = remove(repo_path, &path, true, app) {
+            warn!(path = ?path, ?e, "Failed to remove the partially set up worktree");
+        }
+        return Err(e);
+    }
+
+    Ok(())
+}
+
+/// Remove the linked worktree at `path` of the repo at `repo_path`, along with
+/// its outlining tree. Git refuses to remove worktrees with changes unless
+/// `force` is set.
+pub fn remove(repo_path: &Path, path: &Path, force: bool, app: Arc<App>) -> Result<()> {
+    let path = std::env::current_dir()
+        .context("Failed to obtain current directory")?
+        .join(path);
+    info!(path = ?path, "Removing worktree");
+    let (mut cmd, scmd) = git_helper::git_command(app.clone())?;
+    let cmd = cmd.current_dir(repo_path).arg("worktree").arg("remove");
+    if force {
+        cmd.arg("--force");
+    }
+    scmd.ensure_success_or_log(cmd.arg(&path), SandboxCommandOutput::Stderr)
+        .context("git worktree remove failed")?;
+
+    // The outlining tree lived in the administrative directory of the
+    // worktree, which is now gone.
+    prune(repo_path, app)
+}
+
+fn prune(repo_path: &Path, app: Arc<App>) -> Result<()> {
+    let (mut cmd, scmd) = git_helper::git_command(app)?;
+    scmd.ensure_success_or_log(
+        cmd.current_dir(repo_path).arg("worktree").arg("prune"),
+        SandboxCommandOutput::Stderr,
+    )
+    .context("git worktree prune failed")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Result;
+    use focus_testing::init_logging;
+
+    use crate::testing::integration::RepoPairFixture;
+
+    use super::*;
+
+    #[test]
+    fn worktree_add_and_remove() -> Result<()> {
+        init_logging();
+        let fixture = RepoPairFixture::new()?;
+        fixture.perform_clone()?;
+        let sparse_repo = fixture.sparse_repo()?;
+        let worktree_path = fixture.dir.path().join("feature");
+
+        add(
+            &fixture.sparse_repo_path,
+            &worktree_path,
+            None,
+            &fixture.tracker,
+            fixture.app.clone(),
+        )?;
+
+        let worktree_repo = Repo::open(&worktree_path, fixture.app.clone())?;
+        assert!(worktree_path.join("WORKSPACE").is_file());
+        assert!(worktree_repo.outliner().is_some());
+        assert_eq!(
+            worktree_repo.selection_manager()?.selection()?,
+            sparse_repo.selection_manager()?.selection()?
+        );
+
+        // The worktree is tracked separately from the repo it was added to.
+        let uuid = worktree_repo.working_tree()?.read_uuid()?.unwrap();
+        assert_ne!(Some(uuid), sparse_repo.working_tree()?.read_uuid()?);
+        let snapshot = fixture.tracker.scan()?;
+        let tracked = snapshot.find_repo_by_id(uuid.as_bytes()).unwrap();
+        assert_eq!(
+            tracked.location().canonicalize()?,
+            worktree_path.canonicalize()?
+        );
+
+        remove(
+            &fixture.sparse_repo_path,
+            &worktree_path,
+            false,
+            fixture.app.clone(),
+        )?;
+        assert!(!worktree_path.exists());
+
+        Ok(())
+    }
+}
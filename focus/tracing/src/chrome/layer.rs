@@ -0,0 +1,247 @@
+// Copyright 2022 Twitter, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+// Records focus's own spans as Chrome trace events, to be merged with the
+// trace2 events of the git processes focus spawns.
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use anyhow::{Context as _, Result};
+use chrono::Utc;
+use focus_util::git_helper::ConfigExt;
+use serde_json::{Map, Value};
+use tracing::span::{Attributes, Id, Record};
+use tracing::Subscriber;
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+use super::{trace, Common, Complete, Event, Phase};
+use crate::git_trace2;
+use crate::json_log::JsonVisitor;
+
+/// The name of the trace file written for each invocation.
+pub const CHROME_TRACE_FILE_NAME: &str = "trace.json";
+
+/// The name of the directory git writes its trace2 events to, one file per
+/// process.
+pub const GIT_TRACE2_EVENT_DIR_NAME: &str = "git_trace2_events";
+
+/// The pid of focus itself in the trace. The git sessions are numbered from 1.
+const FOCUS_PID: u64 = 0;
+
+const CHROME_TRACE_ENABLED_KEY: &str = "focus.trace.chrome";
+
+/// Whether recording a Chrome trace of each invocation is enabled in the git
+/// config.
+pub fn enabled_from_git(config: &mut git2::Config) -> Result<bool> {
+    config.get_bool_with_default(CHROME_TRACE_ENABLED_KEY, false)
+}
+
+static NEXT_THREAD_ID: AtomicU64 = AtomicU64::new(1);
+
+thread_local! {
+    static THREAD_ID: u64 = NEXT_THREAD_ID.fetch_add(1, Ordering::Relaxed);
+}
+
+/// The data recorded for each open span.
+struct SpanData {
+    fields: Map<String, Value>,
+    opened_at_micros: i64,
+    opened_at: std::time::Instant,
+    tid: u64,
+}
+
+/// Records each span as a Chrome `Complete` event when it closes.
+pub struct ChromeLayer {
+    events: Arc<Mutex<Vec<Event>>>,
+}
+
+impl<S> Layer<S> for ChromeLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            let mut fields = Map::new();
+            attrs.record(&mut JsonVisitor(&mut fields));
+            span.extensions_mut().insert(SpanData {
+                fields,
+                opened_at_micros: Utc::now().timestamp_micros(),
+                opened_at: std::time::Instant::now(),
+                tid: THREAD_ID.with(|tid| *tid),
+            });
+        }
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            if let Some(data) = span.extensions_mut().get_mut::<SpanData>() {
+                values.record(&mut JsonVisitor(&mut data.fields));
+            }
+        }
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        let span = match ctx.span(&id) {
+            Some(span) => span,
+            None => return,
+        };
+        let data = match span.extensions_mut().remove::<SpanData>() {
+            Some(data) => data,
+            None => return,
+        };
+        let event = Event::from(Complete {
+            common: Common {
+                ts: data.opened_at_micros,
+                pid: FOCUS_PID,
+                tid: data.tid,
+                name: span.name().to_owned(),
+                cat: span.metadata().target().to_owned(),
+                ph: Phase::Complete,
+                tts: None,
+            },
+            dur: data.opened_at.elapsed().as_micros() as i64,
+            args: Value::Object(data.fields),
+        });
+        if let Ok(mut events) = self.events.lock() {
+            events.push(event);
+        }
+    }
+}
+
+/// Writes the trace recorded by a [`ChromeLayer`], merged with the trace2
+/// events of the git processes spawned meanwhile.
+#[derive(Debug)]
+pub struct ChromeTraceWriter {
+    events: Arc<Mutex<Vec<Event>>>,
+    git_trace2_event_dir: PathBuf,
+    output_path: PathBuf,
+}
+
+impl ChromeTraceWriter {
+    /// Write the merged trace to the output path.
+    pub fn write(&self) -> Result<()> {
+        let mut builder = trace::Builder::default();
+        for entry in git_trace2::Events::file_iter(&self.git_trace2_event_dir)
+            .filter(|entry| entry.path().is_file())
+        {
+            match git_trace2::Events::from_file(entry.path()) {
+                Ok(events) => {
+                    builder.add_events(events);
+                }
+                // Leave out the processes whose events we can't parse, such as
+                // those from git versions with events we don't know about,
+                // rather than the whole trace.
+                Err(e) => eprintln!(
+                    "Skipping git trace2 events in {}: {:#}",
+                    entry.path().display(),
+                    e
+                ),
+            }
+        }
+        let events = std::mem::take(
+            &mut *self
+                .events
+                .lock()
+                .map_err(|_| anyhow::anyhow!("Chrome trace events lock was poisoned"))?,
+        );
+        builder.add_chrome_events(events);
+        builder
+            .build()?
+            .write_trace_json_to(&self.output_path)
+            .with_context(|| format!("Writing {}", self.output_path.display()))
+    }
+
+    /// The path the trace is written to.
+    pub fn output_path(&self) -> &Path {
+        &self.output_path
+    }
+}
+
+/// Create a layer which records focus's spans and a writer which merges them
+/// with the trace2 events git writes to `trace_dir` into a Chrome trace file in
+/// the same directory.
+///
+/// Git only writes its trace2 events there if `GIT_TRACE2_EVENT` is set to the
+/// directory returned alongside, which must be done before spawning any git
+/// processes.
+pub fn chrome_layer(trace_dir: &Path) -> Result<(ChromeLayer, ChromeTraceWriter, PathBuf)> {
+    let git_trace2_event_dir = trace_dir.join(GIT_TRACE2_EVENT_DIR_NAME);
+    std::fs::create_dir_all(&git_trace2_event_dir)
+        .with_context(|| format!("Creating {}", git_trace2_event_dir.display()))?;
+    let events = Arc::new(Mutex::new(Vec::new()));
+    Ok((
+        ChromeLayer {
+            events: events.clone(),
+        },
+        ChromeTraceWriter {
+            events,
+            git_trace2_event_dir: git_trace2_event_dir.clone(),
+            output_path: trace_dir.join(CHROME_TRACE_FILE_NAME),
+        },
+        git_trace2_event_dir,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use tracing::info_span;
+    use tracing_subscriber::prelude::*;
+
+    use super::*;
+    use crate::chrome::Trace;
+    use crate::testing::fixture_path;
+
+    #[test]
+    fn test_chrome_layer() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let (layer, writer, git_trace2_event_dir) = chrome_layer(temp_dir.path())?;
+        std::fs::copy(
+            fixture_path("status.json")?,
+            git_trace2_event_dir.join("status.json"),
+        )?;
+
+        let subscriber = tracing_subscriber::registry().with(layer);
+        tracing::subscriber::with_default(subscriber, || {
+            let outer = info_span!("sync", commit = "abc123");
+            let _outer = outer.enter();
+            let inner = info_span!("resolve");
+            let _inner = inner.enter();
+        });
+
+        writer.write()?;
+        let trace: Trace = serde_json::from_slice(&std::fs::read(writer.output_path())?)?;
+
+        let focus_events: Vec<&Complete> = trace
+            .trace_events
+            .iter()
+            .filter_map(|event| match event {
+                Event::Complete(complete) if complete.common.pid == FOCUS_PID => Some(complete),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(focus_events.len(), 2);
+        let sync = focus_events
+            .iter()
+            .find(|event| event.common.name == "sync")
+            .unwrap();
+        assert_eq!(sync.args["commit"], "abc123");
+        let resolve = focus_events
+            .iter()
+            .find(|event| event.common.name == "resolve")
+            .unwrap();
+        assert!(sync.common.ts <= resolve.common.ts);
+        assert!(sync.dur >= resolve.dur);
+        assert_eq!(sync.common.tid, resolve.common.tid);
+
+        assert!(trace
+            .trace_events
+            .iter()
+            .any(|event| event.common().pid != FOCUS_PID));
+
+        Ok(())
+    }
+}
@@ -1,6 +1,7 @@
 // Copyright 2022 Twitter, Inc.
 // SPDX-License-Identifier: Apache-2.0
 
+pub mod layer;
 mod tests;
 pub mod trace;
 
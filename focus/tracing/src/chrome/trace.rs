@@ -252,6 +252,7 @@ impl<T> AsSlice<T> for Option<T> {
 #[derive(Debug, Default)]
 pub struct Builder {
     git_events: Vec<GitEvent>,
+    chrome_events: Vec<Event>,
 }
 
 impl Builder {
@@ -265,6 +266,14 @@ impl Builder {
         self
     }
 
+    /// Add events which are already in the Chrome format, such as those recorded
+    /// for focus's own spans. Their pids should not collide with those assigned
+    /// to git sessions, which start at 1.
+    pub fn add_chrome_events<I: IntoIterator<Item = Event>>(&mut self, events: I) -> &mut Self {
+        self.chrome_events.extend(events);
+        self
+    }
+
     fn relativize_timestamps(events: &mut [Event]) {
         if let Some(min) = events.iter().min_by_key(|ev| ev.ts()) {
             let min_ts = min.ts();
@@ -279,14 +288,13 @@ impl Builder {
         events.sort_by(|a, b| a.common().cmp(b.common()))
     }
 
-    /// Take all git events added to this Builder and create Session instances for them.
+    /// Take the git events added to a Builder and create Session instances for them.
     /// Each Session instance will contain the events for a single git "sid" which is
     /// equivalent to a single git process.
-    fn into_sessions(self) -> Vec<Session> {
+    fn into_sessions(git_events: Vec<GitEvent>) -> Vec<Session> {
         let mut spmap = SidPidMapper::default();
 
-        let map: HashMap<Sid, Session> = self
-            .git_events
+        let map: HashMap<Sid, Session> = git_events
             .into_iter()
             .map(|gev| {
                 let sid = gev.sid().clone();
@@ -317,13 +325,18 @@ impl Builder {
     pub fn build(self) -> Result<Trace> {
         use rayon::prelude::*;
 
-        let sessions: Vec<Session> = self.into_sessions();
+        let Self {
+            git_events,
+            chrome_events,
+        } = self;
+        let sessions: Vec<Session> = Self::into_sessions(git_events);
 
         let mut events: Vec<Event> = sessions
             .into_par_iter()
             .map(|session| session.build())
             .flatten()
             .collect();
+        events.extend(chrome_events);
 
         Self::relativize_timestamps(&mut events);
         Self::sort_events(&mut events);
@@ -3,23 +3,45 @@
 
 // module to collect tracing setup and config for the focus app itself
 
-use std::fs::OpenOptions;
-use std::io::{self, BufWriter};
+use std::fmt;
+use std::io;
 use std::path::PathBuf;
 
 use anyhow::{Context, Result};
 use tracing::dispatcher::DefaultGuard;
 use tracing::metadata::LevelFilter;
+use tracing::{Event, Subscriber};
 use tracing_appender::non_blocking::WorkerGuard;
 use tracing_error::ErrorLayer;
 use tracing_subscriber::filter::Targets;
+use tracing_subscriber::fmt::format::Writer;
+use tracing_subscriber::fmt::{FmtContext, FormatEvent, FormatFields};
 use tracing_subscriber::prelude::*;
+use tracing_subscriber::registry::LookupSpan;
 use tracing_subscriber::{self, util::SubscriberInitExt, EnvFilter};
 
+use crate::chrome::{self, layer::ChromeTraceWriter};
+use crate::json_log::{self, InvocationContext, JsonLogLayer, JSON_LOG_FILE_NAME};
+use crate::rotation::{RotatingFileWriter, RotationConfig, LOG_FILE_NAME};
+
 #[derive(Debug)]
 pub enum GuardWrapper {
     WorkerGuard(WorkerGuard),
     DefaultGuard(DefaultGuard),
+    ChromeTraceGuard(ChromeTraceGuard),
+}
+
+/// Writes the Chrome trace of the invocation when dropped.
+#[derive(Debug)]
+pub struct ChromeTraceGuard(ChromeTraceWriter);
+
+impl Drop for ChromeTraceGuard {
+    fn drop(&mut self) {
+        // Tracing may already have been torn down, so report errors directly.
+        if let Err(e) = self.0.write() {
+            eprintln!("Failed to write Chrome trace: {:#}", e);
+        }
+    }
 }
 
 #[derive(Debug, Default)]
@@ -33,15 +55,49 @@ pub struct TracingOpts {
     pub is_tty: bool,
     pub no_color: bool,
     pub log_dir: Option<PathBuf>,
+    /// Identifies this invocation in the logs. The invocation ID is prefixed to
+    /// every line written to the log file, so that the lines from one
+    /// invocation can be found among those of others, and the whole context is
+    /// included in every record of the JSON lines log, if enabled.
+    pub invocation: InvocationContext,
+    /// The directory to write a Chrome trace of this invocation to, if enabled
+    /// in the git config. The trace includes the spans of focus itself and of
+    /// the git processes it spawns.
+    pub trace_dir: Option<PathBuf>,
+}
+
+/// Prefixes each event formatted by the inner formatter with the invocation ID.
+struct InvocationFormat<F> {
+    invocation_id: Option<String>,
+    inner: F,
 }
 
-const LOG_FILE_NAME: &str = "focus.log";
+impl<S, N, F> FormatEvent<S, N> for InvocationFormat<F>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+    F: FormatEvent<S, N>,
+{
+    fn format_event(
+        &self,
+        ctx: &FmtContext<'_, S, N>,
+        mut writer: Writer<'_>,
+        event: &Event<'_>,
+    ) -> fmt::Result {
+        if let Some(invocation_id) = &self.invocation_id {
+            write!(writer, "[{}] ", invocation_id)?;
+        }
+        self.inner.format_event(ctx, writer, event)
+    }
+}
 
 pub fn init_tracing(opts: TracingOpts) -> Result<Guard> {
     let TracingOpts {
         is_tty,
         no_color,
         log_dir,
+        invocation,
+        trace_dir,
     } = opts;
 
     let use_color = is_tty && !no_color;
@@ -55,14 +111,59 @@ pub fn init_tracing(opts: TracingOpts) -> Result<Guard> {
 
     let log_path = log_dir.join(LOG_FILE_NAME);
 
-    // TODO: figure out log file rotation
-    let (log_file_writer, log_file_guard) = tracing_appender::non_blocking(BufWriter::new(
-        OpenOptions::new()
-            .append(true)
-            .create(true)
-            .open(&log_path)
+    let (rotation_config, json_log_enabled, chrome_trace_enabled) =
+        match git2::Config::open_default() {
+            Ok(mut config) => (
+                RotationConfig::try_from_git(&mut config).unwrap_or_else(|e| {
+                    eprintln!(
+                        "Failed to read log rotation config, using defaults: {:#}",
+                        e
+                    );
+                    RotationConfig::default()
+                }),
+                json_log::enabled_from_git(&mut config).unwrap_or(false),
+                chrome::layer::enabled_from_git(&mut config).unwrap_or(false),
+            ),
+            Err(e) => {
+                eprintln!("Failed to open git config, using default log config: {}", e);
+                (RotationConfig::default(), false, false)
+            }
+        };
+    let (log_file_writer, log_file_guard) = tracing_appender::non_blocking(
+        RotatingFileWriter::open(&log_path, rotation_config.clone())
             .context("failed to open log file")?,
-    ));
+    );
+
+    let mut guards = vec![GuardWrapper::WorkerGuard(log_file_guard)];
+    let json_log_layer = if json_log_enabled {
+        let (json_log_writer, json_log_guard) = tracing_appender::non_blocking(
+            RotatingFileWriter::open(log_dir.join(JSON_LOG_FILE_NAME), rotation_config)
+                .context("failed to open JSON log file")?,
+        );
+        guards.push(GuardWrapper::WorkerGuard(json_log_guard));
+        Some(JsonLogLayer::new(invocation.clone(), json_log_writer))
+    } else {
+        None
+    };
+
+    let chrome_layer = match trace_dir {
+        Some(trace_dir) if chrome_trace_enabled => {
+            let (layer, writer, git_trace2_event_dir) = chrome::layer::chrome_layer(&trace_dir)?;
+            // Git processes spawned from now on inherit this, and write their
+            // trace2 events to a file of their own in the directory. Git only
+            // writes to one target, so leave a value set by the user alone.
+            if std::env::var_os("GIT_TRACE2_EVENT").is_none() {
+                std::env::set_var("GIT_TRACE2_EVENT", &git_trace2_event_dir);
+            } else {
+                eprintln!(
+                    "GIT_TRACE2_EVENT is already set, so the trace won't include git's events"
+                );
+            }
+            guards.push(GuardWrapper::ChromeTraceGuard(ChromeTraceGuard(writer)));
+            Some(layer)
+        }
+        _ => None,
+    };
 
     let (stderr_writer, stderr_guard) = tracing_appender::non_blocking(io::stderr());
 
@@ -101,15 +202,16 @@ pub fn init_tracing(opts: TracingOpts) -> Result<Guard> {
             tracing_subscriber::fmt::layer()
                 .with_ansi(false)
                 .with_span_events(tracing_subscriber::fmt::format::FmtSpan::CLOSE)
-                .with_target(false)
+                .event_format(InvocationFormat {
+                    invocation_id: invocation.invocation_id,
+                    inner: tracing_subscriber::fmt::format().with_target(false),
+                })
                 .with_writer(log_file_writer),
         )
+        .with(json_log_layer)
+        .with(chrome_layer)
         .try_init()?;
 
-    Ok(Guard {
-        _inner: vec![
-            GuardWrapper::WorkerGuard(stderr_guard),
-            GuardWrapper::WorkerGuard(log_file_guard),
-        ],
-    })
+    guards.insert(0, GuardWrapper::WorkerGuard(stderr_guard));
+    Ok(Guard { _inner: guards })
 }
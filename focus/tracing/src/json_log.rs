@@ -0,0 +1,301 @@
+// Copyright 2022 Twitter, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+// A tracing layer which writes events as JSON lines tagged with the invocation
+// that produced them.
+
+use std::fmt;
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::Instant;
+
+use anyhow::Result;
+use chrono::{SecondsFormat, Utc};
+use focus_util::git_helper::ConfigExt;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::layer::Context;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::Layer;
+
+/// The name of the JSON lines log file.
+pub const JSON_LOG_FILE_NAME: &str = "focus.jsonl";
+
+const JSON_LOG_ENABLED_KEY: &str = "focus.log.json";
+
+/// Whether the JSON lines log is enabled in the git config.
+pub fn enabled_from_git(config: &mut git2::Config) -> Result<bool> {
+    config.get_bool_with_default(JSON_LOG_ENABLED_KEY, false)
+}
+
+/// Identifies the invocation of focus which wrote a log record.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct InvocationContext {
+    /// A unique ID for this invocation.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub invocation_id: Option<String>,
+
+    /// The tool insights trace ID, shared with the processes that spawned or
+    /// were spawned by this one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trace_id: Option<String>,
+
+    /// The tool insights span ID of this process.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub span_id: Option<String>,
+
+    /// The name of the subcommand being run.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub feature_name: Option<String>,
+
+    /// The repository the invocation was run in, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub repo_path: Option<PathBuf>,
+}
+
+#[derive(Serialize)]
+struct LogRecord<'a> {
+    timestamp: String,
+    level: &'a str,
+    target: &'a str,
+    #[serde(flatten)]
+    invocation: &'a InvocationContext,
+    #[serde(skip_serializing_if = "Map::is_empty")]
+    fields: Map<String, Value>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    spans: Vec<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    elapsed_ms: Option<f64>,
+}
+
+/// The data recorded for each open span.
+struct SpanData {
+    fields: Map<String, Value>,
+    opened_at: Instant,
+}
+
+pub(crate) struct JsonVisitor<'a>(&'a mut Map<String, Value>);
+
+impl Visit for JsonVisitor<'_> {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.0.insert(field.name().to_owned(), Value::from(value));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().to_owned(), Value::from(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().to_owned(), Value::from(value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().to_owned(), Value::from(value));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_owned(), Value::from(value));
+    }
+
+    fn record_error(&mut self, field: &Field, value: &(dyn std::error::Error + 'static)) {
+        self.0
+            .insert(field.name().to_owned(), Value::from(value.to_string()));
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0
+            .insert(field.name().to_owned(), Value::from(format!("{:?}", value)));
+    }
+}
+
+/// Writes each event, and the closing of each span, as a line of JSON which
+/// includes the [`InvocationContext`].
+pub struct JsonLogLayer<W> {
+    invocation: InvocationContext,
+    make_writer: W,
+}
+
+impl<W> JsonLogLayer<W> {
+    pub fn new(invocation: InvocationContext, make_writer: W) -> Self {
+        Self {
+            invocation,
+            make_writer,
+        }
+    }
+}
+
+impl<W> JsonLogLayer<W>
+where
+    W: for<'w> MakeWriter<'w> + 'static,
+{
+    fn write_record(&self, record: &LogRecord<'_>) {
+        let mut line = match serde_json::to_vec(record) {
+            Ok(line) => line,
+            Err(_) => return,
+        };
+        line.push(b'\n');
+        // There's nowhere left to report a failure to write the log.
+        let _ = self.make_writer.make_writer().write_all(&line);
+    }
+
+    fn span_values<S>(
+        scope: impl Iterator<Item = tracing_subscriber::registry::SpanRef<'_, S>>,
+    ) -> Vec<Value>
+    where
+        S: for<'a> LookupSpan<'a>,
+    {
+        scope
+            .map(|span| {
+                let mut value = Map::new();
+                value.insert("name".to_owned(), Value::from(span.name()));
+                if let Some(data) = span.extensions().get::<SpanData>() {
+                    value.extend(data.fields.clone());
+                }
+                Value::Object(value)
+            })
+            .collect()
+    }
+}
+
+impl<S, W> Layer<S> for JsonLogLayer<W>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    W: for<'w> MakeWriter<'w> + 'static,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            let mut fields = Map::new();
+            attrs.record(&mut JsonVisitor(&mut fields));
+            span.extensions_mut().insert(SpanData {
+                fields,
+                opened_at: Instant::now(),
+            });
+        }
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+        if let Some(span) = ctx.span(id) {
+            if let Some(data) = span.extensions_mut().get_mut::<SpanData>() {
+                values.record(&mut JsonVisitor(&mut data.fields));
+            }
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let mut fields = Map::new();
+        event.record(&mut JsonVisitor(&mut fields));
+        let spans = ctx
+            .event_scope(event)
+            .map(|scope| Self::span_values(scope.from_root()))
+            .unwrap_or_default();
+        let metadata = event.metadata();
+        self.write_record(&LogRecord {
+            timestamp: Utc::now().to_rfc3339_opts(SecondsFormat::Micros, true),
+            level: metadata.level().as_str(),
+            target: metadata.target(),
+            invocation: &self.invocation,
+            fields,
+            spans,
+            elapsed_ms: None,
+        });
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+        let span = match ctx.span(&id) {
+            Some(span) => span,
+            None => return,
+        };
+        let elapsed_ms = span
+            .extensions()
+            .get::<SpanData>()
+            .map(|data| data.opened_at.elapsed().as_secs_f64() * 1000.0);
+        let mut fields = Map::new();
+        fields.insert("message".to_owned(), Value::from("close"));
+        let metadata = span.metadata();
+        self.write_record(&LogRecord {
+            timestamp: Utc::now().to_rfc3339_opts(SecondsFormat::Micros, true),
+            level: metadata.level().as_str(),
+            target: metadata.target(),
+            invocation: &self.invocation,
+            fields,
+            spans: Self::span_values(span.scope().from_root()),
+            elapsed_ms,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::sync::{Arc, Mutex};
+
+    use tracing::{info, info_span};
+    use tracing_subscriber::prelude::*;
+
+    use super::*;
+
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_json_log_layer() -> Result<()> {
+        let buffer = SharedBuffer::default();
+        let invocation = InvocationContext {
+            invocation_id: Some("focus_sandbox_sync_abc".to_owned()),
+            trace_id: Some("1234abcd".to_owned()),
+            span_id: Some("5678ef".to_owned()),
+            feature_name: Some("sync".to_owned()),
+            repo_path: Some(PathBuf::from("/repo")),
+        };
+        let subscriber = tracing_subscriber::registry().with(JsonLogLayer::new(invocation, {
+            let buffer = buffer.clone();
+            move || buffer.clone()
+        }));
+        tracing::subscriber::with_default(subscriber, || {
+            let span = info_span!("outer", commit = "abc123");
+            let _guard = span.enter();
+            info!(count = 3, "Synced");
+        });
+
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone())?;
+        let records: Vec<Value> = output
+            .lines()
+            .map(serde_json::from_str)
+            .collect::<Result<_, _>>()?;
+        assert_eq!(records.len(), 2);
+
+        let event = &records[0];
+        assert_eq!(event["level"], "INFO");
+        assert_eq!(event["invocation_id"], "focus_sandbox_sync_abc");
+        assert_eq!(event["trace_id"], "1234abcd");
+        assert_eq!(event["span_id"], "5678ef");
+        assert_eq!(event["feature_name"], "sync");
+        assert_eq!(event["repo_path"], "/repo");
+        assert_eq!(event["fields"]["message"], "Synced");
+        assert_eq!(event["fields"]["count"], 3);
+        assert_eq!(event["spans"][0]["name"], "outer");
+        assert_eq!(event["spans"][0]["commit"], "abc123");
+        assert!(event.get("elapsed_ms").is_none());
+
+        let close = &records[1];
+        assert_eq!(close["fields"]["message"], "close");
+        assert_eq!(close["spans"][0]["name"], "outer");
+        assert!(close["elapsed_ms"].is_number());
+
+        Ok(())
+    }
+}
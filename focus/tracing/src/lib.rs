@@ -4,10 +4,14 @@
 pub mod chrome;
 pub mod focus;
 pub mod git_trace2;
+pub mod json_log;
+pub mod logs;
+pub mod rotation;
 
 use std::path::{Path, PathBuf};
 
 pub use crate::focus::{init_tracing, Guard, TracingOpts};
+pub use crate::json_log::InvocationContext;
 pub use chrome::Trace;
 
 use anyhow::Result;
@@ -0,0 +1,285 @@
+// Copyright 2022 Twitter, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+// Reading the focus log files, including those which have been rotated.
+
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use anyhow::{Context, Result};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use regex::Regex;
+
+use crate::json_log::JSON_LOG_FILE_NAME;
+use crate::rotation::{rotated_log_files, LOG_FILE_NAME};
+
+const FOLLOW_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// All log files in the directory, oldest first. The file currently being
+/// written is last.
+pub fn log_files(log_dir: &Path) -> Result<Vec<PathBuf>> {
+    log_files_for(&log_dir.join(LOG_FILE_NAME))
+}
+
+fn log_files_for(current: &Path) -> Result<Vec<PathBuf>> {
+    let mut result = rotated_log_files(current)?;
+    if current.is_file() {
+        result.push(current.to_owned());
+    }
+    Ok(result)
+}
+
+/// Open a log file for reading, decompressing it if it was compressed during
+/// rotation.
+pub fn open_log_file(path: &Path) -> Result<Box<dyn BufRead>> {
+    let file = File::open(path).with_context(|| format!("Opening {}", path.display()))?;
+    if path.extension().and_then(|ext| ext.to_str()) == Some("gz") {
+        Ok(Box::new(BufReader::new(GzDecoder::new(file))))
+    } else {
+        Ok(Box::new(BufReader::new(file)))
+    }
+}
+
+/// Extract the invocation ID from a log line, if it has one.
+pub fn invocation_id_of_line(line: &str) -> Option<&str> {
+    let rest = line.strip_prefix('[')?;
+    let end = rest.find(']')?;
+    Some(&rest[..end])
+}
+
+/// Write the last `lines` lines of the logs. If `follow` is set, keep writing
+/// lines as they are appended, following the log file across rotations.
+pub fn tail(log_dir: &Path, lines: usize, follow: bool, out: &mut dyn Write) -> Result<()> {
+    let mut last_lines = VecDeque::with_capacity(lines);
+    // Read the files newest first, stopping once enough lines have been found.
+    for path in log_files(log_dir)?.iter().rev() {
+        if last_lines.len() >= lines {
+            break;
+        }
+        let mut file_lines = VecDeque::new();
+        for line in open_log_file(path)?.lines() {
+            file_lines.push_back(line.with_context(|| format!("Reading {}", path.display()))?);
+            if file_lines.len() > lines - last_lines.len() {
+                file_lines.pop_front();
+            }
+        }
+        for line in file_lines.into_iter().rev() {
+            last_lines.push_front(line);
+        }
+    }
+    for line in last_lines {
+        writeln!(out, "{}", line)?;
+    }
+    out.flush()?;
+
+    if follow {
+        follow_current_log_file(log_dir, out)?;
+    }
+    Ok(())
+}
+
+fn follow_current_log_file(log_dir: &Path, out: &mut dyn Write) -> Result<()> {
+    let path = log_dir.join(LOG_FILE_NAME);
+    let mut file = File::open(&path).with_context(|| format!("Opening {}", path.display()))?;
+    let mut position = file.seek(SeekFrom::End(0))?;
+    let mut buf = Vec::new();
+    loop {
+        buf.clear();
+        file.read_to_end(&mut buf)?;
+        if !buf.is_empty() {
+            position += buf.len() as u64;
+            out.write_all(&buf)?;
+            out.flush()?;
+            continue;
+        }
+
+        thread::sleep(FOLLOW_POLL_INTERVAL);
+        match std::fs::metadata(&path) {
+            // The log file was rotated and a new one started, so switch to it.
+            Ok(metadata) if metadata.len() < position || is_replaced(&file, &metadata)? => {
+                file = File::open(&path).with_context(|| format!("Opening {}", path.display()))?;
+                position = 0;
+            }
+            Ok(_) => {}
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e).with_context(|| format!("Reading {}", path.display())),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn is_replaced(file: &File, metadata: &std::fs::Metadata) -> io::Result<bool> {
+    use std::os::unix::fs::MetadataExt;
+
+    let ours = file.metadata()?;
+    Ok(ours.dev() != metadata.dev() || ours.ino() != metadata.ino())
+}
+
+#[cfg(not(unix))]
+fn is_replaced(_file: &File, _metadata: &std::fs::Metadata) -> io::Result<bool> {
+    Ok(false)
+}
+
+/// Write the log lines which belong to the given invocation, if any, and match
+/// the given pattern, if any. Returns the number of lines written.
+pub fn grep(
+    log_dir: &Path,
+    invocation_id: Option<&str>,
+    pattern: Option<&Regex>,
+    out: &mut dyn Write,
+) -> Result<usize> {
+    let mut count = 0;
+    for path in log_files(log_dir)? {
+        // Events spanning several lines only have the invocation ID on their
+        // first line, so remember whose lines we're reading.
+        let mut in_invocation = false;
+        for line in open_log_file(&path)?.lines() {
+            let line = line.with_context(|| format!("Reading {}", path.display()))?;
+            if let Some(invocation_id) = invocation_id {
+                if let Some(line_invocation_id) = invocation_id_of_line(&line) {
+                    in_invocation = line_invocation_id == invocation_id;
+                }
+                if !in_invocation {
+                    continue;
+                }
+            }
+            if let Some(pattern) = pattern {
+                if !pattern.is_match(&line) {
+                    continue;
+                }
+            }
+            writeln!(out, "{}", line)?;
+            count += 1;
+        }
+    }
+    Ok(count)
+}
+
+/// Write a gzipped tarball of the log files, including the JSON lines log
+/// files, modified in the last `since` to `output`. Returns the paths of the
+/// files which were included.
+pub fn bundle(log_dir: &Path, since: Duration, output: &Path) -> Result<Vec<PathBuf>> {
+    let cutoff = SystemTime::now()
+        .checked_sub(since)
+        .unwrap_or(SystemTime::UNIX_EPOCH);
+    let mut included = Vec::new();
+    let mut paths = log_files(log_dir)?;
+    paths.extend(log_files_for(&log_dir.join(JSON_LOG_FILE_NAME))?);
+    for path in paths {
+        let modified = path
+            .metadata()
+            .and_then(|metadata| metadata.modified())
+            .with_context(|| format!("Reading metadata of {}", path.display()))?;
+        if modified >= cutoff {
+            included.push(path);
+        }
+    }
+
+    let file = File::create(output).with_context(|| format!("Creating {}", output.display()))?;
+    let mut builder = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+    for path in included.iter() {
+        let name = path
+            .file_name()
+            .with_context(|| format!("Log file {} has no name", path.display()))?;
+        builder
+            .append_path_with_name(path, Path::new("focus-logs").join(name))
+            .with_context(|| format!("Adding {} to bundle", path.display()))?;
+    }
+    builder
+        .into_inner()
+        .and_then(|encoder| encoder.finish())
+        .and_then(|mut file| file.flush())
+        .with_context(|| format!("Writing {}", output.display()))?;
+    Ok(included)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use crate::rotation::{RotatingFileWriter, RotationConfig};
+
+    use super::*;
+
+    fn write_rotated_logs(log_dir: &Path) -> Result<()> {
+        let mut writer = RotatingFileWriter::open(
+            log_dir.join(LOG_FILE_NAME),
+            RotationConfig {
+                max_size_bytes: 20,
+                max_age: Duration::ZERO,
+                retained_files: 10,
+                compress: true,
+            },
+        )?;
+        writer.write_all(b"[a] first line\n[a] second line\n")?;
+        writer.write_all(b"[b] third line\ncontinued\n")?;
+        writer.write_all(b"[a] fourth line\n[b] fifth line\n")?;
+        writer.write_all(b"[a] sixth line\n")?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_tail() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        write_rotated_logs(temp_dir.path())?;
+        assert!(log_files(temp_dir.path())?
+            .iter()
+            .any(|path| path.to_string_lossy().ends_with(".gz")));
+
+        let mut out = Vec::new();
+        tail(temp_dir.path(), 4, false, &mut out)?;
+        assert_eq!(
+            String::from_utf8(out)?,
+            "continued\n[a] fourth line\n[b] fifth line\n[a] sixth line\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_grep() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        write_rotated_logs(temp_dir.path())?;
+
+        let mut out = Vec::new();
+        let count = grep(temp_dir.path(), Some("b"), None, &mut out)?;
+        assert_eq!(count, 3);
+        assert_eq!(
+            String::from_utf8(out)?,
+            "[b] third line\ncontinued\n[b] fifth line\n"
+        );
+
+        let mut out = Vec::new();
+        let pattern = Regex::new("(first|sixth)")?;
+        grep(temp_dir.path(), Some("a"), Some(&pattern), &mut out)?;
+        assert_eq!(String::from_utf8(out)?, "[a] first line\n[a] sixth line\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_bundle() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let log_dir = temp_dir.path().join("logs");
+        fs::create_dir(&log_dir)?;
+        write_rotated_logs(&log_dir)?;
+
+        let output = temp_dir.path().join("bundle.tar.gz");
+        let included = bundle(&log_dir, Duration::from_secs(3600), &output)?;
+        assert_eq!(included, log_files(&log_dir)?);
+
+        let mut archive = tar::Archive::new(GzDecoder::new(File::open(&output)?));
+        let names: Vec<PathBuf> = archive
+            .entries()?
+            .map(|entry| Ok(entry?.path()?.into_owned()))
+            .collect::<Result<_>>()?;
+        assert_eq!(names.len(), included.len());
+        assert!(names.contains(&PathBuf::from("focus-logs").join(LOG_FILE_NAME)));
+        Ok(())
+    }
+}
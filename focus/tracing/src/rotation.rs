@@ -0,0 +1,466 @@
+// Copyright 2022 Twitter, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+// Size- and age-based rotation of the focus log file.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use focus_util::git_helper::ConfigExt;
+use focus_util::lock_file::WaitingLock;
+use tracing::warn;
+
+/// The name of the log file currently being written.
+pub const LOG_FILE_NAME: &str = "focus.log";
+
+const COMPRESSED_SUFFIX: &str = ".gz";
+
+const LOCK_SUFFIX: &str = ".lock";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RotationConfig {
+    /// Rotate the log file once it reaches this many bytes. If 0, the log file
+    /// is not rotated based on its size.
+    pub max_size_bytes: u64,
+    /// Rotate the log file once it was created this long ago. If zero, the log
+    /// file is not rotated based on its age.
+    pub max_age: Duration,
+    /// The number of rotated log files to keep. Older ones are deleted.
+    pub retained_files: usize,
+    /// Whether to gzip rotated log files.
+    pub compress: bool,
+}
+
+impl Default for RotationConfig {
+    fn default() -> Self {
+        Self {
+            max_size_bytes: Self::DEFAULT_MAX_SIZE_BYTES,
+            max_age: Duration::from_secs(Self::DEFAULT_MAX_AGE_HOURS * 3600),
+            retained_files: Self::DEFAULT_RETAINED_FILES,
+            compress: true,
+        }
+    }
+}
+
+impl RotationConfig {
+    pub const DEFAULT_MAX_SIZE_BYTES: u64 = 32 * 1024 * 1024;
+    pub const DEFAULT_MAX_AGE_HOURS: u64 = 24;
+    pub const DEFAULT_RETAINED_FILES: usize = 10;
+    const MAX_SIZE_KEY: &'static str = "focus.log.maxsize";
+    const MAX_AGE_HOURS_KEY: &'static str = "focus.log.maxagehours";
+    const RETAINED_FILES_KEY: &'static str = "focus.log.retain";
+    const COMPRESS_KEY: &'static str = "focus.log.compress";
+
+    /// Try to load the config from the global git config, falling back to
+    /// defaults for anything that isn't configured.
+    pub fn try_from_git_default() -> Result<Self> {
+        let mut config = git2::Config::open_default()?;
+        Self::try_from_git(&mut config)
+    }
+
+    pub fn try_from_git(config: &mut git2::Config) -> Result<Self> {
+        let mut get_non_negative = |key: &str, default: u64| -> Result<u64> {
+            let value = config.get_i64_with_default(key, default as i64)?;
+            match u64::try_from(value) {
+                Ok(value) => Ok(value),
+                Err(_) => {
+                    warn!(key, value, default, "invalid negative value, using default");
+                    Ok(default)
+                }
+            }
+        };
+
+        // Note that git config integers accept `k`, `m`, and `g` suffixes, so
+        // the size can be written like `64m`.
+        let max_size_bytes = get_non_negative(Self::MAX_SIZE_KEY, Self::DEFAULT_MAX_SIZE_BYTES)?;
+        let max_age_hours = get_non_negative(Self::MAX_AGE_HOURS_KEY, Self::DEFAULT_MAX_AGE_HOURS)?;
+        let retained_files = get_non_negative(
+            Self::RETAINED_FILES_KEY,
+            Self::DEFAULT_RETAINED_FILES as u64,
+        )?;
+
+        Ok(Self {
+            max_size_bytes,
+            max_age: Duration::from_secs(max_age_hours * 3600),
+            retained_files: retained_files as usize,
+            compress: config.get_bool_with_default(Self::COMPRESS_KEY, true)?,
+        })
+    }
+}
+
+/// The prefix and suffix of the names of the files that the log file at
+/// `log_path` is rotated to. For `focus.log`, these are `focus-` and `.log`.
+fn rotated_name_affixes(log_path: &Path) -> (String, String) {
+    let stem = log_path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let suffix = log_path
+        .extension()
+        .map(|extension| format!(".{}", extension.to_string_lossy()))
+        .unwrap_or_default();
+    (format!("{}-", stem), suffix)
+}
+
+/// Whether the file name is that of a file which the log file at `log_path`
+/// was rotated to, compressed or not.
+pub fn is_rotated_log_file_name(log_path: &Path, name: &str) -> bool {
+    let (prefix, suffix) = rotated_name_affixes(log_path);
+    let name = name.strip_suffix(COMPRESSED_SUFFIX).unwrap_or(name);
+    name.starts_with(&prefix) && name.ends_with(&suffix)
+}
+
+/// The files which the log file at `log_path` was rotated to, oldest first.
+pub fn rotated_log_files(log_path: &Path) -> Result<Vec<PathBuf>> {
+    let mut result = Vec::new();
+    let log_dir = match log_path.parent() {
+        Some(log_dir) => log_dir,
+        None => return Ok(result),
+    };
+    let entries = match fs::read_dir(log_dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(result),
+        Err(e) => return Err(e).with_context(|| format!("Reading log dir {}", log_dir.display())),
+    };
+    for entry in entries {
+        let entry = entry.context("Reading log dir entry")?;
+        if let Some(name) = entry.file_name().to_str() {
+            if is_rotated_log_file_name(log_path, name) && entry.file_type()?.is_file() {
+                result.push(entry.path());
+            }
+        }
+    }
+    // The timestamps in the names sort chronologically.
+    result.sort();
+    Ok(result)
+}
+
+/// A log file writer which moves the log file aside once it becomes too large
+/// or too old, and cleans up old log files.
+///
+/// Several processes may append to the same log file at once, so rotation is
+/// serialized with a lock on a file next to the log file. A process which
+/// finds that another has already rotated the log file reopens it instead of
+/// rotating it again.
+#[derive(Debug)]
+pub struct RotatingFileWriter {
+    path: PathBuf,
+    config: RotationConfig,
+    file: File,
+    size: u64,
+    created_at: Option<SystemTime>,
+}
+
+impl RotatingFileWriter {
+    /// Open the log file at `path` for appending, rotating it first if
+    /// necessary.
+    pub fn open(path: impl AsRef<Path>, config: RotationConfig) -> Result<Self> {
+        let path = path.as_ref().to_owned();
+        let (file, size, created_at) =
+            Self::open_file(&path).with_context(|| format!("Opening {}", path.display()))?;
+        let mut writer = Self {
+            path,
+            config,
+            file,
+            size,
+            created_at,
+        };
+        if writer.needs_rotation() {
+            writer.rotate().context("Rotating log file")?;
+        }
+        Ok(writer)
+    }
+
+    fn open_file(path: &Path) -> io::Result<(File, u64, Option<SystemTime>)> {
+        let file = OpenOptions::new().append(true).create(true).open(path)?;
+        let metadata = file.metadata()?;
+        Ok((file, metadata.len(), metadata.created().ok()))
+    }
+
+    fn needs_rotation(&self) -> bool {
+        let RotationConfig {
+            max_size_bytes,
+            max_age,
+            ..
+        } = &self.config;
+        if *max_size_bytes > 0 && self.size >= *max_size_bytes {
+            return true;
+        }
+        match self.created_at {
+            Some(created_at) if !max_age.is_zero() => created_at
+                .elapsed()
+                .map(|age| age >= *max_age)
+                .unwrap_or(false),
+            _ => false,
+        }
+    }
+
+    /// Whether the file at our path is still the one that we have open.
+    #[cfg(unix)]
+    fn is_current_file(&self) -> io::Result<bool> {
+        use std::os::unix::fs::MetadataExt;
+
+        let ours = self.file.metadata()?;
+        match fs::metadata(&self.path) {
+            Ok(theirs) => Ok(ours.dev() == theirs.dev() && ours.ino() == theirs.ino()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn is_current_file(&self) -> io::Result<bool> {
+        Ok(true)
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        let mut lock_path = self.path.as_os_str().to_owned();
+        lock_path.push(LOCK_SUFFIX);
+        let _lock = WaitingLock::acquire(Path::new(&lock_path))
+            .map_err(|e| io::Error::new(io::ErrorKind::Other, format!("{:#}", e)))?;
+
+        if self.is_current_file()? {
+            let rotated_path = self.rotated_path();
+            fs::rename(&self.path, &rotated_path)?;
+        }
+
+        let (file, size, created_at) = Self::open_file(&self.path)?;
+        self.file = file;
+        self.size = size;
+        self.created_at = created_at;
+
+        if let Err(e) = self.clean_up_rotated_files() {
+            warn!(?e, "Failed to clean up old log files");
+        }
+        Ok(())
+    }
+
+    fn rotated_path(&self) -> PathBuf {
+        let (prefix, suffix) = rotated_name_affixes(&self.path);
+        let mut timestamp: DateTime<Utc> = Utc::now();
+        loop {
+            let name = format!(
+                "{}{}{}",
+                prefix,
+                timestamp.format("%Y%m%dT%H%M%S%.9fZ"),
+                suffix
+            );
+            let path = self.path.with_file_name(name);
+            let mut compressed_path = path.as_os_str().to_owned();
+            compressed_path.push(COMPRESSED_SUFFIX);
+            // The clock may be too coarse to tell apart rotations happening in
+            // quick succession, so nudge the timestamp until the name is free.
+            if !path.exists() && !Path::new(&compressed_path).exists() {
+                return path;
+            }
+            timestamp = timestamp + chrono::Duration::nanoseconds(1);
+        }
+    }
+
+    fn clean_up_rotated_files(&self) -> Result<()> {
+        let mut rotated_files = rotated_log_files(&self.path)?;
+
+        let excess = rotated_files
+            .len()
+            .saturating_sub(self.config.retained_files);
+        for path in rotated_files.drain(..excess) {
+            fs::remove_file(&path).with_context(|| format!("Removing {}", path.display()))?;
+        }
+
+        if self.config.compress {
+            // Leave the most recently rotated file alone, since other processes
+            // may not have noticed the rotation yet and still be writing to it.
+            rotated_files.pop();
+            for path in rotated_files.iter() {
+                if path.extension().and_then(|ext| ext.to_str()) != Some("gz") {
+                    compress_file(path)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Replace the file at `path` with a gzipped copy whose name has `.gz`
+/// appended.
+fn compress_file(path: &Path) -> Result<()> {
+    let mut compressed_path = path.as_os_str().to_owned();
+    compressed_path.push(COMPRESSED_SUFFIX);
+    let compressed_path = PathBuf::from(compressed_path);
+
+    let mut input = File::open(path).with_context(|| format!("Opening {}", path.display()))?;
+    let temp = tempfile::NamedTempFile::new_in(path.parent().unwrap_or_else(|| Path::new(".")))
+        .context("Creating temporary file")?;
+    let mut encoder = GzEncoder::new(temp, Compression::default());
+    io::copy(&mut input, &mut encoder)
+        .with_context(|| format!("Compressing {}", path.display()))?;
+    let temp = encoder.finish().context("Finishing compression")?;
+    temp.persist(&compressed_path)
+        .with_context(|| format!("Writing {}", compressed_path.display()))?;
+    fs::remove_file(path).with_context(|| format!("Removing {}", path.display()))?;
+    Ok(())
+}
+
+impl Write for RotatingFileWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.needs_rotation() {
+            self.rotate()?;
+        }
+        let written = self.file.write(buf)?;
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_config() -> RotationConfig {
+        RotationConfig {
+            max_size_bytes: 10,
+            max_age: Duration::ZERO,
+            retained_files: 2,
+            compress: true,
+        }
+    }
+
+    fn file_names(log_dir: &Path) -> Result<Vec<String>> {
+        let mut names: Vec<String> = fs::read_dir(log_dir)?
+            .map(|entry| Ok(entry?.file_name().to_string_lossy().into_owned()))
+            .collect::<Result<_>>()?;
+        names.sort();
+        Ok(names)
+    }
+
+    #[test]
+    fn test_rotation_by_size() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let path = temp_dir.path().join(LOG_FILE_NAME);
+        let mut writer = RotatingFileWriter::open(&path, test_config())?;
+
+        writer.write_all(b"0123456789")?;
+        assert!(rotated_log_files(&path)?.is_empty());
+
+        // Each subsequent write rotates the full log file first.
+        for i in 0..4 {
+            writer.write_all(format!("line {i}...\n").as_bytes())?;
+        }
+        writer.flush()?;
+        assert_eq!(fs::read_to_string(&path)?, "line 3...\n");
+
+        let rotated = rotated_log_files(&path)?;
+        assert_eq!(rotated.len(), 2, "{:?}", file_names(temp_dir.path())?);
+        assert!(rotated[0].to_string_lossy().ends_with(".log.gz"));
+        assert!(rotated[1].to_string_lossy().ends_with(".log"));
+
+        let mut decoded = String::new();
+        std::io::Read::read_to_string(
+            &mut flate2::read::GzDecoder::new(File::open(&rotated[0])?),
+            &mut decoded,
+        )?;
+        assert_eq!(decoded, "line 1...\n");
+        assert_eq!(fs::read_to_string(&rotated[1])?, "line 2...\n");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rotation_on_open() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let path = temp_dir.path().join(LOG_FILE_NAME);
+        fs::write(&path, "too much content already")?;
+
+        let _writer = RotatingFileWriter::open(&path, test_config())?;
+        assert_eq!(fs::read_to_string(&path)?, "");
+        assert_eq!(rotated_log_files(&path)?.len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rotation_by_another_writer() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let path = temp_dir.path().join(LOG_FILE_NAME);
+        let mut first = RotatingFileWriter::open(&path, test_config())?;
+        let mut second = RotatingFileWriter::open(&path, test_config())?;
+
+        first.write_all(b"0123456789")?;
+        second.size = first.size;
+
+        // The first writer rotates the file, so the second should only reopen
+        // it rather than rotating the fresh file too.
+        first.write_all(b"first\n")?;
+        second.write_all(b"second\n")?;
+        assert_eq!(fs::read_to_string(&path)?, "first\nsecond\n");
+        assert_eq!(rotated_log_files(&path)?.len(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_concurrent_rotation_keeps_every_line() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let path = temp_dir.path().join(LOG_FILE_NAME);
+        let config = RotationConfig {
+            retained_files: 1000,
+            compress: false,
+            ..test_config()
+        };
+
+        let handles = (0..4)
+            .map(|thread| {
+                let path = path.clone();
+                let config = config.clone();
+                std::thread::spawn(move || -> Result<()> {
+                    let mut writer = RotatingFileWriter::open(&path, config)?;
+                    for i in 0..50 {
+                        writer.write_all(format!("{thread}-{i}\n").as_bytes())?;
+                    }
+                    Ok(())
+                })
+            })
+            .collect::<Vec<_>>();
+        for handle in handles {
+            handle.join().unwrap()?;
+        }
+
+        let mut line_count = fs::read_to_string(&path)?.lines().count();
+        for rotated in rotated_log_files(&path)? {
+            line_count += fs::read_to_string(&rotated)?.lines().count();
+        }
+        assert_eq!(line_count, 200);
+        Ok(())
+    }
+
+    #[test]
+    fn test_config_from_git() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let config_path = temp_dir.path().join("gitconfig");
+        fs::write(
+            &config_path,
+            "[focus \"log\"]\n\tmaxsize = 2m\n\tmaxagehours = 0\n\tcompress = false\n",
+        )?;
+        let mut config = git2::Config::open(&config_path)?;
+        assert_eq!(
+            RotationConfig::try_from_git(&mut config)?,
+            RotationConfig {
+                max_size_bytes: 2 * 1024 * 1024,
+                max_age: Duration::ZERO,
+                retained_files: RotationConfig::DEFAULT_RETAINED_FILES,
+                compress: false,
+            }
+        );
+        Ok(())
+    }
+}
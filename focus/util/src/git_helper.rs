@@ -39,11 +39,35 @@ pub fn git_dir(path: &Path, app: Arc<App>) -> Result<PathBuf> {
     }
 }
 
+/// The git directory shared by the repo at `path` and all of its linked
+/// worktrees.
+pub fn git_common_dir(path: &Path, app: Arc<App>) -> Result<PathBuf> {
+    let found_path = run_consuming_stdout(path, vec!["rev-parse", "--git-common-dir"], app)?;
+    let found_path = PathBuf::from(&found_path);
+    if found_path.is_absolute() {
+        Ok(found_path)
+    } else {
+        Ok(path.join(found_path.as_path()))
+    }
+}
+
+/// Set in the environment of the git commands focus runs, so that the hooks
+/// they run, such as post-checkout when syncing, can tell not to act on them.
+pub const INSIDE_FOCUS_ENV_VAR: &str = "FOCUS_INSIDE_FOCUS";
+
+const GIT_TRACE2_EVENT_ENV_VAR: &str = "GIT_TRACE2_EVENT";
+
 pub fn git_command_with_git_binary(
     app: Arc<App>,
     git_binary: &GitBinary,
 ) -> Result<(Command, SandboxCommand)> {
     let mut cmd = git_binary.command();
+    cmd.env(INSIDE_FOCUS_ENV_VAR, "1");
+    // The git binary's environment is captured when the app is created, which
+    // is before tracing is set up and asks git to write its trace2 events.
+    if let Some(trace2_event) = std::env::var_os(GIT_TRACE2_EVENT_ENV_VAR) {
+        cmd.env(GIT_TRACE2_EVENT_ENV_VAR, trace2_event);
+    }
     let scmd = SandboxCommand::with_command(&mut cmd, app)?;
     Ok((cmd, scmd))
 }
@@ -806,6 +830,24 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_git_common_dir_is_shared_with_worktrees() -> Result<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let app = Arc::new(App::new_for_testing()?);
+        let repo = focus_testing::ScratchGitRepo::new_static_fixture(temp_dir.path())?;
+        let worktree_path = temp_dir.path().join("worktree");
+        repo.add_worktree(&worktree_path)?;
+
+        let common_dir = git_common_dir(repo.path(), app.clone())?.canonicalize()?;
+        assert_eq!(common_dir, repo.path().join(".git").canonicalize()?);
+        assert_eq!(
+            git_common_dir(&worktree_path, app.clone())?.canonicalize()?,
+            common_dir
+        );
+        assert_ne!(git_dir(&worktree_path, app)?.canonicalize()?, common_dir);
+        Ok(())
+    }
+
     #[test]
     fn test_git_binary_from_env() -> Result<()> {
         // just make sure this doesn't barf
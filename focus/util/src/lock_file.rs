@@ -92,6 +92,60 @@ impl LockFile {
     }
 }
 
+/// An exclusive advisory lock which waits for other holders to release it.
+///
+/// Unlike [LockFile], the file is left in place when the lock is released:
+/// removing it would let a process still waiting on the old file and one
+/// creating a new file both hold the lock.
+pub struct WaitingLock {
+    path: PathBuf,
+    file: File,
+}
+
+impl WaitingLock {
+    /// Lock the file at `path`, creating it if necessary and blocking until
+    /// any other holder releases it.
+    pub fn acquire(path: &Path) -> Result<Self> {
+        use nix::libc;
+        use std::os::unix::prelude::*;
+
+        let file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .write(true)
+            .open(path)
+            .with_context(|| format!("Creating lock file {} failed", path.display()))?;
+        let ret = unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) };
+        if ret < 0 {
+            bail!(
+                "Acquiring exclusive advisory lock on {} failed: {}",
+                path.display(),
+                std::io::Error::last_os_error()
+            );
+        }
+        Ok(Self {
+            path: path.to_owned(),
+            file,
+        })
+    }
+}
+
+impl Drop for WaitingLock {
+    fn drop(&mut self) {
+        use nix::libc;
+        use std::os::unix::prelude::*;
+
+        let ret = unsafe { libc::flock(self.file.as_raw_fd(), libc::LOCK_UN) };
+        if ret < 0 {
+            warn!(
+                ?self.path,
+                e = ?std::io::Error::last_os_error(),
+                "Releasing advisory lock on file failed",
+            );
+        }
+    }
+}
+
 impl Drop for LockFile {
     fn drop(&mut self) {
         if let Err(e) = std::fs::remove_file(self.path.as_path()) {
@@ -168,6 +222,32 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn waiting_lock_waits_for_the_holder() -> Result<()> {
+        testing::init_logging();
+        let dir = tempdir()?;
+        let path = dir.path().join("waiting_lock");
+
+        let held = WaitingLock::acquire(&path)?;
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let waiter = {
+            let path = path.clone();
+            std::thread::spawn(move || -> Result<()> {
+                let _lock = WaitingLock::acquire(&path)?;
+                sender.send(()).unwrap();
+                Ok(())
+            })
+        };
+        assert!(receiver
+            .recv_timeout(std::time::Duration::from_millis(200))
+            .is_err());
+        drop(held);
+        receiver.recv_timeout(std::time::Duration::from_secs(10))?;
+        waiter.join().unwrap()?;
+        assert!(path.exists());
+        Ok(())
+    }
+
     #[test]
     fn failing_to_create_a_lock_in_an_inextant_directory() -> Result<()> {
         testing::init_logging();
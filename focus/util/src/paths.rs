@@ -59,6 +59,15 @@ lazy_static! {
     static ref STARLARK_EXTENSION: OsString = OsString::from("bzl");
 }
 
+/// Files at the root of a repository which, in addition to the `WORKSPACE`
+/// file, affect the evaluation of the entire build graph.
+pub const GLOBAL_BUILD_INPUTS: &[&str] = &[
+    "MODULE.bazel",
+    "MODULE.bazel.lock",
+    ".bazelversion",
+    ".bazelrc",
+];
+
 /// Determine if the Path is a build definition.
 pub fn is_build_definition<P: AsRef<Path>>(path: P) -> bool {
     let path = path.as_ref();
@@ -71,6 +80,15 @@ pub fn is_build_definition<P: AsRef<Path>>(path: P) -> bool {
 /// Determine if the Path is a file relevant to the build graph.
 pub fn is_relevant_to_build_graph<P: AsRef<Path>>(path: P) -> bool {
     let path = path.as_ref();
+    if let Some(file_name) = path.file_name() {
+        if GLOBAL_BUILD_INPUTS
+            .iter()
+            .any(|global_input| file_name.eq(*global_input))
+        {
+            return true;
+        }
+    }
+
     if let Some(stem) = path.file_stem() {
         if stem.eq(WORKSPACE_STEM.as_os_str()) || stem.eq(BUILD_STEM.as_os_str()) {
             return true;
@@ -149,7 +167,12 @@ mod tests {
         assert!(is_relevant_to_build_graph(Path::new("BUILD.weird")));
         assert!(is_relevant_to_build_graph(Path::new("BUILD")));
         assert!(is_relevant_to_build_graph(Path::new("jank.bzl")));
+        assert!(is_relevant_to_build_graph(Path::new("MODULE.bazel")));
+        assert!(is_relevant_to_build_graph(Path::new("MODULE.bazel.lock")));
+        assert!(is_relevant_to_build_graph(Path::new(".bazelversion")));
+        assert!(is_relevant_to_build_graph(Path::new(".bazelrc")));
         assert!(!is_relevant_to_build_graph(Path::new("foo.c")));
+        assert!(!is_relevant_to_build_graph(Path::new("MODULE.txt")));
     }
 
     #[test]
@@ -13,17 +13,21 @@ pub mod tool_insights_client {
     #[derive(Clone)]
     pub struct Client {
         underlying: Arc<Mutex<Underlying>>,
+        trace_id: u64,
     }
 
     impl Client {
         pub fn new(_tool_name: String, _tool_version: String, _start_time: SystemTime) -> Self {
             Client {
                 underlying: Arc::new(Mutex::new(Underlying)),
+                trace_id: uuid::Uuid::new_v4().as_u128() as u64,
             }
         }
 
         pub fn get_context(&self) -> ClientGuard {
-            ClientGuard
+            ClientGuard {
+                trace_id: self.trace_id,
+            }
         }
 
         pub fn get_inner(&self) -> MutexGuard<'_, Underlying> {
@@ -31,9 +35,17 @@ pub mod tool_insights_client {
         }
     }
 
-    pub struct ClientGuard;
+    pub struct ClientGuard {
+        trace_id: u64,
+    }
 
     impl ClientGuard {
+        pub fn get_trace_id(&self) -> u64 {
+            self.trace_id
+        }
+        pub fn get_span_id(&self) -> u64 {
+            self.trace_id
+        }
         pub fn set_tool_feature_name(&mut self, _tool_feature_name: impl Into<String>) {}
         pub fn set_custom_map(&mut self, _custom_map: HashMap<String, String>) {}
         pub fn set_exit_code(&mut self, _exit_code: i32) {}
@@ -61,5 +73,11 @@ pub mod tool_insights_client {
             _custom_map: Option<&HashMap<String, String>>,
         ) {
         }
+
+        pub fn spool_messages(&mut self) {}
+
+        pub fn flush_spool(&self) -> Result<usize> {
+            Ok(0)
+        }
     }
 }
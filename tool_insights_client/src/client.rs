@@ -41,12 +41,14 @@ use std::ops::{Deref, DerefMut};
 use std::sync::{Arc, Mutex, MutexGuard};
 use std::time::SystemTime;
 
-use crate::{get_span_id, get_trace_id, set_trace_env_vars};
+use crate::{get_parent_span_id, get_span_id, get_trace_id, set_trace_env_vars};
 use anyhow::Result;
 use tracing::error;
 
 use crate::json_writer::JsonWriter;
 use crate::message::{Message, MessageKind};
+use crate::otlp_writer::OtlpWriter;
+use crate::spool_writer::{self, SpoolWriter};
 use crate::util::encode_zipkin_compatible_id;
 use crate::writer::Writer;
 
@@ -105,7 +107,7 @@ impl DerefMut for ClientGuard<'_> {
 pub struct Underlying {
     messages: Vec<Message>,
     ti_context: Context,
-    writer: JsonWriter,
+    writer: Box<dyn Writer + Send>,
 }
 
 impl Underlying {
@@ -114,7 +116,7 @@ impl Underlying {
         Underlying {
             messages: vec![],
             ti_context,
-            writer: JsonWriter::new(None),
+            writer: default_writer(false),
         }
     }
 
@@ -165,11 +167,54 @@ impl Underlying {
         }
     }
 
+    /// Append messages to a spool rather than writing a file for each, or
+    /// sending them to the OpenTelemetry collector, for tools which are invoked
+    /// often, such as from git hooks. The spool is delivered by
+    /// [`Underlying::flush_spool`].
+    pub fn spool_messages(&mut self) {
+        self.writer = default_writer(true);
+    }
+
+    /// Deliver the spooled messages to the tool insights daemon, and the
+    /// spooled spans to the OpenTelemetry collector if one is configured,
+    /// returning how many there were.
+    pub fn flush_spool(&self) -> Result<usize> {
+        let mut count = spool_writer::flush_spool()?;
+        if let Some(writer) = OtlpWriter::from_env(true)? {
+            count += writer.flush()?;
+        }
+        Ok(count)
+    }
+
     fn add_message(&mut self, message: Message) {
         self.messages.push(message);
     }
 }
 
+/// Export to an OpenTelemetry collector if one is configured, or otherwise
+/// write files for the tool insights daemon. Either way, write to a spool
+/// instead if `spool` is set.
+fn default_writer(spool: bool) -> Box<dyn Writer + Send> {
+    let tool_insights_writer = || -> Box<dyn Writer + Send> {
+        if spool {
+            Box::new(SpoolWriter::new(None))
+        } else {
+            Box::new(JsonWriter::new(None))
+        }
+    };
+    match OtlpWriter::from_env(spool) {
+        Ok(Some(writer)) => Box::new(writer),
+        Ok(None) => tool_insights_writer(),
+        Err(e) => {
+            error!(
+                ?e,
+                "Could not create OTLP writer, falling back to tool insights"
+            );
+            tool_insights_writer()
+        }
+    }
+}
+
 // Disabling the `Drop` for now because it does not run reliably. The burden is on the user to write
 // the TI message at the end, for now.
 // impl Drop for ToolInsightsClient {
@@ -191,6 +236,7 @@ pub struct Context {
     exit_code: Option<i32>,
     span_id: u64,
     trace_id: u64,
+    parent_span_id: Option<u64>,
 }
 
 impl Context {
@@ -198,6 +244,7 @@ impl Context {
         let mut custom_map: HashMap<String, String> = HashMap::new();
         let trace_id = get_trace_id();
         let span_id = get_span_id(trace_id);
+        let parent_span_id = get_parent_span_id();
         custom_map.insert(
             "trace_id".to_string(),
             encode_zipkin_compatible_id(trace_id, true),
@@ -217,6 +264,7 @@ impl Context {
             exit_code: None,
             span_id,
             trace_id,
+            parent_span_id,
         }
     }
 
@@ -273,4 +321,8 @@ impl Context {
     pub fn get_span_id(&self) -> u64 {
         self.span_id
     }
+
+    pub fn get_parent_span_id(&self) -> Option<u64> {
+        self.parent_span_id
+    }
 }
@@ -1,7 +1,8 @@
 // Copyright 2022 Twitter, Inc.
 // SPDX-License-Identifier: Apache-2.0
 
-use std::fs;
+use std::fs::{self, File};
+use std::io::Write;
 use std::os::unix::fs::PermissionsExt;
 use std::path::PathBuf;
 
@@ -46,25 +47,36 @@ impl JsonWriter {
         D: serde::Serialize,
     {
         for message in data {
-            let temporary_file = NamedTempFile::new_in(self.write_location.as_path())
-                .context("Failed to create temporary file")?;
-            let temporary_file_path = temporary_file.as_ref().to_path_buf();
-            let (file, _) = temporary_file.keep()?;
-            // temporarily files are created with mode 0o100600, that makes the tool insights daemon
-            // fail while trying to process the log because the tool insights daemon is run by
-            // the MDE user. We're making the file readable to all to make sure the logs being
-            // written can be processed by the tool insights daemon.
-            fs::set_permissions(
-                temporary_file_path.clone(),
-                fs::Permissions::from_mode(
-                    (S_IFREG | S_IRUSR | S_IWUSR | S_IRGRP | S_IROTH) as u32,
-                ),
-            )?;
-            debug!("Writing to {}", temporary_file_path.clone().display());
+            let file = self.create_file()?;
             serde_json::to_writer(file, message).context("Could not write message to file")?;
         }
         Ok(())
     }
+
+    /// Write a message which has already been serialized to JSON.
+    pub(crate) fn write_serialized(&self, message: &str) -> Result<()> {
+        let mut file = self.create_file()?;
+        file.write_all(message.as_bytes())
+            .context("Could not write message to file")?;
+        Ok(())
+    }
+
+    fn create_file(&self) -> Result<File> {
+        let temporary_file = NamedTempFile::new_in(self.write_location.as_path())
+            .context("Failed to create temporary file")?;
+        let temporary_file_path = temporary_file.as_ref().to_path_buf();
+        let (file, _) = temporary_file.keep()?;
+        // temporarily files are created with mode 0o100600, that makes the tool insights daemon
+        // fail while trying to process the log because the tool insights daemon is run by
+        // the MDE user. We're making the file readable to all to make sure the logs being
+        // written can be processed by the tool insights daemon.
+        fs::set_permissions(
+            temporary_file_path.clone(),
+            fs::Permissions::from_mode((S_IFREG | S_IRUSR | S_IWUSR | S_IRGRP | S_IROTH) as u32),
+        )?;
+        debug!("Writing to {}", temporary_file_path.display());
+        Ok(file)
+    }
 }
 
 impl Writer for JsonWriter {
@@ -4,8 +4,12 @@
 mod client;
 mod json_writer;
 mod message;
+mod otlp_writer;
+mod spool_writer;
 mod util;
 mod writer;
 
 pub use client::*;
 pub use message::*;
+pub use otlp_writer::{OtlpWriter, OTLP_ENDPOINT_ENV_VAR};
+pub use spool_writer::{flush_spool, SpoolWriter};
@@ -26,7 +26,7 @@ const LIBRARY_VERSION: &str = "Rust v1"; // update when changes are made to the
 #[derive(Serialize, Debug)]
 pub struct Message {
     schema_version: u32,
-    messages: Vec<MessageBody>,
+    pub(crate) messages: Vec<MessageBody>,
 }
 
 impl Message {
@@ -52,10 +52,24 @@ impl Message {
 
 #[derive(Serialize, Debug)]
 pub struct MessageBody {
-    message_type: String,
-    core_data: CoreData,
+    pub(crate) message_type: String,
+    pub(crate) core_data: CoreData,
     #[serde(skip_serializing_if = "Option::is_none")]
     duration_seconds: Option<f64>,
+    /// Tool insights only records the truncated ids in the custom map, but
+    /// other collectors need the full ids and timestamps.
+    #[serde(skip)]
+    pub(crate) span: SpanData,
+}
+
+/// The tracing data of the invocation a message is about.
+#[derive(Debug, Clone)]
+pub(crate) struct SpanData {
+    pub(crate) trace_id: u64,
+    pub(crate) span_id: u64,
+    pub(crate) parent_span_id: Option<u64>,
+    pub(crate) start_time: SystemTime,
+    pub(crate) end_time: Option<SystemTime>,
 }
 
 impl MessageBody {
@@ -69,14 +83,21 @@ impl MessageBody {
             message_type: message_type.to_string(),
             core_data: CoreData::new(ti_context, map),
             duration_seconds: end_time.map(|t| duration_in_seconds(ti_context.get_start_time(), t)),
+            span: SpanData {
+                trace_id: ti_context.get_trace_id(),
+                span_id: ti_context.get_span_id(),
+                parent_span_id: ti_context.get_parent_span_id(),
+                start_time: ti_context.get_start_time(),
+                end_time,
+            },
         }
     }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct CoreData {
-    tool_name: String,
-    tool_version: String,
+    pub(crate) tool_name: String,
+    pub(crate) tool_version: String,
     tool_feature_name: String,
     run_id: String,
     run_time_epoch: u64,
@@ -86,8 +107,8 @@ pub struct CoreData {
     run_exit_code: Option<i32>,
     run_current_working_directory: String,
     session_id: String,
-    user_username: String,
-    machine_hostname: String,
+    pub(crate) user_username: String,
+    pub(crate) machine_hostname: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     custom_map: Option<HashMap<String, String>>,
     lib_ver: String,
@@ -174,6 +195,13 @@ pub fn get_span_id(trace_id: u64) -> u64 {
     }
 }
 
+pub fn get_parent_span_id() -> Option<u64> {
+    // If the span id env var is set, it is the span of the process which spawned this one
+    env::var(GITSTATS_SPAN_ID)
+        .ok()
+        .map(decode_zipkin_compatible_id)
+}
+
 pub fn set_trace_env_vars(trace_id: u64, span_id: u64) {
     env::set_var(
         GITSTATS_TRACE_ID,
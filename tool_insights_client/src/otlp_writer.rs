@@ -0,0 +1,414 @@
+// Copyright 2022 Twitter, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Context, Result};
+use reqwest::blocking::Client;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, CONTENT_TYPE};
+use serde_json::{json, Value};
+use tracing::debug;
+
+use crate::message::{Message, MessageBody};
+use crate::spool_writer::SpoolWriter;
+use crate::writer::Writer;
+
+/// The standard OpenTelemetry variable naming the collector's base URL, e.g.
+/// `http://localhost:4318`.
+pub const OTLP_ENDPOINT_ENV_VAR: &str = "OTEL_EXPORTER_OTLP_ENDPOINT";
+
+/// The standard OpenTelemetry variable listing extra headers to send, as
+/// comma-separated `key=value` pairs.
+const OTLP_HEADERS_ENV_VAR: &str = "OTEL_EXPORTER_OTLP_HEADERS";
+
+const OTLP_SPOOL_DIR_ENV_VAR: &str = "FOCUS_OTLP_SPOOL_DIR";
+
+const OTLP_SPOOL_DIR_DEFAULT: &str = "~/.cache/focus/otlp-spool";
+
+const OTLP_TRACES_PATH: &str = "v1/traces";
+
+/// Keep the client from holding up the tool when the collector is unreachable.
+const OTLP_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Give up quickly on collectors which aren't listening.
+const OTLP_CONNECT_TIMEOUT: Duration = Duration::from_millis(250);
+
+const SPAN_KIND_INTERNAL: u32 = 1;
+const STATUS_CODE_OK: u32 = 1;
+const STATUS_CODE_ERROR: u32 = 2;
+
+/// Exports messages as spans to an OpenTelemetry collector using OTLP over
+/// HTTP with JSON encoding. Requests which can't be delivered, or all requests
+/// if the writer is spooling, are appended to a [`SpoolWriter`] spool and sent
+/// by [`OtlpWriter::flush`].
+pub struct OtlpWriter {
+    traces_url: String,
+    client: Client,
+    spool: SpoolWriter,
+    spool_only: bool,
+}
+
+impl OtlpWriter {
+    /// Create a writer for the collector at `endpoint`. If `spool_only` is set,
+    /// requests are always spooled rather than sent. If no spool directory is
+    /// specified:
+    ///   then spool to the path specified by the `FOCUS_OTLP_SPOOL_DIR`
+    ///   environment variable, or if that is unset, to `OTLP_SPOOL_DIR_DEFAULT`.
+    pub fn new(endpoint: &str, spool_dir: Option<PathBuf>, spool_only: bool) -> Result<OtlpWriter> {
+        let spool_dir = match spool_dir {
+            Some(path) => path,
+            None => {
+                let spool_dir = std::env::var(OTLP_SPOOL_DIR_ENV_VAR)
+                    .unwrap_or_else(|_| OTLP_SPOOL_DIR_DEFAULT.to_string());
+                PathBuf::from(shellexpand::tilde(&spool_dir).to_string())
+            }
+        };
+        let headers = match std::env::var(OTLP_HEADERS_ENV_VAR) {
+            Ok(headers) => parse_headers(&headers)?,
+            Err(_) => HeaderMap::new(),
+        };
+        let client = Client::builder()
+            .timeout(OTLP_TIMEOUT)
+            .connect_timeout(OTLP_CONNECT_TIMEOUT)
+            .default_headers(headers)
+            .build()
+            .context("Creating HTTP client failed")?;
+        Ok(OtlpWriter {
+            traces_url: format!("{}/{}", endpoint.trim_end_matches('/'), OTLP_TRACES_PATH),
+            client,
+            spool: SpoolWriter::new(Some(spool_dir)),
+            spool_only,
+        })
+    }
+
+    /// Create a writer for the collector named by `OTEL_EXPORTER_OTLP_ENDPOINT`,
+    /// if it is set.
+    pub fn from_env(spool_only: bool) -> Result<Option<OtlpWriter>> {
+        match std::env::var(OTLP_ENDPOINT_ENV_VAR) {
+            Ok(endpoint) if !endpoint.is_empty() => {
+                Ok(Some(OtlpWriter::new(&endpoint, None, spool_only)?))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    fn send(&self, body: &[u8]) -> Result<()> {
+        let response = self
+            .client
+            .post(&self.traces_url)
+            .header(CONTENT_TYPE, "application/json")
+            .body(body.to_vec())
+            .send()
+            .with_context(|| format!("Sending spans to {}", self.traces_url))?;
+        if !response.status().is_success() {
+            bail!(
+                "Collector at {} responded with {}",
+                self.traces_url,
+                response.status()
+            );
+        }
+        Ok(())
+    }
+
+    fn spool(&self, mut body: Vec<u8>) -> Result<()> {
+        body.push(b'\n');
+        self.spool.append(&body)?;
+        debug!("Spooled spans");
+        Ok(())
+    }
+
+    /// Send the spooled requests to the collector, oldest first, stopping at
+    /// the first failure. Returns the number of requests sent.
+    pub fn flush(&self) -> Result<usize> {
+        self.spool.flush_with(|line| self.send(line.as_bytes()))
+    }
+}
+
+impl Writer for OtlpWriter {
+    fn write(&self, messages: &[Message]) -> Result<()> {
+        let body =
+            serde_json::to_vec(&export_request(messages)).context("Serializing OTLP request")?;
+        if self.spool_only {
+            return self.spool(body);
+        }
+        match self.send(&body) {
+            Ok(()) => Ok(()),
+            Err(e) => {
+                debug!(?e, "Failed to export spans, spooling them");
+                self.spool(body)
+            }
+        }
+    }
+}
+
+fn parse_headers(headers: &str) -> Result<HeaderMap> {
+    let mut result = HeaderMap::new();
+    for pair in headers.split(',').filter(|pair| !pair.trim().is_empty()) {
+        let (key, value) = pair
+            .split_once('=')
+            .with_context(|| format!("Invalid header {:?} in {}", pair, OTLP_HEADERS_ENV_VAR))?;
+        result.insert(
+            HeaderName::from_bytes(key.trim().as_bytes())?,
+            HeaderValue::from_str(value.trim())?,
+        );
+    }
+    Ok(result)
+}
+
+fn unix_nanos(time: SystemTime) -> String {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos()
+        .to_string()
+}
+
+fn attribute(key: &str, value: &Value) -> Option<Value> {
+    let value = match value {
+        Value::String(s) => json!({ "stringValue": s }),
+        Value::Bool(b) => json!({ "boolValue": b }),
+        Value::Number(n) if n.is_f64() => json!({ "doubleValue": n }),
+        // OTLP JSON encodes 64 bit integers as strings.
+        Value::Number(n) => json!({ "intValue": n.to_string() }),
+        Value::Array(values) => json!({
+            "arrayValue": {
+                "values": values
+                    .iter()
+                    .filter_map(|value| attribute("", value))
+                    .map(|attribute| attribute["value"].clone())
+                    .collect::<Vec<_>>()
+            }
+        }),
+        Value::Object(_) | Value::Null => return None,
+    };
+    Some(json!({ "key": key, "value": value }))
+}
+
+/// Convert a message into a span. The fields of the message become attributes,
+/// with the custom map flattened into `custom.<key>` attributes.
+fn span(body: &MessageBody) -> Value {
+    let core_data = serde_json::to_value(&body.core_data).unwrap_or_default();
+    let mut attributes = vec![attribute(
+        "tool_insights.message_type",
+        &Value::from(body.message_type.as_str()),
+    )];
+    if let Value::Object(fields) = &core_data {
+        for (key, value) in fields {
+            match (key.as_str(), value) {
+                ("custom_map", Value::Object(custom_map)) => {
+                    attributes.extend(custom_map.iter().map(|(custom_key, custom_value)| {
+                        attribute(&format!("custom.{}", custom_key), custom_value)
+                    }));
+                }
+                _ => attributes.push(attribute(&format!("tool_insights.{}", key), value)),
+            }
+        }
+    }
+
+    let span = &body.span;
+    let exit_code = core_data.get("run_exit_code").and_then(Value::as_i64);
+    let mut result = json!({
+        "traceId": format!("{:032x}", span.trace_id),
+        "spanId": format!("{:016x}", span.span_id),
+        "name": core_data
+            .get("tool_feature_name")
+            .and_then(Value::as_str)
+            .unwrap_or("__invocation__"),
+        "kind": SPAN_KIND_INTERNAL,
+        "startTimeUnixNano": unix_nanos(span.start_time),
+        "endTimeUnixNano": unix_nanos(span.end_time.unwrap_or(span.start_time)),
+        "attributes": attributes.into_iter().flatten().collect::<Vec<_>>(),
+    });
+    if let Some(parent_span_id) = span.parent_span_id {
+        result["parentSpanId"] = Value::from(format!("{:016x}", parent_span_id));
+    }
+    if let Some(exit_code) = exit_code {
+        result["status"] = json!({
+            "code": if exit_code == 0 { STATUS_CODE_OK } else { STATUS_CODE_ERROR }
+        });
+    }
+    result
+}
+
+/// Build an OTLP `ExportTraceServiceRequest` with a span for each message,
+/// grouped by the tool which sent them.
+fn export_request(messages: &[Message]) -> Value {
+    let resource_spans: Vec<Value> = messages
+        .iter()
+        .flat_map(|message| message.messages.iter())
+        .map(|body| {
+            let resource_attributes = [
+                ("service.name", &body.core_data.tool_name),
+                ("service.version", &body.core_data.tool_version),
+                ("host.name", &body.core_data.machine_hostname),
+                ("enduser.id", &body.core_data.user_username),
+            ]
+            .into_iter()
+            .filter_map(|(key, value)| attribute(key, &Value::from(value.as_str())))
+            .collect::<Vec<_>>();
+            json!({
+                "resource": { "attributes": resource_attributes },
+                "scopeSpans": [{
+                    "scope": {
+                        "name": env!("CARGO_PKG_NAME"),
+                        "version": env!("CARGO_PKG_VERSION"),
+                    },
+                    "spans": [span(body)],
+                }],
+            })
+        })
+        .collect();
+    json!({ "resourceSpans": resource_spans })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    use crate::client::Client as TiClient;
+    use crate::message::MessageKind;
+
+    use super::*;
+
+    fn message() -> Message {
+        let client = TiClient::new(
+            "focus".to_string(),
+            "1.2.3".to_string(),
+            UNIX_EPOCH + Duration::from_secs(1000),
+        );
+        let mut context = client.get_context();
+        context.set_tool_feature_name("sync");
+        context.set_exit_code(1);
+        context.add_to_custom_map("repo", "/repo");
+        Message::new(
+            MessageKind::PerformanceMessage,
+            &context,
+            Some(UNIX_EPOCH + Duration::from_secs(1002)),
+            None,
+        )
+    }
+
+    /// Accept `count` HTTP requests, responding with 200 to each, and return
+    /// their bodies.
+    fn serve(listener: TcpListener, count: usize) -> thread::JoinHandle<Vec<String>> {
+        thread::spawn(move || {
+            let mut bodies = Vec::new();
+            for stream in listener.incoming().take(count) {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut content_length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line == "\r\n" {
+                        break;
+                    }
+                    if let Some((name, value)) = line.split_once(':') {
+                        if name.eq_ignore_ascii_case("content-length") {
+                            content_length = value.trim().parse().unwrap();
+                        }
+                    }
+                }
+                let mut body = vec![0; content_length];
+                reader.read_exact(&mut body).unwrap();
+                bodies.push(String::from_utf8(body).unwrap());
+                stream
+                    .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+                    .unwrap();
+            }
+            bodies
+        })
+    }
+
+    #[test]
+    fn test_export_request() {
+        let request = export_request(&[message()]);
+        let resource_spans = &request["resourceSpans"][0];
+        assert!(resource_spans["resource"]["attributes"]
+            .as_array()
+            .unwrap()
+            .contains(&json!({ "key": "service.name", "value": { "stringValue": "focus" } })));
+
+        let span = &resource_spans["scopeSpans"][0]["spans"][0];
+        assert_eq!(span["name"], "sync");
+        assert_eq!(span["traceId"].as_str().unwrap().len(), 32);
+        assert_eq!(span["spanId"].as_str().unwrap().len(), 16);
+        assert_eq!(span["startTimeUnixNano"], "1000000000000");
+        assert_eq!(span["endTimeUnixNano"], "1002000000000");
+        assert_eq!(span["status"]["code"], STATUS_CODE_ERROR);
+        let attributes = span["attributes"].as_array().unwrap();
+        assert!(attributes
+            .contains(&json!({ "key": "custom.repo", "value": { "stringValue": "/repo" } })));
+        assert!(attributes.contains(
+            &json!({ "key": "tool_insights.run_exit_code", "value": { "intValue": "1" } })
+        ));
+    }
+
+    fn assert_sync_spans(bodies: &[String]) -> Result<()> {
+        for body in bodies.iter() {
+            let request: Value = serde_json::from_str(body)?;
+            assert_eq!(
+                request["resourceSpans"][0]["scopeSpans"][0]["spans"][0]["name"],
+                "sync"
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_spools_when_offline_and_flushes() -> Result<()> {
+        let spool_dir = tempfile::tempdir()?;
+
+        // Nothing listens on this port once the listener is dropped.
+        let unused_port = TcpListener::bind("127.0.0.1:0")?.local_addr()?.port();
+        let offline = OtlpWriter::new(
+            &format!("http://127.0.0.1:{}", unused_port),
+            Some(spool_dir.path().to_owned()),
+            false,
+        )?;
+        offline.write(&[message()])?;
+
+        // A successful export doesn't resend the spool.
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let endpoint = format!("http://{}/", listener.local_addr()?);
+        let server = serve(listener, 1);
+        let online = OtlpWriter::new(&endpoint, Some(spool_dir.path().to_owned()), false)?;
+        online.write(&[message()])?;
+        let bodies = server.join().unwrap();
+        assert_eq!(bodies.len(), 1);
+        assert_sync_spans(&bodies)?;
+
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let endpoint = format!("http://{}/", listener.local_addr()?);
+        let server = serve(listener, 1);
+        let online = OtlpWriter::new(&endpoint, Some(spool_dir.path().to_owned()), false)?;
+        assert_eq!(online.flush()?, 1);
+        let bodies = server.join().unwrap();
+        assert_eq!(bodies.len(), 1);
+        assert_sync_spans(&bodies)?;
+
+        assert_eq!(online.flush()?, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_spool_only() -> Result<()> {
+        let spool_dir = tempfile::tempdir()?;
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let endpoint = format!("http://{}/", listener.local_addr()?);
+
+        let spooling = OtlpWriter::new(&endpoint, Some(spool_dir.path().to_owned()), true)?;
+        spooling.write(&[message()])?;
+        spooling.write(&[message()])?;
+
+        // Nothing was sent until the spool is flushed.
+        let server = serve(listener, 2);
+        assert_eq!(spooling.flush()?, 2);
+        let bodies = server.join().unwrap();
+        assert_eq!(bodies.len(), 2);
+        assert_sync_spans(&bodies)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,359 @@
+// Copyright 2022 Twitter, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+use crate::json_writer::JsonWriter;
+use crate::message::Message;
+use crate::writer::Writer;
+
+const SPOOL_DIR_ENV_VAR: &str = "FOCUS_TOOL_INSIGHTS_SPOOL_DIR";
+
+const SPOOL_DIR_DEFAULT: &str = "~/.cache/focus/tool-insights-spool";
+
+const LOCK_FILE_NAME: &str = "spool.lock";
+
+const CURRENT_SEGMENT_FILE_NAME: &str = "current.ndjson";
+
+const SEGMENT_PREFIX: &str = "segment-";
+
+const SEGMENT_SUFFIX: &str = ".ndjson";
+
+/// The size at which the current segment is closed and a new one started.
+const MAX_SEGMENT_BYTES_DEFAULT: u64 = 1024 * 1024;
+
+/// The size beyond which the oldest segments are dropped, so that the spool
+/// can't grow without bound if it is never flushed.
+const MAX_SPOOL_BYTES_DEFAULT: u64 = 64 * 1024 * 1024;
+
+/// Appends messages as lines of JSON to segment files in a spool directory,
+/// rather than writing a file per message. The spool is delivered to the tool
+/// insights daemon in one go by [`SpoolWriter::flush`].
+///
+/// Writers in concurrent processes are serialized with a lock file.
+pub struct SpoolWriter {
+    spool_dir: PathBuf,
+    max_segment_bytes: u64,
+    max_spool_bytes: u64,
+}
+
+/// Holds the lock on the spool until dropped.
+struct SpoolLock {
+    _file: File,
+}
+
+impl SpoolWriter {
+    /// Create a writer for the specified `spool_dir`. If no directory is specified:
+    ///   then spool to the path specified by the `FOCUS_TOOL_INSIGHTS_SPOOL_DIR`
+    ///   environment variable, or if that is unset, to `SPOOL_DIR_DEFAULT`.
+    pub fn new(spool_dir: Option<PathBuf>) -> SpoolWriter {
+        let spool_dir = match spool_dir {
+            Some(path) => path,
+            None => {
+                let spool_dir = std::env::var(SPOOL_DIR_ENV_VAR)
+                    .unwrap_or_else(|_| SPOOL_DIR_DEFAULT.to_string());
+                PathBuf::from(shellexpand::tilde(&spool_dir).to_string())
+            }
+        };
+        SpoolWriter {
+            spool_dir,
+            max_segment_bytes: MAX_SEGMENT_BYTES_DEFAULT,
+            max_spool_bytes: MAX_SPOOL_BYTES_DEFAULT,
+        }
+    }
+
+    #[cfg(test)]
+    pub(crate) fn with_limits(
+        spool_dir: PathBuf,
+        max_segment_bytes: u64,
+        max_spool_bytes: u64,
+    ) -> Self {
+        SpoolWriter {
+            spool_dir,
+            max_segment_bytes,
+            max_spool_bytes,
+        }
+    }
+
+    /// Deliver the spooled messages to `writer`, one file each, oldest first,
+    /// and remove them from the spool. Returns the number of messages
+    /// delivered.
+    ///
+    /// If delivery fails part way through a segment, the whole segment is kept,
+    /// so the messages before the failure will be delivered again next time.
+    pub fn flush(&self, writer: &JsonWriter) -> Result<usize> {
+        self.flush_with(|line| writer.write_serialized(line))
+    }
+
+    /// Pass each spooled line to `deliver`, oldest first, removing each
+    /// segment from the spool once all of its lines are delivered. Returns the
+    /// number of lines delivered.
+    pub(crate) fn flush_with(&self, mut deliver: impl FnMut(&str) -> Result<()>) -> Result<usize> {
+        if !self.spool_dir.exists() {
+            return Ok(0);
+        }
+        let _lock = self.lock()?;
+        self.close_current_segment()?;
+        let mut count = 0;
+        for path in self.segments()? {
+            let file = File::open(&path).with_context(|| format!("Opening {}", path.display()))?;
+            for line in BufReader::new(file).lines() {
+                let line = line.with_context(|| format!("Reading {}", path.display()))?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                deliver(&line)?;
+                count += 1;
+            }
+            fs::remove_file(&path).with_context(|| format!("Removing {}", path.display()))?;
+        }
+        debug!(count, spool_dir = ?self.spool_dir, "Flushed spool");
+        Ok(count)
+    }
+
+    /// Append `lines`, which must each end in a newline, to the current
+    /// segment, rotating it and dropping the oldest segments as needed.
+    pub(crate) fn append(&self, lines: &[u8]) -> Result<()> {
+        let _lock = self.lock()?;
+        let current = self.current_segment_path();
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&current)
+            .with_context(|| format!("Opening {}", current.display()))?;
+        file.write_all(lines)
+            .with_context(|| format!("Writing {}", current.display()))?;
+        let len = file
+            .metadata()
+            .with_context(|| format!("Reading {}", current.display()))?
+            .len();
+        drop(file);
+
+        if len >= self.max_segment_bytes {
+            self.close_current_segment()?;
+            self.enforce_size_cap()?;
+        }
+        Ok(())
+    }
+
+    fn lock(&self) -> Result<SpoolLock> {
+        fs::create_dir_all(&self.spool_dir)
+            .with_context(|| format!("Creating {}", self.spool_dir.display()))?;
+        let path = self.spool_dir.join(LOCK_FILE_NAME);
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .open(&path)
+            .with_context(|| format!("Opening {}", path.display()))?;
+        // The lock is released when the file is closed.
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } != 0 {
+            return Err(std::io::Error::last_os_error())
+                .with_context(|| format!("Locking {}", path.display()));
+        }
+        Ok(SpoolLock { _file: file })
+    }
+
+    fn current_segment_path(&self) -> PathBuf {
+        self.spool_dir.join(CURRENT_SEGMENT_FILE_NAME)
+    }
+
+    /// Rename the current segment so that it will be flushed, if it has any
+    /// messages in it. Must be called with the lock held.
+    fn close_current_segment(&self) -> Result<()> {
+        let current = self.current_segment_path();
+        match fs::metadata(&current) {
+            Ok(metadata) if metadata.len() > 0 => {}
+            Ok(_) => return Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e).with_context(|| format!("Reading {}", current.display())),
+        }
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        // The timestamp is zero-padded so that the segments sort oldest first.
+        let closed = self.spool_dir.join(format!(
+            "{}{:024}-{}{}",
+            SEGMENT_PREFIX,
+            nanos,
+            Uuid::new_v4(),
+            SEGMENT_SUFFIX
+        ));
+        fs::rename(&current, &closed)
+            .with_context(|| format!("Renaming {} to {}", current.display(), closed.display()))
+    }
+
+    /// The closed segments, oldest first.
+    fn segments(&self) -> Result<Vec<PathBuf>> {
+        let mut segments = Vec::new();
+        for entry in fs::read_dir(&self.spool_dir)
+            .with_context(|| format!("Reading {}", self.spool_dir.display()))?
+        {
+            let entry = entry?;
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            if name.starts_with(SEGMENT_PREFIX) && name.ends_with(SEGMENT_SUFFIX) {
+                segments.push(entry.path());
+            }
+        }
+        segments.sort();
+        Ok(segments)
+    }
+
+    /// Drop the oldest segments until the spool fits within its size cap. Must
+    /// be called with the lock held.
+    fn enforce_size_cap(&self) -> Result<()> {
+        let segments = self.segments()?;
+        let mut sizes = Vec::with_capacity(segments.len());
+        let mut total = file_len(&self.current_segment_path())?;
+        for path in segments.iter() {
+            let len = file_len(path)?;
+            total += len;
+            sizes.push(len);
+        }
+        for (path, len) in segments.iter().zip(sizes) {
+            if total <= self.max_spool_bytes {
+                break;
+            }
+            warn!(
+                "Spool at {} is over {} bytes, dropping {}",
+                self.spool_dir.display(),
+                self.max_spool_bytes,
+                path.display()
+            );
+            fs::remove_file(path).with_context(|| format!("Removing {}", path.display()))?;
+            total -= len;
+        }
+        Ok(())
+    }
+}
+
+fn file_len(path: &Path) -> Result<u64> {
+    match fs::metadata(path) {
+        Ok(metadata) => Ok(metadata.len()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(0),
+        Err(e) => Err(e).with_context(|| format!("Reading {}", path.display())),
+    }
+}
+
+impl Writer for SpoolWriter {
+    fn write(&self, messages: &[Message]) -> Result<()> {
+        let mut lines = Vec::new();
+        for message in messages {
+            serde_json::to_writer(&mut lines, message).context("Could not serialize message")?;
+            lines.push(b'\n');
+        }
+        self.append(&lines)
+    }
+}
+
+/// Deliver the messages spooled by the default [`SpoolWriter`] to the default
+/// tool insights directory. Returns the number of messages delivered.
+pub fn flush_spool() -> Result<usize> {
+    SpoolWriter::new(None).flush(&JsonWriter::new(None))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::SystemTime;
+
+    use super::*;
+    use crate::client::Client as TiClient;
+    use crate::message::MessageKind;
+
+    fn messages(count: usize) -> Vec<Message> {
+        let client = TiClient::new("focus".to_string(), "0.1".to_string(), SystemTime::now());
+        let context = client.get_context();
+        (0..count)
+            .map(|_| {
+                Message::new(
+                    MessageKind::PerformanceMessage,
+                    &context,
+                    Some(SystemTime::now()),
+                    None,
+                )
+            })
+            .collect()
+    }
+
+    fn files_in(dir: &Path) -> Result<Vec<PathBuf>> {
+        let mut files = fs::read_dir(dir)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<std::io::Result<Vec<_>>>()?;
+        files.sort();
+        Ok(files)
+    }
+
+    #[test]
+    fn test_appends_to_current_segment() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let writer = SpoolWriter::with_limits(dir.path().to_path_buf(), u64::MAX, u64::MAX);
+        writer.write(&messages(2))?;
+        writer.write(&messages(1))?;
+
+        let contents = fs::read_to_string(dir.path().join(CURRENT_SEGMENT_FILE_NAME))?;
+        assert_eq!(contents.lines().count(), 3);
+        for line in contents.lines() {
+            serde_json::from_str::<serde_json::Value>(line)?;
+        }
+        assert!(writer.segments()?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_rotates_and_caps_segments() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let message_len = serde_json::to_vec(&messages(1)[0])?.len() as u64 + 1;
+        // Every write closes the segment, and only two segments fit.
+        let writer = SpoolWriter::with_limits(
+            dir.path().to_path_buf(),
+            1,
+            message_len * 2 + message_len / 2,
+        );
+        for _ in 0..5 {
+            writer.write(&messages(1))?;
+        }
+
+        assert_eq!(writer.segments()?.len(), 2);
+        assert!(!dir.path().join(CURRENT_SEGMENT_FILE_NAME).exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_flush() -> Result<()> {
+        let spool_dir = tempfile::tempdir()?;
+        let ti_dir = tempfile::tempdir()?;
+        let writer = SpoolWriter::with_limits(spool_dir.path().to_path_buf(), 1, u64::MAX);
+        writer.write(&messages(2))?;
+        writer.write(&messages(1))?;
+        let writer = SpoolWriter::with_limits(spool_dir.path().to_path_buf(), u64::MAX, u64::MAX);
+        writer.write(&messages(1))?;
+
+        let count = writer.flush(&JsonWriter::new(Some(ti_dir.path().to_path_buf())))?;
+        assert_eq!(count, 4);
+
+        let delivered = files_in(ti_dir.path())?;
+        assert_eq!(delivered.len(), 4);
+        for path in delivered {
+            serde_json::from_slice::<serde_json::Value>(&fs::read(path)?)?;
+        }
+        assert_eq!(
+            files_in(spool_dir.path())?,
+            vec![spool_dir.path().join(LOCK_FILE_NAME)]
+        );
+
+        assert_eq!(
+            writer.flush(&JsonWriter::new(Some(ti_dir.path().to_path_buf())))?,
+            0
+        );
+        Ok(())
+    }
+}